@@ -1,5 +1,15 @@
 //! Module to hold all the commandline arguments related code.
 
+use crate::fuzzer::scheduler::SCHEDULERS;
+use crate::profiles::profile::ProfileType;
+use crate::utils::random::Random;
+use crate::execution::execution::MEMORY_LIMIT;
+use crate::ir::program::{MAX_INSTS, MISSING_PROPS};
+use crate::lifter::lifter::{DECLARATIONS, DECLARATION_WEIGHTS,
+                            WRAP_IN_IIFE_PROBABLITY};
+use crate::warn;
+use crate::fuzzer::settings::{GENERATORS, GENERATOR_NAMES, generator_index};
+
 #[derive(Debug)]
 struct CmdLineError(&'static str);
 impl std::fmt::Display for CmdLineError {
@@ -12,26 +22,331 @@ impl std::error::Error for CmdLineError {}
 
 type CmdLineResult<T> = Result<T, Box<dyn std::error::Error>>;
 
+/// Parse a timeout that is either in seconds, like `2` or `0.5`, or in
+/// milliseconds, like `500ms`, into milliseconds. A timeout of 0 is not valid.
+fn parse_timeout(timeout: &str) -> Option<u64> {
+    let timeout = match timeout.strip_suffix("ms") {
+        Some(ms) => ms.parse::<u64>().ok()?,
+        None     => {
+            let secs = timeout.parse::<f64>().ok()?;
+            if !secs.is_finite() || secs < 0.0 {
+                return None;
+            }
+            (secs * 1000.0).round() as u64
+        }
+    };
+    if timeout == 0 {
+        None
+    } else {
+        Some(timeout)
+    }
+}
+
+/// Parse the no. of fuzzer threads to run, which is either a number above 0 or
+/// `auto` for one thread on each of the cores that are available
+fn parse_jobs(jobs: &str) -> Option<usize> {
+    if jobs == "auto" {
+        return Some(std::thread::available_parallelism()
+                    .map_or(1, |cores| cores.get()));
+    }
+    jobs.parse::<usize>().ok().filter(|&jobs| jobs > 0)
+}
+
+/// The minutes that the campaign may run below the `--min-fcps` for, unless
+/// they are given with it
+const MIN_FCPS_MINUTES: u64 = 5;
+
+/// Parse the `--min-fcps`, which is the fcps along with the minutes that the
+/// campaign may run slower than that for, like `50/10`. The minutes can be
+/// left out.
+fn parse_min_fcps(min_fcps: &str) -> Option<(f64, u64)> {
+    let (fcps, minutes) = match min_fcps.split_once('/') {
+        Some((fcps, minutes)) => (fcps, minutes.parse::<u64>().ok()?),
+        None                  => (min_fcps, MIN_FCPS_MINUTES),
+    };
+    let fcps = fcps.parse::<f64>().ok()?;
+    if fcps.is_finite() && fcps > 0.0 && minutes > 0 {
+        Some((fcps, minutes))
+    } else {
+        None
+    }
+}
+
+/// Parse the weights of the keywords that the variables are declared with,
+/// like `var=2,let=1,const=1`. The keywords that are left out are not used, and
+/// at least one of them has to be.
+fn parse_declarations(declarations: &str) -> Option<[u16; 3]> {
+    let mut weights = [0; 3];
+    for declaration in declarations.split(',') {
+        let (name, weight) = declaration.split_once('=')?;
+        let idx = DECLARATIONS.iter().position(|&known| known == name)?;
+        weights[idx] = weight.parse::<u16>().ok()?;
+    }
+    if weights.iter().any(|&weight| weight > 0) {
+        Some(weights)
+    } else {
+        None
+    }
+}
+
+/// The options that all of the commands take
+const COMMON_OPTIONS: [&str; 11] = [
+    "-h", "--help", "--builtins", "--corpus", "--list-generators", "-v", "-vv",
+    "--verbose", "-q", "-qq", "--quiet",
+];
+
+/// The options for starting the engine and running programs on it
+const ENGINE_OPTIONS: [&str; 14] = [
+    "-f", "--file", "-p", "--profile", "-t", "--timeout", "-d", "--disk",
+    "--forkserver", "--engine-arg", "--env", "--mem-limit", "--restart-every",
+    "--restart-rss",
+];
+
+/// The options for generating new programs
+const GENERATOR_OPTIONS: [&str; 6] = [
+    "--seed", "--min-insts", "--max-insts", "--weight", "--eval",
+    "--missing-props",
+];
+
+/// The options for lifting programs to JS
+const LIFTER_OPTIONS: [&str; 8] = [
+    "--digest", "--ir-comments", "--literal-forms", "--iife-probability",
+    "--declarations", "--realistic-names", "--strip-dead-code", "--print-ir",
+];
+
+/// The options that only the fuzz command takes. The old spellings of the
+/// replay and bench commands are options of it too.
+const FUZZ_OPTIONS: [&str; 27] = [
+    "--dry-run", "--differential", "--adaptive-timeout", "--output-dir",
+    "--log-files", "--no-tui", "--resume",
+    "--import-fuzzilli", "--stop-on-crash", "--max-buckets", "--max-iterations",
+    "--max-time", "--save-timeouts", "--save-incorrect", "--check-syntax",
+    "--keep-slowest", "--reprl-batch", "--scheduler", "-j", "--jobs",
+    "--recheck-incorrect", "--min-fcps", "--max-timeouts", "--abort-on-alert",
+    "--replay", "--bench-gen", "--dedup",
+];
+
+/// What zebra is asked to do, which is given as the first arg. Each command
+/// only takes the options that apply to it, see [Command::takes].
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Fuzz the engine. This is what is done when no command is given.
+    Fuzz,
+
+    /// Run the program of a saved crash or a seed once on the engine
+    Replay(String),
+
+    /// Print the JS of the program of a saved crash or a seed, without running
+    /// it
+    Lift(String),
+
+    /// Write the entries of the corpus that add coverage to a new corpus
+    /// directory
+    Minimize(String),
+
+    /// Add the programs of a Fuzzilli corpus directory to the corpus
+    CorpusImport(String),
+
+    /// Print the no. of entries of the corpus and how big they are
+    CorpusInfo,
+
+    /// Generate and lift this many programs without running them
+    Bench(u64),
+}
+
+impl Command {
+
+    /// The name of the command, as it is given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Fuzz            => "fuzz",
+            Command::Replay(_)       => "replay",
+            Command::Lift(_)         => "lift",
+            Command::Minimize(_)     => "minimize",
+            Command::CorpusImport(_) => "corpus import",
+            Command::CorpusInfo      => "corpus info",
+            Command::Bench(_)        => "bench",
+        }
+    }
+
+    /// Check if the command takes the `option`. The ones that none of the
+    /// commands take are not checked here.
+    fn takes(&self, option: &str) -> bool {
+        let groups: &[&[&str]] = match self {
+            Command::Fuzz            => &[&ENGINE_OPTIONS, &GENERATOR_OPTIONS,
+                                          &LIFTER_OPTIONS, &FUZZ_OPTIONS],
+            Command::Replay(_)       => &[&ENGINE_OPTIONS, &GENERATOR_OPTIONS,
+                                          &LIFTER_OPTIONS],
+            Command::Lift(_) |
+            Command::Bench(_)        => &[&GENERATOR_OPTIONS, &LIFTER_OPTIONS],
+            Command::Minimize(_)     => &[&ENGINE_OPTIONS],
+            Command::CorpusImport(_) |
+            Command::CorpusInfo      => &[],
+        };
+        let all: [&[&str]; 5] = [&COMMON_OPTIONS, &ENGINE_OPTIONS,
+                                 &GENERATOR_OPTIONS, &LIFTER_OPTIONS,
+                                 &FUZZ_OPTIONS];
+        COMMON_OPTIONS.contains(&option) ||
+            groups.iter().any(|group| group.contains(&option)) ||
+            !all.iter().any(|group| group.contains(&option))
+    }
+
+    /// Parse the command at the start of `cmdline`, which is the args without
+    /// the name of the binary. Returns the command along with the no. of args
+    /// that it took up.
+    fn parse(cmdline: &[String]) -> CmdLineResult<(Self, usize)> {
+        let arg = |idx: usize, what: &str| -> CmdLineResult<String> {
+            match cmdline.get(idx) {
+                Some(arg) => Ok(arg.to_string()),
+                None      => Err(format!("Please specify {} for the {} \
+                                          command", what, cmdline[0]).into()),
+            }
+        };
+
+        let first = match cmdline.first() {
+            Some(first) => first.as_str(),
+            None        => return Ok((Command::Fuzz, 0)),
+        };
+        let command = match first {
+            "fuzz"     => return Ok((Command::Fuzz, 1)),
+            "replay"   => Command::Replay(arg(1, "the crash file or seed")?),
+            "lift"     => Command::Lift(arg(1, "the crash file or seed")?),
+
+            // corpus-min is the old name of the minimize command
+            "minimize" |
+            "corpus-min" => Command::Minimize(
+                arg(1, "the directory for the minimized corpus")?),
+
+            "bench"    => match arg(1, "the number of programs")?.parse() {
+                Ok(count) => Command::Bench(count),
+                Err(_)    => return Err("Please specify a valid number of \
+                                         programs to generate".into()),
+            },
+            "corpus"   => match cmdline.get(1).map(|arg| arg.as_str()) {
+                Some("import") => return Ok((Command::CorpusImport(
+                    arg(2, "the Fuzzilli corpus directory")?), 3)),
+                Some("info")   => return Ok((Command::CorpusInfo, 2)),
+                _ => return Err("Please specify one of import or info for the \
+                                 corpus command".into()),
+            },
+            _          => return Ok((Command::Fuzz, 0)),
+        };
+        Ok((command, 2))
+    }
+}
+
 /// This structure hold the command line arguments that will be used in the
 /// fuzzing process.
 #[derive(Debug)]
 pub struct CmdLineOptions {
     pub dry_run:  bool,
-    pub threads:  u8,
+    pub print_ir: bool,
+    pub threads:  usize,
     pub filename: String,
-    pub timeout:  u8,
+    pub timeout:  u64,
+    pub adaptive_timeout: bool,
+    pub dedup:    bool,
+    pub keep_slowest: usize,
+    pub reprl_batch: usize,
     pub disk:     bool,
+    pub forkserver: bool,
+    pub eval:     bool,
+    pub builtins: Option<String>,
+    pub corpus:   Option<String>,
+    pub output_dir: String,
+    pub resume:   Option<String>,
+    pub scheduler: String,
+    pub profile:  ProfileType,
+    pub engine_args: Vec<String>,
+    pub env:      Vec<(String, String)>,
+    pub mem_limit: u64,
+    pub command:  Command,
+    pub fuzzilli_corpus: Option<String>,
+    pub seed:     u64,
+    pub min_insts: u8,
+    pub max_insts: usize,
+    pub weights:  Vec<(usize, u16)>,
+    pub missing_props: f64,
+    pub max_iterations: Option<u64>,
+    pub max_time: Option<u64>,
+    pub max_buckets: Option<usize>,
+    pub min_fcps: Option<(f64, u64)>,
+    pub max_timeouts: Option<f64>,
+    pub abort_on_alert: bool,
+    pub restart_every: Option<u64>,
+    pub restart_rss: Option<u64>,
+    pub save_timeouts:  f64,
+    pub save_incorrect: f64,
+    pub digest:   bool,
+    pub ir_comments: bool,
+    pub literal_forms: bool,
+    pub iife_probability: f64,
+    pub declarations: [u16; 3],
+    pub realistic_names: bool,
+    pub strip_dead_code: bool,
+    pub differential: bool,
+    pub recheck_incorrect: u8,
+    pub check_syntax: f64,
+    pub verbosity: i8,
+    pub log_files: bool,
+    pub no_tui: bool,
 }
 
 impl Default for CmdLineOptions {
     fn default() -> Self {
         Self {
             dry_run: false,
+            print_ir: false,
             threads: 1,
             filename: "/home/vignesh/Documents/exploits/temp/webkit_new_source\
                        /WebKit/FuzzBuild/Debug/bin/jsc".to_string(),
-            timeout: 5,
+            timeout: 5000,
+            adaptive_timeout: false,
+            dedup:   false,
+            keep_slowest: 10,
+            reprl_batch: 1,
             disk:    false,
+            forkserver: false,
+            eval:    false,
+            builtins: None,
+            corpus:   None,
+            output_dir: "./output".to_string(),
+            resume:   None,
+            scheduler: SCHEDULERS[0].to_string(),
+            profile:  ProfileType::Spidermonkey,
+            engine_args: vec![],
+            env:      vec![],
+            mem_limit: MEMORY_LIMIT >> 20,
+            command:  Command::Fuzz,
+            fuzzilli_corpus: None,
+            seed:     Random::new(0).rand(),
+            min_insts: 5,
+            max_insts: MAX_INSTS,
+            weights:  vec![],
+            missing_props: MISSING_PROPS,
+            max_iterations: None,
+            max_time: None,
+            max_buckets: None,
+            min_fcps: None,
+            max_timeouts: None,
+            abort_on_alert: false,
+            restart_every: None,
+            restart_rss: None,
+            save_timeouts:  0.0,
+            save_incorrect: 0.0,
+            digest:   false,
+            ir_comments: false,
+            literal_forms: false,
+            iife_probability: WRAP_IN_IIFE_PROBABLITY,
+            declarations: DECLARATION_WEIGHTS,
+            realistic_names: false,
+            strip_dead_code: false,
+            differential: false,
+            recheck_incorrect: 0,
+            check_syntax: 0.0,
+            verbosity: 0,
+            log_files: false,
+            no_tui: false,
         }
     }
 }
@@ -42,25 +357,143 @@ impl CmdLineOptions {
     /// [CmdLineOptions](CmdLineOptions) struct
     pub fn parse(cmdline: Vec<String>) -> CmdLineResult<Self> {
         let mut arguments = Self::default();
+        let (command, start) = Command::parse(&cmdline[1..])?;
+        arguments.command = command;
+
         let mut skip = false;
-        for (idx, value) in cmdline[1..].iter().enumerate() {
+        let mut output_dir_given = false;
+        for (idx, value) in cmdline.iter().enumerate().skip(start + 1) {
 
             if skip {
                 skip = false;
                 continue;
             }
 
+            if !arguments.command.takes(value) {
+                return Err(format!("{} is not an option of the {} command",
+                                   value, arguments.command.name()).into());
+            }
+
             match value.as_str() {
                
                 "--dry-run" => arguments.dry_run = true,
 
+                "--print-ir" => arguments.print_ir = true,
+
                 "-d" |
                 "--disk"    => arguments.disk = true,
 
+                "--forkserver" => arguments.forkserver = true,
+
+                "--eval"    => arguments.eval = true,
+
+                "--digest"  => arguments.digest = true,
+
+                "--ir-comments" => arguments.ir_comments = true,
+
+                "--literal-forms" => arguments.literal_forms = true,
+
+                "--realistic-names" => arguments.realistic_names = true,
+
+                "--strip-dead-code" => arguments.strip_dead_code = true,
+
+                "--adaptive-timeout" => arguments.adaptive_timeout = true,
+
+                "--dedup" => arguments.dedup = true,
+
+                "--log-files" => arguments.log_files = true,
+
+                "--no-tui" => arguments.no_tui = true,
+
+                "--abort-on-alert" => arguments.abort_on_alert = true,
+
+                "--declarations" => {
+                    arguments.declarations =
+                        if let Some(weights) = cmdline.get(idx + 1) {
+                            skip = true;
+                            parse_declarations(weights).ok_or(CmdLineError(
+                                "Please specify the weights of var, let and \
+                                 const, like var=2,let=1,const=1"))?
+                        } else {
+                            return Err(Box::new(CmdLineError(
+                                "Please specify the weights of var, let and \
+                                 const")));
+                        }
+                },
+
+                "--min-fcps" => {
+                    arguments.min_fcps =
+                        if let Some(min) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Some(parse_min_fcps(min).ok_or(CmdLineError(
+                                "Please specify the fcps above 0 and \
+                                 optionally the minutes, like 50/10"))?)
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the lowest fcps")));
+                        }
+                },
+
+                "--max-timeouts" => {
+                    arguments.max_timeouts =
+                        if let Some(percent) = cmdline.get(idx + 1) {
+                            match percent.trim_end_matches('%').parse::<f64>() {
+                                Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                                    skip = true;
+                                    Some(percent / 100.0)
+                                },
+                                _ => return Err(Box::new(CmdLineError(
+                                    "Please specify a percentage between 0 and \
+                                     100 of the programs that may time out"))),
+                            }
+                        } else {
+                            return Err(Box::new(CmdLineError(
+                                "Please specify the percentage of the programs \
+                                 that may time out")));
+                        };
+                },
+
+                "--missing-props" => {
+                    arguments.missing_props =
+                        if let Some(percent) = cmdline.get(idx + 1) {
+                            match percent.trim_end_matches('%').parse::<f64>() {
+                                Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                                    skip = true;
+                                    percent / 100.0
+                                },
+                                _ => return Err(Box::new(CmdLineError(
+                                    "Please specify a percentage between 0 and \
+                                     100 of the property loads"))),
+                            }
+                        } else {
+                            return Err(Box::new(CmdLineError(
+                                "Please specify the percentage of the property \
+                                 loads that read a missing property")));
+                        };
+                },
+
+                "-v" | "-vv" |
+                "--verbose" => {
+                    let steps = if value == "-vv" { 2 } else { 1 };
+                    arguments.verbosity = arguments.verbosity.saturating_add(steps)
+                },
+
+                "-q" | "-qq" |
+                "--quiet"   => {
+                    let steps = if value == "-qq" { 2 } else { 1 };
+                    arguments.verbosity = arguments.verbosity.saturating_sub(steps)
+                },
+
+                // The programs are compared by their digests
+                "--differential" => {
+                    arguments.differential = true;
+                    arguments.digest = true;
+                },
+
                 "-f" |
                 "--file"    => {
                     arguments.filename =
-                        if let Some(name) = cmdline.get(idx + 2) {
+                        if let Some(name) = cmdline.get(idx + 1) {
                             skip = true;
                             name.to_string()
                         } else {
@@ -69,18 +502,442 @@ impl CmdLineOptions {
                         }
                 },
 
+                "--builtins" => {
+                    arguments.builtins =
+                        if let Some(path) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Some(path.to_string())
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the builtins file")));
+                        }
+                },
+
+                "--corpus" => {
+                    arguments.corpus =
+                        if let Some(dir) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Some(dir.to_string())
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the corpus directory")));
+                        }
+                },
+
+                "--output-dir" => {
+                    arguments.output_dir =
+                        if let Some(dir) = cmdline.get(idx + 1) {
+                            skip = true;
+                            output_dir_given = true;
+                            dir.to_string()
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the output directory")));
+                        }
+                },
+
+                "--resume" => {
+                    arguments.resume =
+                        if let Some(dir) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Some(dir.trim_end_matches('/').to_string())
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the session directory to resume")));
+                        }
+                },
+
+                "corpus-min" => {
+                    arguments.command =
+                        if let Some(dir) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Command::Minimize(dir.to_string())
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the directory for the minimized corpus")));
+                        }
+                },
+
+                "--import-fuzzilli" => {
+                    arguments.fuzzilli_corpus =
+                        if let Some(dir) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Some(dir.to_string())
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the Fuzzilli corpus directory")));
+                        }
+                },
+
+                "--seed" => {
+                    arguments.seed =
+                        if let Some(seed) = cmdline.get(idx + 1) {
+                            if let Ok(seed) = seed.parse::<u64>() {
+                                skip = true;
+                                seed
+                            } else {
+                                return Err(Box::new(
+                                    CmdLineError("Please specify a valid number for \
+                                                  the seed")));
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the seed")));
+                        };
+                },
+
+                "--replay" => {
+                    arguments.command =
+                        if let Some(crash) = cmdline.get(idx + 1) {
+                            skip = true;
+                            Command::Replay(crash.to_string())
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the crash file or seed to replay")));
+                        }
+                },
+
+                "--bench-gen" => {
+                    arguments.command =
+                        if let Some(count) = cmdline.get(idx + 1) {
+                            if let Ok(count) = count.parse::<u64>() {
+                                skip = true;
+                                Command::Bench(count)
+                            } else {
+                                return Err(Box::new(
+                                    CmdLineError("Please specify a valid number of \
+                                                  programs to generate")));
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the number of programs \
+                                              to generate")));
+                        };
+                },
+
+                "--min-insts" |
+                "--max-insts" => {
+                    let count =
+                        if let Some(count) = cmdline.get(idx + 1) {
+                            match count.parse::<usize>() {
+                                Ok(count) if count > 0 => {
+                                    skip = true;
+                                    count
+                                },
+                                _ => return Err(Box::new(
+                                    CmdLineError("Please specify a valid number \
+                                                  of instructions"))),
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the number of \
+                                              instructions")));
+                        };
+
+                    if value == "--min-insts" {
+                        arguments.min_insts = match u8::try_from(count) {
+                            Ok(count) => count,
+                            Err(_)    => return Err(Box::new(CmdLineError(
+                                "The programs can be asked for at most 255 \
+                                 instructions"))),
+                        };
+                    } else {
+                        arguments.max_insts = count;
+                    }
+                },
+
+                "--weight" => {
+                    let weight = match cmdline.get(idx + 1) {
+                        Some(weight) => weight.split_once('='),
+                        None => return Err(Box::new(CmdLineError(
+                            "Please specify the generator and its weight"))),
+                    };
+                    let (name, weight) = match weight {
+                        Some((name, weight)) => (name, weight.parse::<u16>()),
+                        None => return Err(Box::new(CmdLineError(
+                            "Please specify the weight as name=N"))),
+                    };
+                    match (generator_index(name), weight) {
+                        (Some(idx), Ok(weight)) => {
+                            skip = true;
+                            arguments.weights.push((idx, weight));
+                        },
+                        (None, _) => return Err(Box::new(CmdLineError(
+                            "Unknown generator for --weight. The generators are \
+                             listed with --list-generators"))),
+                        (_, Err(_)) => return Err(Box::new(CmdLineError(
+                            "Please specify a weight from 0 to 65535"))),
+                    }
+                },
+
+                "--list-generators" => {
+                    for (name, (_, weight)) in GENERATOR_NAMES.iter()
+                                                              .zip(GENERATORS) {
+                        println!("{:<34}{}", name, weight);
+                    }
+                    std::process::exit(0);
+                },
+
+                "--stop-on-crash" => arguments.max_buckets = Some(1),
+
+                "--max-buckets" => {
+                    arguments.max_buckets =
+                        if let Some(buckets) = cmdline.get(idx + 1) {
+                            match buckets.parse::<usize>() {
+                                Ok(buckets) if buckets > 0 => {
+                                    skip = true;
+                                    Some(buckets)
+                                },
+                                _ => return Err(Box::new(CmdLineError(
+                                    "Please specify a valid number of crash \
+                                     buckets to stop at"))),
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the number of crash \
+                                              buckets to stop at")));
+                        };
+                },
+
+                "--max-iterations" |
+                "--max-time" => {
+                    let limit =
+                        if let Some(limit) = cmdline.get(idx + 1) {
+                            if let Ok(limit) = limit.parse::<u64>() {
+                                skip = true;
+                                limit
+                            } else {
+                                return Err(Box::new(
+                                    CmdLineError("Please specify a valid number \
+                                                  for the limit of the campaign")));
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the limit of the \
+                                              campaign")));
+                        };
+
+                    if value == "--max-iterations" {
+                        arguments.max_iterations = Some(limit);
+                    } else {
+                        arguments.max_time = Some(limit);
+                    }
+                },
+
+                "--restart-every" |
+                "--restart-rss" => {
+                    let limit =
+                        if let Some(limit) = cmdline.get(idx + 1) {
+                            match limit.parse::<u64>() {
+                                Ok(limit) if limit > 0 => {
+                                    skip = true;
+                                    limit
+                                },
+                                _ => return Err(Box::new(
+                                    CmdLineError("Please specify a valid limit \
+                                                  for restarting the engine"))),
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the limit for \
+                                              restarting the engine")));
+                        };
+
+                    if value == "--restart-every" {
+                        arguments.restart_every = Some(limit);
+                    } else {
+                        arguments.restart_rss = Some(limit);
+                    }
+                },
+
+                "--save-timeouts" |
+                "--save-incorrect" => {
+                    let fraction =
+                        if let Some(fraction) = cmdline.get(idx + 1) {
+                            match fraction.parse::<f64>() {
+                                Ok(fraction) if (0.0..=1.0).contains(&fraction) => {
+                                    skip = true;
+                                    fraction
+                                },
+                                _ => return Err(Box::new(CmdLineError(
+                                    "Please specify a fraction between 0 and 1 \
+                                     of the programs to save"))),
+                            }
+                        } else {
+                            return Err(Box::new(CmdLineError(
+                                "Please specify the fraction of the programs to save")));
+                        };
+
+                    if value == "--save-timeouts" {
+                        arguments.save_timeouts = fraction;
+                    } else {
+                        arguments.save_incorrect = fraction;
+                    }
+                },
+
+                "--check-syntax" => {
+                    arguments.check_syntax =
+                        if let Some(fraction) = cmdline.get(idx + 1) {
+                            match fraction.parse::<f64>() {
+                                Ok(fraction) if (0.0..=1.0).contains(&fraction) => {
+                                    skip = true;
+                                    fraction
+                                },
+                                _ => return Err(Box::new(CmdLineError(
+                                    "Please specify a fraction between 0 and 1 \
+                                     of the programs to check"))),
+                            }
+                        } else {
+                            return Err(Box::new(CmdLineError(
+                                "Please specify the fraction of the programs to check")));
+                        };
+                },
+
+                "--iife-probability" => {
+                    arguments.iife_probability =
+                        if let Some(fraction) = cmdline.get(idx + 1) {
+                            match fraction.parse::<f64>() {
+                                Ok(fraction) if (0.0..=1.0).contains(&fraction) => {
+                                    skip = true;
+                                    fraction
+                                },
+                                _ => return Err(Box::new(CmdLineError(
+                                    "Please specify a fraction between 0 and 1 \
+                                     of the programs to wrap in a function"))),
+                            }
+                        } else {
+                            return Err(Box::new(CmdLineError(
+                                "Please specify the fraction of the programs to wrap")));
+                        };
+                },
+
+                "-p" |
+                "--profile" => {
+                    arguments.profile =
+                        match cmdline.get(idx + 1) {
+                            Some(name) => match ProfileType::from_name(name) {
+                                Some(profile) => {
+                                    skip = true;
+                                    profile
+                                },
+                                None => return Err(Box::new(CmdLineError(
+                                    "Please specify one of jsc, spidermonkey, \
+                                     v8 or custom for the profile"))),
+                            },
+                            None => return Err(Box::new(
+                                CmdLineError("Please specify the profile"))),
+                        }
+                },
+
+                "--engine-arg" => {
+                    if let Some(arg) = cmdline.get(idx + 1) {
+                        skip = true;
+                        arguments.engine_args.push(arg.to_string());
+                    } else {
+                        return Err(Box::new(
+                            CmdLineError("Please specify the arg for the engine")));
+                    }
+                },
+
+                "--env" => {
+                    let var = match cmdline.get(idx + 1) {
+                        Some(var) => var.split_once('='),
+                        None => return Err(Box::new(CmdLineError(
+                            "Please specify the environment variable"))),
+                    };
+                    match var {
+                        Some((name, value)) if !name.is_empty() => {
+                            skip = true;
+                            arguments.env.push((name.to_string(),
+                                                value.to_string()));
+                        },
+                        _ => return Err(Box::new(CmdLineError(
+                            "Please specify the environment variable as \
+                             KEY=VAL"))),
+                    }
+                },
+
+                "--keep-slowest" => {
+                    arguments.keep_slowest =
+                        if let Some(count) = cmdline.get(idx + 1) {
+                            if let Ok(count) = count.parse::<usize>() {
+                                skip = true;
+                                count
+                            } else {
+                                return Err(Box::new(
+                                    CmdLineError("Please specify a valid number of \
+                                                  slow programs to keep")));
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the number of slow \
+                                              programs to keep")));
+                        };
+                },
+
+                "--reprl-batch" => {
+                    arguments.reprl_batch =
+                        if let Some(count) = cmdline.get(idx + 1) {
+                            match count.parse::<usize>() {
+                                Ok(count) if count > 0 => {
+                                    skip = true;
+                                    count
+                                },
+                                _ => return Err(Box::new(
+                                    CmdLineError("Please specify a valid number \
+                                                  of programs per batch"))),
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the number of \
+                                              programs per batch")));
+                        };
+                },
+
+                "--mem-limit" => {
+                    arguments.mem_limit =
+                        if let Some(limit) = cmdline.get(idx + 1) {
+                            if let Ok(limit) = limit.parse::<u64>() {
+                                skip = true;
+                                limit
+                            } else {
+                                return Err(Box::new(
+                                    CmdLineError("Please specify a valid number of \
+                                                  MB for the memory limit")));
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the memory limit")));
+                        };
+                },
+
+                "--scheduler" => {
+                    arguments.scheduler =
+                        match cmdline.get(idx + 1) {
+                            Some(name) if SCHEDULERS.contains(&name.as_str()) => {
+                                skip = true;
+                                name.to_string()
+                            },
+                            Some(_) => return Err(Box::new(CmdLineError(
+                                "Please specify one of uniform, rarity or \
+                                 energy for the scheduler"))),
+                            None => return Err(Box::new(
+                                CmdLineError("Please specify the scheduler"))),
+                        }
+                },
+
                 "-j" |
                 "--jobs" => {
                     arguments.threads =
-                        if let Some(jobs) = cmdline.get(idx + 2) {
-                            if let Ok(jobs) = jobs.parse::<u8>() {
-                                skip = true;
-                                jobs
-                            } else {
-                                println!();
-                                return Err(Box::new(CmdLineError(
-                                    "Please specify a valid number for the no.\
-                                     of jobs")));
+                        if let Some(jobs) = cmdline.get(idx + 1) {
+                            match parse_jobs(jobs) {
+                                Some(jobs) => {
+                                    skip = true;
+                                    jobs
+                                },
+                                None => return Err(Box::new(CmdLineError(
+                                    "Please specify a valid number for the no. \
+                                     of jobs, or auto"))),
                             }
                         } else {
                             return Err(Box::new(
@@ -89,16 +946,34 @@ impl CmdLineOptions {
                         };
                 },
 
+                "--recheck-incorrect" => {
+                    arguments.recheck_incorrect =
+                        if let Some(times) = cmdline.get(idx + 1) {
+                            if let Ok(times) = times.parse::<u8>() {
+                                skip = true;
+                                times
+                            } else {
+                                return Err(Box::new(
+                                    CmdLineError("Please specify a valid number of \
+                                                  times to run incorrect programs again")));
+                            }
+                        } else {
+                            return Err(Box::new(
+                                CmdLineError("Please specify the number of times \
+                                              to run incorrect programs again")));
+                        };
+                },
+
                 "-t" |
                 "--timeout" => {
                     arguments.timeout =
-                        if let Some(timeout) = cmdline.get(idx + 2) {
-                            if let Ok(timeout) = timeout.parse::<u8>() {
+                        if let Some(timeout) = cmdline.get(idx + 1) {
+                            if let Some(timeout) = parse_timeout(timeout) {
                                 skip = true;
                                 timeout
                             } else {
                                 return Err(Box::new(
-                                    CmdLineError("Please specify a valid number\
+                                    CmdLineError("Please specify a valid number \
                                                   for the timeout")));
                             }
                         } else {
@@ -114,39 +989,343 @@ impl CmdLineOptions {
                     std::process::exit(0);
                 },
 
-                others => println!("Invalid arg passed: {}", others),
+                others => warn!("Invalid arg passed: {}", others),
+            }
+        }
+
+        // The session is in the output directory that it was started with, so
+        // that is where its corpus is too
+        if let (Some(session), false) = (&arguments.resume, output_dir_given) {
+            if let Some(parent) = std::path::Path::new(session).parent() {
+                if !parent.as_os_str().is_empty() {
+                    arguments.output_dir = parent.to_string_lossy().to_string();
+                }
             }
         }
 
+        if arguments.min_insts as usize > arguments.max_insts {
+            return Err(Box::new(CmdLineError("The --min-insts can't be more \
+                                              than the --max-insts")));
+        }
+
+        let total = GENERATORS.iter().enumerate().map(|(idx, (_, weight))| {
+            let weight = arguments.weights.iter().rev()
+                .find(|(given, _)| *given == idx)
+                .map_or(*weight, |(_, weight)| *weight);
+            weight as u32
+        }).sum::<u32>();
+        if total == 0 {
+            return Err(Box::new(CmdLineError("At least one of the generators \
+                                              needs a weight above 0")));
+        }
+
         Ok(arguments)
     }
 
+    /// The directory of the corpus. Unless it is given, the corpus is kept in
+    /// the output directory.
+    pub fn corpus_dir(&self) -> String {
+        match &self.corpus {
+            Some(dir) => dir.to_string(),
+            None      => format!("{}/corpus", self.output_dir),
+        }
+    }
+
+    /// The no. of bytes that the target may allocate, or 0 for no limit
+    pub fn memory_limit(&self) -> u64 {
+        self.mem_limit.saturating_mul(1 << 20)
+    }
+
     /// Print out a help menu to the screen describing how to use this and the
     /// options that are available.
     pub fn help() {
         println!("
-Usage: ./zebra [OPTIONS]
+Usage: ./zebra [fuzz] [OPTIONS]
+       ./zebra replay <crash.json|seed> [OPTIONS]
+       ./zebra lift <crash.json|seed> [OPTIONS]
+       ./zebra minimize <path/to/dir> [OPTIONS]
+       ./zebra corpus import <path/to/dir> [OPTIONS]
+       ./zebra corpus info [OPTIONS]
+       ./zebra bench <count> [OPTIONS]
+
+Commands -
+
+    fuzz                           Fuzz the engine. This is the command when none is given, and it takes all the options.
+
+    replay <crash.json|seed>       Lift the program of a saved crash, or generate the program with the given program seed
+                                   again, print it out and run it once on the target. This takes the engine, generator and
+                                   lifter options.
+
+    lift <crash.json|seed>         Print out the program like replay does, without running it. This takes the generator
+                                   and lifter options.
+
+    minimize <path/to/dir>         Run every program of the corpus on the target again and write the ones that cover
+                                   edges no other program does to a new corpus directory. The target has to be built
+                                   with coverage. This takes the engine options. corpus-min is another name for it.
+
+    corpus import <path/to/dir>    Add the programs of a Fuzzilli corpus directory (.fzil files) to the corpus. Only the
+                                   operations that zebra knows about can be imported.
+
+    corpus info                    Print the no. of entries of the corpus and how big they are.
+
+    bench <count>                  Generate and lift this many programs without running any of them, and print how fast
+                                   that was and how much was allocated for it. This takes the generator and lifter
+                                   options.
+
+    All the commands take --help, --builtins, --corpus and --list-generators.
+    The engine options are -f, -p, -t, -d, --forkserver, --engine-arg, --env, --mem-limit, --restart-every and
+    --restart-rss. The generator options are --seed, --min-insts, --max-insts, --weight, --eval and --missing-props. The
+    lifter options are --digest, --ir-comments, --literal-forms, --iife-probability, --declarations, --realistic-names,
+    --strip-dead-code and --print-ir. The rest of the options only apply to fuzz.
 
 Options -
 
     -h, --help                     Print this help menu and exit
 
+    -v, --verbose                  Log more of what is going on, like the engines that are started and stopped. Given
+                                   twice or as -vv, this logs each code generator that runs too.
+
+    -q, --quiet                    Only log warnings and errors. Given twice or as -qq, only errors are logged.
+
+    --log-files                    Log what each fuzzer thread does to its own file in the logs directory of the session
+                                   instead of to stderr. This is false by default.
+
+    --no-tui                       Print the stats one after the other instead of redrawing them on a single screen,
+                                   for logs. The stats are only redrawn when stdout is a terminal either way.
+
     --dry-run                      Just generate a program, print it out to stdout, execute it and exit
                                    This is false by default.
 
+    --print-ir                     Print the IR of the program along with the types of its variables before the JS, in a
+                                   dry run or with replay and lift. This is false by default.
+
     -d, --disk                     Tell the fuzzer to save testcases into a file and then use those as args to the engine.
                                    This will result in lots of writes to disk.
                                    If this is not specified, then the fuzzer will pass the testcases via a memory mapped
                                    file however, this involves modifing the engine being fuzzed so it executes programs via a memory mapped file.
                                    This is false by default.
 
-    -j, --jobs <nthreads>          No. of threads to use to run the fuzzer.
-                                   Default value of 1 thread.
+    --forkserver                   Run the programs with an AFL style fork server. The engine given with -f has to be a
+                                   driver that starts up the engine once and forks a fresh child of itself for each
+                                   program, which gets every program a clean heap. This is false by default.
+
+    --eval                         Allow the generated programs to call eval() on small generated snippets of code.
+                                   This is false by default.
+
+    --digest                       Add the values of the variables of each program to a digest that is printed at the end,
+                                   so that the output of two runs of a program can be compared.
+                                   This is false by default.
+
+    --ir-comments                  Put a comment with the index and the IR of each instruction before the code that it is
+                                   lifted to, so that the lines of a saved program can be mapped back to its IR.
+                                   This is false by default.
+
+    --literal-forms                Sometimes write the numbers in other forms than the decimal one, like 0x1f, 0b101,
+                                   0o17, 1e3, 65_536 or 5e-1, to exercise the number parsing of the engine.
+                                   This is false by default.
+
+    --iife-probability <fraction>  Run this fraction (0 to 1) of the programs from inside of a function that is called
+                                   right away, as the engines treat function code differently from top level code. 0
+                                   never does. This is 0.1 by default.
+
+    --declarations <weights>       How often the variables are declared with var, let and const, like
+                                   var=2,let=1,const=1. The ones that are left out are not used, and the variables that
+                                   are assigned to are never declared with const. This is var=50,let=30,const=20 by
+                                   default.
+
+    --realistic-names              Name the variables like real code does, with short names, the names of the properties
+                                   of the builtins and unicode names, instead of v and their ids.
+                                   This is false by default.
+
+    --strip-dead-code              Leave the instructions whose values are never used and that have no other effect, like
+                                   loads and arithmetic on primitives, out of the lifted code.
+                                   This is false by default.
+
+    --differential                 Run each program that runs successfully again with the JIT of the engine turned off and
+                                   save the ones whose digests differ to the miscompile directory of the session.
+                                   This turns on --digest and is false by default.
+
+    --builtins <path/to/file>      A JSON file with builtin definitions to add to the ones known by the fuzzer.
+                                   The format is described in src/jsruntime/spec.rs
+
+    --corpus <path/to/dir>         The directory that the corpus is saved to and loaded from on startup.
+                                   Default value of <output dir>/corpus
+
+    --output-dir <path/to/dir>     The directory that all the results are saved to. Each run of the fuzzer saves its
+                                   crashes to a session_<timestamp>/crashes directory in here, sorted by the signal
+                                   and then by the top frames of the stack trace or the last line of stderr.
+                                   Default value of ./output
+
+    --resume <path/to/session>     Go on with a session that was stopped, or that the host went down in the middle of. The
+                                   stats, the crashes that were seen, the edges that were found, the random streams of
+                                   the threads and the run time are picked up from where the session last saved them,
+                                   which is every minute and when it ends. The output dir defaults to the one that the
+                                   session is in, so the corpus of the session is loaded too.
+
+    --import-fuzzilli <path/to/dir> Add the programs of a Fuzzilli corpus directory (.fzil files) to the corpus on startup.
+                                   Only the operations that zebra knows about can be imported.
+
+    --save-timeouts <fraction>     Save this fraction (0 to 1) of the programs that time out to the timeout directory of
+                                   the session, as they can hide hangs in the engine. Default value of 0.
+
+    --save-incorrect <fraction>    Save this fraction (0 to 1) of the programs that exit with a non zero status to the
+                                   incorrect directory of the session, along with the status. Default value of 0.
+
+    --recheck-incorrect <times>    Run the programs that exit with a non zero status this many more times. They are only
+                                   counted as incorrect if they exit with the same status every time, and as unstable otherwise.
+                                   Default value of 0.
+
+    --check-syntax <fraction>      Compile this fraction (0 to 1) of the programs on the target without running them, and
+                                   report how many of them are syntactically valid for each generator in the stats.
+                                   Default value of 0.
+
+    --scheduler <name>             How the corpus entries to mutate are picked. One of
+                                       rarity  - Prefer recent entries and the ones that found more edges or were picked less
+                                       uniform - Pick every entry with the same chance
+                                       energy  - Give each entry an energy that drops quickly with the no. of times it was picked
+                                   Default value of rarity.
+
+    --seed <number>                The seed that all the random choices of the fuzzer are derived from. Each thread gets
+                                   its own stream of seeds out of it, and every program gets its own seed from that
+                                   stream, which is saved along with the crashes it causes. With a single thread and the
+                                   same corpus, the same seed runs the same programs in the same order.
+                                   A random seed is picked by default.
+
+    --min-insts <count>            The no. of code generators that a new program is generated with, each of which adds at
+                                   least one instruction to it. At most 255. Default value of 5.
+
+    --max-insts <count>            The generators stop adding instructions to a program once it has this many, which keeps
+                                   the generators that nest blocks in each other from building huge programs. Default
+                                   value of 1000.
+
+    --weight <name=N>              Set the weight of a code generator, which is how often it is picked compared to the
+                                   others. A weight of 0 turns the generator off. The `_generator` at the end of the name
+                                   can be left out, as in `--weight method_call=80`. This can be given more than once.
+
+    --missing-props <percent>      The percentage of the property loads that read a property which the object is sure not
+                                   to have. The rest of them read one that it is known to have where that is known.
+                                   Default value of 10%.
+
+    --list-generators              Print the names of the code generators along with their default weights and exit.
+
+    --max-iterations <count>       Stop fuzzing once this many programs were run in total by all the threads, and print
+                                   the final stats. There is no limit by default.
+
+    --max-time <secs>              Stop fuzzing after this many seconds, once the threads are done with the programs that
+                                   they are running, and print the final stats. There is no limit by default.
+
+    --min-fcps <fcps>[/<minutes>]  Warn when the campaign runs fewer programs per second than this over the last minute,
+                                   for 5 minutes or the minutes that are given, like 50/10. There is no limit by default.
+
+    --max-timeouts <percent>       Warn when more than this percentage of the programs of the last minute timed out.
+                                   There is no limit by default.
+
+    --abort-on-alert               Stop fuzzing when --min-fcps or --max-timeouts warn, and print the final stats.
+
+    --restart-every <count>        Restart the REPRL engine after it ran this many programs, as the engine keeps global
+                                   state and leaks memory over time, which changes how the later programs run. The engine
+                                   is only restarted when a program crashes or times out by default.
+
+    --restart-rss <MB>             Restart the REPRL engine once it uses this many MB of memory more than right after it
+                                   started up. This only works on Linux.
+                                   There is no limit by default.
+
+    --stop-on-crash                Stop fuzzing after the first crash, and print the final stats.
+
+    --max-buckets <count>          Stop fuzzing once the crashes were sorted into this many different buckets of the
+                                   crashes directory, and print the final stats. There is no limit by default.
+
+    -j, --jobs <nthreads|auto>     No. of threads to use to run the fuzzer. auto runs one thread for each of the cores that
+                                   are available. Default value of 1 thread.
 
     -f, --file <path/to/jsengine>  The full path of the js engine to fuzz.
 
-    -t, --timeout <timout in secs> The timeout that is to be applied for each run of jsc.
+    -p, --profile <name>           The engine that is fuzzed, which decides the args that it is started with, the shell
+                                   helpers that the programs can call and the code that ends each program. One of
+                                       spidermonkey, jsc, v8
+                                       custom - For other engines, with no args, helpers or code of its own
+                                   Default value of spidermonkey.
+
+    --engine-arg <arg>             An arg to start the engine with, after the ones of the profile. This can be given more
+                                   than once, like --engine-arg --gc-zeal=2 --engine-arg --ion-eager.
+
+    --env <KEY=VAL>                An environment variable to start the engine with. This can be given more than once.
+                                   The options in ASAN_OPTIONS and UBSAN_OPTIONS are added to the ones that zebra sets
+                                   for sanitized engines, and win over them.
+
+    --mem-limit <MB>               The memory that the engine may allocate. Runs that go over it are counted as out of
+                                   memory instead of as crashes. 0 turns the limit off.
+                                   Default value of 4096 MB.
+
+    -t, --timeout <timout in secs> The timeout that is to be applied for each run of jsc. This is in seconds and can
+                                   have a fraction, or in milliseconds with an `ms` after it, like `250ms`.
                                    Default value of 5 seconds.
+
+    --adaptive-timeout             Derive the timeout from how long the programs that ran before took, at 4 times the 99th
+                                   percentile of their run times. The timeout given with --timeout is the longest it gets.
+                                   This is false by default.
+
+    --dedup                        Skip the programs that are the same as one that ran lately, once their variables are
+                                   numbered in order, the Nops are left out and the constants are written the same way.
+                                   This is false by default.
+
+    --keep-slowest <count>         Keep this many of the slowest programs that ran to completion in the slow directory of
+                                   the session, as they can point to quadratic blowups in the engine. 0 keeps none.
+                                   Default value of 10.
+    --reprl-batch <count>          Send this many programs to the REPRL target at once and get back all their statuses in
+                                   one go, which saves round trips through the control pipes. The REPRL harness of the
+                                   engine has to support the `btch` command for this. Programs of a batch that crashes,
+                                   times out or finds new coverage are run again one by one. Default value of 1.
     ");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let parse = |args: &str| {
+            CmdLineOptions::parse(std::iter::once("zebra")
+                                  .chain(args.split_whitespace())
+                                  .map(|arg| arg.to_string())
+                                  .collect())
+        };
+
+        assert_eq!(parse("").unwrap().command, Command::Fuzz);
+        assert_eq!(parse("fuzz -j 2").unwrap().threads, 2);
+        assert_eq!(parse("replay 12 -f js").unwrap().command,
+                   Command::Replay("12".into()));
+        assert_eq!(parse("--replay 12").unwrap().command,
+                   Command::Replay("12".into()));
+        assert_eq!(parse("corpus-min out").unwrap().command,
+                   Command::Minimize("out".into()));
+        assert_eq!(parse("corpus import dir").unwrap().command,
+                   Command::CorpusImport("dir".into()));
+        assert_eq!(parse("bench 10 --seed 1").unwrap().command,
+                   Command::Bench(10));
+        assert!(parse("lift 12 -f js").is_err());
+        assert!(parse("corpus info -j 2").is_err());
+        assert!(parse("corpus").is_err());
+        assert!(parse("bench").is_err());
+        assert_eq!(parse("--resume out/session_1/").unwrap().output_dir, "out");
+        assert_eq!(parse("--min-fcps 50/10").unwrap().min_fcps, Some((50.0, 10)));
+        assert_eq!(parse("--min-fcps 2.5").unwrap().min_fcps, Some((2.5, 5)));
+        assert!(parse("--min-fcps 0").is_err());
+        assert_eq!(parse("--max-timeouts 90%").unwrap().max_timeouts, Some(0.9));
+        assert_eq!(parse("lift 1 --missing-props 25").unwrap().missing_props,
+                   0.25);
+        assert!(parse("--missing-props 101%").is_err());
+        assert!(parse("replay 1 --abort-on-alert").is_err());
+        assert_eq!(parse("lift 1 --declarations let=1,var=3").unwrap()
+                   .declarations, [3, 1, 0]);
+        assert!(parse("--declarations var=0").is_err());
+        assert!(parse("--declarations val=1").is_err());
+        assert_eq!(parse("lift 1 --iife-probability 0").unwrap()
+                   .iife_probability, 0.0);
+        assert!(parse("--iife-probability 2").is_err());
+        assert_eq!(parse("--resume out/session_1 --output-dir o").unwrap()
+                   .output_dir, "o");
+    }
+}
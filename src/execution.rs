@@ -1,18 +1,26 @@
 //! Contains the code associated with harnessing the target being fuzzed. This
-//! offers two modes -
+//! offers three modes -
 //!
 //! * Repl - A read-eval-print-loop which means that the target will not be
-//! spawned each time and will instead only be spawned when it times out or
-//! crashes. This mode also supports memory mapped trasfer of JS files to the
-//! target which will reduce the disk overhead. However for this mode to be
-//! used, the target code needs to be modified with the Fuzzilli patch. If using
-//! a engine which cannot be patched, use the disk mode.
+//!   spawned each time and will instead only be spawned when it times out or
+//!   crashes. This mode also supports memory mapped trasfer of JS files to the
+//!   target which will reduce the disk overhead. However for this mode to be
+//!   used, the target code needs to be modified with the Fuzzilli patch. If using
+//!   a engine which cannot be patched, use the disk mode.
+//!
+//! * Fork server - A driver that is built against the engine starts it up once
+//!   and then forks a fresh child of itself for each input, which is almost as
+//!   fast as REPRL but keeps the inputs from affecting each other.
 //!
 //! * Disk - The generated JS code is written to the disk and then the target is
-//! invoked to run this program. This requires no modification of the target but
-//! will also incur the overheads of disk usage.
+//!   invoked to run this program. This requires no modification of the target but
+//!   will also incur the overheads of disk usage.
 
 pub mod repl;
 pub mod execution;
 pub mod ffi;
 pub mod spawn;
+pub mod forkserver;
+pub mod memfile;
+pub mod coverage;
+pub mod sanitizer;
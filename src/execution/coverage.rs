@@ -0,0 +1,190 @@
+//! Edge coverage feedback from targets that are built with the Fuzzilli
+//! coverage patch. Such a target looks up the name of a shared memory region in
+//! the `SHM_ID` environment variable on startup, maps it and then sets a bit in
+//! it for each edge of its control flow graph that gets executed. The region
+//! starts with a `u32` holding the total no. of edges in the target, followed
+//! by the edge bitmap.
+
+use std::ffi::CString;
+
+use super::ffi::*;
+use crate::warn;
+
+/// The size of the shared memory region. This is what the coverage patch
+/// expects, so don't change it.
+const SHM_SIZE: usize = 0x100000;
+
+/// The max no. of edges that fit in the bitmap
+pub const MAX_EDGES: usize = (SHM_SIZE - 4) * 8;
+
+/// This holds the coverage region and the edges that were seen so far
+pub struct Coverage {
+    name:    CString,
+    fd:      i32,
+    mapping: *mut u8,
+
+    /// One bit for each edge that was executed by any of the earlier
+    /// executions
+    seen:    Vec<u8>,
+}
+
+impl Coverage {
+
+    /// Create and map the shared memory region. Each instance needs a unique
+    /// `id` as the region is shared by name.
+    pub fn new(id: usize) -> Option<Self> {
+        let name = format!("/zebra_shm_{}_{}", std::process::id(), id);
+        let name = CString::new(name).ok()?;
+
+        let (fd, mapping);
+        unsafe {
+            fd = shm_open(name.as_ptr(), O_RDWR | O_CREAT, 0o600u32);
+            if fd == -1 {
+                return None;
+            }
+
+            if ftruncate(fd, SHM_SIZE) != 0 {
+                close(fd);
+                shm_unlink(name.as_ptr());
+                return None;
+            }
+
+            mapping = mmap(std::ptr::null_mut(), SHM_SIZE,
+                           PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+            if mapping as usize == !0 {
+                close(fd);
+                shm_unlink(name.as_ptr());
+                return None;
+            }
+        }
+
+        Some(Self {
+            name,
+            fd,
+            mapping,
+            seen: vec![0; MAX_EDGES / 8],
+        })
+    }
+
+    /// Whether a target that is built with the coverage patch mapped the
+    /// region. Such a target writes the no. of its edges to the start of it on
+    /// startup, so this is known once it is up, and any other target never
+    /// writes to it.
+    pub fn is_instrumented(&self) -> bool {
+        unsafe { (self.mapping as *const u32).read_volatile() != 0 }
+    }
+
+    /// The name that the target should be given in `SHM_ID`
+    pub fn shm_id(&self) -> &str {
+        self.name.to_str().unwrap()
+    }
+
+    /// Find the edges that the last execution hit for the first time and
+    /// return their indices. The bitmap is cleared for the next execution.
+    pub fn evaluate(&mut self) -> Vec<u32> {
+        let num_edges = unsafe { (self.mapping as *const u32).read_volatile() };
+        let num_edges = std::cmp::min(num_edges as usize, MAX_EDGES);
+
+        let edges = unsafe {
+            std::slice::from_raw_parts_mut(self.mapping.add(4),
+                                           num_edges.div_ceil(8))
+        };
+
+        let mut new_edges = Vec::new();
+        for (idx, (edge, seen)) in edges.iter_mut().zip(self.seen.iter_mut())
+                                        .enumerate() {
+            let new = *edge & !*seen;
+            for bit in 0..8 {
+                if new & (1 << bit) != 0 {
+                    new_edges.push((idx * 8 + bit) as u32);
+                }
+            }
+            *seen |= new;
+            *edge = 0;
+        }
+
+        new_edges
+    }
+
+    /// Check if the executions since the last [Coverage::evaluate] hit any
+    /// edges that were not seen before, without marking them as seen
+    pub fn has_new_edges(&self) -> bool {
+        let num_edges = unsafe { (self.mapping as *const u32).read_volatile() };
+        let num_edges = std::cmp::min(num_edges as usize, MAX_EDGES);
+
+        let edges = unsafe {
+            std::slice::from_raw_parts(self.mapping.add(4),
+                                       num_edges.div_ceil(8))
+        };
+        edges.iter().zip(self.seen.iter()).any(|(edge, seen)| edge & !seen != 0)
+    }
+
+    /// Clear the bitmap without marking the edges in it as seen, so that they
+    /// are found again by the next execution that hits them
+    pub fn clear(&mut self) {
+        let num_edges = unsafe { (self.mapping as *const u32).read_volatile() };
+        let num_edges = std::cmp::min(num_edges as usize, MAX_EDGES);
+        unsafe { std::ptr::write_bytes(self.mapping.add(4), 0,
+                                       num_edges.div_ceil(8)) };
+    }
+
+    /// Forget the edges that were seen so far, so that the next execution
+    /// counts all the edges that it hits as new.
+    pub fn reset(&mut self) {
+        self.seen.fill(0);
+    }
+}
+
+/// Warn when the target that was just started with `coverage` did not map it,
+/// as the programs are kept for running successfully then, like without it
+pub fn warn_uninstrumented(coverage: Option<&Coverage>) {
+    if coverage.is_some_and(|coverage| !coverage.is_instrumented()) {
+        warn!("The target does not report coverage, keeping the programs \
+               that run successfully instead");
+    }
+}
+
+impl Drop for Coverage {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.mapping, SHM_SIZE);
+            close(self.fd);
+            shm_unlink(self.name.as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Coverage;
+
+    #[test]
+    fn test_coverage() {
+        let mut coverage = Coverage::new(!0).unwrap();
+        assert!(!coverage.is_instrumented());
+
+        // Pretend to be a target with 16 edges that hit edges 0, 2 and 9
+        unsafe {
+            (coverage.mapping as *mut u32).write(16);
+            *coverage.mapping.add(4) = 0b101;
+            *coverage.mapping.add(5) = 0b10;
+        }
+        assert!(coverage.is_instrumented());
+        assert_eq!(coverage.evaluate(), vec![0, 2, 9]);
+
+        unsafe { *coverage.mapping.add(4) = 0b11 };
+        assert_eq!(coverage.evaluate(), vec![1]);
+        assert!(coverage.evaluate().is_empty());
+
+        coverage.reset();
+        unsafe { *coverage.mapping.add(4) = 0b11 };
+        assert_eq!(coverage.evaluate(), vec![0, 1]);
+
+        unsafe { *coverage.mapping.add(5) = 0b1 };
+        assert!(coverage.has_new_edges());
+        coverage.clear();
+        assert!(!coverage.has_new_edges());
+        unsafe { *coverage.mapping.add(4) = 0b1 };
+        assert!(!coverage.has_new_edges());
+    }
+}
@@ -1,14 +1,206 @@
+use std::io;
+use std::time::Duration;
+
+use super::ffi::{setrlimit, Rlimit, RLIMIT_DATA};
+use super::ffi::{close, dup2, fcntl, F_DUPFD};
+
+/// The max no. of bytes of the stderr and the output of the target that is kept
+pub const MAX_OUTPUT: usize = 0x4000;
+
+/// The max amount of memory that the target may allocate by default, so that
+/// programs which build giant arrays or strings can't take down the host
+pub const MEMORY_LIMIT: u64 = 4096 << 20;
+
+/// The messages that engines and sanitizers print to stderr when they fail an
+/// allocation, in lowercase
+const OOM_MESSAGES: [&str; 4] = [
+    "out of memory",
+    "out-of-memory",
+    "allocation-size-too-big",
+    "rss limit exhausted",
+];
+
+/// The signal that the kernel OOM killer terminates processes with
+const SIGKILL: i32 = 9;
+
 /// This will be the status when the target finishes execution.
 /// * Timeout: the target timed out
 /// * Crash(code): The target crashed with the signal number `code`
 /// * Status(code): The target successfully executed and returned `code`
+/// * Oom: The target was terminated because it ran out of memory
 pub enum ReturnCode {
     Timeout,
     Crash(i32),
-    Status(i32)
+    Status(i32),
+    Oom,
+}
+
+/// The result of running a single input on the target
+pub struct ExecutionResult {
+    pub code:      ReturnCode,
+
+    /// The edges that were covered for the first time by this input, out of
+    /// all the inputs that ran on this target. This is always empty when the
+    /// target does not report coverage.
+    pub new_edges: Vec<u32>,
+
+    /// What the target wrote to stderr while running the input, like assertion
+    /// messages and sanitizer reports. This is only kept when the target
+    /// crashed or ran out of memory and is empty otherwise.
+    pub stderr:    String,
+
+    /// What the input printed. REPRL targets print through the `fuzzilli`
+    /// builtin and the others to stdout. The digests of the lifter end up here.
+    pub output:    String,
+
+    /// How long the input ran for. This includes starting up the engine when
+    /// it is started anew for each input.
+    pub duration:  Duration,
+
+    /// The most memory that the target used while running the input, in bytes,
+    /// for the targets where that is known
+    pub peak_rss:  Option<u64>,
+}
+
+/// The reason that a target failed to start up, along with what it wrote to
+/// stderr until then, which is usually what tells why
+#[derive(Debug)]
+pub struct StartupError {
+    pub reason: String,
+    pub stderr: String,
+}
+
+impl std::fmt::Display for StartupError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.reason)?;
+        if !self.stderr.trim().is_empty() {
+            write!(fmt, "\nThe engine said:\n{}", self.stderr.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+/// Turn the stderr or output of a target into a string of at most
+/// [MAX_OUTPUT] bytes. The reason for a crash and the digest of a program are
+/// printed last, so the end of it is kept.
+pub fn truncate_output(output: &[u8]) -> String {
+    let start = output.len().saturating_sub(MAX_OUTPUT);
+    String::from_utf8_lossy(&output[start..]).into_owned()
+}
+
+/// Get the return code for a target that was terminated by `signal` after it
+/// wrote `stderr`. Running out of memory is not a bug in the target, so it is
+/// kept apart from the crashes.
+pub fn classify_crash(signal: i32, stderr: &str) -> ReturnCode {
+    let stderr = stderr.to_lowercase();
+    if signal == SIGKILL ||
+        OOM_MESSAGES.iter().any(|message| stderr.contains(message)) {
+        ReturnCode::Oom
+    } else {
+        ReturnCode::Crash(signal)
+    }
+}
+
+/// Limit the memory that the current process can allocate to `bytes`. This is
+/// meant to be called in the child, right before the target is executed.
+pub fn limit_memory(bytes: u64) -> io::Result<()> {
+    let limit = Rlimit { cur: bytes, max: bytes };
+    if unsafe { setrlimit(RLIMIT_DATA, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Put the fds of the fuzzer that the target is given at the nos. that it
+/// expects them at. `fds` has pairs of the fd of the fuzzer and the no. for
+/// the target, and the fds below 0 are skipped. With enough threads, some of
+/// the fds of the fuzzer have the nos. that the target expects, so they are
+/// all moved above those first, or putting one of them in place could close
+/// another. This is meant to be called in the child, right before the target
+/// is executed, so it doesn't allocate.
+pub fn place_fds<const N: usize>(fds: [(i32, i32); N]) -> io::Result<()> {
+    let above = fds.iter().map(|&(_, target)| target).max().unwrap_or(0) + 1;
+    let mut moved = [-1; N];
+    for (idx, &(fd, _)) in fds.iter().enumerate() {
+        if fd >= 0 {
+            moved[idx] = unsafe { fcntl(fd, F_DUPFD, above) };
+            if moved[idx] < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    for (idx, &(_, target)) in fds.iter().enumerate() {
+        if moved[idx] >= 0 {
+            unsafe {
+                if dup2(moved[idx], target) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                close(moved[idx]);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the `field` of the status of the process with `pid` in bytes, like
+/// `VmRSS` for the memory that it uses right now. It is read out of `/proc`, so
+/// this is only known on Linux.
+pub fn process_memory(pid: u32, field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status.lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim()
+                         .parse::<u64>().ok())
+        .map(|kb| kb << 10)
+}
+
+/// Reset the `VmHWM` of the process with `pid`, which is the most memory that
+/// it used so far, to the memory that it uses right now
+pub fn reset_peak_memory(pid: u32) {
+    let _ = std::fs::write(format!("/proc/{}/clear_refs", pid), "5");
+}
+
+impl From<ReturnCode> for ExecutionResult {
+    fn from(code: ReturnCode) -> Self {
+        Self {
+            code,
+            new_edges: Vec::new(),
+            stderr:    String::new(),
+            output:    String::new(),
+            duration:  Duration::ZERO,
+            peak_rss:  None,
+        }
+    }
 }
 
 pub trait Execution {
     // fn new(path: String, args: Vec<&'static str>, timeout: u32) -> Self;
-    fn execute(&mut self, input: &String) -> ReturnCode;
+    fn execute(&mut self, input: &String) -> ExecutionResult;
+
+    /// Whether the target reports the coverage of its executions
+    fn has_coverage(&self) -> bool {
+        false
+    }
+
+    /// Forget the edges that were covered so far
+    fn reset_coverage(&mut self) {}
+
+    /// Run all the `inputs` on the target in one go, for targets that support
+    /// it. Returns the status of each of them, or `None` when they have to be
+    /// run one by one with [Execution::execute] to find out what happened,
+    /// because one of them crashed, timed out or covered new edges.
+    fn execute_batch(&mut self, _inputs: &[String]) -> Option<Vec<i32>> {
+        None
+    }
+
+    /// Change the timeout of the inputs that run from now on, in milliseconds
+    fn set_timeout(&mut self, timeout: u64);
+
+    /// The no. of times that the target was started again since it first
+    /// started up, for the targets that keep running across inputs
+    fn restarts(&self) -> u64 {
+        0
+    }
 }
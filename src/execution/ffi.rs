@@ -3,27 +3,96 @@ use std::os::raw::c_char;
 extern "C" {
     pub fn close(fd: i32) -> i32;
     pub fn perror(s: *const c_char);
-    pub fn alarm(seconds: u32) -> u32;
+    pub fn setitimer(which: i32, new: *const Itimerval,
+                     old: *mut Itimerval) -> i32;
     pub fn pipe(pipefd: *mut Pipefd) -> i32;
     pub fn dup2(oldfd: i32, newfd: i32) -> i32;
+    pub fn fcntl(fd: i32, cmd: i32, ...) -> i32;
     pub fn ftruncate(fd: i32, length: usize) -> i32;
     pub fn munmap(addr: *mut u8, length: usize) -> i32;
-    pub fn lseek(fd: i32, offset: i32, whence: i32) -> i32;
+    pub fn lseek(fd: i32, offset: i64, whence: i32) -> i64;
     pub fn read(fd: i32, buf: *mut u8, count: usize) -> i32;
     pub fn write(fd: i32, buf: *const u8, count: usize) -> i32;
-    pub fn memfd_create(name: *const c_char, flags: u32) -> i32;
+    pub fn shm_open(name: *const c_char, oflag: i32, ...) -> i32;
+    pub fn shm_unlink(name: *const c_char) -> i32;
     pub fn poll(fds: *mut Pollfd, nfds_t: u64, timeout: i32) -> i32;
+    pub fn setrlimit(resource: i32, rlim: *const Rlimit) -> i32;
+    pub fn kill(pid: i32, sig: i32) -> i32;
     pub fn mmap(addr: *mut u8, length: usize, prot: i32, flags: i32,
-            fd: i32, offset: i32) -> *mut u8;
+            fd: i32, offset: i64) -> *mut u8;
+}
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    pub fn memfd_create(name: *const c_char, flags: u32) -> i32;
 }
 
 
 pub const MAP_SHARED:  i32 = 0x1;
 pub const PROT_READ:   i32 = 0x1;
 pub const PROT_WRITE:  i32 = 0x2;
-pub const MFD_CLOEXEC: u32 = 0x1;
 pub const SEEK_SET:    i32 = 0x0;
 pub const POLLIN:      i16 = 0x1;
+pub const O_RDWR:      i32 = 0o2;
+pub const RLIMIT_DATA: i32 = 0x2;
+pub const SIGKILL:     i32 = 0x9;
+pub const ITIMER_REAL: i32 = 0x0;
+pub const F_DUPFD:     i32 = 0x0;
+
+/// The constants that differ between Linux and the BSDs like macOS
+#[cfg(target_os = "linux")]
+pub const MFD_CLOEXEC: u32 = 0x1;
+#[cfg(target_os = "linux")]
+pub const O_CREAT:     i32 = 0o100;
+#[cfg(not(target_os = "linux"))]
+pub const O_CREAT:     i32 = 0x200;
+#[cfg(not(target_os = "linux"))]
+pub const O_EXCL:      i32 = 0x800;
+#[cfg(not(target_os = "linux"))]
+pub const F_SETFD:     i32 = 0x2;
+#[cfg(not(target_os = "linux"))]
+pub const FD_CLOEXEC:  i32 = 0x1;
+
+/// The microseconds of a [Timeval] are only 32 bits on macOS
+#[cfg(target_os = "linux")]
+pub type Usec = i64;
+#[cfg(not(target_os = "linux"))]
+pub type Usec = i32;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct Rlimit {
+    pub cur: u64,
+    pub max: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Timeval {
+    pub sec:  i64,
+    pub usec: Usec,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Itimerval {
+    pub interval: Timeval,
+    pub value:    Timeval,
+}
+
+impl Itimerval {
+
+    /// A timer that fires once after `ms` milliseconds
+    pub fn once(ms: u64) -> Self {
+        Self {
+            interval: Timeval::default(),
+            value:    Timeval {
+                sec:  (ms / 1000) as i64,
+                usec: (ms % 1000 * 1000) as Usec,
+            },
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug)]
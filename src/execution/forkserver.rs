@@ -0,0 +1,360 @@
+//! An AFL style fork server. The target is a driver that sets up the engine
+//! once and then forks a fresh child of itself for each input, so every input
+//! gets a clean heap while the engine only has to start up once.
+//!
+//! The driver talks to the fuzzer over two pipes, on fds [CTRL_FD] and
+//! [STATUS_FD]. It writes 4 bytes to the status pipe once it is set up. Then
+//! for each input, the fuzzer writes 4 bytes to the control pipe, the driver
+//! forks and writes the pid of the child to the status pipe, followed by the
+//! status that `waitpid` returned for it when it is done. The input is all of
+//! the in memory file on fd [INPUT_FD], and what the child prints through the
+//! `fuzzilli` builtin goes to fd [OUTPUT_FD] like with REPRL.
+
+use std::io::{self, Seek, Write};
+use std::fs::File;
+use std::process;
+use std::time::Instant;
+use std::os::unix::process::CommandExt;
+use std::os::unix::io::AsRawFd;
+
+use crate::{error, warn};
+
+use super::execution::{ReturnCode, Execution, ExecutionResult, StartupError};
+use super::execution::{classify_crash, limit_memory, place_fds};
+use super::memfile;
+use super::coverage::{Coverage, warn_uninstrumented};
+use super::sanitizer;
+use super::ffi::*;
+
+/// The fd that the driver reads the commands of the fuzzer from. The status
+/// pipe is the one after it, as AFL has it.
+const CTRL_FD:   i32 = 198;
+const STATUS_FD: i32 = 199;
+
+/// The fds of the input and the output of the child, the same as with REPRL
+const INPUT_FD:  i32 = 102;
+const OUTPUT_FD: i32 = 103;
+
+/// The time that a child gets to die after it was killed for timing out, in
+/// milliseconds
+const KILL_TIMEOUT: i32 = 1000;
+
+pub struct ForkServer {
+    path:          String,
+    args:          Vec<String>,
+    timeout:       u64,
+
+    /// The driver, while it runs
+    server:        Option<process::Child>,
+    ctrl:          Pipefd,
+    status:        Pipefd,
+
+    /// Set when the last child was killed for timing out, which is passed on to
+    /// the driver with the next input
+    killed:        bool,
+
+    /// The in memory files that the input is passed in and that the target
+    /// writes its stderr and output to
+    input:         Option<File>,
+    stderr:        Option<File>,
+    output:        Option<File>,
+
+    /// The edge coverage region that is shared with the target
+    coverage:      Option<Coverage>,
+
+    /// Whether the target is built with sanitizers
+    sanitized:     bool,
+
+    /// The environment variables that the target is started with
+    env:           Vec<(String, String)>,
+
+    /// The no. of bytes that the target may allocate, or 0 for no limit
+    memory_limit:  u64,
+
+    /// The no. of times that the driver was started
+    starts:        u64,
+}
+
+/// Get the return code out of the `status` that `waitpid` returned for a child
+fn wait_status(status: i32, sanitized: bool) -> ReturnCode {
+    if status & 0x7f == 0 {
+        sanitizer::exit_status((status >> 8) & 0xff, sanitized)
+    } else {
+        ReturnCode::Crash(status & 0x7f)
+    }
+}
+
+impl ForkServer {
+
+    /// Start the driver at `path`. Returns why it failed if the driver does not
+    /// get ready.
+    pub fn new(id: usize, path: String, args: Vec<String>,
+               env: Vec<(String, String)>, timeout: u64, memory_limit: u64,
+               sanitized: bool) -> Result<Self, StartupError> {
+        let coverage = Coverage::new(id);
+        if coverage.is_none() {
+            warn!("Failed to create the coverage region, \
+                   running without coverage");
+        }
+
+        let mut server = Self {
+            path,
+            args,
+            timeout,
+            server:   None,
+            ctrl:     Pipefd::default(),
+            status:   Pipefd::default(),
+            killed:   false,
+            input:    memfile::create("Input"),
+            stderr:   memfile::create("Stderr"),
+            output:   memfile::create("Output"),
+            coverage,
+            sanitized,
+            env,
+            memory_limit,
+            starts:   0,
+        };
+        if let Err(err) = server.start() {
+            return Err(StartupError {
+                reason: format!("The fork server did not start: {}", err),
+                stderr: memfile::read(server.stderr.as_mut()),
+            });
+        }
+        warn_uninstrumented(server.coverage.as_ref());
+        Ok(server)
+    }
+
+    /// Start the driver and wait for it to say that it is ready
+    fn start(&mut self) -> io::Result<()> {
+        self.stop();
+
+        let input_fd = match self.input.as_ref() {
+            Some(input) => input.as_raw_fd(),
+            None        => return Err(io::Error::other("No input file")),
+        };
+
+        unsafe {
+            if pipe(&mut self.ctrl) != 0 || pipe(&mut self.status) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let (ctrl_read, ctrl_write) = (self.ctrl.readfd, self.ctrl.writefd);
+        let (status_read, status_write) = (self.status.readfd,
+                                           self.status.writefd);
+        let output_fd = self.output.as_ref().map(|file| file.as_raw_fd());
+        let sanitized = self.sanitized;
+        let memory_limit = self.memory_limit;
+        let pre_exec = move || -> io::Result<()> {
+            unsafe {
+                close(ctrl_write);
+                close(status_read);
+            }
+            place_fds([(ctrl_read, CTRL_FD),
+                       (status_write, STATUS_FD),
+                       (input_fd, INPUT_FD),
+                       (output_fd.unwrap_or(-1), OUTPUT_FD)])?;
+
+            // The limit is inherited by all the children of the driver. The
+            // sanitizers are given their own limit instead.
+            if !sanitized && memory_limit != 0 {
+                limit_memory(memory_limit)?;
+            }
+            Ok(())
+        };
+
+        let mut command = process::Command::new(&self.path);
+        if let Some(coverage) = self.coverage.as_ref() {
+            command.env("SHM_ID", coverage.shm_id());
+        }
+        command.envs(self.env.iter().cloned());
+
+        let stderr = match self.stderr.as_ref().map(|file| file.try_clone()) {
+            Some(Ok(file)) => process::Stdio::from(file),
+            _              => process::Stdio::null(),
+        };
+
+        // The children of the driver share its process group, which keeps a
+        // Ctrl-C on the terminal away from all of them
+        let server = unsafe {
+            command
+                .args(&self.args)
+                .pre_exec(pre_exec)
+                .process_group(0)
+                .stdin(process::Stdio::null())
+                .stdout(process::Stdio::null())
+                .stderr(stderr)
+                .spawn()?
+        };
+        self.server = Some(server);
+        self.starts += 1;
+
+        // Only the driver needs these ends of the pipes
+        self.ctrl.close_read();
+        self.status.close_write();
+
+        // Engines can take a while to start up, so give the driver the whole
+        // timeout to get ready
+        self.read_status(self.timeout())
+            .map(|_| ())
+            .ok_or_else(|| io::Error::other("The driver did not start up"))
+    }
+
+    /// Kill the driver and close the pipes to it
+    fn stop(&mut self) {
+        if let Some(mut server) = self.server.take() {
+            let _ = server.kill();
+            let _ = server.wait();
+        }
+        self.ctrl = Pipefd::default();
+        self.status = Pipefd::default();
+        self.killed = false;
+    }
+
+    /// The timeout in the form that `poll` takes it
+    fn timeout(&self) -> i32 {
+        std::cmp::min(self.timeout, i32::MAX as u64) as i32
+    }
+
+    /// Wait for up to `timeout` ms for the driver to write 4 bytes to the
+    /// status pipe
+    fn read_status(&self, timeout: i32) -> Option<i32> {
+        let mut pollfd = Pollfd {
+            fd:      self.status.readfd,
+            events:  POLLIN,
+            revents: 0,
+        };
+        if unsafe { poll(&mut pollfd as *mut Pollfd, 1, timeout) } <= 0 {
+            return None;
+        }
+
+        let mut value = 0i32;
+        let read = unsafe {
+            read(self.status.readfd, &mut value as *mut i32 as *mut u8, 4)
+        };
+        if read == 4 {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Have the driver run `input` in a new child. Returns `None` if the
+    /// driver itself went away.
+    fn run(&mut self, input: &str) -> Option<ReturnCode> {
+        let file = self.input.as_mut()?;
+        file.set_len(0).ok()?;
+        file.rewind().ok()?;
+        file.write_all(input.as_bytes()).ok()?;
+        file.rewind().ok()?;
+
+        for file in [self.stderr.as_mut(), self.output.as_mut()].into_iter()
+                                                                 .flatten() {
+            let _ = file.set_len(0);
+            let _ = file.rewind();
+        }
+
+        let killed = self.killed as i32;
+        let written = unsafe {
+            write(self.ctrl.writefd, &killed as *const i32 as *const u8, 4)
+        };
+        if written != 4 {
+            return None;
+        }
+        let pid = self.read_status(KILL_TIMEOUT)?;
+
+        self.killed = false;
+        if let Some(status) = self.read_status(self.timeout()) {
+            return Some(wait_status(status, self.sanitized));
+        }
+
+        // The child timed out, so kill it and wait for the driver to reap it
+        unsafe { kill(pid, SIGKILL) };
+        self.killed = true;
+        self.read_status(KILL_TIMEOUT)?;
+        Some(ReturnCode::Timeout)
+    }
+}
+
+impl Execution for ForkServer {
+
+    /// Run `input` in a fresh child of the driver. The driver is started again
+    /// if it went away, and the fuzzer stops if that doesn't help either.
+    fn execute(&mut self, input: &String) -> ExecutionResult {
+        let start = Instant::now();
+        let code = match self.run(input) {
+            Some(code) => code,
+            None => {
+                let _ = self.start();
+                match self.run(input) {
+                    Some(code) => code,
+                    None => {
+                        error!("The fork server stopped working");
+                        process::exit(-1);
+                    }
+                }
+            }
+        };
+
+        let new_edges = match self.coverage.as_mut() {
+            Some(coverage) => coverage.evaluate(),
+            None           => Vec::new(),
+        };
+
+        let mut stderr = String::new();
+        let code = match code {
+            ReturnCode::Crash(signal) => {
+                stderr = memfile::read(self.stderr.as_mut());
+                classify_crash(signal, &stderr)
+            },
+            code => code,
+        };
+
+        let output = memfile::read(self.output.as_mut());
+
+        // The child is gone by now, so there is no telling how much memory it
+        // used
+        ExecutionResult { code, new_edges, stderr, output,
+                          duration: start.elapsed(), peak_rss: None }
+    }
+
+    /// Only the targets that are built with the coverage patch report it. The
+    /// region is there for the others as well, but they never write to it.
+    fn has_coverage(&self) -> bool {
+        self.coverage.as_ref().is_some_and(Coverage::is_instrumented)
+    }
+
+    fn reset_coverage(&mut self) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.reset();
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = timeout;
+    }
+
+    fn restarts(&self) -> u64 {
+        self.starts.saturating_sub(1)
+    }
+}
+
+impl Drop for ForkServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wait_status() {
+        assert!(matches!(wait_status(0, false), ReturnCode::Status(0)));
+        assert!(matches!(wait_status(3 << 8, false), ReturnCode::Status(3)));
+        assert!(matches!(wait_status(11, false), ReturnCode::Crash(11)));
+        assert!(matches!(wait_status(sanitizer::SANITIZER_EXIT_CODE << 8, true),
+                         ReturnCode::Crash(_)));
+    }
+}
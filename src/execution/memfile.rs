@@ -0,0 +1,139 @@
+//! In memory files for passing the inputs to the target and for what it prints.
+//! Linux has `memfd_create` for all of these. Elsewhere, like on macOS, the
+//! inputs that the target maps go in a shared memory object instead, and what
+//! it prints goes to a temp file that is removed right away, as writing to a
+//! shared memory object is not supported there.
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::warn;
+
+use super::execution::{MAX_OUTPUT, truncate_output};
+use super::ffi::*;
+
+/// Create a file called `name` for the target to write to
+#[cfg(target_os = "linux")]
+pub fn create(name: &str) -> Option<File> {
+    use std::os::unix::io::FromRawFd;
+
+    let cname = CString::new(name).expect("Failed to create cstring");
+    let fd = unsafe { memfd_create(cname.as_ptr(), MFD_CLOEXEC) };
+    if fd < 0 {
+        warn!("Failed to create the {} file, running without it", name);
+        return None;
+    }
+    Some(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Create a file called `name` for the target to write to
+#[cfg(not(target_os = "linux"))]
+pub fn create(name: &str) -> Option<File> {
+    let path = std::env::temp_dir().join(format!("zebra_{}_{}_{}", name,
+                                                 std::process::id(),
+                                                 unique()));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path);
+    match file {
+        Ok(file) => {
+            let _ = std::fs::remove_file(&path);
+            Some(file)
+        },
+        Err(_) => {
+            warn!("Failed to create the {} file, running without it",
+                  name);
+            None
+        }
+    }
+}
+
+/// Create `size` bytes of memory called `name` that can be mapped by the target
+/// as well. Returns the fd of it, or -1 if it can't be created.
+#[cfg(target_os = "linux")]
+pub fn shared(name: &str, size: usize) -> i32 {
+    let cname = CString::new(name).expect("Failed to create cstring");
+    unsafe {
+        let fd = memfd_create(cname.as_ptr(), MFD_CLOEXEC);
+        if fd < 0 {
+            return -1;
+        }
+        if ftruncate(fd, size) != 0 {
+            close(fd);
+            return -1;
+        }
+        fd
+    }
+}
+
+/// Create `size` bytes of memory called `name` that can be mapped by the target
+/// as well. Returns the fd of it, or -1 if it can't be created.
+#[cfg(not(target_os = "linux"))]
+pub fn shared(name: &str, size: usize) -> i32 {
+    // The names are short, as macOS doesn't take more than 31 bytes
+    let cname = CString::new(format!("/zebra_{}_{}_{}",
+                                     &name[..name.len().min(4)],
+                                     std::process::id(), unique()))
+        .expect("Failed to create cstring");
+    unsafe {
+        let fd = shm_open(cname.as_ptr(), O_RDWR | O_CREAT | O_EXCL,
+                          0o600u32);
+        if fd < 0 {
+            return -1;
+        }
+
+        // The fd keeps the memory around, so it doesn't need the name
+        shm_unlink(cname.as_ptr());
+        if fcntl(fd, F_SETFD, FD_CLOEXEC) != 0 || ftruncate(fd, size) != 0 {
+            close(fd);
+            return -1;
+        }
+        fd
+    }
+}
+
+/// A no. that is different for each call, to keep the names apart
+#[cfg(not(target_os = "linux"))]
+fn unique() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+    COUNT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Read what the target wrote to `file` since the last input was sent to it
+pub fn read(file: Option<&mut File>) -> String {
+    let file = match file {
+        Some(file) => file,
+        None       => return String::new(),
+    };
+
+    // Only the end of the output is kept, so don't read all of it
+    let len = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    let start = len.saturating_sub(MAX_OUTPUT as u64);
+    let mut output = Vec::new();
+    if file.seek(SeekFrom::Start(start)).is_err() ||
+        file.read_to_end(&mut output).is_err() {
+        return String::new();
+    }
+    truncate_output(&output)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    #[test]
+    fn test_memfile() {
+        let mut file = super::create("Test").unwrap();
+        file.write_all(b"hello").unwrap();
+        assert_eq!(super::read(Some(&mut file)), "hello");
+        assert_eq!(super::read(None), "");
+
+        let fd = super::shared("Test", 0x1000);
+        assert!(fd >= 0);
+        unsafe { super::close(fd) };
+    }
+}
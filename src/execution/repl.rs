@@ -1,20 +1,31 @@
-use std::io;
+use std::io::{self, Seek};
+use std::fs::File;
 use std::process;
 use std::ffi::CString;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::os::unix::process::CommandExt;
 use std::os::unix::process::ExitStatusExt;
+use std::os::unix::io::AsRawFd;
 
-use super::execution::{ReturnCode, Execution};
+use crate::{error, warn, debug};
+
+use super::execution::{ReturnCode, Execution, ExecutionResult, StartupError};
+use super::execution::{classify_crash, limit_memory, place_fds};
+use super::execution::{process_memory, reset_peak_memory};
+use super::memfile;
+use super::coverage::{Coverage, warn_uninstrumented};
+use super::sanitizer;
 use super::ffi::*;
 
 const CRFD: i32 = 100;
 const CWFD: i32 = 101;
 const DRFD: i32 = 102;
-const _DWFD: i32 = 103;
+const DWFD: i32 = 103;
 
-const MAX_SIZE: usize = 0x10000;
+/// The size of the shared memory that the inputs are copied to. Longer inputs
+/// are cut off, and a batch of inputs has to fit in it as a whole.
+pub const MAX_SIZE: usize = 0x10000;
 
 // Error to wrap around all the repl related errors
 #[derive(Debug, Eq, PartialEq)]
@@ -90,6 +101,7 @@ macro_rules! check {
 enum CtrlCmd {
     Helo,
     Exec,
+    Batch,
     Exit,
     Misc(i32)
 }
@@ -100,6 +112,7 @@ impl From<i32> for CtrlCmd {
         match val {
             0x4f4c4548 => CtrlCmd::Helo,
             0x63657865 => CtrlCmd::Exec,
+            0x68637462 => CtrlCmd::Batch,
             0x74697865 => CtrlCmd::Exit,
             _          => CtrlCmd::Misc(val),
         }
@@ -111,6 +124,7 @@ impl From<&CtrlCmd> for CString {
         match cmd {
             CtrlCmd::Helo    => cstring!("HELO"),
             CtrlCmd::Exec    => cstring!("exec"),
+            CtrlCmd::Batch   => cstring!("btch"),
             CtrlCmd::Exit    => cstring!("exit"),
             CtrlCmd::Misc(_) => {
                 unimplemented!();
@@ -127,8 +141,44 @@ pub struct ReplConnection {
     mapping:       Option<*mut u8>,
     child:         Option<process::Child>,
     path:          Option<String>,
-    args:          Option<Vec<&'static str>>,
-    timeout:       Option<u32>,
+    args:          Option<Vec<String>>,
+    timeout:       Option<u64>,
+
+    /// The edge coverage region that is shared with the child. This outlives
+    /// the child, so that the edges seen so far are kept across restarts.
+    coverage:      Option<Coverage>,
+
+    /// An in memory file that the child writes its stderr to. This is cleared
+    /// before each input, so after a crash it holds what led up to it.
+    stderr:        Option<File>,
+
+    /// An in memory file for what the child prints through the `fuzzilli`
+    /// builtin, which is cleared before each input like the stderr file
+    output:        Option<File>,
+
+    /// Whether the target is built with sanitizers
+    sanitized:     bool,
+
+    /// The environment variables that the target is started with
+    env:           Vec<(String, String)>,
+
+    /// The no. of bytes that the target may allocate, or 0 for no limit
+    memory_limit:  u64,
+
+    /// Restart the child after it ran this many inputs
+    restart_every: Option<u64>,
+
+    /// Restart the child once its RSS grew by this many bytes since it was
+    /// started
+    restart_rss:   Option<u64>,
+
+    /// The no. of inputs that the child ran so far and its RSS right after it
+    /// was started
+    executions:    u64,
+    start_rss:     u64,
+
+    /// The no. of times that the child was started
+    starts:        u64,
 }
 
 impl Execution for ReplConnection {
@@ -136,38 +186,187 @@ impl Execution for ReplConnection {
     /// Wrapper function to call execute_impl. This function will check if
     /// execute_impl failed and if so try a second time. If both fail, then this
     /// function terminates the process
-    fn execute(&mut self, input: &String) -> ReturnCode {
-        match self.execute_impl(input) {
-            Ok(code) => code,
+    fn execute(&mut self, input: &String) -> ExecutionResult {
+        let (code, duration) = match self.execute_impl(input) {
+            Ok(result) => result,
             Err(_)   => {
                 // For some reason, execution failed. Lets re-initialize the
                 // child and try again.
                 self.reset_connection();
                 match self.execute_impl(input) {
-                    Ok(code) => code,
+                    Ok(result) => result,
                     Err(err) => {
                         // We failed yet again. Now lets stop trying.
-                        println!("[-] Repl Execution Failure: {err}");
+                        error!("Repl Execution Failure: {err}");
                         process::exit(-1);
                     }
                 }
             }
+        };
+
+        // Targets that are not built with coverage leave the region untouched
+        // so this just finds no new edges for them.
+        let new_edges = match self.coverage.as_mut() {
+            Some(coverage) => coverage.evaluate(),
+            None           => Vec::new(),
+        };
+
+        // Check the stderr of crashes for failed allocations
+        let mut stderr = String::new();
+        let code = match code {
+            ReturnCode::Crash(signal) => {
+                stderr = memfile::read(self.stderr.as_mut());
+                classify_crash(signal, &stderr)
+            },
+            code => code,
+        };
+
+        let output = memfile::read(self.output.as_mut());
+        let peak_rss = self.peak_rss();
+
+        self.recycle(1);
+
+        ExecutionResult { code, new_edges, stderr, output, duration, peak_rss }
+    }
+
+    /// Run all the `inputs` with a single round trip through the control
+    /// pipes. A failed batch only restarts the child, as the inputs are run
+    /// one by one with [Execution::execute] after that anyway.
+    fn execute_batch(&mut self, inputs: &[String]) -> Option<Vec<i32>> {
+        match self.execute_batch_impl(inputs) {
+            Ok(statuses) => {
+                self.recycle(inputs.len() as u64);
+                statuses
+            },
+            Err(_)       => {
+                self.reset_connection();
+                None
+            }
+        }
+    }
+
+    /// Only the targets that are built with the coverage patch report it. The
+    /// region is there for the others as well, but they never write to it.
+    fn has_coverage(&self) -> bool {
+        self.coverage.as_ref().is_some_and(Coverage::is_instrumented)
+    }
+
+    fn reset_coverage(&mut self) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.reset();
         }
     }
+
+    fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = Some(timeout);
+    }
+
+    fn restarts(&self) -> u64 {
+        self.starts.saturating_sub(1)
+    }
 }
 
 impl ReplConnection {
 
-    pub fn new(path: String, args: Vec<&'static str>, timeout: u32) -> Self {
+    /// Start the target at `path` and do the handshake with it. Returns why it
+    /// failed if the target does not start up in REPRL mode.
+    pub fn new(id: usize, path: String, args: Vec<String>,
+               env: Vec<(String, String)>, timeout: u64, memory_limit: u64,
+               sanitized: bool) -> Result<Self, StartupError> {
         let mut replcon = Self::default();
-        replcon.path    = Some(path);
-        replcon.args    = Some(args);
-        replcon.timeout = Some(timeout);
+        replcon.path      = Some(path);
+        replcon.args      = Some(args);
+        replcon.env       = env;
+        replcon.memory_limit = memory_limit;
+        replcon.timeout   = Some(timeout);
+        replcon.sanitized = sanitized;
+        replcon.coverage = Coverage::new(id);
+        if replcon.coverage.is_none() {
+            warn!("Failed to create the coverage region, \
+                   running without coverage");
+        }
+        replcon.stderr = memfile::create("Stderr");
+        replcon.output = memfile::create("Output");
         if let Err(err) = replcon.init() {
-                println!("[-] ReplConnection Initialization Failure! {err}");
-                process::exit(-1);
+            let reason = match (replcon.exit_status(), err) {
+                (Some(status), _) => match status.code() {
+                    Some(code) => format!("The engine exited with {} before \
+                                           the REPRL handshake", code),
+                    None => format!("The engine was killed by signal {} \
+                                     before the REPRL handshake",
+                                    status.signal().unwrap_or(0)),
+                },
+                (None, ReplError::Timeout) => {
+                    "Timed out waiting for the REPRL handshake".to_string()
+                },
+                (None, ReplError::Other(msg)) => {
+                    format!("The REPRL handshake failed: {}", msg)
+                },
+            };
+            return Err(StartupError {
+                reason,
+                stderr: memfile::read(replcon.stderr.as_mut()),
+            });
         };
-        replcon
+        warn_uninstrumented(replcon.coverage.as_ref());
+        Ok(replcon)
+    }
+
+    /// Get how the child exited, if it did. It is given a moment to exit, as
+    /// its end of the pipes can be closed a little before it is gone.
+    fn exit_status(&mut self) -> Option<process::ExitStatus> {
+        let child = self.child.as_mut()?;
+        for _ in 0..10 {
+            match child.try_wait() {
+                Ok(Some(status)) => return Some(status),
+                Ok(None)         => sleep(Duration::from_millis(10)),
+                Err(_)           => return None,
+            }
+        }
+        None
+    }
+
+    /// Restart the child after it ran `executions` inputs, or once its RSS
+    /// grew by `rss` bytes, whichever comes first. Without either, the child
+    /// is only started again when it crashes or times out.
+    pub fn set_restart(&mut self, executions: Option<u64>, rss: Option<u64>) {
+        self.restart_every = executions;
+        self.restart_rss = rss;
+    }
+
+    /// Get the RSS of the child in bytes, or 0 if it can't be read. It is read
+    /// out of `/proc`, so this is always 0 on systems other than Linux.
+    fn child_rss(&self) -> u64 {
+        self.child.as_ref()
+            .and_then(|child| process_memory(child.id(), "VmRSS"))
+            .unwrap_or(0)
+    }
+
+    /// Get the most memory that the child used since this was called last, if
+    /// it is still running
+    fn peak_rss(&self) -> Option<u64> {
+        let pid = self.child.as_ref()?.id();
+        let peak = process_memory(pid, "VmHWM");
+        reset_peak_memory(pid);
+        peak
+    }
+
+    /// Count the `executions` that the child just did, and stop it if it is
+    /// due for a restart. It is started again on the next input.
+    fn recycle(&mut self, executions: u64) {
+        if self.child.is_none() {
+            return;
+        }
+
+        self.executions += executions;
+        let ran_enough = self.restart_every
+            .is_some_and(|limit| self.executions >= limit);
+        let grew = self.restart_rss.is_some_and(|limit| {
+            self.child_rss().saturating_sub(self.start_rss) >= limit
+        });
+        if ran_enough || grew {
+            self.reset_connection();
+        }
     }
 
     /// Setup and initialize a new connection to a program at `path`
@@ -176,16 +375,14 @@ impl ReplConnection {
         // First reset the connection to clean up any existing resources
         self.reset_connection();
 
-        let shmname = cstring!("SHMRegion");
         let mut ctrl_fd_read  = Pipefd::default();
         let mut ctrl_fd_write = Pipefd::default();
 
         let (address, fd);
 
         unsafe {
-            fd = memfd_create(shmname.as_ptr(), MFD_CLOEXEC);
-            check!(fd, "memfd_create")?;
-            check!(ftruncate(fd, MAX_SIZE), "ftruncate")?;
+            fd = memfile::shared("SHMRegion", MAX_SIZE);
+            check!(fd, "shared memory")?;
 
             address = mmap(std::ptr::null_mut(), MAX_SIZE,
                         PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
@@ -198,6 +395,9 @@ impl ReplConnection {
         // This closure will be run in the forked child process. It will do the
         // necessary initialization of the fd's that the target process will
         // expect and close the unused fds.
+        let sanitized = self.sanitized;
+        let memory_limit = self.memory_limit;
+        let output_fd = self.output.as_ref().map(|file| file.as_raw_fd());
         let pre_exec =  move || -> io::Result<()> {
             // Macro to wrap `check!` to return an io::Error.
             macro_rules! check_ioerr {
@@ -211,28 +411,51 @@ impl ReplConnection {
             }
 
             unsafe {
-
-                // Duplicate the fd's for use in the spawned process
-                check_ioerr!(dup2(fd, DRFD), "dup2")?;
-                check_ioerr!(dup2(ctrl_fd_write.readfd, CRFD), "dup2")?;
-                check_ioerr!(dup2(ctrl_fd_read.writefd, CWFD), "dup2")?;
-
                 // Close the unused fd's of the pipe
                 check_ioerr!(close(ctrl_fd_write.writefd), "close")?;
                 check_ioerr!(close(ctrl_fd_read.readfd), "close")?;
             }
 
+            // Duplicate the fd's for use in the spawned process
+            place_fds([(fd, DRFD),
+                       (ctrl_fd_write.readfd, CRFD),
+                       (ctrl_fd_read.writefd, CWFD),
+                       (output_fd.unwrap_or(-1), DWFD)])?;
+
+            // The sanitizers reserve a lot of memory up front, so they are
+            // given their own limit instead
+            if !sanitized && memory_limit != 0 {
+                limit_memory(memory_limit)?;
+            }
+
             Ok(())
         };
 
         // Execute the child. Its safe to unwrap path and args here as these
         // should be set when an instance of this struct is created.
+        let mut command = process::Command::new(self.path.as_ref().unwrap());
+        if let Some(coverage) = self.coverage.as_ref() {
+            command.env("SHM_ID", coverage.shm_id());
+        }
+        command.envs(self.env.iter().cloned());
+
+        // The child gets its own handle to the stderr file, which shares the
+        // offset with ours
+        let stderr = match self.stderr.as_ref().map(|file| file.try_clone()) {
+            Some(Ok(file)) => process::Stdio::from(file),
+            _              => process::Stdio::null(),
+        };
+
+        // The child is put in a process group of its own, so that a Ctrl-C on
+        // the terminal only reaches the fuzzer, which then stops it itself
         let child = unsafe {
-            process::Command::new(self.path.as_ref().unwrap())
+            command
                 .args(self.args.as_ref().unwrap())
                 .pre_exec(pre_exec)
+                .process_group(0)
+                .stdin(process::Stdio::null())
                 .stdout(process::Stdio::null())
-                .stderr(process::Stdio::null())
+                .stderr(stderr)
                 .spawn()
                 .map_err(|_|
                          ReplError::Other("Failed to execute target Process"))?
@@ -247,6 +470,7 @@ impl ReplConnection {
         self.ctrl_read_fd  = Some(ctrl_fd_read);
         self.mapping       = Some(address);
         self.child         = Some(child);
+        self.starts       += 1;
 
         // Receive the Helo message from the child to ensure that the connection
         // is successfully setup.
@@ -257,6 +481,14 @@ impl ReplConnection {
             return Err(ReplError::Other("Incorrect msg received"));
         }
 
+        self.executions = 0;
+        if self.restart_rss.is_some() {
+            self.start_rss = self.child_rss();
+        }
+
+        // The memory used to start up is not part of the peak of the inputs
+        let _ = self.peak_rss();
+
         Ok(())
     }
 
@@ -279,7 +511,7 @@ impl ReplConnection {
             let ret = unsafe { close(self.dwfd()) };
             self.data_write_fd = None;
             if ret != 0 {
-                println!("ERROR closing dwfd");
+                error!("Failed to close the data fd of the REPRL connection");
                 unsafe {core::ptr::copy(0x414141usize as *const u8, 0x414141usize as *mut u8, 8)};
             }
         }
@@ -301,7 +533,8 @@ impl ReplConnection {
     /// finish executing the input and returning a return code, or for it to
     /// crash or up till the timeout is reached. It returns either the status
     /// returned by the child, the signal that terminated it or a timeout.
-    fn execute_impl(&mut self, input: &str) -> ReplResult<ReturnCode> {
+    fn execute_impl(&mut self, input: &str)
+        -> ReplResult<(ReturnCode, Duration)> {
 
         // Check if the connection is already initialized. Initialize it if not.
         if !self.is_initialized() {
@@ -313,6 +546,14 @@ impl ReplConnection {
         // Reset the file descriptors of the backing buffer
         unsafe { check!(lseek(self.dwfd(), 0, SEEK_SET), "lseek")? };
 
+        // Only keep the stderr and output of this input. Failing to clear them
+        // is not worth stopping the fuzzer for, they are just longer then.
+        for file in [self.stderr.as_mut(), self.output.as_mut()].into_iter()
+                                                                 .flatten() {
+            let _ = file.set_len(0);
+            let _ = file.rewind();
+        }
+
         // Make sure that the size of the input does not go beyond the
         // `MAX_SIZE` and then copy the input over the to the backing shared
         // memory
@@ -321,6 +562,7 @@ impl ReplConnection {
 
         // Send the execute command to the child and then tell it the size of
         // the input
+        let start = Instant::now();
         self.send_cmd(CtrlCmd::Exec)?;
         self.send_u64(size as u64)?;
 
@@ -355,9 +597,10 @@ impl ReplConnection {
                             // normally returned
                             self.reset_connection();
                             if let Some(code) = status.code() {
-                                // Normal exit: This should ideally never
-                                // happen, but lets handle it in case it does.
-                                ReturnCode::Status(code)
+                                // Normal exit: This only happens when a
+                                // sanitizer found an error and exited, but
+                                // lets handle the other cases too.
+                                sanitizer::exit_status(code, self.sanitized)
                             } else {
                                 // Its definitely ternimated by a signal. Lets
                                 // return the signal that terminated it.
@@ -391,7 +634,77 @@ impl ReplConnection {
             }
         };
 
-        Ok(result)
+        Ok((result, start.elapsed()))
+    }
+
+    /// Send all the `inputs` to the child at once. The inputs are copied to the
+    /// shared memory one after the other, and the child is sent the `btch`
+    /// command followed by the no. of inputs and the size of each of them. It
+    /// runs them in that order and sends back their statuses as it goes, so
+    /// each input gets the whole timeout to itself. Returns `None` if the
+    /// inputs don't all fit in the shared memory, or if any of them made the
+    /// child crash or time out or covered new edges.
+    fn execute_batch_impl(&mut self, inputs: &[String])
+        -> ReplResult<Option<Vec<i32>>> {
+
+        let total = inputs.iter().map(|input| input.len()).sum::<usize>();
+        if total >= MAX_SIZE {
+            return Ok(None);
+        }
+
+        if !self.is_initialized() {
+            self.init()?;
+        }
+
+        unsafe { check!(lseek(self.dwfd(), 0, SEEK_SET), "lseek")? };
+        for file in [self.stderr.as_mut(), self.output.as_mut()].into_iter()
+                                                                 .flatten() {
+            let _ = file.set_len(0);
+            let _ = file.rewind();
+        }
+
+        let mut offset = 0;
+        for input in inputs {
+            unsafe {
+                core::ptr::copy(input.as_ptr(), self.mapping().add(offset),
+                                input.len())
+            };
+            offset += input.len();
+        }
+
+        self.send_cmd(CtrlCmd::Batch)?;
+        self.send_u64(inputs.len() as u64)?;
+        for input in inputs {
+            self.send_u64(input.len() as u64)?;
+        }
+
+        let mut statuses = Vec::with_capacity(inputs.len());
+        for _ in inputs {
+            match self.recv_cmd() {
+                Ok(CtrlCmd::Misc(ret)) => statuses.push(ret),
+                Ok(_)  => {
+                    return Err(ReplError::Other("Invalid message received"));
+                },
+                Err(_) => {
+                    // Whatever happened to the child, the input that caused
+                    // it is found when the inputs are run one by one
+                    self.reset_connection();
+                    return Ok(None);
+                },
+            }
+        }
+
+        // The edges can't be told apart by input, so leave them to be found
+        // again when the inputs are run one by one
+        if let Some(coverage) = self.coverage.as_mut() {
+            let new_edges = coverage.has_new_edges();
+            coverage.clear();
+            if new_edges {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(statuses))
     }
 
     fn recv_cmd(&self) -> ReplResult<CtrlCmd> {
@@ -407,9 +720,9 @@ impl ReplConnection {
         };
 
         let timeout = self.timeout.ok_or(ReplError::Other("Missing timeout"))?;
-        let timeout = timeout * 1000;
+        let timeout = std::cmp::min(timeout, i32::MAX as u64) as i32;
         let result = unsafe {
-            check!(poll(&mut pollfd as *mut Pollfd, 1, timeout as i32), "poll")?
+            check!(poll(&mut pollfd as *mut Pollfd, 1, timeout), "poll")?
         };
 
         // Check if we timed out on the poll. If so, then just return a Timeout
@@ -506,6 +819,17 @@ impl Default for ReplConnection {
             path:          None,
             args:          None,
             timeout:       None,
+            coverage:      None,
+            stderr:        None,
+            output:        None,
+            sanitized:     false,
+            env:           vec![],
+            memory_limit:  0,
+            restart_every: None,
+            restart_rss:   None,
+            executions:    0,
+            start_rss:     0,
+            starts:        0,
         }
     }
 }
@@ -513,7 +837,35 @@ impl Default for ReplConnection {
 
 impl Drop for ReplConnection {
     fn drop(&mut self) {
-        println!("[+] ReplConnection Drop Handler");
+        debug!("Closing the REPRL connection");
         self.reset_connection();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A REPRL target that runs every input successfully, without the
+    /// coverage patch. It needs bash, as sh can't use the fds above 9.
+    const TARGET: &str = "printf HELO >&101; head -c 4 <&100 >/dev/null
+                          while [ -n \"$(head -c 4 <&100)\" ]; do
+                              head -c 8 <&100 >/dev/null
+                              printf '\\000\\000\\000\\000' >&101
+                          done";
+
+    #[test]
+    fn test_uninstrumented() {
+        let args = vec!["-c".to_string(), TARGET.to_string()];
+        let mut exec = ReplConnection::new(!0, "bash".to_string(), args,
+                                           vec![], 1000, 0, false)
+            .unwrap();
+        assert!(exec.coverage.is_some());
+        for _ in 0..3 {
+            let result = exec.execute(&"1 + 1".to_string());
+            assert!(matches!(result.code, ReturnCode::Status(0)));
+            assert!(result.new_edges.is_empty());
+        }
+        assert!(!exec.has_coverage());
+    }
+}
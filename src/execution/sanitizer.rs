@@ -0,0 +1,175 @@
+//! Support for targets that are built with sanitizers like ASAN and UBSAN. The
+//! sanitizers are configured to stop the target on the first error they find,
+//! and the type of the error is read out of the report that they print to
+//! stderr.
+
+use std::fs::File;
+use std::io::Read;
+
+use super::execution::ReturnCode;
+
+/// The exit code that the sanitizers are told to exit with when they find an
+/// error and don't abort
+pub const SANITIZER_EXIT_CODE: i32 = 77;
+
+/// The signal that the target is considered to have crashed with when it exits
+/// with [SANITIZER_EXIT_CODE]. This is what the sanitizers abort with.
+const SIGABRT: i32 = 6;
+
+/// Symbols that are only found in binaries that are built with a sanitizer
+const MARKERS: [&[u8]; 4] = [
+    b"__asan_init",
+    b"__ubsan_handle_",
+    b"__msan_init",
+    b"__tsan_init",
+];
+
+/// The options that are set for each sanitizer. Any options that are already
+/// set in the environment of the fuzzer or with `--env` are put after these,
+/// so they win. The
+/// memory limit is set with the options too, as sanitized targets reserve too
+/// much memory to be limited with `setrlimit`.
+const OPTIONS: [(&str, &str); 2] = [
+    ("ASAN_OPTIONS",  "abort_on_error=1:detect_leaks=0:symbolize=1"),
+    ("UBSAN_OPTIONS", "halt_on_error=1:abort_on_error=1:print_stacktrace=1"),
+];
+
+/// Check if any of the [MARKERS] is in `data`. The markers all start with two
+/// underscores, so only the places with those are checked.
+fn has_marker(data: &[u8]) -> bool {
+    data.windows(2).enumerate()
+        .filter(|(_, window)| window == b"__")
+        .any(|(idx, _)| {
+            MARKERS.iter().any(|marker| data[idx..].starts_with(marker))
+        })
+}
+
+/// Check if the binary at `path` is built with any of the sanitizers. The
+/// binary is read in chunks, as debug builds of engines can be huge.
+pub fn is_sanitized(path: &str) -> bool {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_)   => return false,
+    };
+
+    // Keep the end of the previous chunk around, so that markers that are
+    // split between two chunks are found too
+    let overlap = MARKERS.iter().map(|marker| marker.len()).max().unwrap();
+    let mut buf = vec![0u8; 0x100000];
+    let mut kept = 0;
+
+    loop {
+        let read = match file.read(&mut buf[kept..]) {
+            Ok(0) | Err(_) => return false,
+            Ok(read)       => read,
+        };
+        let data = &buf[..kept + read];
+
+        if has_marker(data) {
+            return true;
+        }
+
+        kept = std::cmp::min(overlap, data.len());
+        let start = data.len() - kept;
+        buf.copy_within(start..start + kept, 0);
+    }
+}
+
+/// The environment variables to run a sanitized target with, when it is given
+/// the variables of `env` as well and may allocate `memory_limit` bytes (0 for
+/// no limit)
+pub fn environment(env: &[(String, String)],
+                   memory_limit: u64) -> Vec<(String, String)> {
+    let mut environment = OPTIONS.iter().map(|(name, options)| {
+        let mut value = format!("{}:exitcode={}", options, SANITIZER_EXIT_CODE);
+        if memory_limit != 0 {
+            value = format!("{}:hard_rss_limit_mb={}", value, memory_limit >> 20);
+        }
+        if let Ok(existing) = std::env::var(name) {
+            value = format!("{}:{}", value, existing);
+        }
+        for (_, given) in env.iter().filter(|(given, _)| given == name) {
+            value = format!("{}:{}", value, given);
+        }
+        (name.to_string(), value)
+    }).collect::<Vec<(String, String)>>();
+
+    environment.extend(env.iter().filter(|(name, _)| {
+        !OPTIONS.iter().any(|(option, _)| option == name)
+    }).cloned());
+    environment
+}
+
+/// Get the return code for a target that exited with `code`. When the target
+/// is `sanitized`, exiting with the exit code of the sanitizers means that they
+/// found an error.
+pub fn exit_status(code: i32, sanitized: bool) -> ReturnCode {
+    if sanitized && code == SANITIZER_EXIT_CODE {
+        ReturnCode::Crash(SIGABRT)
+    } else {
+        ReturnCode::Status(code)
+    }
+}
+
+/// Get the type of the error out of the sanitizer report in `stderr`, like
+/// `heap-buffer-overflow` or `heap-use-after-free`. Only letters, digits and
+/// dashes are kept, so it can be used in a filename.
+pub fn report_type(stderr: &str) -> Option<String> {
+    for prefix in ["ERROR: ", "SUMMARY: "] {
+        for line in stderr.lines() {
+            let rest = match line.find(prefix) {
+                Some(idx) => &line[idx + prefix.len()..],
+                None      => continue,
+            };
+
+            // The report looks like `ERROR: AddressSanitizer: <type> ...`
+            let mut parts = rest.splitn(2, ": ");
+            match (parts.next(), parts.next()) {
+                (Some(tool), Some(kind)) if tool.ends_with("Sanitizer") => {
+                    let kind = kind.split_whitespace().next().unwrap_or("")
+                        .chars()
+                        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                        .collect::<String>();
+                    if !kind.is_empty() {
+                        return Some(kind);
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sanitizer_reports() {
+        let asan = "==1==ERROR: AddressSanitizer: heap-use-after-free on \
+                    address 0x6020\n\
+                    SUMMARY: AddressSanitizer: heap-use-after-free foo.cpp:3";
+        assert_eq!(report_type(asan).as_deref(), Some("heap-use-after-free"));
+
+        let ubsan = "foo.cpp:3:7: runtime error: signed integer overflow\n\
+                     SUMMARY: UndefinedBehaviorSanitizer: undefined-behavior \
+                     foo.cpp:3:7";
+        assert_eq!(report_type(ubsan).as_deref(), Some("undefined-behavior"));
+        assert_eq!(report_type("Assertion failed: x"), None);
+
+        assert!(matches!(exit_status(SANITIZER_EXIT_CODE, true),
+                         ReturnCode::Crash(_)));
+        assert!(matches!(exit_status(SANITIZER_EXIT_CODE, false),
+                         ReturnCode::Status(SANITIZER_EXIT_CODE)));
+        assert!(has_marker(b"\0_a__asan_init\0"));
+        assert!(!has_marker(b"__asan"));
+        assert!(!is_sanitized("/nonexistent"));
+
+        let env = environment(&[("ASAN_OPTIONS".into(), "detect_leaks=1".into()),
+                                ("FOO".into(), "bar".into())], 1 << 20);
+        assert_eq!(env.len(), OPTIONS.len() + 1);
+        assert!(env[0].1.ends_with(":hard_rss_limit_mb=1:detect_leaks=1"));
+        assert_eq!(env[OPTIONS.len()], ("FOO".into(), "bar".into()));
+    }
+}
@@ -1,11 +1,15 @@
 use std::process;
 use std::fs::File;
 use std::io::{self, Write};
+use std::time::Instant;
 use std::os::unix::process::CommandExt;
 use std::os::unix::process::ExitStatusExt;
 
-use super::execution::{ReturnCode, Execution};
-use super::ffi::alarm;
+use super::execution::{ReturnCode, Execution, ExecutionResult, truncate_output};
+use super::execution::{classify_crash, limit_memory};
+use super::ffi::{setitimer, Itimerval, ITIMER_REAL};
+use super::sanitizer;
+use crate::utils::random::timestamp;
 
 /// Create `filename` and write `data` to it
 pub fn write_file(filename: &str, data: &String) -> io::Result<()> {
@@ -16,58 +20,103 @@ pub fn write_file(filename: &str, data: &String) -> io::Result<()> {
 
 pub struct Spawn {
     path:          String,
-    args:          Vec<&'static str>,
-    timeout:       u32,
+    args:          Vec<String>,
+    timeout:       u64,
+
+    /// The directory that the inputs are written to, which no other thread
+    /// uses, and the file that the next input is written to in there
+    dir:           String,
     pname:         String,
+
+    /// Whether the target is built with sanitizers
+    sanitized:     bool,
+
+    /// The environment variables that the target is started with
+    env:           Vec<(String, String)>,
+
+    /// The no. of bytes that the target may allocate, or 0 for no limit
+    memory_limit:  u64,
 }
 
 impl Spawn {
 
-    pub fn new(path: String, args: Vec<&'static str>, timeout: u32) -> Self {
+    pub fn new(dir: String, path: String, args: Vec<String>,
+               env: Vec<(String, String)>, timeout: u64, memory_limit: u64,
+               sanitized: bool) -> Self {
 
-        let rand  = unsafe { std::arch::x86_64::_rdtsc() };
-        let pname = format!("tests/testfile_{}.js", rand);
+        std::fs::create_dir_all(&dir)
+            .expect("Failed to create the directory for the inputs");
+        let pname = Spawn::testfile(&dir);
 
         Spawn {
-            path:    path,
-            args:    args,
-            timeout: timeout,
-            pname:   pname,
+            path:      path,
+            args:      args,
+            timeout:   timeout,
+            dir,
+            pname:     pname,
+            sanitized,
+            env,
+            memory_limit,
         }
     }
+
+    /// Get a new name for the file of an input in `dir`
+    fn testfile(dir: &str) -> String {
+        let rand = timestamp();
+        format!("{}/testfile_{}.js", dir, rand)
+    }
 }
 
 impl Execution for Spawn {
 
-    fn execute(&mut self, input: &String) -> ReturnCode {
+    fn execute(&mut self, input: &String) -> ExecutionResult {
 
         write_file(&self.pname, input)
             .expect("Error when writting out to file");
 
         let timeout = self.timeout;
+        let sanitized = self.sanitized;
+        let memory_limit = self.memory_limit;
         let child_pre_exec = move || -> io::Result<()> {
 
-            unsafe {
-                alarm(timeout);
+            // The target gets a SIGALRM once the timeout is up
+            let timer = Itimerval::once(timeout);
+            if unsafe { setitimer(ITIMER_REAL, &timer, std::ptr::null_mut()) }
+                != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // The sanitizers reserve a lot of memory up front, so they are
+            // given their own limit instead
+            if !sanitized && memory_limit != 0 {
+                limit_memory(memory_limit)?;
             }
 
             Ok(())
         };
 
-        let status = unsafe {
-            process::Command::new(&self.path)
+        let mut command = process::Command::new(&self.path);
+        command.envs(self.env.iter().cloned());
+
+        let start = Instant::now();
+        // A Ctrl-C on the terminal is left to the fuzzer, which lets the
+        // program finish before it stops
+        let output = unsafe {
+            command
                     .pre_exec(child_pre_exec)
+                    .process_group(0)
                     .args(&self.args)
                     .arg(&self.pname)
-                    .stdout(process::Stdio::null())
-                    .stderr(process::Stdio::null())
-                    .status()
+                    .stdout(process::Stdio::piped())
+                    .stderr(process::Stdio::piped())
+                    .output()
                     .expect("Failed to exe proc")
         };
+        let status = output.status;
 
-        match status.code() {
+        let code = match status.code() {
             Some(code) => {
-                ReturnCode::Status(code)
+                sanitizer::exit_status(code, self.sanitized)
             },
             None => {
                 let signal = status.signal().unwrap_or(0);
@@ -79,7 +128,76 @@ impl Execution for Spawn {
                 }
 
             }
+        };
+
+        // Check the stderr of crashes for failed allocations
+        let mut stderr = String::new();
+        let code = match code {
+            ReturnCode::Crash(signal) => {
+                stderr = truncate_output(&output.stderr);
+                classify_crash(signal, &stderr)
+            },
+            code => code,
+        };
+
+        // The inputs that crashed are kept around to look at, and the next
+        // input goes to a new file. The others are not needed any more.
+        if let ReturnCode::Crash(_) = code {
+            self.pname = Spawn::testfile(&self.dir);
+        } else {
+            let _ = std::fs::remove_file(&self.pname);
+        }
+
+        let mut result = ExecutionResult::from(code);
+        result.stderr = stderr;
+        result.output = truncate_output(&output.stdout);
+        result.duration = start.elapsed();
+        result
+    }
+
+    fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = timeout;
+    }
+}
+
+impl Drop for Spawn {
+
+    /// Remove the directory of the inputs, and the one that holds the
+    /// directories of all the threads, unless they still have files in them
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir(&self.dir);
+        if let Some(parent) = std::path::Path::new(&self.dir).parent() {
+            let _ = std::fs::remove_dir(parent);
         }
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::execution::execution::MAX_OUTPUT;
+
+    #[test]
+    fn test_spawn_stderr() {
+        let dir = std::env::temp_dir().join(format!("zebra_spawn_{}",
+                                                    std::process::id()))
+                                      .to_string_lossy().into_owned();
+        let mut spawn = Spawn::new(dir.clone(), "/bin/sh".to_string(),
+                                   vec!["-c".to_string(),
+                                        "echo boom >&2; kill -SEGV $$".to_string()],
+                                   vec![], 5000, 0, false);
+        let result = spawn.execute(&String::new());
+        assert!(matches!(result.code, ReturnCode::Crash(11)));
+        assert_eq!(result.stderr, "boom\n");
+
+        // Only the input that crashed is left in the directory
+        spawn.args[1] = "true".to_string();
+        spawn.execute(&String::new());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let long = truncate_output(&vec![b'a'; MAX_OUTPUT * 2]);
+        assert_eq!(long.len(), MAX_OUTPUT);
+    }
+}
@@ -6,3 +6,16 @@ pub mod fuzz_globals;
 pub mod settings;
 pub mod stats;
 pub mod interesting;
+pub mod corpus;
+pub mod scheduler;
+pub mod minimize;
+pub mod discoveries;
+pub mod replay;
+pub mod triage;
+pub mod bench;
+pub mod timeout;
+pub mod slowest;
+pub mod preflight;
+pub mod session;
+pub mod throughput;
+pub mod health;
@@ -0,0 +1,66 @@
+//! Measure how fast programs are generated and lifted, without running them on
+//! the target. Comparing this to the no. of programs that the fuzzer runs per
+//! second shows whether the fuzzer or the target is what holds it back.
+
+use std::time::{Duration, Instant};
+
+use crate::cmdlineoptions::CmdLineOptions;
+use crate::ir::program::Program;
+use crate::jsruntime::jsruntime::JSRuntime;
+use crate::lifter::lifter::Lifter;
+use crate::utils::alloc::{start_counting, stop_counting};
+use crate::utils::random::Random;
+
+use super::fuzzer::regenerate_program;
+
+/// Generate and lift `count` programs the same way the fuzzer does, and print
+/// out the no. of programs per second, their average size and the allocations
+/// that were made for them
+pub fn bench_gen(cmdline: &CmdLineOptions, jsruntime: &JSRuntime, count: u64) {
+    let mut rng = Random::new(cmdline.seed);
+    let mut lifter = Lifter::new();
+    lifter.set_digest(cmdline.digest);
+    lifter.set_comments(cmdline.ir_comments);
+    lifter.set_literal_forms(cmdline.literal_forms);
+    lifter.set_iife_probability(cmdline.iife_probability);
+    lifter.set_declarations(cmdline.declarations);
+    lifter.set_realistic_names(cmdline.realistic_names);
+    lifter.set_strip_dead_code(cmdline.strip_dead_code);
+
+    let mut generating = Duration::ZERO;
+    let mut lifting    = Duration::ZERO;
+    let mut instructions = 0;
+    let mut bytes = 0;
+
+    // The fuzzer reuses the memory of its program too
+    let mut program = Program::new(jsruntime);
+    start_counting();
+    for _ in 0..count {
+        let seed = rng.rand();
+
+        let start = Instant::now();
+        regenerate_program(&mut program, cmdline, seed);
+        generating += start.elapsed();
+
+        let start = Instant::now();
+        lifter.reset();
+        lifter.seed(seed);
+        lifter.do_lifting(&program);
+        lifter.finalize();
+        lifting += start.elapsed();
+
+        instructions += program.buffer.len();
+        bytes += lifter.get_code().len();
+    }
+    let (allocations, allocated) = stop_counting();
+
+    let count = std::cmp::max(count, 1) as f64;
+    let total = (generating + lifting).as_secs_f64();
+    println!("[+] {:.0} programs/s, of which {:.2}s were spent generating and \
+              {:.2}s lifting", count / total, generating.as_secs_f64(),
+             lifting.as_secs_f64());
+    println!("[+] {:.1} instructions and {:.0} bytes of JS per program",
+             instructions as f64 / count, bytes as f64 / count);
+    println!("[+] {:.0} allocations of {:.0} bytes in total per program",
+             allocations as f64 / count, allocated as f64 / count);
+}
@@ -0,0 +1,460 @@
+//! The corpus holds the programs that found new coverage in the target. These
+//! are the programs that the fuzzer mutates, so that it builds upon the code
+//! that already reaches interesting parts of the engine.
+//!
+//! The corpus can be backed by a directory, in which case each program is also
+//! saved there as `program_<id>.json`, holding its IR, next to the lifted
+//! `program_<id>.js`. The IR files are read back when the fuzzer restarts.
+//! Programs that can be converted to Fuzzilli's format are also saved as
+//! `program_<id>.fzil`. The files are written by a thread of their own, so that
+//! the fuzzer threads don't wait on the disk while they hold the corpus.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::ir::program::Program;
+use crate::ir::serialize::{encode_program, decode_program};
+use crate::ir::fuzzilli::{export_program, import_program};
+use crate::lifter::lifter::Lifter;
+use crate::utils::json::JsonValue;
+use crate::ir::instruction::Instruction;
+use crate::jsruntime::jsruntime::JSRuntime;
+use crate::utils::random::Random;
+use crate::{error, warn};
+use super::scheduler::{Scheduler, RarityScheduler};
+
+/// The extensions of the files that are saved for each entry
+const ENTRY_EXTENSIONS: [&str; 3] = ["json", "js", "fzil"];
+
+/// The max no. of programs that are kept in the corpus
+const MAX_CORPUS_SIZE: usize = 1000;
+
+/// A program in the corpus. Only the instructions are kept, as the program
+/// itself borrows the runtime. See [Corpus::get_program]. They are shared with
+/// the threads that sample the entry, so that the corpus doesn't have to stay
+/// locked while they rebuild the program.
+pub struct CorpusEntry {
+    /// A unique id for the entry, which is also the name of its files
+    pub id:           u64,
+
+    pub instructions: Arc<[Instruction]>,
+
+    /// The no. of edges that this program covered for the first time
+    pub new_edges:    usize,
+
+    /// The no. of times that this entry was picked for mutation. Sampling only
+    /// needs to read the corpus, so this is counted atomically.
+    pub selected:     AtomicU64,
+}
+
+impl CorpusEntry {
+    pub fn selected(&self) -> u64 {
+        self.selected.load(Ordering::Relaxed)
+    }
+}
+
+/// A change to the files of the corpus, see [CorpusWriter]
+enum FileChange {
+    /// Save a new entry with its id, instructions, new edges and lifted code
+    Save(u64, Arc<[Instruction]>, usize, String),
+
+    /// Remove the files of the entry with the id
+    Remove(u64),
+}
+
+/// Makes the changes to the files of the corpus on a thread of its own, in the
+/// order in which they were sent
+struct CorpusWriter {
+    sender: Sender<FileChange>,
+    thread: JoinHandle<()>,
+}
+
+impl CorpusWriter {
+
+    /// Start the thread that writes the entries to `dir`, where the next entry
+    /// is saved as `next_id`
+    fn new(dir: PathBuf, mut next_id: u64) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+
+            // Other runs of the fuzzer can save to the same directory, so the
+            // files of an entry may have to go under another id than its own
+            let mut file_ids = HashMap::<u64, u64>::new();
+
+            for change in receiver {
+                match change {
+                    FileChange::Save(id, instructions, new_edges, code) => {
+                        let mut file_id = std::cmp::max(id, next_id);
+                        while Corpus::entry_path(&dir, file_id, "json")
+                                    .exists() {
+                            file_id += 1;
+                        }
+                        next_id = file_id + 1;
+                        if file_id != id {
+                            file_ids.insert(id, file_id);
+                        }
+
+                        if let Err(err) = Corpus::write_entry(
+                            &dir, file_id, &instructions, new_edges, &code) {
+                            error!("Failed to save the corpus entry: {}", err);
+                        }
+                    },
+                    FileChange::Remove(id) => {
+                        let file_id = file_ids.remove(&id).unwrap_or(id);
+                        for extension in ENTRY_EXTENSIONS.iter() {
+                            let _ = fs::remove_file(
+                                Corpus::entry_path(&dir, file_id, extension));
+                        }
+                    },
+                }
+            }
+        });
+
+        Self { sender, thread }
+    }
+}
+
+pub struct Corpus {
+    /// The entries in the order in which they were added, so the most recent
+    /// ones are at the end
+    entries:   Vec<CorpusEntry>,
+    max_size:  usize,
+    next_id:   u64,
+
+    /// Saves the entries to the directory of the corpus, if it has one
+    writer:    Option<CorpusWriter>,
+
+    /// Picks the entries that are mutated
+    scheduler: Box<dyn Scheduler>,
+}
+
+impl Corpus {
+
+    pub fn new() -> Self {
+        Self {
+            entries:   Vec::new(),
+            max_size:  MAX_CORPUS_SIZE,
+            next_id:   0,
+            writer:    None,
+            scheduler: Box::new(RarityScheduler),
+        }
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Box<dyn Scheduler>) {
+        self.scheduler = scheduler;
+    }
+
+    /// Create a corpus that is saved to `dir` and load the programs that were
+    /// saved there before. Files that can't be read are skipped.
+    pub fn load(dir: &str) -> std::io::Result<Self> {
+        let mut corpus = Corpus::new();
+        fs::create_dir_all(dir)?;
+
+        let mut saved = Vec::new();
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            let id = path.file_name().and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("program_"))
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|id| id.parse::<u64>().ok());
+
+            if let Some(id) = id {
+                saved.push((id, path));
+            }
+        }
+
+        // Keep the order in which the entries were added
+        saved.sort_unstable();
+
+        for (id, path) in saved {
+            match Corpus::read_entry(id, &path) {
+                Ok(entry) => corpus.entries.push(entry),
+                Err(err)  => {
+                    warn!("Skipping corpus file {}: {}",
+                          path.display(), err);
+                }
+            }
+            corpus.next_id = id + 1;
+        }
+
+        // A corpus that grew past the limit, like one that was merged from
+        // other runs, keeps the entries that the corpus would have kept
+        corpus.writer = Some(CorpusWriter::new(PathBuf::from(dir),
+                                               corpus.next_id));
+        while corpus.entries.len() > corpus.max_size {
+            corpus.evict();
+        }
+        Ok(corpus)
+    }
+
+    /// Add the programs of the Fuzzilli corpus in `dir` that can be converted
+    /// to zebra's IR. Returns the no. of programs that were added.
+    pub fn import_fuzzilli(&mut self, dir: &str,
+                           jsruntime: &JSRuntime) -> std::io::Result<usize> {
+        let mut files = fs::read_dir(dir)?
+            .map(|file| file.map(|file| file.path()))
+            .collect::<std::io::Result<Vec<PathBuf>>>()?;
+        files.retain(|path| path.extension().is_some_and(|ext| ext == "fzil"));
+        files.sort_unstable();
+
+        let mut lifter = Lifter::new();
+        let mut imported = 0;
+        for path in files {
+            let instructions = match import_program(&fs::read(&path)?,
+                                                    jsruntime) {
+                Ok(instructions) => instructions,
+                Err(err) => {
+                    warn!("Skipping Fuzzilli program {}: {}",
+                          path.display(), err);
+                    continue;
+                }
+            };
+
+            let program = Program::from_instructions(jsruntime, instructions);
+            lifter.reset();
+            lifter.do_lifting(&program);
+            lifter.finalize();
+
+            self.add(&program, 0, lifter.get_code());
+            imported += 1;
+        }
+
+        Ok(imported)
+    }
+
+    pub fn entries(&self) -> &[CorpusEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add `program`, that was lifted to `code`, to the corpus. If the corpus
+    /// is full, then an entry is evicted to make space for it. The files of
+    /// the entry are saved later on, by the writer of the corpus.
+    pub fn add(&mut self, program: &Program, new_edges: usize, code: &str) {
+        if self.entries.len() >= self.max_size {
+            self.evict();
+        }
+
+        let entry = CorpusEntry {
+            id:           self.next_id,
+            instructions: program.buffer.as_slice().into(),
+            new_edges,
+            selected:     AtomicU64::new(0),
+        };
+        self.next_id += 1;
+
+        if let Some(writer) = &self.writer {
+            let _ = writer.sender.send(FileChange::Save(
+                entry.id, entry.instructions.clone(), new_edges,
+                code.to_string()));
+        }
+
+        self.entries.push(entry);
+    }
+
+    /// Pick an entry to mutate, as decided by the scheduler of the corpus, and
+    /// get its instructions. This only reads the corpus, and the instructions
+    /// are shared with the entry rather than copied.
+    pub fn sample(&self, rng: &mut Random) -> Option<Arc<[Instruction]>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let idx = self.scheduler.select(&self.entries, rng);
+        let entry = &self.entries[idx];
+        entry.selected.fetch_add(1, Ordering::Relaxed);
+        Some(entry.instructions.clone())
+    }
+
+    /// Rebuild a program from the instructions of `entry`
+    pub fn get_program<'a>(entry: &CorpusEntry,
+                           jsruntime: &'a JSRuntime) -> Program<'a> {
+        Program::from_instructions(jsruntime, entry.instructions.to_vec())
+    }
+
+    /// Wait for the files of all the entries that were added so far to be
+    /// saved. Nothing is saved after this.
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            drop(writer.sender);
+            let _ = writer.thread.join();
+        }
+    }
+
+    /// Remove the least useful entry from the corpus. That is the one with the
+    /// fewest new edges that was picked the most times, out of the older half
+    /// of the corpus.
+    pub fn evict(&mut self) {
+        let old = std::cmp::max(1, self.entries.len() / 2);
+        let victim = self.entries[..old].iter().enumerate()
+            .min_by_key(|(_, entry)| {
+                (entry.new_edges, std::cmp::Reverse(entry.selected()))
+            })
+            .map(|(idx, _)| idx);
+
+        if let Some(idx) = victim {
+            let entry = self.entries.remove(idx);
+
+            if let Some(writer) = &self.writer {
+                let _ = writer.sender.send(FileChange::Remove(entry.id));
+            }
+        }
+    }
+
+    /// The path of the file with `extension` of the entry `id` in `dir`
+    fn entry_path(dir: &Path, id: u64, extension: &str) -> PathBuf {
+        dir.join(format!("program_{}.{}", id, extension))
+    }
+
+    fn write_entry(dir: &Path, id: u64, instructions: &[Instruction],
+                   new_edges: usize, code: &str) -> std::io::Result<()> {
+        let saved = JsonValue::Object(vec![
+            ("new_edges".into(), JsonValue::Number(new_edges as f64)),
+            ("program".into(),   encode_program(instructions)),
+        ]);
+
+        let path = |extension| Corpus::entry_path(dir, id, extension);
+        fs::write(path("json"), saved.to_string())?;
+        fs::write(path("js"), code)?;
+
+        match export_program(instructions) {
+            Ok(data) => fs::write(path("fzil"), data),
+            Err(_)   => Ok(()),
+        }
+    }
+
+    fn read_entry(id: u64, path: &Path)
+                  -> Result<CorpusEntry, Box<dyn std::error::Error>> {
+        let saved = JsonValue::parse(&fs::read_to_string(path)?)?;
+        let program = match saved.get("program") {
+            Some(program) => decode_program(program)?,
+            None => return Err("Missing program".into()),
+        };
+        let new_edges = saved.get("new_edges").and_then(|n| n.as_number())
+                                              .unwrap_or(0.0);
+
+        Ok(CorpusEntry {
+            id,
+            instructions: program.into(),
+            new_edges:    new_edges as usize,
+            selected:     AtomicU64::new(0),
+        })
+    }
+}
+
+impl Drop for Corpus {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_corpus() {
+        let runtime = JSRuntime::new();
+        let mut corpus = Corpus::new();
+        let mut rng = Random::new(0);
+        assert!(corpus.sample(&mut rng).is_none());
+
+        for edges in 0..corpus.max_size + 1 {
+            let mut program = Program::new(&runtime);
+            program.load_int(edges as isize);
+            corpus.add(&program, edges, "");
+        }
+
+        // The entry that found no new edges went first
+        assert_eq!(corpus.len(), corpus.max_size);
+        assert!(corpus.entries.iter().all(|entry| entry.new_edges != 0));
+
+        let instructions = corpus.sample(&mut rng).unwrap();
+        assert_eq!(instructions.len(), 1);
+        let selected = corpus.entries.iter().map(CorpusEntry::selected);
+        assert_eq!(selected.sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn test_corpus_reload() {
+        let runtime = JSRuntime::new();
+        let dir = std::env::temp_dir().join(format!("zebra_corpus_{}",
+                                                    std::process::id()));
+        let dir = dir.to_str().unwrap();
+
+        let mut corpus = Corpus::load(dir).unwrap();
+        let mut program = Program::new(&runtime);
+        program.load_int(1337);
+        program.load_int(1);
+        corpus.add(&program, 5, "");
+        corpus.flush();
+
+        let mut corpus = Corpus::load(dir).unwrap();
+        assert_eq!(corpus.len(), 1);
+        assert_eq!(corpus.entries()[0].new_edges, 5);
+        let instructions = corpus.sample(&mut Random::new(0)).unwrap();
+        assert_eq!(instructions.len(), program.buffer.len());
+        assert_eq!(corpus.next_id, 1);
+
+        // Another run that saves to the same directory doesn't overwrite it,
+        // and removes the files that it saved under another id
+        let mut other = Corpus::load(dir).unwrap();
+        corpus.add(&program, 1, "");
+        corpus.flush();
+        other.add(&program, 2, "");
+        other.add(&program, 3, "");
+        other.evict();
+        other.evict();
+        other.flush();
+
+        let reloaded = Corpus::load(dir).unwrap();
+        let mut edges = reloaded.entries().iter()
+            .map(|entry| entry.new_edges)
+            .collect::<Vec<usize>>();
+        edges.sort_unstable();
+        assert_eq!(edges, vec![1, 3]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_corpus_load_limit() {
+        let runtime = JSRuntime::new();
+        let dir = std::env::temp_dir().join(format!("zebra_limit_{}",
+                                                    std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut program = Program::new(&runtime);
+        program.load_int(1);
+        let count = MAX_CORPUS_SIZE as u64 + 10;
+        for id in 0..count {
+            Corpus::write_entry(&dir, id, &program.buffer, 1, "").unwrap();
+        }
+
+        // The newest entries are kept, and the files of the others are gone
+        let mut corpus = Corpus::load(dir.to_str().unwrap()).unwrap();
+        corpus.flush();
+        assert_eq!(corpus.len(), MAX_CORPUS_SIZE);
+        assert_eq!(corpus.entries().last().unwrap().id, count - 1);
+        let saved = fs::read_dir(&dir).unwrap()
+            .filter(|file| {
+                file.as_ref().unwrap().path().extension().unwrap() == "json"
+            })
+            .count();
+        assert_eq!(saved, MAX_CORPUS_SIZE);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
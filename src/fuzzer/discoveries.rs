@@ -0,0 +1,114 @@
+//! The findings that are shared by all the fuzzer threads. Every thread runs its
+//! own target, which only knows about the edges that it covered itself. This
+//! keeps track of what the threads found together, so that a program is only
+//! added to the shared corpus when it found something that no thread had seen.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::execution::coverage::MAX_EDGES;
+
+pub struct Discoveries {
+    /// One bit for each edge found by any thread. Threads set the bits
+    /// atomically, so they never wait on each other here.
+    edges:   Vec<AtomicU64>,
+
+    /// The signals of the crashes that were seen so far
+    crashes: Mutex<HashSet<i32>>,
+
+    /// The signals and buckets of the crashes that were seen so far, see
+    /// [super::triage]
+    buckets: Mutex<HashSet<(i32, String)>>,
+}
+
+impl Discoveries {
+
+    pub fn new() -> Self {
+        Self {
+            edges:   (0..MAX_EDGES.div_ceil(64)).map(|_| AtomicU64::new(0))
+                                                .collect(),
+            crashes: Mutex::new(HashSet::new()),
+            buckets: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Record the `edges` that a thread found. Returns how many of them were
+    /// not found by any thread before.
+    pub fn add_edges(&self, edges: &[u32]) -> usize {
+        edges.iter().filter(|&&edge| {
+            let bit = 1 << (edge % 64);
+            match self.edges.get(edge as usize / 64) {
+                Some(word) => word.fetch_or(bit, Ordering::Relaxed) & bit == 0,
+                None => false,
+            }
+        }).count()
+    }
+
+    /// Record a crash with `signal`. Returns true if this is the first crash
+    /// with that signal.
+    pub fn add_crash(&self, signal: i32) -> bool {
+        self.crashes.lock().expect("Lock Poisoned").insert(signal)
+    }
+
+    /// Record a crash with `signal` that was sorted into `bucket`. Returns the
+    /// no. of different buckets that were seen so far.
+    pub fn add_bucket(&self, signal: i32, bucket: String) -> usize {
+        let mut buckets = self.buckets.lock().expect("Lock Poisoned");
+        buckets.insert((signal, bucket));
+        buckets.len()
+    }
+
+    /// All the edges that were found so far
+    pub fn edges(&self) -> Vec<u32> {
+        let mut edges = Vec::new();
+        for (idx, word) in self.edges.iter().enumerate() {
+            let mut bits = word.load(Ordering::Relaxed);
+            while bits != 0 {
+                edges.push((idx * 64) as u32 + bits.trailing_zeros());
+                bits &= bits - 1;
+            }
+        }
+        edges
+    }
+
+    /// The signals of all the crashes that were seen so far
+    pub fn crashes(&self) -> Vec<i32> {
+        let mut crashes = self.crashes.lock().expect("Lock Poisoned")
+            .iter().copied().collect::<Vec<i32>>();
+        crashes.sort();
+        crashes
+    }
+
+    /// The signals and buckets of all the crashes that were seen so far
+    pub fn buckets(&self) -> Vec<(i32, String)> {
+        let mut buckets = self.buckets.lock().expect("Lock Poisoned")
+            .iter().cloned().collect::<Vec<(i32, String)>>();
+        buckets.sort();
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Discoveries;
+
+    #[test]
+    fn test_discoveries() {
+        let discoveries = Discoveries::new();
+        assert_eq!(discoveries.add_edges(&[1, 64, 1000]), 3);
+        assert_eq!(discoveries.add_edges(&[1, 2, u32::MAX]), 1);
+
+        assert!(discoveries.add_crash(11));
+        assert!(!discoveries.add_crash(11));
+
+        assert_eq!(discoveries.add_bucket(11, "a".into()), 1);
+        assert_eq!(discoveries.add_bucket(11, "a".into()), 1);
+        assert_eq!(discoveries.add_bucket(6, "a".into()), 2);
+
+        assert_eq!(discoveries.edges(), vec![1, 2, 64, 1000]);
+        assert_eq!(discoveries.crashes(), vec![11]);
+        assert_eq!(discoveries.buckets(), vec![(6, "a".into()),
+                                               (11, "a".into())]);
+    }
+}
@@ -2,61 +2,628 @@
 //! common for all the Fuzzers. This includes the user preferences, JS Runtime
 //! constants and statistics.
 
-use std::sync::RwLock;
-use std::thread;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
 use crate::jsruntime::jsruntime::JSRuntime;
 use crate::cmdlineoptions::CmdLineOptions;
+use crate::profiles::profile::Profile;
+use crate::utils::log::log_to_file;
+use crate::{error, info, warn};
+use super::settings::{GENERATOR_NAMES, generators};
 use super::stats::Stats;
+use super::corpus::Corpus;
+use super::discoveries::Discoveries;
+use super::slowest::Slowest;
+use super::fuzzer::Fuzzer;
+use super::session::SessionState;
+use super::throughput::Throughput;
+use super::health::Health;
+use super::triage::{signal_name, bucket};
 
 /// This holds the data that will not change during the fuzzing runs like the
 /// user provided options, JS constants etc.
 pub struct FuzzGlobals {
     pub program_name: String,
     pub cmdline:      CmdLineOptions,
-    pub stats:        RwLock<Stats>,
+
+    /// The stats of the whole campaign, as of when the stats that the threads
+    /// sent were last added up, see [FuzzGlobals::stats]
+    stats:            Mutex<Stats>,
     pub jsruntime:    JSRuntime,
+    pub corpus:       RwLock<Corpus>,
+    pub discoveries:  Discoveries,
+
+    /// The profile of the engine that is fuzzed
+    pub profile:      Box<dyn Profile>,
+
+    /// The slowest programs that any thread ran
+    pub slowest:      Slowest,
+
+    /// The directory that the results of this run of the fuzzer are saved to
+    pub session_dir:  String,
+
+    /// The no. of programs that the threads started running so far
+    iterations:       AtomicU64,
+
+    /// Set when the campaign has to end. The threads stop after the program
+    /// that they are running right now.
+    stop:             AtomicBool,
+
+    /// When the globals were created, which the heartbeats are relative to
+    created:          Instant,
+
+    /// One for each of the fuzzer threads
+    workers:          Vec<Worker>,
+
+    /// How fast the programs ran lately, which the main thread samples
+    throughput:       Mutex<Throughput>,
+
+    /// Whether the campaign runs as fast as the cmdline asks for
+    health:           Mutex<Health>,
+
+    /// When the first and the last crash of this run were found
+    crash_times:      Mutex<Option<(Instant, Instant)>>,
+
+    /// The last [RECENT_CRASHES] crashes, with when they were found, by which
+    /// thread and the bucket that they went to
+    recent_crashes:   Mutex<VecDeque<(Instant, usize, String)>>,
+
+    /// Whether the stats are redrawn on a single screen instead of printed one
+    /// after the other
+    tui:              bool,
+}
+
+/// What the main thread knows about one of the fuzzer threads
+struct Worker {
+    /// When the thread last started a program, in ms since the globals were
+    /// created
+    heartbeat: AtomicU64,
+
+    /// The no. of times that the thread was started again after it died
+    restarts:  AtomicU64,
+
+    /// Where the random stream of the thread was at when it last sent its
+    /// stats, or 0 if it didn't yet
+    rng:       AtomicU64,
+
+    /// The programs that the thread ran, and how many of them crashed or timed
+    /// out, as of when it last sent its stats
+    execs:     AtomicU64,
+    crashes:   AtomicU64,
+    timeouts:  AtomicU64,
+
+    /// The no. of times that the targets of the thread were started again
+    engine_restarts: AtomicU64,
+
+    /// The stats that the thread sent since they were last added up. Each
+    /// thread has its own, so that the threads don't wait on each other to
+    /// send them.
+    stats:     Mutex<Stats>,
+}
+
+/// A thread is reported as stuck once it didn't start a program for this long.
+/// The timeouts of the programs that it may be running are added to this.
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The time in between two reports of the statistics
+const REPORT_INTERVAL: Duration = Duration::from_millis(3000);
+
+/// The no. of crashes that the screen shows
+const RECENT_CRASHES: usize = 5;
+
+/// The time in between two saves of the state of the session, see
+/// [super::session]
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+const SIGINT:  i32 = 2;
+const SIGTERM: i32 = 15;
+#[cfg(target_os = "linux")]
+const SIGUSR1: i32 = 10;
+#[cfg(not(target_os = "linux"))]
+const SIGUSR1: i32 = 30;
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn _exit(status: i32) -> !;
+}
+
+/// Whether the fuzzer got a SIGINT or a SIGTERM. It is [INTERRUPTED] until the
+/// main thread sees it and stops the campaign, and [STOPPING] from then on.
+static INTERRUPT: AtomicU8 = AtomicU8::new(0);
+const INTERRUPTED: u8 = 1;
+const STOPPING:    u8 = 2;
+
+/// Setting a flag is about all that can be done safely in a signal handler. A
+/// signal that comes after the campaign was stopped exits right away, in case
+/// the threads don't stop. The ones before that are taken as the same one, as
+/// the whole process group tends to get them at once.
+extern "C" fn on_interrupt(_signum: i32) {
+    if INTERRUPT.swap(INTERRUPTED, Ordering::SeqCst) == STOPPING {
+        unsafe { _exit(130) };
+    }
+}
+
+/// Set when the fuzzer got a SIGUSR1, until the main thread dumps the state
+static DUMP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_dump(_signum: i32) {
+    DUMP.store(true, Ordering::SeqCst);
+}
+
+/// Stop the campaign cleanly on a Ctrl-C or a SIGTERM, instead of dying in the
+/// middle of it, and dump the state of the campaign on a SIGUSR1
+pub fn handle_interrupts() {
+    unsafe {
+        signal(SIGINT, on_interrupt);
+        signal(SIGTERM, on_interrupt);
+        signal(SIGUSR1, on_dump);
+    }
 }
 
 impl FuzzGlobals {
 
     /// Initialize and create a new instance of the fuzzing global values.
-    pub fn new(name: String, cmdline: CmdLineOptions, jsruntime: JSRuntime)
-               -> Self {
+    pub fn new(name: String, cmdline: CmdLineOptions, jsruntime: JSRuntime,
+               corpus: Corpus, profile: Box<dyn Profile>,
+               session_dir: String) -> Self {
+
+        let slowest = Slowest::new(cmdline.keep_slowest);
+        let tui = !cmdline.no_tui && std::io::stdout().is_terminal();
+        let health = Health::new(
+            cmdline.min_fcps.map(|(fcps, minutes)| {
+                (fcps, Duration::from_secs(minutes * 60))
+            }),
+            cmdline.max_timeouts);
+        let workers = (0..cmdline.threads).map(|_| Worker {
+            heartbeat: AtomicU64::new(0),
+            restarts:  AtomicU64::new(0),
+            rng:       AtomicU64::new(0),
+            execs:     AtomicU64::new(0),
+            crashes:   AtomicU64::new(0),
+            timeouts:  AtomicU64::new(0),
+            engine_restarts: AtomicU64::new(0),
+            stats:     Mutex::new(Stats::default()),
+        }).collect();
 
         Self {
             program_name: name,
             cmdline:      cmdline,
-            stats:        RwLock::new(Stats::default()),
+            stats:        Mutex::new(Stats::default()),
             jsruntime:    jsruntime,
+            corpus:       RwLock::new(corpus),
+            discoveries:  Discoveries::new(),
+            slowest,
+            profile,
+            session_dir,
+            iterations:   AtomicU64::new(0),
+            stop:         AtomicBool::new(false),
+            created:      Instant::now(),
+            workers,
+            throughput:   Mutex::new(Throughput::new()),
+            health:       Mutex::new(health),
+            crash_times:  Mutex::new(None),
+            recent_crashes: Mutex::new(VecDeque::new()),
+            tui,
+        }
+    }
+
+    /// Start the fuzzer thread with `id`. It fuzzes until the campaign is
+    /// over.
+    pub fn spawn_worker(globals: &Arc<Self>, id: usize) -> JoinHandle<()> {
+        globals.heartbeat(id);
+        let globals = globals.clone();
+        thread::spawn(move || {
+            if globals.cmdline.log_files {
+                globals.open_log(id);
+            }
+            let mut fuzzer = Fuzzer::new(id, globals);
+            fuzzer.fuzzloop();
+        })
+    }
+
+    /// Send what the current thread, which is the one with `id`, logs to its
+    /// own file in the logs directory of the session. A thread that is
+    /// started again keeps adding to the same file.
+    fn open_log(&self, id: usize) {
+        let dir = format!("{}/logs", self.session_dir);
+        let file = fs::create_dir_all(&dir).and_then(|_| {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(format!("{}/thread_{}.log", dir, id))
+        });
+        match file {
+            Ok(file) => log_to_file(file),
+            Err(err) => error!("Failed to open the log file of thread {}: {}",
+                               id, err),
+        }
+    }
+
+    /// Called by the thread with `id` each time it starts a program, to show
+    /// that it still makes progress
+    pub fn heartbeat(&self, id: usize) {
+        let now = self.created.elapsed().as_millis() as u64;
+        self.workers[id].heartbeat.store(now, Ordering::Relaxed);
+    }
+
+    /// The no. of times that the thread with `id` was started again
+    pub fn restarts(&self, id: usize) -> u64 {
+        self.workers[id].restarts.load(Ordering::Relaxed)
+    }
+
+    /// Called by the thread with `id` when it sends its stats, with where its
+    /// random stream is at
+    pub fn save_rng(&self, id: usize, rng: u64) {
+        self.workers[id].rng.store(rng, Ordering::Relaxed);
+    }
+
+    /// Where the random stream of the thread with `id` was at when it last
+    /// sent its stats, if it did in this session or the one that was resumed
+    pub fn saved_rng(&self, id: usize) -> Option<u64> {
+        match self.workers[id].rng.load(Ordering::Relaxed) {
+            0   => None,
+            rng => Some(rng),
+        }
+    }
+
+    /// The state of the session for a campaign that began at `start`
+    fn session_state(&self, start: &Instant) -> SessionState {
+        SessionState {
+            seed:       self.cmdline.seed,
+            elapsed:    start.elapsed(),
+            iterations: self.iterations.load(Ordering::Relaxed),
+            stats:      self.stats().clone(),
+            edges:      self.discoveries.edges(),
+            crashes:    self.discoveries.crashes(),
+            buckets:    self.discoveries.buckets(),
+            rngs:       self.workers.iter()
+                            .map(|worker| worker.rng.load(Ordering::Relaxed))
+                            .collect(),
+        }
+    }
+
+    /// Save the state of the session to its directory, so that it can be
+    /// resumed from there
+    pub fn save_session(&self, start: &Instant) {
+        if let Err(err) = self.session_state(start).save(&self.session_dir) {
+            error!("Failed to save the state of the session: {}", err);
+        }
+    }
+
+    /// Go on from the `state` of a session that was saved before. This is done
+    /// before any of the threads start.
+    pub fn resume(&mut self, state: SessionState) {
+        self.iterations.store(state.iterations, Ordering::Relaxed);
+        *self.stats.get_mut().unwrap() = state.stats;
+        self.discoveries.add_edges(&state.edges);
+        for signal in state.crashes {
+            self.discoveries.add_crash(signal);
+        }
+        for (signal, bucket) in state.buckets {
+            self.discoveries.add_bucket(signal, bucket);
+        }
+        for (worker, rng) in self.workers.iter().zip(state.rngs) {
+            worker.rng.store(rng, Ordering::Relaxed);
+        }
+    }
+
+    /// The longest that a thread may go without starting a program before it
+    /// counts as stuck. A thread can be running a whole batch of programs that
+    /// all time out, and that on the target with the JIT turned off as well.
+    fn stall_limit(&self) -> Duration {
+        let programs = self.cmdline.reprl_batch as u32 + 1;
+        let programs = if self.cmdline.differential {
+            programs * 2
+        } else {
+            programs
+        };
+        STALL_TIMEOUT + Duration::from_millis(self.cmdline.timeout) * programs
+    }
+
+    /// Start any of the `threads` that died again, and report the ones that
+    /// stopped making progress. The ones in `stalled` were reported already,
+    /// so they are only reported again once they made progress in between.
+    fn supervise(globals: &Arc<Self>, threads: &mut [JoinHandle<()>],
+                 stalled: &mut [bool]) {
+        let now = globals.created.elapsed();
+        let limit = globals.stall_limit();
+        for (id, thread) in threads.iter_mut().enumerate() {
+            let worker = &globals.workers[id];
+
+            // A thread only finishes before the campaign is over when it
+            // panicked
+            if thread.is_finished() {
+                if globals.stopped() {
+                    return;
+                }
+
+                let count = worker.restarts.fetch_add(1, Ordering::Relaxed) + 1;
+                error!("Fuzzer thread {} died, starting it again \
+                        (restart {})", id, count);
+                let dead = std::mem::replace(thread,
+                                             Self::spawn_worker(globals, id));
+                let _ = dead.join();
+                stalled[id] = false;
+                continue;
+            }
+
+            let heartbeat = worker.heartbeat.load(Ordering::Relaxed);
+            let idle = now.saturating_sub(Duration::from_millis(heartbeat));
+            if idle < limit {
+                stalled[id] = false;
+            } else if !stalled[id] {
+                warn!("Fuzzer thread {} made no progress for {}s",
+                      id, idle.as_secs());
+                stalled[id] = true;
+            }
+        }
+    }
+
+    /// Called by the threads before they run a program. Returns false when the
+    /// campaign is over, either because it was stopped or because the
+    /// `--max-iterations` programs were already started. The programs are
+    /// counted either way, as the count is saved with the session.
+    pub fn next_iteration(&self) -> bool {
+        if self.stopped() {
+            return false;
         }
+
+        let iteration = self.iterations.fetch_add(1, Ordering::Relaxed);
+        if self.cmdline.max_iterations.is_some_and(|max| iteration >= max) {
+            self.iterations.fetch_sub(1, Ordering::Relaxed);
+            self.stop();
+            return false;
+        }
+        true
+    }
+
+    /// End the campaign
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Add the `stats` that the thread with `id` sends, and the no. of times
+    /// that its targets were started again since it last sent them, to the
+    /// counters of the thread
+    pub fn update_worker(&self, id: usize, stats: &Stats, engine_restarts: u64) {
+        let worker = &self.workers[id];
+        worker.execs.fetch_add(stats.iter, Ordering::Relaxed);
+        worker.crashes.fetch_add(stats.crashes, Ordering::Relaxed);
+        worker.timeouts.fetch_add(stats.timeouts, Ordering::Relaxed);
+        worker.engine_restarts.fetch_add(engine_restarts, Ordering::Relaxed);
+    }
+
+    /// Called by the thread with `id` when a program crashed with `signal`
+    pub fn record_crash(&self, id: usize, signal: i32, stderr: &str) {
+        let now = Instant::now();
+        {
+            let mut times = self.crash_times.lock().expect("Lock Poisoned");
+            let first = times.map_or(now, |(first, _)| first);
+            *times = Some((first, now));
+        }
+
+        let mut crashes = self.recent_crashes.lock().expect("Lock Poisoned");
+        if crashes.len() == RECENT_CRASHES {
+            crashes.pop_front();
+        }
+        crashes.push_back((now, id, format!("{}/{}", signal_name(signal),
+                                            bucket(stderr))));
     }
 
     /// Update the global store from the data that is collected by the worker
-    /// threads. TODO: Make this thread safe by adding a RW lock
-    pub fn update(&self, stats: &Stats ) {
+    /// thread with `id`. Only the main thread adds them up, see
+    /// [FuzzGlobals::stats].
+    pub fn update(&self, id: usize, stats: &Stats) {
+        self.workers[id].stats.lock().expect("Lock Poisoned").update(stats);
+    }
 
-        // All the updates are done in blocks of their own so that the lock is
-        // dropped when the write is done.
+    /// The stats of the whole campaign. The stats that the threads sent since
+    /// the last time are added up first.
+    fn stats(&self) -> MutexGuard<'_, Stats> {
+        let mut total = self.stats.lock().expect("Lock Poisoned");
+        for worker in &self.workers {
+            let mut stats = worker.stats.lock().expect("Lock Poisoned");
+            total.update(&stats);
+            stats.reset();
+        }
+        total
+    }
+
+
+    /// The loop that will run on the main thread. This loop prints out the
+    /// statistics to the screen every [REPORT_INTERVAL], watches over the
+    /// fuzzer `threads` and stops the campaign once it ran for `--max-time` or
+    /// was interrupted. It returns when the campaign is stopped.
+    pub fn mainloop(globals: &Arc<Self>, start: Instant,
+                    threads: &mut [JoinHandle<()>]) {
+
+        let mut reported = Instant::now();
+        let mut saved = Instant::now();
+        let mut stalled = vec![false; threads.len()];
+        while !globals.stopped() {
+            thread::sleep(Duration::from_millis(100));
+
+            if let Some(max) = globals.cmdline.max_time {
+                if start.elapsed().as_secs() >= max {
+                    globals.stop();
+                }
+            }
+
+            if INTERRUPT.compare_exchange(INTERRUPTED, STOPPING, Ordering::SeqCst,
+                                          Ordering::SeqCst).is_ok() {
+                info!("Interrupted, stopping once the running programs are \
+                       done. Press Ctrl-C again to exit right away");
+                globals.stop();
+            }
+
+            let (total, timeouts) = {
+                let stats = globals.stats();
+                (stats.iter, stats.timeouts)
+            };
+            let alert = {
+                let mut throughput = globals.throughput.lock().unwrap();
+                throughput.add(Instant::now(), total, timeouts);
+                globals.health.lock().unwrap().check(Instant::now(), &throughput)
+            };
+            if let Some(alert) = alert {
+                if globals.cmdline.abort_on_alert {
+                    error!("{}. Stopping the campaign", alert);
+                    globals.stop();
+                } else {
+                    warn!("!!! {} !!!", alert);
+                }
+            }
+
+            if DUMP.swap(false, Ordering::SeqCst) {
+                globals.dump_state(&start);
+            }
+
+            if !globals.stopped() {
+                Self::supervise(globals, threads, &mut stalled);
+            }
+
+            if reported.elapsed() >= REPORT_INTERVAL {
+                globals.print_stats(&start);
+                reported = Instant::now();
+            }
+
+            if saved.elapsed() >= SAVE_INTERVAL {
+                globals.save_session(&start);
+                saved = Instant::now();
+            }
+        }
+    }
+
+    /// The current statistics, the way they are printed
+    fn report(&self, start: &Instant) -> String {
+        let mut report = self.stats().report(start);
         {
-            let mut gstats = self.stats.write().expect("Lock Poisoned");
-            gstats.update(stats);
+            let throughput = self.throughput.lock().unwrap();
+            if let Some(rate) = throughput.rate() {
+                report += &format!("Recent fcps     = {:.0}/s over the last {} \
+                                    seconds\n", rate,
+                                   throughput.span().as_secs_f64().round());
+            }
         }
+        report += &format!("Corpus Size     = {}\n",
+                           self.corpus.read().unwrap().len());
 
+        if let Some((first, last)) = *self.crash_times.lock().unwrap() {
+            report += &format!("First Crash     = after {} seconds\n",
+                               first.saturating_duration_since(*start)
+                                    .as_secs());
+            report += &format!("Last Crash      = {} seconds ago\n",
+                               last.elapsed().as_secs());
+        }
+
+        let restarts = self.workers.iter()
+            .map(|worker| worker.restarts.load(Ordering::Relaxed))
+            .sum::<u64>();
+        if restarts > 0 {
+            report += &format!("Restarts        = {}\n", restarts);
+        }
+
+        // A thread that is much slower than the others, or that keeps having
+        // to start its engine again, stands out in here
+        if self.workers.len() > 1 {
+            report += &format!("{:<8}{:>12}{:>10}{:>10}{:>18}{:>18}\n",
+                               "Thread", "Execs", "Crashes", "Timeouts",
+                               "Engine Restarts", "Thread Restarts");
+            for (id, worker) in self.workers.iter().enumerate() {
+                let load = |count: &AtomicU64| count.load(Ordering::Relaxed);
+                report += &format!("{:<8}{:>12}{:>10}{:>10}{:>18}{:>18}\n", id,
+                                   load(&worker.execs), load(&worker.crashes),
+                                   load(&worker.timeouts),
+                                   load(&worker.engine_restarts),
+                                   load(&worker.restarts));
+            }
+        }
+        report
     }
 
+    /// Print out the current statistics. On a terminal, the screen is cleared
+    /// first, so that it only has the latest ones along with the last crashes.
+    pub fn print_stats(&self, start: &Instant) {
+        if !self.tui {
+            print!("{}", self.report(start));
+            return;
+        }
+
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        screen += &format!("zebra - {}\n", self.session_dir);
+        screen += &self.report(start);
+        let crashes = self.recent_crashes.lock().unwrap();
+        if !crashes.is_empty() {
+            screen += "\nRecent Crashes\n";
+            for (found, id, bucket) in crashes.iter().rev() {
+                screen += &format!("{:>6}s ago  thread {:<4} {}\n",
+                                   found.elapsed().as_secs(), id, bucket);
+            }
+        }
 
-    /// The loop that will run on the main thread. This loop only prints out the
-    /// statistics to the screen once every second
-    pub fn mainloop(&self, start: Instant) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(screen.as_bytes());
+        let _ = stdout.flush();
+    }
 
-        loop {
-            // The reporting is done once every second
-            thread::sleep(Duration::from_millis(3000));
+    /// Write the current statistics to `stats.txt` in the session directory,
+    /// along with the table of the generators
+    pub fn save_stats(&self, start: &Instant) {
+        let path = format!("{}/stats.txt", self.session_dir);
+        let stats = self.report(start).trim_start().to_string() +
+                    &self.generator_table();
+        if let Err(err) = fs::write(&path, stats) {
+            error!("Failed to save the stats to {}: {}", path, err);
+        }
+    }
+
+    /// A table with the weight of each of the generators, how often it was
+    /// called and failed, the instructions that it added, how many of the
+    /// programs that it added code to crashed or timed out, and how many of
+    /// them were checked and valid
+    fn generator_table(&self) -> String {
+        let mut table = format!("\n{:<35} {:>6} {:>10} {:>10} {:>12} {:>8} \
+                                 {:>8} {:>10} {:>10}\n", "Generator", "Weight",
+                                "Chosen", "Failed", "Instructions", "Crashes",
+                                "Timeouts", "Checked", "Valid");
+        let stats = self.stats();
+        for (idx, (name, (_, weight))) in GENERATOR_NAMES.iter()
+                                                         .zip(generators())
+                                                         .enumerate() {
+            let count = |counts: &[u64]| *counts.get(idx).unwrap_or(&0);
+            table += &format!("{:<35} {:>6} {:>10} {:>10} {:>12} {:>8} {:>8} \
+                               {:>10} {:>10}\n", name, weight,
+                              count(&stats.generator_chosen),
+                              count(&stats.generator_failed),
+                              count(&stats.generator_insts),
+                              count(&stats.generator_crashes),
+                              count(&stats.generator_timeouts),
+                              count(&stats.generator_checked),
+                              count(&stats.generator_valid));
+        }
+        table
+    }
 
-            // Print out the current statistics
-            self.stats.read().unwrap().print(&start);
+    /// Write the current statistics to `state.txt` in the session directory,
+    /// along with the table of the generators. The file is written again on
+    /// each dump.
+    fn dump_state(&self, start: &Instant) {
+        let state = self.report(start).trim_start().to_string() +
+                    &self.generator_table();
+        let path = format!("{}/state.txt", self.session_dir);
+        match fs::write(&path, state) {
+            Ok(())   => info!("Dumped the state of the campaign to {}", path),
+            Err(err) => error!("Failed to dump the state to {}: {}", path, err),
         }
     }
 
@@ -1,20 +1,52 @@
+use std::collections::HashSet;
 use std::fs::File;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::io::{self, Write};
+use std::time::{Duration, Instant};
 
 use crate::ir::program::Program;
-use crate::lifter::lifter::Lifter;
-use crate::execution::execution::{ReturnCode, Execution};
-use crate::execution::repl::ReplConnection;
+use crate::ir::instruction::Instruction;
+use crate::ir::codeanalysis::liveness::Liveness;
+use crate::ir::normalize::structural_hash;
+use crate::jsruntime::jsruntime::JSRuntime;
+use crate::lifter::lifter::{Lifter, find_digest};
+use crate::execution::execution::{ReturnCode, Execution, StartupError};
+use crate::execution::repl::{self, ReplConnection};
 use crate::execution::spawn::Spawn;
+use crate::execution::forkserver::ForkServer;
+use crate::execution::sanitizer::{self, is_sanitized, report_type};
+use crate::utils::random::{Random, derive_seed, timestamp};
+use crate::utils::probablity::Probablity;
+use crate::cmdlineoptions::CmdLineOptions;
+use crate::ir::serialize::{encode_program, encode_types};
+use crate::utils::json::JsonValue;
+use crate::profiles::profile::Profile;
+use crate::{error, warn, info};
 
 use super::stats::Stats;
 use super::fuzz_globals::FuzzGlobals;
+use super::settings::{MUTATORS, mutator_weights};
+use super::corpus::Corpus;
+use super::triage::{signal_name, bucket, UNKNOWN_BUCKET};
+use super::timeout::AdaptiveTimeout;
 
 /// The amount of iterations after which we should update the statistics of each
 /// thread on to the `Globals` stat
 const REPORT_INTERVEL: u64 = 10;
 
+/// The probablity with which we mutate an entry of the corpus instead of
+/// generating a fresh program, once the corpus has entries to mutate.
+const MUTATION_PROBABLITY: f64 = 0.9;
+
+/// The no. of programs that `--dedup` remembers the hashes of. They are all
+/// forgotten once there are this many, so that a long campaign doesn't hold
+/// on to all of them.
+const DEDUP_WINDOW: usize = 1 << 16;
+
+/// The no. of programs in a row that `--dedup` skips, after which the next one
+/// runs even if it ran before
+const MAX_DUPLICATES: usize = 10;
+
 /// Create `filename` and write `data` to it
 pub fn write_file(filename: &str, data: &String) -> io::Result<()> {
     let mut file = File::create(filename)?;
@@ -23,79 +55,443 @@ pub fn write_file(filename: &str, data: &String) -> io::Result<()> {
 }
 
 pub struct Fuzzer {
-    id:         u8,
+    id:         usize,
     stats:      Stats,
     lifter:     Lifter,
     globals:    Arc<FuzzGlobals>,
     exec:       Box<dyn Execution>,
+
+    /// The target with its JIT turned off, that the programs are run on again
+    /// in the differential mode
+    reference:  Option<Box<dyn Execution>>,
+
+    /// The target in a mode where it only compiles the programs, to check if
+    /// they are syntactically valid
+    syntax_checker: Option<Box<dyn Execution>>,
+
+    /// The timeout that is derived from the execution times of the programs
+    /// with `--adaptive-timeout`
+    adaptive_timeout: Option<AdaptiveTimeout>,
+
+    /// The no. of programs that are sent to the target at once
+    batch:      usize,
+    prob:       Probablity,
+
+    /// The seed of the stream of the thread, and the no. of programs that it
+    /// took out of it so far. The seed of each program is derived from both,
+    /// so the programs don't depend on what else the thread drew at random.
+    stream:     u64,
+    programs:   u64,
+
+    /// The seed of the program that is being run right now
+    seed:       u64,
+
+    /// Whether the program that is being run right now is a mutation of a
+    /// corpus entry. Only programs that are not can be generated again from
+    /// their seed alone.
+    mutated:    bool,
+
+    /// The no. of times that the targets of this thread were started again,
+    /// as of when the stats were last sent
+    engine_restarts: u64,
+
+    /// The structural hashes of the programs that ran lately, with `--dedup`
+    recent:     HashSet<u64>,
+
+    /// The programs that ran successfully on this thread, when the target
+    /// doesn't report coverage. These are too many to share with the other
+    /// threads or save, so they are only kept in memory for this thread to
+    /// mutate.
+    local:      Corpus,
+}
+
+/// Get the bucket of a crash of `program` with `stderr`, see [bucket]. The
+/// crashes that leave nothing in the stderr to tell them apart by are put in
+/// a bucket of the structure of the program instead, so that the same program
+/// crashing again ends up next to itself.
+fn crash_bucket(program: &Program, stderr: &str) -> String {
+    match bucket(stderr) {
+        bucket if bucket == UNKNOWN_BUCKET => {
+            format!("program_{:016x}", structural_hash(&program.buffer))
+        },
+        bucket => bucket,
+    }
+}
+
+/// Start up the target that is given in `cmdline` with the args of its
+/// `profile`, in the mode that it asks for. Each running target needs a unique
+/// `id`. The fuzzer stops if the target fails to start up.
+pub fn create_executor(id: usize, cmdline: &CmdLineOptions,
+                       profile: &dyn Profile) -> Box<dyn Execution> {
+    expect_started(start_target(id, cmdline, profile, true))
+}
+
+/// Get the target out of `started`, or stop the fuzzer if it failed to start
+/// up. The engine was already checked before the fuzzer started, so this only
+/// happens if something changed since then.
+fn expect_started(started: Result<Box<dyn Execution>, StartupError>)
+    -> Box<dyn Execution> {
+    match started {
+        Ok(exec) => exec,
+        Err(err) => {
+            error!("Failed to start the target! {}", err);
+            std::process::exit(-1);
+        }
+    }
+}
+
+/// Start up the target like [create_executor], with its JIT turned off unless
+/// `jit` is set. Returns why it failed if it does not start up.
+pub fn start_target(id: usize, cmdline: &CmdLineOptions, profile: &dyn Profile,
+                    jit: bool) -> Result<Box<dyn Execution>, StartupError> {
+
+    let mut args = profile.get_args().clone();
+    if !jit {
+        args.extend(profile.get_no_jit_args());
+    }
+    let args = engine_args(cmdline, args);
+
+    let sanitized = target_sanitized(cmdline);
+    let env = target_environment(cmdline, sanitized);
+    if cmdline.forkserver {
+        Ok(Box::new(ForkServer::new(id, cmdline.filename.to_string(), args,
+                                    env, cmdline.timeout,
+                                    cmdline.memory_limit(), sanitized)?))
+    } else if cmdline.disk || !profile.supports_reprl() {
+        Ok(Box::new(Spawn::new(input_dir(cmdline, id),
+                               cmdline.filename.to_string(), args, env,
+                               cmdline.timeout, cmdline.memory_limit(),
+                               sanitized)))
+    } else {
+        let mut repl = ReplConnection::new(id, cmdline.filename.to_string(),
+                                           args, env, cmdline.timeout,
+                                           cmdline.memory_limit(), sanitized)?;
+        let restart_rss = cmdline.restart_rss
+            .map(|rss| rss.saturating_mul(1 << 20));
+        repl.set_restart(cmdline.restart_every, restart_rss);
+        Ok(Box::new(repl))
+    }
+}
+
+/// The directory in the `--output-dir` that the target with `id` gets the
+/// inputs in when they are run from disk. Fuzzers that share the output
+/// directory each have their own.
+fn input_dir(cmdline: &CmdLineOptions, id: usize) -> String {
+    format!("{}/tmp/{}_{}", cmdline.output_dir, std::process::id(), id)
+}
+
+/// Put the `--engine-arg`s of `cmdline` after the `args` of the profile, so
+/// that they win over the ones of the profile
+fn engine_args(cmdline: &CmdLineOptions, args: Vec<&str>) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string())
+        .chain(cmdline.engine_args.iter().cloned())
+        .collect()
+}
+
+/// The environment variables to start the target with. These are the ones of
+/// `--env`, along with the options of the sanitizers if the target is
+/// `sanitized`.
+fn target_environment(cmdline: &CmdLineOptions,
+                      sanitized: bool) -> Vec<(String, String)> {
+    if sanitized {
+        sanitizer::environment(&cmdline.env, cmdline.memory_limit())
+    } else {
+        cmdline.env.clone()
+    }
+}
+
+/// Check if the target is built with sanitizers. All the threads run the same
+/// target, so only look for the sanitizers in it once.
+fn target_sanitized(cmdline: &CmdLineOptions) -> bool {
+    static SANITIZED: OnceLock<bool> = OnceLock::new();
+    *SANITIZED.get_or_init(|| {
+        let sanitized = is_sanitized(&cmdline.filename);
+        if sanitized {
+            info!("The target is built with sanitizers");
+        }
+        sanitized
+    })
+}
+
+/// Generate a fresh program whose instructions only depend on `seed` and the
+/// options of `cmdline`, so that the program can be generated again when
+/// replaying a crash
+pub fn generate_program<'a>(jsruntime: &'a JSRuntime, cmdline: &CmdLineOptions,
+                            seed: u64) -> Program<'a> {
+    let mut program = Program::new(jsruntime);
+    regenerate_program(&mut program, cmdline, seed);
+    program
+}
+
+/// Generate the program of [generate_program] in place of `program`, reusing
+/// its memory
+pub fn regenerate_program(program: &mut Program, cmdline: &CmdLineOptions,
+                          seed: u64) {
+    program.reset();
+    program.seed(seed);
+    program.allow_eval = cmdline.eval;
+    program.max_insts = cmdline.max_insts;
+    program.missing_props = cmdline.missing_props;
+    program.generate_random_insts(cmdline.min_insts);
 }
 
 impl Fuzzer {
-    pub fn new(id: u8, globals: Arc<FuzzGlobals>) -> Self {
-
-        let mut args = vec![
-                "--baseline-warmup-threshold=10",
-                "--ion-warmup-threshold=100",
-                "--ion-check-range-analysis",
-                "--ion-extra-checks",
-                "--fuzzing-safe",
-        ];
+    pub fn new(id: usize, globals: Arc<FuzzGlobals>) -> Self {
+
+        let cmdline = &globals.cmdline;
+        let profile = globals.profile.as_ref();
+        let exec = create_executor(id, cmdline, profile);
+
+        // The coverage regions are named after the id, so the reference target
+        // needs one that no thread uses
+        let reference = if cmdline.differential {
+            Some(expect_started(start_target(cmdline.threads + id, cmdline,
+                                             profile, false)))
+        } else {
+            None
+        };
 
-        if !globals.cmdline.disk {
-            args.push("--reprl");
+        // Only a few of the programs are checked, so start the target anew for
+        // each of them rather than keeping a second target around in memory
+        let compile_only = profile.get_compile_only_args()
+            .filter(|_| cmdline.check_syntax > 0.0);
+        if cmdline.check_syntax > 0.0 && compile_only.is_none() && id == 0 {
+            warn!("The engine can't check the syntax of the programs");
         }
+        let syntax_checker = compile_only.map(|args| {
+            let sanitized = target_sanitized(cmdline);
+            Box::new(Spawn::new(input_dir(cmdline, id),
+                                cmdline.filename.to_string(),
+                                engine_args(cmdline, args),
+                                target_environment(cmdline, sanitized),
+                                cmdline.timeout, cmdline.memory_limit(),
+                                sanitized))
+                as Box<dyn Execution>
+        });
+        let mut lifter = Lifter::new();
+        lifter.set_digest(globals.cmdline.digest);
+        lifter.set_comments(globals.cmdline.ir_comments);
+        lifter.set_literal_forms(globals.cmdline.literal_forms);
+        lifter.set_iife_probability(globals.cmdline.iife_probability);
+        lifter.set_declarations(globals.cmdline.declarations);
+        lifter.set_realistic_names(globals.cmdline.realistic_names);
+        lifter.set_strip_dead_code(globals.cmdline.strip_dead_code);
+        lifter.set_epilogue(profile.get_epilogue());
+
+        let adaptive_timeout = if cmdline.adaptive_timeout {
+            Some(AdaptiveTimeout::new(
+                Duration::from_millis(cmdline.timeout)))
+        } else {
+            None
+        };
 
-        let exec: Box<dyn Execution> = if globals.cmdline.disk {
-            Box::new(Spawn::new(globals.cmdline.filename.to_string(),
-                                args, globals.cmdline.timeout as u32))
+        // Batches need a REPRL target, and the programs that are checked
+        // against the reference target need to run on their own
+        let batch = if cmdline.disk || cmdline.forkserver ||
+            !profile.supports_reprl() || cmdline.differential ||
+            cmdline.dry_run {
+            1
         } else {
-            Box::new(ReplConnection::new(globals.cmdline.filename.to_string(),
-                                         args, globals.cmdline.timeout as u32))
+            cmdline.reprl_batch
+        };
+
+        // Each thread gets its own stream of random numbers out of the seed. A
+        // thread that is started again after it died gets a new one, so that
+        // it doesn't run into the same program again. A thread of a session
+        // that is resumed goes on with the seed that its stream was at.
+        let seed = derive_seed(globals.cmdline.seed, id as u64);
+        let seed = match (globals.restarts(id), globals.saved_rng(id)) {
+            (0, Some(rng)) => rng,
+            (0, None)      => seed,
+            (restarts, _)  => derive_seed(seed, restarts << 32),
         };
 
         Self {
             id:         id,
             stats:      Stats::default(),
-            lifter:     Lifter::new(),
+            lifter,
             globals:    globals,
             exec:       exec,
+            reference,
+            syntax_checker,
+            adaptive_timeout,
+            batch,
+            prob:       Probablity::new(Random::new(derive_seed(seed, 0))),
+            stream:     derive_seed(seed, 1),
+            programs:   0,
+            seed:       0,
+            mutated:    false,
+            engine_restarts: 0,
+            recent:     HashSet::new(),
+            local:      Corpus::new(),
         }
     }
 
     /// The fuzzing front end that will call the fuzz_one function and update
-    /// the global data
+    /// the global data, until the campaign is over
     pub fn fuzzloop(&mut self) {
-        loop {
 
-            for _ in 0..REPORT_INTERVEL {
+        // The same programs are used for all of the rounds, so that their
+        // memory is only allocated once. The parent of a mutation is loaded
+        // into one of them and mutated into the other. The programs borrow the
+        // runtime, so hold on to a handle of the globals that is separate from
+        // the one on the fuzzer itself.
+        let globals = self.globals.clone();
+        let mut program = Program::new(&globals.jsruntime);
+        let mut parent = Program::new(&globals.jsruntime);
+
+        while self.globals.next_iteration() {
+            self.globals.heartbeat(self.id);
+
+            // Perform one round of fuzzing
+            if self.batch > 1 {
+                self.fuzz_batch(&mut parent);
+            } else {
+                self.fuzz_one(&mut program, &mut parent);
+            }
 
-                // Perform one round of fuzzing
-                self.fuzz_one();
+            // If this is a dry run then just exit here
+            if self.globals.cmdline.dry_run {
+                return;
+            }
 
-                // If this is a dry run then just exit here
-                if self.globals.cmdline.dry_run {
-                    return;
-                }
+            if self.stats.iter >= REPORT_INTERVEL {
+                self.send_stats();
+            }
+        }
+
+        // Don't lose the programs that ran since the last update
+        self.send_stats();
+    }
+
+    /// Update the stats of this thread to the global pool, along with the
+    /// counters of the thread itself, and reset the thread local stats
+    fn send_stats(&mut self) {
+        let restarts = [Some(&self.exec), self.reference.as_ref(),
+                        self.syntax_checker.as_ref()].into_iter()
+            .flatten()
+            .map(|exec| exec.restarts())
+            .sum::<u64>();
+        self.globals.update_worker(self.id, &self.stats,
+                                   restarts - self.engine_restarts);
+        self.engine_restarts = restarts;
+
+        self.globals.update(self.id, &self.stats);
+        self.globals.save_rng(self.id, derive_seed(self.stream, self.programs));
+        self.stats.reset();
+    }
 
+    /// The core fuzzing logic. This function performs one round of fuzzing on
+    /// the target binary. The program that is run is either a mutation of an
+    /// entry of the corpus or a freshly generated one. If it finds new
+    /// coverage, then it is added to the corpus. The program is built in
+    /// place of `program`, with the help of `parent`.
+    fn fuzz_one(&mut self, program: &mut Program, parent: &mut Program) {
+        self.next_program(program, parent);
+        self.run(program);
+    }
+
+    /// Fuzz like [Fuzzer::fuzz_one], but send up to `--reprl-batch` programs to
+    /// the target at once. The target sends back the status of each of them.
+    /// When any of them crashes, times out or covers new edges, all of them are
+    /// run again one by one, as the target can't tell which of them it was.
+    fn fuzz_batch(&mut self, parent: &mut Program) {
+
+        let globals = self.globals.clone();
+        let mut programs = Vec::with_capacity(self.batch);
+        let mut code = Vec::with_capacity(self.batch);
+        let mut size = 0;
+
+        // A program that doesn't fit in the shared memory with the others is
+        // run on its own after them
+        let mut left_over = None;
+        loop {
+            let mut program = Program::new(&globals.jsruntime);
+            self.next_program(&mut program, parent);
+            let len = self.lifter.get_code().len();
+            if !programs.is_empty() && size + len >= repl::MAX_SIZE {
+                left_over = Some((program, self.seed, self.mutated));
+                break;
+            }
+
+            size += len;
+            programs.push((program, self.seed, self.mutated));
+            code.push(self.lifter.get_code().to_string());
+            if programs.len() == self.batch || !globals.next_iteration() {
+                break;
             }
+        }
 
-            // Update the stats of this thread to the global pool
-            self.globals.update(&self.stats);
+        let start = Instant::now();
+        if let Some(statuses) = self.exec.execute_batch(&code) {
+            // Only the time of the whole batch is known, so each program is
+            // taken to have run for the same time. It is not enough to tell
+            // which of them are slow.
+            let time = start.elapsed() / programs.len() as u32;
+            let batch = programs.iter().zip(code.iter()).zip(statuses);
+            for (((program, seed, mutated), code), status) in batch {
+                self.stats.add_exec_time(time);
+                self.add_execution_time(time);
+
+                if status == 0 {
+                    // Without coverage feedback every successful program is
+                    // kept
+                    if !self.exec.has_coverage() {
+                        self.local.add(program, 0, code);
+                    }
+                } else {
+                    // It may have to be run again or saved, which is done
+                    // with the code in the lifter
+                    self.lift(program, *seed, *mutated);
+                    self.count_incorrect(program, status);
+                }
+            }
+            self.stats.iter += programs.len() as u64;
+            programs.clear();
+        }
 
-            // Reset the thread local stats
-            self.stats.reset();
+        for (program, seed, mutated) in programs.into_iter().chain(left_over) {
+            self.lift(&program, seed, mutated);
+            self.run(&program);
         }
     }
 
-    /// The core fuzzing logic. This function performs one round of fuzzing on
-    /// the target binary.
-    fn fuzz_one(&mut self) {
+    /// Lift `program` again, which was built from `seed` and is `mutated` or
+    /// not, after the lifter moved on to other programs
+    fn lift(&mut self, program: &Program, seed: u64, mutated: bool) {
+        self.seed = seed;
+        self.mutated = mutated;
+        self.lifter.reset();
+        self.lifter.seed(seed);
+        self.lifter.do_lifting(program);
+        self.lifter.finalize();
+    }
+
+    /// Pick the next program to run, build it in place of `program` and lift
+    /// it. The syntax of some of the programs is checked on the way.
+    fn next_program(&mut self, program: &mut Program, parent: &mut Program) {
 
-        let mut program = Program::new(&self.globals.jsruntime);
         self.lifter.reset();
 
-        // Create an IR with at least 10 instructions
-        program.generate_random_insts(5);
+        // With `--dedup` the programs that are the same as one that ran lately
+        // are skipped, unless there are too many of them in a row
+        for _ in 0..MAX_DUPLICATES {
+            self.build_program(program, parent);
+            if !self.globals.cmdline.dedup {
+                break;
+            }
+            if self.recent.len() >= DEDUP_WINDOW {
+                self.recent.clear();
+            }
+            if self.recent.insert(structural_hash(&program.buffer)) {
+                break;
+            }
+            self.stats.duplicates += 1;
+        }
+
+        let liveness = Liveness::analyze(&program.buffer);
+        self.stats.insts += program.buffer.len() as u64;
+        self.stats.dead_insts += liveness.dead_count() as u64;
 
         // Now lift that IR into JavaScript
         self.lifter.do_lifting(program);
@@ -103,17 +499,96 @@ impl Fuzzer {
         // Finalize the JS code. No more additions to the code will be done
         self.lifter.finalize();
 
+        if self.sample(self.globals.cmdline.check_syntax) {
+            self.check_syntax(program);
+        }
+        self.stats.add_generator_calls(&program.generator_calls);
+    }
+
+    /// Build the next program of the stream of this thread in place of
+    /// `program`. A mutation loads its parent into `parent` first.
+    fn build_program(&mut self, program: &mut Program,
+                     parent: &mut Program) {
+
+        // All the random choices that are made for this program are derived
+        // from its seed, so that the seed is enough to generate it again. That
+        // includes whether it is a mutation and of which entry, so that the
+        // same seed picks the same parent out of the same corpus.
+        let seed = derive_seed(self.stream, self.programs);
+        self.programs += 1;
+        self.seed = seed;
+        self.lifter.seed(seed);
+        let mut prob = Probablity::new(Random::new(derive_seed(seed, 4)));
+
+        // The parent is loaded into `parent`, and the mutated program is built
+        // from it in place of `program`. The corpus is only locked to sample
+        // the parent, and the program is rebuilt from its instructions after
+        // that.
+        let instructions = if prob.probablity(MUTATION_PROBABLITY) {
+            self.sample_parent(&mut prob.0)
+        } else {
+            None
+        };
+
+        self.mutated = if let Some(instructions) = instructions {
+            parent.load(&instructions);
+            parent.seed(seed);
+            parent.max_insts = self.globals.cmdline.max_insts;
+            let idx = prob.choose_from_table(mutator_weights());
+            let mutator = MUTATORS[idx].0;
+            let mut rng = Random::new(derive_seed(seed, 3));
+            if mutator.takes_donor() {
+                // The donor is another entry of the corpus, which may well be
+                // the parent itself if the corpus is small
+                let donor = self.sample_parent(&mut prob.0);
+                donor.is_some_and(|donor| {
+                    mutator.mutate_with_into(parent, &donor, program, &mut rng)
+                })
+            } else {
+                mutator.mutate_into(parent, program, &mut rng)
+            }
+        } else {
+            false
+        };
+
+        if !self.mutated {
+            regenerate_program(program, &self.globals.cmdline, seed);
+        }
+    }
+
+    /// Sample the instructions of an entry to mutate. Half of them come from
+    /// the programs of this thread that ran successfully, if it keeps any, and
+    /// the others from the shared corpus.
+    fn sample_parent(&self, rng: &mut Random) -> Option<Arc<[Instruction]>> {
+        if !self.local.is_empty() && rng.rand_idx(2) == 0 {
+            return self.local.sample(rng);
+        }
+
+        self.globals.corpus.read().expect("Lock Poisoned").sample(rng)
+            .or_else(|| self.local.sample(rng))
+    }
+
+    /// Run the program that was lifted last and add it to the corpus if it is
+    /// interesting
+    fn run(&mut self, program: &Program) {
+
         // Execute the program and handle how it returns
-        self.execute();
+        if let Some(new_edges) = self.execute(program) {
+            self.globals.corpus.write().expect("Lock Poisoned")
+                               .add(program, new_edges, self.lifter.get_code());
+        }
 
         // Update the stats
         self.stats.iter += 1;
-
     }
 
-    /// Executes the JS program passed. Returns true if the program crashed,
-    /// else returns false
-    fn execute(&mut self) {
+    /// Executes the JS that `ir` was lifted to. Returns the no. of new edges
+    /// that it covered if it should be added to the corpus. Without coverage
+    /// feedback, every program that runs to completion without any errors is
+    /// kept in the corpus of this thread instead.
+    /// Programs that crash with a signal that no thread saw before are added
+    /// as well, so that all the threads get to mutate them.
+    fn execute(&mut self, ir: &Program) -> Option<usize> {
 
 
         let program = self.lifter.get_code();
@@ -133,6 +608,9 @@ impl Fuzzer {
         // }
 
         if self.globals.cmdline.dry_run {
+            if self.globals.cmdline.print_ir {
+                println!("{}", ir.print_ir());
+            }
             println!("{}", self.lifter.get_code());
         }
 
@@ -162,31 +640,298 @@ impl Fuzzer {
 
         // };
 
-        let return_code = self.exec.execute(program);
+        let result = self.exec.execute(program);
+        if let ReturnCode::Status(_) = result.code {
+            let time = result.duration;
+            if let Some(peak_rss) = result.peak_rss {
+                self.stats.peak_rss = std::cmp::max(self.stats.peak_rss,
+                                                    peak_rss);
+            }
+            self.stats.add_exec_time(time);
+            self.add_execution_time(time);
+            if self.globals.slowest.is_slow(time) {
+                self.save_slow(ir, time);
+            }
+        }
+
+        // The target only knows about the edges that this thread found, so
+        // check which ones are new to all the threads
+        let new_edges = self.globals.discoveries.add_edges(&result.new_edges);
+        self.stats.edges += new_edges as u64;
 
-        match return_code {
+        let mut new_crash = false;
+
+        let success = match result.code {
             ReturnCode::Timeout => {
                 self.stats.timeouts += 1;
+                self.stats.add_finding(&ir.generators, false);
+                if self.sample(self.globals.cmdline.save_timeouts) {
+                    let timeout = self.globals.cmdline.timeout;
+                    self.archive(ir, "timeout",
+                                 format!("Timed out after {}ms", timeout),
+                                 vec![("timeout_ms".into(),
+                                       JsonValue::Number(timeout as f64))]);
+                }
+                false
             },
             ReturnCode::Status(code) => {
                 if code != 0 {
-                    self.stats.incorrect += 1;
+                    self.count_incorrect(ir, code);
+                } else {
+                    self.differential(ir, &result.output);
                 }
+                code == 0
+            },
+            ReturnCode::Oom => {
+                self.stats.ooms += 1;
+                false
             },
             ReturnCode::Crash(signal) => {
-                self.save(signal);
+                self.save(ir, signal, &result.stderr);
                 self.stats.crashes += 1;
+                self.stats.add_finding(&ir.generators, true);
+                self.globals.record_crash(self.id, signal, &result.stderr);
+                new_crash = self.globals.discoveries.add_crash(signal);
+                let bucket = crash_bucket(ir, &result.stderr);
+                self.check_crash_limit(signal, bucket);
+                false
+            }
+        };
+
+        if !self.exec.has_coverage() && success && !new_crash {
+            self.local.add(ir, 0, self.lifter.get_code());
+        }
+
+        if new_edges > 0 || new_crash {
+            Some(new_edges)
+        } else {
+            None
+        }
+    }
+
+    /// Write the program that was run last to `filename`.js, with the lines of
+    /// `comment` after it. Along with the JS, the IR and the types of its
+    /// variables are saved in `filename`.json next to the `fields`, so that the
+    /// program can be minimized or lifted again later.
+    fn write_program(&self, filename: &str, program: &Program, comment: &str,
+                     mut fields: Vec<(String, JsonValue)>) -> io::Result<()> {
+        let mut tosave = format!("{}\n\n", self.lifter.get_code());
+        for line in comment.lines() {
+            tosave.push_str(&format!("// {}\n", line));
+        }
+        write_file(&format!("{}.js", filename), &tosave)?;
+
+        fields.extend(vec![
+            // Too large to be kept exactly as a JSON number
+            ("seed".into(),    JsonValue::String(self.seed.to_string())),
+            ("mutated".into(), JsonValue::Bool(self.mutated)),
+            ("program".into(), encode_program(&program.buffer)),
+            ("types".into(),   encode_types(program)),
+        ]);
+        write_file(&format!("{}.json", filename),
+                   &JsonValue::Object(fields).to_string())
+    }
+
+    /// Add the `time` that a program took to run to completion to the adaptive
+    /// timeout, and pass the timeout that is derived from that on to the
+    /// target every now and then
+    fn add_execution_time(&mut self, time: Duration) {
+        let adaptive_timeout = match self.adaptive_timeout.as_mut() {
+            Some(adaptive_timeout) => adaptive_timeout,
+            None                   => return,
+        };
+
+        if adaptive_timeout.add(time) {
+            let timeout = adaptive_timeout.timeout().as_millis();
+            self.exec.set_timeout(timeout as u64);
+        }
+    }
+
+    /// Count the program that was lifted last, `ir`, which exited with the
+    /// non-zero status `code`, as unstable or incorrect. Some of the incorrect
+    /// ones are saved.
+    fn count_incorrect(&mut self, ir: &Program, code: i32) {
+        if !self.is_stable(code) {
+            self.stats.unstable += 1;
+            return;
+        }
+
+        self.stats.incorrect += 1;
+        if self.sample(self.globals.cmdline.save_incorrect) {
+            self.archive(ir, "incorrect",
+                         format!("Exited with Status: {}", code),
+                         vec![("status".into(),
+                               JsonValue::Number(code as f64))]);
+        }
+    }
+
+    /// Run the program that just exited with the status `code` again, as often
+    /// as the `--recheck-incorrect` option asks for. The result is stable if
+    /// it exits with the same status every time, and not because of things
+    /// like the timing of the GC.
+    fn is_stable(&mut self, code: i32) -> bool {
+        for _ in 0..self.globals.cmdline.recheck_incorrect {
+            match self.exec.execute(self.lifter.get_code()).code {
+                ReturnCode::Status(again) if again == code => {},
+                _ => return false,
             }
         }
+        true
     }
 
-    fn save(&self, signal: i32) {
-        let rand = unsafe { std::arch::x86_64::_rdtsc() };
-        let filename = format!("crashes/crash.{}.{}.{}.js",
-                                self.id, self.stats.iter, rand);
-        let tosave = format!("{}\n\n// Crash with Signal: {}\n",
-                             self.lifter.get_code(), signal);
-        write_file(&filename, &tosave)
+    /// Run the program that just ran successfully again on the reference
+    /// target, which has its JIT turned off. When the digests in the `output`
+    /// of both runs differ, the JIT computed something else than the
+    /// interpreter, and the program is saved as a miscompile. Both runs are
+    /// repeated first, to rule out programs that compute something else on
+    /// every run, like the ones that use Math.random.
+    fn differential(&mut self, program: &Program, output: &str) {
+        let reference = match self.reference.as_mut() {
+            Some(reference) => reference,
+            None            => return,
+        };
+        let code = self.lifter.get_code();
+
+        let jit = match find_digest(output) {
+            Some(digest) => digest.to_string(),
+            None         => return,
+        };
+        let result = reference.execute(code);
+        let interpreter = match (result.code, find_digest(&result.output)) {
+            (ReturnCode::Status(0), Some(digest)) => digest.to_string(),
+            _ => return,
+        };
+        if jit == interpreter {
+            return;
+        }
+
+        let jit_again = self.exec.execute(code);
+        let interpreter_again = reference.execute(code);
+        if find_digest(&jit_again.output) != Some(jit.as_str()) ||
+            find_digest(&interpreter_again.output) !=
+                Some(interpreter.as_str()) {
+            return;
+        }
+
+        self.stats.miscompiles += 1;
+        self.archive(program, "miscompile",
+                     format!("JIT digest: {}\nInterpreter digest: {}",
+                             jit, interpreter),
+                     vec![("jit".into(),         JsonValue::String(jit)),
+                          ("interpreter".into(), JsonValue::String(interpreter))]);
+    }
+
+    /// Compile the program without running it, and count whether it was
+    /// syntactically valid towards each of the generators that added code to
+    /// it
+    fn check_syntax(&mut self, program: &Program) {
+        let checker = match self.syntax_checker.as_mut() {
+            Some(checker) => checker,
+            None          => return,
+        };
+        let valid = match checker.execute(self.lifter.get_code()).code {
+            ReturnCode::Status(code) => code == 0,
+            // None of the others tell if the program is valid
+            _ => return,
+        };
+
+        let mut generators = program.generators.clone();
+        generators.sort_unstable();
+        generators.dedup();
+        self.stats.add_syntax_check(&generators, valid);
+    }
+
+    /// Record the `bucket` of the crash with `signal`, and end the campaign if
+    /// that makes for as many different crash buckets as `--stop-on-crash` or
+    /// `--max-buckets` ask for
+    fn check_crash_limit(&self, signal: i32, bucket: String) {
+        let buckets = self.globals.discoveries.add_bucket(signal, bucket);
+        let max = match self.globals.cmdline.max_buckets {
+            Some(max) => max,
+            None      => return,
+        };
+        if buckets >= max {
+            info!("Found {} different crashes, stopping", buckets);
+            self.globals.stop();
+        }
+    }
+
+    /// Decide whether to save a program, out of all the programs that are
+    /// saved with the chance `fraction`
+    fn sample(&mut self, fraction: f64) -> bool {
+        fraction > 0.0 && self.prob.probablity(fraction)
+    }
+
+    /// Save a program that did not run successfully but didn't crash either,
+    /// to the `kind` directory of the session. The `fields` that describe how
+    /// it ran are added to the files. Failing to save it is not worth stopping
+    /// for.
+    fn archive(&self, program: &Program, kind: &str, comment: String,
+               fields: Vec<(String, JsonValue)>) {
+        let rand = timestamp();
+        let dir = format!("{}/{}", self.globals.session_dir, kind);
+        let filename = format!("{}/{}.{}.{}.{}", dir, kind, self.id,
+                               self.stats.iter, rand);
+        let saved = std::fs::create_dir_all(&dir).and_then(|_| {
+            self.write_program(&filename, program, &comment, fields)
+        });
+        if let Err(err) = saved {
+            error!("Failed to save the {} program: {}", kind, err);
+        }
+    }
+
+    /// Save a program that ran for `time`, which is one of the slowest so far,
+    /// to the `slow` directory of the session. The program that it pushes out
+    /// of the slowest ones is removed from there.
+    fn save_slow(&self, program: &Program, time: Duration) {
+        let rand = timestamp();
+        let dir = format!("{}/slow", self.globals.session_dir);
+        let filename = format!("{}/slow.{}.{}.{}.{}", dir, time.as_millis(),
+                               self.id, self.stats.iter, rand);
+        let fields = vec![("time_ms".into(),
+                           JsonValue::Number(time.as_millis() as f64))];
+        let saved = std::fs::create_dir_all(&dir).and_then(|_| {
+            self.write_program(&filename, program,
+                               &format!("Ran for {:?}", time), fields)
+        });
+        if let Err(err) = saved {
+            error!("Failed to save the slow program: {}", err);
+            return;
+        }
+
+        if let Some(removed) = self.globals.slowest.add(time, filename) {
+            let _ = std::fs::remove_file(format!("{}.js", removed));
+            let _ = std::fs::remove_file(format!("{}.json", removed));
+        }
+    }
+
+    /// Save the crashing program. The seed of the program is also printed out,
+    /// so it is not lost when the files can't be written. The `stderr` of the
+    /// target is added to both files, and the type of the sanitizer report in
+    /// it to their name. The crashes are sorted into directories by their
+    /// signal and bucket, see [super::triage].
+    fn save(&self, program: &Program, signal: i32, stderr: &str) {
+        warn!("Crash with signal {} from the program with seed {}",
+              signal, self.seed);
+
+        let rand = timestamp();
+        let dir = format!("{}/crashes/{}/{}", self.globals.session_dir,
+                          signal_name(signal), crash_bucket(program, stderr));
+        std::fs::create_dir_all(&dir)
+            .expect("Failed to create the crash directory");
+
+        let filename = match report_type(stderr) {
+            Some(report) => format!("{}/crash.{}.{}.{}.{}", dir, report,
+                                    self.id, self.stats.iter, rand),
+            None => format!("{}/crash.{}.{}.{}",
+                            dir, self.id, self.stats.iter, rand),
+        };
+        let comment = format!("Crash with Signal: {}\n{}", signal, stderr);
+        let fields = vec![
+            ("signal".into(), JsonValue::Number(signal as f64)),
+            ("stderr".into(), JsonValue::String(stderr.to_string())),
+        ];
+        self.write_program(&filename, program, &comment, fields)
             .expect("Failed to write crash to file");
     }
 }
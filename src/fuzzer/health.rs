@@ -0,0 +1,114 @@
+//! Warns when the campaign stops getting anywhere, so that it doesn't go on
+//! like that unnoticed overnight. A campaign is unhealthy when it runs fewer
+//! programs per second than `--min-fcps` asks for, for as long as it says, or
+//! when more of its programs time out lately than `--max-timeouts` allows.
+
+use std::time::{Duration, Instant};
+
+use super::throughput::Throughput;
+
+/// The timeouts are only judged on at least this many programs, so that a few
+/// slow ones at startup don't count as the campaign timing out
+const MIN_PROGRAMS: u64 = 100;
+
+pub struct Health {
+    /// The fcps that the campaign has to run at, and for how long it may run
+    /// slower than that
+    min_fcps:     Option<(f64, Duration)>,
+
+    /// The share of the programs that may time out, from 0 to 1
+    max_timeouts: Option<f64>,
+
+    /// Since when the campaign runs slower than `min_fcps`
+    slow_since:   Option<Instant>,
+
+    /// Whether the campaign was reported for running slow or timing out. It is
+    /// reported once each time that it gets unhealthy.
+    slow:         bool,
+    timing_out:   bool,
+}
+
+impl Health {
+
+    pub fn new(min_fcps: Option<(f64, Duration)>,
+               max_timeouts: Option<f64>) -> Self {
+        Self {
+            min_fcps,
+            max_timeouts,
+            slow_since: None,
+            slow:       false,
+            timing_out: false,
+        }
+    }
+
+    /// Check the `throughput` of the campaign at `now`. Returns what is wrong
+    /// with it when it just got unhealthy.
+    pub fn check(&mut self, now: Instant, throughput: &Throughput)
+                 -> Option<String> {
+        let mut alerts = Vec::new();
+
+        if let (Some((min, limit)), Some(rate)) = (self.min_fcps,
+                                                   throughput.rate()) {
+            if rate >= min {
+                self.slow_since = None;
+                self.slow = false;
+            } else {
+                let since = *self.slow_since.get_or_insert(now);
+                if !self.slow && now.duration_since(since) >= limit {
+                    self.slow = true;
+                    alerts.push(format!("The campaign ran at {:.0} fcps, below \
+                                         {} fcps, for {} seconds", rate, min,
+                                        now.duration_since(since).as_secs()));
+                }
+            }
+        }
+
+        let (programs, timeouts) = throughput.recent();
+        if let (Some(max), true) = (self.max_timeouts, programs >= MIN_PROGRAMS) {
+            let ratio = timeouts as f64 / programs as f64;
+            if ratio <= max {
+                self.timing_out = false;
+            } else if !self.timing_out {
+                self.timing_out = true;
+                alerts.push(format!("{:.1}% of the last {} programs timed out, \
+                                     more than {}%", ratio * 100.0, programs,
+                                    max * 100.0));
+            }
+        }
+
+        if alerts.is_empty() {
+            None
+        } else {
+            Some(alerts.join(". "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut health = Health::new(Some((50.0, Duration::from_secs(30))),
+                                     Some(0.5));
+        let mut throughput = Throughput::new();
+
+        // 10 programs a second, all of which time out after the first minute
+        let mut alerts = Vec::new();
+        for secs in 0..=120u64 {
+            let timeouts = secs.saturating_sub(60) * 10;
+            throughput.add(at(secs), secs * 10, timeouts);
+            if let Some(alert) = health.check(at(secs), &throughput) {
+                alerts.push((secs, alert));
+            }
+        }
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].0, 31);
+        assert!(alerts[0].1.contains("below 50 fcps"));
+        assert!(alerts[1].1.contains("timed out"));
+    }
+}
@@ -16,3 +16,39 @@ pub const INTERESTING_INTS: [isize ; 61] = [
     4294967295, 4294967296, 4294967297,                               // Uint32 max
     9007199254740991, 9007199254740992, 9007199254740993,             // Biggest integer value that is still precisely representable by a double
 ];
+
+/// List of interesting Float values. The edges of the doubles along with the
+/// values around where they stop being precise integers.
+pub const INTERESTING_FLOATS: [f64 ; 26] = [
+    f64::NAN, f64::INFINITY, f64::NEG_INFINITY,                       // Not finite
+    -0.0, 0.0, 0.1, -0.5, 0.5, 1.5, -1.5,                             // Numbers around 0
+    5e-324, -5e-324, 2.225073858507201e-308,                         // Denormals
+    f64::MIN_POSITIVE, f64::EPSILON,                                  // Smallest normal and epsilon
+    4503599627370495.5, 4503599627370496.0,                           // 2**52, the last of the halves
+    9007199254740991.0, 9007199254740992.0, -9007199254740992.0,      // 2**53, the last of the integers
+    18014398509481984.0,                                              // 2**54
+    2147483647.5, 4294967295.5,                                       // Just past Int32 and Uint32 max
+    1e308, f64::MAX, f64::MIN,                                        // Biggest doubles
+];
+
+/// List of interesting String values. The ones that convert to numbers in odd
+/// ways, the names of the properties that everything has, and the unicode that
+/// takes the engines off of their one byte strings. The lone surrogates are
+/// written with their stand-ins, see
+/// [SURROGATE_BASE](crate::lifter::emitter::SURROGATE_BASE).
+pub const INTERESTING_STRINGS: [&str ; 43] = [
+    "", " ", "a",                                                     // Short ones
+    "\"", "'", "\\", "\n", "\0", "\r\n", "a\"b'c\\d",                 // Ones that need escaping
+    "0", "-0", "1", "-1", "0x10", "0b11", "0o17", "1e9", "1e400",     // Numbers
+    ".5", "5.", " 12 ", "Infinity", "-Infinity", "NaN", "4294967295", // More numbers
+    "__proto__", "length", "valueOf", "toString", "constructor",      // Property names
+    "prototype", "Symbol.iterator", "then",
+    "\u{e9}", "\u{fffd}", "\u{2028}", "\u{1f600}",                    // Unicode
+    "\u{1d49c}\u{1d49e}",
+    "\u{10f800}", "\u{10fc00}", "a\u{10f800}b",                       // Lone surrogates
+    "\u{10fc00}\u{10f800}",                                           // Reversed pair
+];
+
+/// Lengths of strings that engines tend to store differently from the shorter
+/// ones
+pub const INTERESTING_LENGTHS: [u64 ; 5] = [12, 13, 256, 1024, 1 << 14];
@@ -0,0 +1,88 @@
+//! Corpus minimization. Long campaigns collect many entries whose edges are all
+//! covered by other entries as well, which just take scheduling time away from
+//! the useful ones. Minimizing runs every entry of the corpus on the target
+//! again and only keeps the entries that cover edges no other kept entry does.
+
+use crate::cmdlineoptions::CmdLineOptions;
+use crate::execution::execution::{Execution, ReturnCode};
+use crate::jsruntime::jsruntime::JSRuntime;
+use crate::lifter::lifter::Lifter;
+use crate::profiles::profile::Profile;
+use crate::{error, info};
+
+use super::corpus::Corpus;
+use super::fuzzer::create_executor;
+
+/// Lift each entry of `corpus` into JS, the way the fuzzer lifts programs for
+/// the engine of `profile`
+fn lift_entries(corpus: &Corpus, jsruntime: &JSRuntime,
+                profile: &dyn Profile) -> Vec<String> {
+    let mut lifter = Lifter::new();
+    lifter.set_epilogue(profile.get_epilogue());
+    corpus.entries().iter().map(|entry| {
+        lifter.reset();
+        lifter.do_lifting(&Corpus::get_program(entry, jsruntime));
+        lifter.finalize();
+        lifter.get_code().to_string()
+    }).collect()
+}
+
+/// Run `code` on its own and get the no. of edges that it covers, or `None`
+/// if it no longer runs successfully
+fn measure(exec: &mut dyn Execution, code: &String) -> Option<usize> {
+    exec.reset_coverage();
+    let result = exec.execute(code);
+    match result.code {
+        ReturnCode::Status(0) => Some(result.new_edges.len()),
+        _ => None,
+    }
+}
+
+/// Minimize `corpus` and write the entries that are kept to the corpus
+/// directory `output`. The entries that cover the most edges are tried first,
+/// so that they can make the smaller ones redundant. Entries that crash or
+/// time out now are dropped.
+pub fn minimize(cmdline: &CmdLineOptions, jsruntime: &JSRuntime,
+                profile: &dyn Profile, corpus: &Corpus,
+                output: &str) -> std::io::Result<()> {
+
+    // The minimized corpus has to start out empty, so it can't be the corpus
+    // that is minimized either
+    let mut minimized = Corpus::load(output)?;
+    if !minimized.is_empty() {
+        error!("The directory for the minimized corpus is not empty");
+        return Ok(());
+    }
+
+    let mut exec = create_executor(0, cmdline, profile);
+    if !exec.has_coverage() {
+        error!("The target has to report coverage to minimize the corpus");
+        return Ok(());
+    }
+
+    let code = lift_entries(corpus, jsruntime, profile);
+
+    let mut order = Vec::new();
+    for (idx, code) in code.iter().enumerate() {
+        if let Some(edges) = measure(exec.as_mut(), code) {
+            order.push((idx, edges));
+        }
+    }
+    order.sort_by_key(|(_, edges)| std::cmp::Reverse(*edges));
+
+    // Now run the entries one after the other and only keep the ones that
+    // still find edges after everything that ran before them
+    exec.reset_coverage();
+    for (idx, _) in order {
+        let result = exec.execute(&code[idx]);
+        if !result.new_edges.is_empty() {
+            let entry = &corpus.entries()[idx];
+            minimized.add(&Corpus::get_program(entry, jsruntime),
+                          result.new_edges.len(), &code[idx]);
+        }
+    }
+
+    info!("Minimized the corpus from {} to {} programs",
+          corpus.len(), minimized.len());
+    Ok(())
+}
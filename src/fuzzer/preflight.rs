@@ -0,0 +1,201 @@
+//! Checks that the engine can be run at all before any of the fuzzer threads
+//! start. A wrong path to the engine, a build for another architecture, an
+//! unsupported flag or an engine without the REPRL patch would otherwise only
+//! show up once the threads try to start it, if at all.
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use crate::cmdlineoptions::CmdLineOptions;
+use crate::execution::execution::ReturnCode;
+use crate::profiles::profile::Profile;
+
+use super::fuzzer::start_target;
+use super::triage::signal_name;
+
+/// The program that the engine has to run for it to be considered working. The
+/// epilogue of the profile is added to it, so that the helpers of the profile
+/// are checked too.
+const PROGRAM: &str = "var zebra = [1, 2, 3].length + 1;";
+
+#[derive(Debug)]
+struct PreflightError(String);
+impl std::fmt::Display for PreflightError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PreflightError {}
+
+type PreflightResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn error<T>(msg: String) -> PreflightResult<T> {
+    Err(Box::new(PreflightError(msg)))
+}
+
+/// The ELF machine no. and the Mach-O cpu type of the host, for the
+/// architectures that are checked
+fn host_machine() -> Option<(u16, u32)> {
+    if cfg!(target_arch = "x86_64") {
+        Some((62, 0x0100_0007))
+    } else if cfg!(target_arch = "aarch64") {
+        Some((183, 0x0100_000c))
+    } else {
+        None
+    }
+}
+
+/// The start of a 64 bit Mach-O binary, in the byte order of the hosts that
+/// run them
+const MACHO_MAGIC: [u8; 4] = [0xcf, 0xfa, 0xed, 0xfe];
+
+/// Find the engine that `Command` would run for `filename`, which is looked up
+/// in the `PATH` if it is just a name
+fn find_engine(filename: &str) -> Option<PathBuf> {
+    if filename.contains('/') {
+        return Some(PathBuf::from(filename));
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(filename))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Check that `filename` is a file that can be executed on this machine
+fn check_binary(filename: &str) -> PreflightResult<()> {
+    let path = match find_engine(filename) {
+        Some(path) if path.exists() => path,
+        _ => return error(format!("The engine {} does not exist. Give the \
+                                   path to it with -f", filename)),
+    };
+    if !path.is_file() {
+        return error(format!("The engine {} is not a file", filename));
+    }
+    if path.metadata()?.permissions().mode() & 0o111 == 0 {
+        return error(format!("The engine {} is not executable", filename));
+    }
+
+    let mut header = [0u8; 20];
+    let read = File::open(&path)?.read(&mut header)?;
+    let header = &header[..read];
+    let (elf_machine, macho_cpu) = match host_machine() {
+        Some(machine) => machine,
+        None          => return Ok(()),
+    };
+
+    let arch = std::env::consts::ARCH;
+    if header.len() >= 20 && header.starts_with(b"\x7fELF") {
+        // The machine is a u16 of the byte order that the 6th byte gives
+        let machine = [header[18], header[19]];
+        let machine = if header[5] == 2 {
+            u16::from_be_bytes(machine)
+        } else {
+            u16::from_le_bytes(machine)
+        };
+        if machine != elf_machine {
+            return error(format!("The engine {} is built for another \
+                                  architecture (ELF machine {}) than {}",
+                                 filename, machine, arch));
+        }
+    } else if header.len() >= 8 && header.starts_with(&MACHO_MAGIC) {
+        let cpu = u32::from_le_bytes([header[4], header[5], header[6],
+                                      header[7]]);
+        if cpu != macho_cpu {
+            return error(format!("The engine {} is built for another \
+                                  architecture (cpu type {:#x}) than {}",
+                                 filename, cpu, arch));
+        }
+    }
+    Ok(())
+}
+
+/// A hint about what to do when the target fails to start up in the mode that
+/// `cmdline` asks for
+fn startup_hint(cmdline: &CmdLineOptions,
+                profile: &dyn Profile) -> &'static str {
+    if cmdline.forkserver {
+        "With --forkserver, the engine has to be a fork server driver."
+    } else if cmdline.disk || !profile.supports_reprl() {
+        "Check that the engine takes the args of the profile and \
+         --engine-arg."
+    } else {
+        "The engine has to be built with the REPRL patch to run the programs \
+         in memory, or else run them from disk with --disk. Check that it takes \
+         the args of the profile and --engine-arg too."
+    }
+}
+
+/// Start the engine the way the fuzzer threads do, and run a trivial program
+/// on it. This is done with the JIT turned off too for the differential mode.
+pub fn check_engine(cmdline: &CmdLineOptions,
+                    profile: &dyn Profile) -> PreflightResult<()> {
+    check_binary(&cmdline.filename)?;
+
+    let code = std::iter::once(PROGRAM.to_string())
+        .chain(profile.get_epilogue())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let modes: &[bool] = if cmdline.differential {
+        &[true, false]
+    } else {
+        &[true]
+    };
+    for &jit in modes {
+        let mut exec = match start_target(0, cmdline, profile, jit) {
+            Ok(exec) => exec,
+            Err(err) => return error(format!("{}\n{}", err,
+                                             startup_hint(cmdline, profile))),
+        };
+
+        let result = exec.execute(&code);
+        let program = if jit {
+            "a trivial program"
+        } else {
+            "a trivial program with the JIT turned off"
+        };
+        let mut msg = match result.code {
+            ReturnCode::Status(0)      => continue,
+            ReturnCode::Status(status) => {
+                format!("The engine exited with {} on {}. {}", status, program,
+                        startup_hint(cmdline, profile))
+            },
+            ReturnCode::Crash(signal)  => {
+                format!("The engine crashed with {} on {}", signal_name(signal),
+                        program)
+            },
+            ReturnCode::Timeout        => {
+                format!("The engine timed out after {}ms on {}. Give it a \
+                         longer --timeout", cmdline.timeout, program)
+            },
+            ReturnCode::Oom            => {
+                format!("The engine ran out of memory on {}. Give it more \
+                         with --mem-limit", program)
+            },
+        };
+        if !result.stderr.trim().is_empty() {
+            msg = format!("{}\nThe engine said:\n{}", msg,
+                          result.stderr.trim_end());
+        }
+        return error(msg);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_binary() {
+        assert!(check_binary("/nonexistent/js").is_err());
+        assert!(check_binary("/").is_err());
+        assert!(check_binary("sh").is_ok());
+        assert!(check_binary(&std::env::current_exe().unwrap()
+                             .to_string_lossy()).is_ok());
+    }
+}
@@ -0,0 +1,133 @@
+//! Replay a single program on the target, or just print it out. The program is
+//! either read back from the `.json` file that was saved along with a crash, or
+//! generated again from its seed. All the random choices that are made for a
+//! program are derived from its seed, so the same seed always gives the same
+//! JS.
+
+use crate::cmdlineoptions::CmdLineOptions;
+use crate::execution::execution::ReturnCode;
+use crate::ir::program::Program;
+use crate::ir::serialize::decode_program;
+use crate::jsruntime::jsruntime::JSRuntime;
+use crate::lifter::lifter::Lifter;
+use crate::profiles::profile::Profile;
+use crate::utils::json::JsonValue;
+
+use super::fuzzer::{create_executor, generate_program};
+
+#[derive(Debug)]
+struct ReplayError(String);
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+type ReplayResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn error<T>(msg: String) -> ReplayResult<T> {
+    Err(Box::new(ReplayError(msg)))
+}
+
+/// Get the program to replay out of `crash`, which is either the seed of a
+/// generated program or the path to a saved crash. Returns the program along
+/// with its seed.
+fn load_program<'a>(cmdline: &CmdLineOptions, jsruntime: &'a JSRuntime,
+                    crash: &str) -> ReplayResult<(Program<'a>, u64)> {
+
+    if let Ok(seed) = crash.parse::<u64>() {
+        return Ok((generate_program(jsruntime, cmdline, seed), seed));
+    }
+
+    let value = JsonValue::parse(&std::fs::read_to_string(crash)?)?;
+    let seed = match value.get("seed").and_then(|seed| seed.as_str()) {
+        Some(seed) => seed.parse::<u64>()?,
+        None => return error(format!("No seed found in {}", crash)),
+    };
+    let instructions = match value.get("program") {
+        Some(program) => decode_program(program)?,
+        None => return error(format!("No program found in {}", crash)),
+    };
+
+    Ok((Program::from_instructions(jsruntime, instructions), seed))
+}
+
+/// Lift the program that `crash` refers to the way it is run on the engine of
+/// `profile`, and print it out. Returns the code of the program.
+fn print_program(cmdline: &CmdLineOptions, jsruntime: &JSRuntime,
+                 profile: &dyn Profile, crash: &str) -> ReplayResult<String> {
+
+    let (program, seed) = load_program(cmdline, jsruntime, crash)?;
+
+    let mut lifter = Lifter::new();
+    lifter.seed(seed);
+    lifter.set_digest(cmdline.digest);
+    lifter.set_comments(cmdline.ir_comments);
+    lifter.set_literal_forms(cmdline.literal_forms);
+    lifter.set_iife_probability(cmdline.iife_probability);
+    lifter.set_declarations(cmdline.declarations);
+    lifter.set_realistic_names(cmdline.realistic_names);
+    lifter.set_strip_dead_code(cmdline.strip_dead_code);
+    lifter.set_epilogue(profile.get_epilogue());
+    lifter.do_lifting(&program);
+    lifter.finalize();
+    if cmdline.print_ir {
+        println!("{}", program.print_ir());
+    }
+    println!("{}", lifter.get_code());
+    Ok(lifter.get_code().to_string())
+}
+
+/// Print out the program that `crash` refers to, without running it
+pub fn lift(cmdline: &CmdLineOptions, jsruntime: &JSRuntime,
+            profile: &dyn Profile, crash: &str) -> ReplayResult<()> {
+    print_program(cmdline, jsruntime, profile, crash).map(|_| ())
+}
+
+/// Print out the program that `crash` refers to and run it once on the target
+pub fn replay(cmdline: &CmdLineOptions, jsruntime: &JSRuntime,
+              profile: &dyn Profile, crash: &str) -> ReplayResult<()> {
+
+    let code = print_program(cmdline, jsruntime, profile, crash)?;
+
+    let mut exec = create_executor(0, cmdline, profile);
+    let result = exec.execute(&code);
+    match result.code {
+        ReturnCode::Timeout        => println!("[+] The program timed out"),
+        ReturnCode::Oom            => println!("[+] The program ran out of memory"),
+        ReturnCode::Status(code)   => println!("[+] The program exited with {}",
+                                               code),
+        ReturnCode::Crash(signal)  => println!("[!] The program crashed with \
+                                                signal {}", signal),
+    }
+    if !result.stderr.is_empty() {
+        println!("{}", result.stderr);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replay_seed() {
+        let runtime = JSRuntime::new();
+        let cmdline = CmdLineOptions::default();
+
+        let lift = |seed: &str| {
+            let (program, seed) = load_program(&cmdline, &runtime, seed)
+                .unwrap();
+            let mut lifter = Lifter::new();
+            lifter.seed(seed);
+            lifter.do_lifting(&program);
+            lifter.finalize();
+            lifter.get_code().to_string()
+        };
+
+        assert_eq!(lift("1234"), lift("1234"));
+        assert!(load_program(&cmdline, &runtime, "/nonexistent.json").is_err());
+    }
+}
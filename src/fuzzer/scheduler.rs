@@ -0,0 +1,150 @@
+//! The schedulers decide which entry of the corpus is mutated next. The
+//! scheduler that is used can be picked with the `--scheduler` option.
+
+use super::corpus::CorpusEntry;
+use crate::utils::random::Random;
+
+/// The fraction of the corpus that counts as recently added when sampling
+const RECENT_FRACTION: usize = 4;
+
+/// The max no. of new edges that adds to the weight of an entry, so that a
+/// single lucky program doesn't take over the corpus
+const MAX_EDGE_WEIGHT: u64 = 100;
+
+/// The names of the available schedulers, the first one is the default
+pub const SCHEDULERS: [&str; 3] = ["rarity", "uniform", "energy"];
+
+/// Trait that all the corpus scheduling policies implement
+pub trait Scheduler: Send + Sync {
+    /// Pick the index of the entry to mutate next out of `entries`, which are
+    /// in the order in which they were added and never empty.
+    fn select(&self, entries: &[CorpusEntry], rng: &mut Random) -> usize;
+}
+
+/// Get the scheduler called `name`, if there is one. See [SCHEDULERS].
+pub fn get_scheduler(name: &str) -> Option<Box<dyn Scheduler>> {
+    let scheduler: Box<dyn Scheduler> = match name {
+        "rarity"  => Box::new(RarityScheduler),
+        "uniform" => Box::new(UniformScheduler),
+        "energy"  => Box::new(EnergyScheduler),
+        _ => return None,
+    };
+    Some(scheduler)
+}
+
+/// Pick an index out of `weights`, with a chance proportional to its weight.
+/// If all of the weights are 0, every index is as likely.
+fn weighted_pick(weights: &[u64], rng: &mut Random) -> usize {
+    let sum = weights.iter().sum::<u64>();
+    if sum == 0 {
+        return rng.rand_idx(weights.len());
+    }
+
+    let mut pick = rng.u64_below(sum);
+    weights.iter().position(|&weight| {
+        if pick < weight {
+            return true;
+        }
+        pick -= weight;
+        false
+    }).unwrap()
+}
+
+/// Every entry is equally likely to be picked
+pub struct UniformScheduler;
+
+impl Scheduler for UniformScheduler {
+    fn select(&self, entries: &[CorpusEntry], rng: &mut Random) -> usize {
+        rng.rand_idx(entries.len())
+    }
+}
+
+/// Half the time the entry is one of the recently added ones, as those are the
+/// closest to the edges that were found last. Otherwise it is weighted by the
+/// no. of new edges that the entry found and how rarely it was picked before.
+pub struct RarityScheduler;
+
+impl RarityScheduler {
+    fn weight(entry: &CorpusEntry) -> u64 {
+        let edges = std::cmp::min(entry.new_edges as u64, MAX_EDGE_WEIGHT) + 1;
+        std::cmp::max(1, edges * 10 / (entry.selected() + 1))
+    }
+}
+
+impl Scheduler for RarityScheduler {
+    fn select(&self, entries: &[CorpusEntry], rng: &mut Random) -> usize {
+        let len = entries.len();
+        if rng.rand_idx(2) == 0 {
+            let recent = std::cmp::max(1, len / RECENT_FRACTION);
+            return len - 1 - rng.rand_idx(recent);
+        }
+
+        let weights = entries.iter()
+            .map(RarityScheduler::weight)
+            .collect::<Vec<u64>>();
+        weighted_pick(&weights, rng)
+    }
+}
+
+/// Gives each entry an energy like the power schedules of AFL. The energy
+/// drops exponentially with the no. of times that the entry was picked, so
+/// that the fuzzer moves on from programs that stopped paying off quickly, and
+/// grows with the age rank of the entry, so that newer entries get more time.
+pub struct EnergyScheduler;
+
+impl EnergyScheduler {
+    fn energy(entry: &CorpusEntry, rank: usize, len: usize) -> u64 {
+        let edges = std::cmp::min(entry.new_edges as u64, MAX_EDGE_WEIGHT) + 1;
+
+        // The newest entries get up to 4x the energy of the oldest ones
+        let age = 1 + (rank * 3 / len) as u64;
+        let decay = std::cmp::min(entry.selected(), 16) as u32;
+        std::cmp::max(1, ((edges * age) << 16) >> decay)
+    }
+}
+
+impl Scheduler for EnergyScheduler {
+    fn select(&self, entries: &[CorpusEntry], rng: &mut Random) -> usize {
+        let weights = entries.iter().enumerate()
+            .map(|(rank, entry)| {
+                EnergyScheduler::energy(entry, rank, entries.len())
+            })
+            .collect::<Vec<u64>>();
+        weighted_pick(&weights, rng)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicU64;
+
+    use super::*;
+
+    #[test]
+    fn test_schedulers() {
+        let entries = (0..10).map(|idx| CorpusEntry {
+            id:           idx,
+            instructions: Arc::new([]),
+            new_edges:    idx as usize,
+            selected:     AtomicU64::new(if idx == 9 { 16 } else { 0 }),
+        }).collect::<Vec<CorpusEntry>>();
+
+        let mut rng = Random::new(0);
+        for name in SCHEDULERS.iter() {
+            let scheduler = get_scheduler(name).unwrap();
+            for _ in 0..100 {
+                assert!(scheduler.select(&entries, &mut rng) < entries.len());
+            }
+        }
+        assert!(get_scheduler("random").is_none());
+
+        // Without any weight to go by, any index is fine
+        assert!(weighted_pick(&[0, 0, 0], &mut rng) < 3);
+        assert_eq!(weighted_pick(&[], &mut rng), 0);
+
+        // An entry that was picked many times has little energy left
+        assert!(EnergyScheduler::energy(&entries[9], 9, 10) <
+                EnergyScheduler::energy(&entries[1], 1, 10));
+    }
+}
@@ -0,0 +1,275 @@
+//! Saves the state of a campaign to its session directory, so that it can go on
+//! with `--resume` after it was stopped or the host went down. The corpus and
+//! the crashes are on disk already. This has the rest of it: the stats so far,
+//! the edges and crashes that the threads found together, where the random
+//! streams of the threads were at, and how long the campaign ran for.
+
+use std::fs;
+use std::time::Duration;
+
+use crate::utils::json::JsonValue;
+
+use super::stats::Stats;
+
+/// The file in the session directory that the state is saved to
+const STATE_FILE: &str = "session.json";
+
+#[derive(Debug)]
+struct SessionError(String);
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+type SessionResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn error<T>(msg: String) -> SessionResult<T> {
+    Err(Box::new(SessionError(msg)))
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SessionState {
+    /// The seed that the campaign was started with
+    pub seed:       u64,
+
+    /// How long the campaign ran for so far
+    pub elapsed:    Duration,
+
+    /// The no. of programs that the threads started running so far
+    pub iterations: u64,
+
+    pub stats:      Stats,
+
+    /// What the threads found together, see [super::discoveries]
+    pub edges:      Vec<u32>,
+    pub crashes:    Vec<i32>,
+    pub buckets:    Vec<(i32, String)>,
+
+    /// The state of the random stream of each thread, or 0 for the threads
+    /// that didn't run yet
+    pub rngs:       Vec<u64>,
+}
+
+/// The names of the counters of [Stats] along with the counters, in the order
+/// that they are saved in
+fn counters(stats: &mut Stats) -> [(&'static str, &mut u64); 15] {
+    [
+        ("iter",        &mut stats.iter),
+        ("crashes",     &mut stats.crashes),
+        ("timeouts",    &mut stats.timeouts),
+        ("incorrect",   &mut stats.incorrect),
+        ("unstable",    &mut stats.unstable),
+        ("ooms",        &mut stats.ooms),
+        ("miscompiles", &mut stats.miscompiles),
+        ("edges",       &mut stats.edges),
+        ("checked",     &mut stats.checked),
+        ("valid",       &mut stats.valid),
+        ("exec_micros", &mut stats.exec_micros),
+        ("peak_rss",    &mut stats.peak_rss),
+        ("insts",       &mut stats.insts),
+        ("dead_insts",  &mut stats.dead_insts),
+        ("duplicates",  &mut stats.duplicates),
+    ]
+}
+
+/// The counters that were added after the first builds that saved sessions
+const LATER_COUNTERS: [&str; 3] = ["insts", "dead_insts", "duplicates"];
+
+/// The counters of [Stats] that are kept for each generator or bucket of run
+/// times
+fn histograms(stats: &mut Stats) -> [(&'static str, &mut Vec<u64>); 8] {
+    [
+        ("generator_checked", &mut stats.generator_checked),
+        ("generator_valid",   &mut stats.generator_valid),
+        ("generator_chosen",  &mut stats.generator_chosen),
+        ("generator_failed",  &mut stats.generator_failed),
+        ("generator_insts",   &mut stats.generator_insts),
+        ("generator_crashes", &mut stats.generator_crashes),
+        ("generator_timeouts", &mut stats.generator_timeouts),
+        ("exec_times",        &mut stats.exec_times),
+    ]
+}
+
+fn numbers(values: impl Iterator<Item = f64>) -> JsonValue {
+    JsonValue::Array(values.map(JsonValue::Number).collect())
+}
+
+/// Get the array of numbers called `key` out of `value`
+fn get_numbers(value: &JsonValue, key: &str) -> SessionResult<Vec<f64>> {
+    let array = match value.get(key).and_then(|array| array.as_array()) {
+        Some(array) => array,
+        None        => return error(format!("No {} found", key)),
+    };
+    array.iter().map(|number| match number.as_number() {
+        Some(number) => Ok(number),
+        None         => error(format!("The {} have to be numbers", key)),
+    }).collect()
+}
+
+/// Get the no. called `key` out of `value`. The ones that don't fit in a JSON
+/// no. are saved as strings.
+fn get_u64(value: &JsonValue, key: &str) -> SessionResult<u64> {
+    match value.get(key) {
+        Some(JsonValue::Number(number)) => Ok(*number as u64),
+        Some(JsonValue::String(number)) => Ok(number.parse::<u64>()?),
+        _ => error(format!("No {} found", key)),
+    }
+}
+
+impl SessionState {
+
+    fn to_json(&self) -> JsonValue {
+        let mut stats = self.stats.clone();
+        let mut saved_stats = counters(&mut stats).into_iter()
+            .map(|(name, count)| (name.to_string(),
+                                  JsonValue::Number(*count as f64)))
+            .collect::<Vec<(String, JsonValue)>>();
+        for (name, counts) in histograms(&mut stats) {
+            saved_stats.push((name.to_string(),
+                              numbers(counts.iter().map(|&count| count as f64))));
+        }
+
+        let buckets = self.buckets.iter().map(|(signal, bucket)| {
+            JsonValue::Array(vec![JsonValue::Number(*signal as f64),
+                                  JsonValue::String(bucket.clone())])
+        }).collect();
+        let rngs = self.rngs.iter()
+            .map(|rng| JsonValue::String(rng.to_string()))
+            .collect();
+
+        JsonValue::Object(vec![
+            ("seed".into(),       JsonValue::String(self.seed.to_string())),
+            ("elapsed".into(),    JsonValue::Number(self.elapsed.as_secs_f64())),
+            ("iterations".into(), JsonValue::Number(self.iterations as f64)),
+            ("stats".into(),      JsonValue::Object(saved_stats)),
+            ("edges".into(),      numbers(self.edges.iter()
+                                              .map(|&edge| edge as f64))),
+            ("crashes".into(),    numbers(self.crashes.iter()
+                                              .map(|&signal| signal as f64))),
+            ("buckets".into(),    JsonValue::Array(buckets)),
+            ("rngs".into(),       JsonValue::Array(rngs)),
+        ])
+    }
+
+    fn from_json(value: &JsonValue) -> SessionResult<Self> {
+        let saved_stats = match value.get("stats") {
+            Some(stats) => stats,
+            None        => return error("No stats found".into()),
+        };
+        let mut stats = Stats::default();
+        for (name, count) in counters(&mut stats) {
+            // The sessions of older builds don't have these counters
+            let missing = saved_stats.get(name).is_none();
+            if missing && LATER_COUNTERS.contains(&name) {
+                continue;
+            }
+            *count = get_u64(saved_stats, name)?;
+        }
+        // The sessions of older builds don't have all of these
+        for (name, counts) in histograms(&mut stats) {
+            if saved_stats.get(name).is_none() {
+                continue;
+            }
+            *counts = get_numbers(saved_stats, name)?.into_iter()
+                .map(|count| count as u64)
+                .collect();
+        }
+
+        let mut buckets = Vec::new();
+        for bucket in value.get("buckets").and_then(|b| b.as_array())
+                           .into_iter().flatten() {
+            let pair = (bucket.as_array().and_then(|pair| pair.first()),
+                        bucket.as_array().and_then(|pair| pair.get(1)));
+            match pair {
+                (Some(JsonValue::Number(signal)),
+                 Some(JsonValue::String(bucket))) => {
+                    buckets.push((*signal as i32, bucket.clone()));
+                },
+                _ => return error("The buckets have to be a signal and a \
+                                   name".into()),
+            }
+        }
+
+        let mut rngs = Vec::new();
+        for rng in value.get("rngs").and_then(|r| r.as_array())
+                        .into_iter().flatten() {
+            match rng.as_str().map(|rng| rng.parse::<u64>()) {
+                Some(Ok(rng)) => rngs.push(rng),
+                _ => return error("The rngs have to be numbers".into()),
+            }
+        }
+
+        let elapsed = match value.get("elapsed").and_then(|e| e.as_number()) {
+            Some(secs) if secs >= 0.0 => Duration::from_secs_f64(secs),
+            _ => return error("No elapsed time found".into()),
+        };
+
+        Ok(Self {
+            seed:       get_u64(value, "seed")?,
+            elapsed,
+            iterations: get_u64(value, "iterations")?,
+            stats,
+            edges:      get_numbers(value, "edges")?.into_iter()
+                                                     .map(|edge| edge as u32)
+                                                     .collect(),
+            crashes:    get_numbers(value, "crashes")?.into_iter()
+                                                       .map(|sig| sig as i32)
+                                                       .collect(),
+            buckets,
+            rngs,
+        })
+    }
+
+    /// Read back the state that was saved to `session_dir`
+    pub fn load(session_dir: &str) -> SessionResult<Self> {
+        let path = format!("{}/{}", session_dir, STATE_FILE);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) => return error(format!("Failed to read {}: {}", path,
+                                             err)),
+        };
+        Self::from_json(&JsonValue::parse(&data)?)
+    }
+
+    /// Save the state to `session_dir`. It is written to a temp file first, so
+    /// that going down in the middle of it doesn't lose the last state.
+    pub fn save(&self, session_dir: &str) -> std::io::Result<()> {
+        let path = format!("{}/{}", session_dir, STATE_FILE);
+        let temp = format!("{}.tmp", path);
+        fs::write(&temp, self.to_json().to_string())?;
+        fs::rename(&temp, &path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_state() {
+        let mut state = SessionState {
+            seed:       u64::MAX - 1,
+            elapsed:    Duration::from_millis(1500),
+            iterations: 42,
+            edges:      vec![1, 7, 1000],
+            crashes:    vec![6, 11],
+            buckets:    vec![(11, "bucket_\"x\"".into())],
+            rngs:       vec![0, 0x8877665544332211],
+            ..Default::default()
+        };
+        state.stats.iter = 40;
+        state.stats.crashes = 2;
+        state.stats.exec_times = vec![0, 3, 5];
+
+        let saved = state.to_json().to_string();
+        let loaded = SessionState::from_json(&JsonValue::parse(&saved).unwrap())
+            .unwrap();
+        assert_eq!(loaded, state);
+
+        assert!(SessionState::from_json(&JsonValue::Null).is_err());
+        assert!(SessionState::load("/nonexistent").is_err());
+    }
+}
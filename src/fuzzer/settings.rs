@@ -1,7 +1,19 @@
+use std::sync::OnceLock;
+
 use crate::ir::codegenerators::CodeGenerators;
 use crate::ir::program::Program;
+use crate::mutators::mutator::Mutator;
+use crate::mutators::inputmutator::InputMutator;
+use crate::mutators::splicemutator::SpliceMutator;
+use crate::mutators::insertionmutator::InsertionMutator;
+use crate::mutators::reductionmutator::ReductionMutator;
+use crate::utils::probablity::WeightTable;
+
+/// A code generator, which adds code to the program or returns `None` if it
+/// can't do that at the current point of the program
+pub type Generator = fn(&mut Program) -> Option<()>;
 
-pub const BASIC_GENERATORS: [fn(&mut Program) -> Option<()>; 5] = [
+pub const BASIC_GENERATORS: [Generator; 5] = [
     CodeGenerators::undefined_literal_generator,
     CodeGenerators::string_literal_generator,
     CodeGenerators::bool_literal_generator,
@@ -9,34 +21,146 @@ pub const BASIC_GENERATORS: [fn(&mut Program) -> Option<()>; 5] = [
     CodeGenerators::integer_literal_generator,
 ];
 
-pub const GENERATORS: [(fn(&mut Program) -> Option<()>, u16); 29] = [
-    (CodeGenerators::create_object_generator,       30),
-    (CodeGenerators::jit_function_generator,        30),
-    (CodeGenerators::load_builtin_generator,        50),
-    (CodeGenerators::method_call_generator,         35),
-    (CodeGenerators::store_property_generator,      45),
-    (CodeGenerators::load_property_generator,       30),
-    (CodeGenerators::function_call_generator,       40),
-    (CodeGenerators::load_element_generator,        30),
-    (CodeGenerators::int_array_generator,           30),
-    (CodeGenerators::if_condition_generator,        10),
-    (CodeGenerators::binary_op_generator,           30),
-    (CodeGenerators::for_loop_generator,            15),
-    (CodeGenerators::store_element_generator,       40),
-    (CodeGenerators::unary_op_generator,            30),
-    (CodeGenerators::compare_op_generator,          30),
-    (CodeGenerators::delete_property_generator,     30),
-    (CodeGenerators::function_return_generator,     10),
-    (CodeGenerators::function_definition_generator, 30),
-    (CodeGenerators::float_array_generator,         30),
-    (CodeGenerators::empty_loop_generator,          20),
-    (CodeGenerators::nop_generator,                 1),
-    (CodeGenerators::copy_generator,                1),
-    (CodeGenerators::break_generator,               5),
-    (CodeGenerators::continue_generator,            5),
-    (CodeGenerators::integer_literal_generator,     5),
-    (CodeGenerators::float_literal_generator,       1),
-    (CodeGenerators::string_literal_generator,      1),
-    (CodeGenerators::bool_literal_generator,        1),
-    (CodeGenerators::undefined_literal_generator,   1),
+/// Defines [GENERATORS] out of the names of the code generators and their
+/// weights, along with [GENERATOR_NAMES] which has the names in the same order
+macro_rules! generators {
+    ($(($name: ident, $weight: expr)),* $(,)?) => {
+        pub const GENERATORS: [(Generator, u16); GENERATOR_NAMES.len()] = [
+            $((CodeGenerators::$name, $weight)),*
+        ];
+
+        pub const GENERATOR_NAMES: [&str; [$(stringify!($name)),*].len()] = [
+            $(stringify!($name)),*
+        ];
+    };
+}
+
+generators! {
+    (create_object_generator,         30),
+    (jit_function_generator,          30),
+    (load_builtin_generator,          50),
+    (method_call_generator,           35),
+    (store_property_generator,        45),
+    (load_property_generator,         30),
+    (function_call_generator,         40),
+    (load_element_generator,          30),
+    (int_array_generator,             30),
+    (if_condition_generator,          10),
+    (binary_op_generator,             30),
+    (for_loop_generator,              15),
+    (store_element_generator,         40),
+    (unary_op_generator,              30),
+    (compare_op_generator,            30),
+    (delete_property_generator,       30),
+    (function_return_generator,       10),
+    (function_definition_generator,   30),
+    (float_array_generator,           30),
+    (empty_loop_generator,            20),
+    (nop_generator,                   1),
+    (copy_generator,                  1),
+    (break_generator,                 5),
+    (continue_generator,              5),
+    (integer_literal_generator,       5),
+    (float_literal_generator,         1),
+    (string_literal_generator,        1),
+    (bool_literal_generator,          1),
+    (undefined_literal_generator,     1),
+    (arraybuffer_detach_generator,    10),
+    (array_length_mutation_generator, 10),
+    (polymorphic_ic_generator,        10),
+    (deopt_trigger_generator,         10),
+    (osr_stress_generator,            5),
+    (higher_order_function_generator, 10),
+    (iife_generator,                  10),
+    (call_apply_bind_generator,       20),
+    (eval_generator,                  5),
+    (builtin_function_call_generator, 15),
+    (method_chain_generator,          15),
+    (constructor_generator,           20),
+    (gc_pressure_generator,           5),
+}
+
+/// The table of generators that the programs are generated with, once it was
+/// built out of the weights given on the command line
+static WEIGHTED: OnceLock<(Vec<(Generator, u16)>, WeightTable)> = OnceLock::new();
+
+/// The weights of [GENERATORS] when no others were set
+static DEFAULT_WEIGHTS: OnceLock<WeightTable> = OnceLock::new();
+
+/// The weights of [MUTATORS]
+static MUTATOR_WEIGHTS: OnceLock<WeightTable> = OnceLock::new();
+
+/// Get the index into [GENERATORS] of the generator called `name`. The
+/// `_generator` at the end of the names can be left out.
+pub fn generator_index(name: &str) -> Option<usize> {
+    GENERATOR_NAMES.iter().position(|&generator| {
+        generator == name || generator.strip_suffix("_generator") == Some(name)
+    })
+}
+
+/// Build a table like [GENERATORS], with the weights of the generators at the
+/// indices in `weights` changed to the ones that are given with them
+fn build_weighted(weights: &[(usize, u16)]) -> Vec<(Generator, u16)> {
+    let mut table = GENERATORS.to_vec();
+    for &(idx, weight) in weights {
+        table[idx].1 = weight;
+    }
+    table
+}
+
+/// Set the weights of the generators at the indices in `weights`, for all the
+/// programs that are generated from here on. This is done once on startup,
+/// and the calls after the first one are ignored.
+pub fn set_generator_weights(weights: &[(usize, u16)]) {
+    let table = build_weighted(weights);
+    let weights = WeightTable::new(&table);
+    let _ = WEIGHTED.set((table, weights));
+}
+
+/// The generators to generate programs with, along with their weights. These
+/// are the ones in [GENERATORS] unless other weights were set with
+/// [set_generator_weights].
+pub fn generators() -> &'static [(Generator, u16)] {
+    match WEIGHTED.get() {
+        Some((table, _)) => table,
+        None             => &GENERATORS,
+    }
+}
+
+/// The weights of [generators], to choose one of them with
+pub fn generator_weights() -> &'static WeightTable {
+    match WEIGHTED.get() {
+        Some((_, weights)) => weights,
+        None => DEFAULT_WEIGHTS.get_or_init(|| WeightTable::new(&GENERATORS)),
+    }
+}
+
+/// The weights of [MUTATORS], to choose one of them with
+pub fn mutator_weights() -> &'static WeightTable {
+    MUTATOR_WEIGHTS.get_or_init(|| WeightTable::new(&MUTATORS))
+}
+
+pub const MUTATORS: [(&dyn Mutator, u16); 4] = [
+    (&InputMutator,                                 10),
+    (&SpliceMutator,                                10),
+    (&InsertionMutator,                             15),
+    (&ReductionMutator,                             5),
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generator_weights() {
+        let idx = generator_index("method_call").unwrap();
+        assert_eq!(generator_index("method_call_generator"), Some(idx));
+        assert_eq!(generator_index("method"), None);
+
+        let table = build_weighted(&[(idx, 80), (0, 0)]);
+        assert_eq!(table.len(), GENERATORS.len());
+        assert_eq!(table[idx].1, 80);
+        assert_eq!(table[0].1, 0);
+        assert_eq!(table[1].1, GENERATORS[1].1);
+    }
+}
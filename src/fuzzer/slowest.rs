@@ -0,0 +1,80 @@
+//! Keeps track of the slowest programs that ran to completion. Programs that
+//! take much longer than the others often hit quadratic behaviour in the
+//! engine or come close to hanging it, which is worth reporting as well.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub struct Slowest {
+    /// The no. of programs to keep
+    keep:      usize,
+
+    /// The run times of the kept programs along with the names of their
+    /// files, slowest first
+    programs:  Mutex<Vec<(Duration, String)>>,
+
+    /// The run time in microseconds that a program has to beat to be kept.
+    /// This is checked first, so the threads only take the lock for programs
+    /// that will be kept.
+    threshold: AtomicU64,
+}
+
+impl Slowest {
+
+    pub fn new(keep: usize) -> Self {
+        Self {
+            keep,
+            programs:  Mutex::new(Vec::with_capacity(keep + 1)),
+            threshold: AtomicU64::new(0),
+        }
+    }
+
+    /// Check if a program that ran for `time` is one of the slowest so far
+    pub fn is_slow(&self, time: Duration) -> bool {
+        self.keep > 0 &&
+            time.as_micros() as u64 > self.threshold.load(Ordering::Relaxed)
+    }
+
+    /// Keep the program that ran for `time` and was saved to `filename`.
+    /// Returns the file of the program that is no longer one of the slowest,
+    /// so that it can be removed.
+    pub fn add(&self, time: Duration, filename: String) -> Option<String> {
+        let mut programs = self.programs.lock().expect("Lock Poisoned");
+        let idx = programs.iter().position(|(slow, _)| *slow < time)
+                          .unwrap_or(programs.len());
+        programs.insert(idx, (time, filename));
+
+        let removed = if programs.len() > self.keep {
+            programs.pop().map(|(_, filename)| filename)
+        } else {
+            None
+        };
+        if programs.len() == self.keep {
+            let fastest = programs[self.keep - 1].0.as_micros() as u64;
+            self.threshold.store(fastest, Ordering::Relaxed);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slowest() {
+        let slowest = Slowest::new(2);
+        let ms = Duration::from_millis;
+
+        assert!(slowest.is_slow(ms(1)));
+        assert_eq!(slowest.add(ms(5), "a".into()), None);
+        assert_eq!(slowest.add(ms(1), "b".into()), None);
+        assert!(!slowest.is_slow(ms(1)));
+        assert!(slowest.is_slow(ms(2)));
+        assert_eq!(slowest.add(ms(3), "c".into()), Some("b".into()));
+        assert!(!slowest.is_slow(ms(3)));
+
+        assert!(!Slowest::new(0).is_slow(ms(100)));
+    }
+}
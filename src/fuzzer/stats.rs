@@ -1,11 +1,83 @@
-use std::time::Instant;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
 
-#[derive(Clone, Debug, Default)]
+use super::settings::GENERATOR_NAMES;
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Stats {
     pub iter:       u64,
     pub crashes:    u64,
     pub timeouts:   u64,
     pub incorrect:  u64,
+    pub unstable:   u64,
+    pub ooms:       u64,
+    pub miscompiles: u64,
+    pub edges:      u64,
+
+    /// The no. of programs whose syntax was checked, and how many of them
+    /// were valid
+    pub checked:    u64,
+    pub valid:      u64,
+
+    /// The same as `checked` and `valid`, for the programs that each of the
+    /// generators added code to. Indexed like [GENERATORS](super::settings::GENERATORS).
+    pub generator_checked: Vec<u64>,
+    pub generator_valid:   Vec<u64>,
+
+    /// How often each of the generators was called, how often it failed, and
+    /// the no. of instructions that it added, indexed like `generator_checked`
+    pub generator_chosen:  Vec<u64>,
+    pub generator_failed:  Vec<u64>,
+    pub generator_insts:   Vec<u64>,
+
+    /// The no. of programs that each of the generators added code to that
+    /// crashed or timed out
+    pub generator_crashes:  Vec<u64>,
+    pub generator_timeouts: Vec<u64>,
+
+    /// The total run time of the programs that ran to completion, in
+    /// microseconds, and how many of them ran for `[2^i, 2^(i+1))`
+    /// microseconds for each bucket `i`
+    pub exec_micros: u64,
+    pub exec_times:  Vec<u64>,
+
+    /// The most memory that any program used, in bytes, when the target
+    /// reports it
+    pub peak_rss:    u64,
+
+    /// The no. of instructions of the programs that ran, and how many of them
+    /// were dead, see [Liveness](crate::ir::codeanalysis::liveness::Liveness)
+    pub insts:       u64,
+    pub dead_insts:  u64,
+
+    /// The no. of programs that were skipped as they were the same as one that
+    /// ran lately, with `--dedup`
+    pub duplicates:  u64,
+}
+
+/// Add each of the `counts` to the one at the same index of `total`
+fn add_counts(total: &mut Vec<u64>, counts: &[u64]) {
+    if total.len() < counts.len() {
+        total.resize(counts.len(), 0);
+    }
+    for (idx, count) in counts.iter().enumerate() {
+        total[idx] += count;
+    }
+}
+
+/// Get the run time that `fraction` of the programs in the histogram `times`
+/// finished within. This is the upper end of the bucket it falls into.
+fn percentile(times: &[u64], fraction: f64) -> Duration {
+    let total = times.iter().sum::<u64>();
+    let wanted = (total as f64 * fraction).ceil() as u64;
+    let mut seen = 0;
+    for (bucket, count) in times.iter().enumerate() {
+        seen += count;
+        if seen >= wanted {
+            return Duration::from_micros(1 << (bucket + 1));
+        }
+    }
+    Duration::ZERO
 }
 
 impl Stats {
@@ -14,36 +86,179 @@ impl Stats {
         self.crashes   = 0;
         self.timeouts  = 0;
         self.incorrect = 0;
+        self.unstable  = 0;
+        self.ooms      = 0;
+        self.miscompiles = 0;
+        self.edges     = 0;
+        self.checked   = 0;
+        self.valid     = 0;
+        self.generator_checked.clear();
+        self.generator_valid.clear();
+        self.generator_chosen.clear();
+        self.generator_failed.clear();
+        self.generator_insts.clear();
+        self.generator_crashes.clear();
+        self.generator_timeouts.clear();
+        self.exec_micros = 0;
+        self.exec_times.clear();
+        self.peak_rss  = 0;
+        self.insts     = 0;
+        self.dead_insts = 0;
+        self.duplicates = 0;
+    }
+
+    /// Count a program that ran to completion in `time`
+    pub fn add_exec_time(&mut self, time: Duration) {
+        let micros = time.as_micros() as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()).saturating_sub(1);
+        if self.exec_times.len() <= bucket as usize {
+            self.exec_times.resize(bucket as usize + 1, 0);
+        }
+        self.exec_times[bucket as usize] += 1;
+        self.exec_micros += micros;
     }
 
-    pub fn print(&self, start: &Instant) {
-        let esc = 27 as char;
-        // let esc = 61 as char;
+    /// Write out the mean and percentiles of the run times to `out`
+    fn report_exec_times(&self, out: &mut String) {
+        let completed = self.exec_times.iter().sum::<u64>();
+        if completed == 0 {
+            return;
+        }
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        let _ = writeln!(out, "Exec Time       = {:.2}ms mean, p50 under {:.1}ms, \
+                               p99 under {:.1}ms",
+                 self.exec_micros as f64 / completed as f64 / 1000.0,
+                 ms(percentile(&self.exec_times, 0.5)),
+                 ms(percentile(&self.exec_times, 0.99)));
+        if self.peak_rss != 0 {
+            let _ = writeln!(out, "Peak Memory     = {} MB", self.peak_rss >> 20);
+        }
+    }
+
+    /// Count the result of checking the syntax of a program that the
+    /// `generators` added code to
+    pub fn add_syntax_check(&mut self, generators: &[usize], valid: bool) {
+        self.checked += 1;
+        self.valid   += valid as u64;
+
+        self.generator_checked.resize(GENERATOR_NAMES.len(), 0);
+        self.generator_valid.resize(GENERATOR_NAMES.len(), 0);
+        for &idx in generators {
+            self.generator_checked[idx] += 1;
+            self.generator_valid[idx]   += valid as u64;
+        }
+    }
+
+    /// Count the `calls` of the generators that were made for a program, see
+    /// [Program::generator_calls](crate::ir::program::Program::generator_calls)
+    pub fn add_generator_calls(&mut self, calls: &[(usize, Option<usize>)]) {
+        if calls.is_empty() {
+            return;
+        }
+
+        self.generator_chosen.resize(GENERATOR_NAMES.len(), 0);
+        self.generator_failed.resize(GENERATOR_NAMES.len(), 0);
+        self.generator_insts.resize(GENERATOR_NAMES.len(), 0);
+        for &(idx, insts) in calls {
+            self.generator_chosen[idx] += 1;
+            match insts {
+                Some(insts) => self.generator_insts[idx] += insts as u64,
+                None        => self.generator_failed[idx] += 1,
+            }
+        }
+    }
+
+    /// Count a crash, or a timeout unless `crashed` is set, of a program that
+    /// the `generators` added code to. Each generator is counted once for the
+    /// program, even if it added code to it more than once.
+    pub fn add_finding(&mut self, generators: &[usize], crashed: bool) {
+        let counts = if crashed {
+            &mut self.generator_crashes
+        } else {
+            &mut self.generator_timeouts
+        };
+        counts.resize(GENERATOR_NAMES.len(), 0);
+
+        let mut seen = vec![false; GENERATOR_NAMES.len()];
+        for &idx in generators {
+            if !std::mem::replace(&mut seen[idx], true) {
+                counts[idx] += 1;
+            }
+        }
+    }
+
+    /// Write out the share of the instructions that were dead to `out`
+    fn report_dead_code(&self, out: &mut String) {
+        if self.insts == 0 {
+            return;
+        }
+        let _ = writeln!(out, "Dead Code       = {:.2}% of {} instructions",
+                 (self.dead_insts as f64 / self.insts as f64) * 100.0,
+                 self.insts);
+        if self.duplicates != 0 {
+            let _ = writeln!(out, "Duplicates      = {} skipped",
+                             self.duplicates);
+        }
+    }
+
+    /// Write out the syntactic validity rate of the checked programs to `out`,
+    /// along with the generators whose programs were not always valid
+    fn report_syntax(&self, out: &mut String) {
+        if self.checked == 0 {
+            return;
+        }
+        let _ = writeln!(out, "Syntax Valid    = {:.2}% of {}",
+                 (self.valid as f64 / self.checked as f64) * 100.0,
+                 self.checked);
+
+        let generators = self.generator_checked.iter()
+            .zip(&self.generator_valid)
+            .zip(GENERATOR_NAMES);
+        for ((&checked, &valid), name) in generators {
+            if valid < checked {
+                let _ = writeln!(out, "    {:<35} = {:.2}% of {}", name,
+                         (valid as f64 / checked as f64) * 100.0, checked);
+            }
+        }
+    }
+
+    /// The statistics as they are printed, for a campaign that began at
+    /// `start`
+    pub fn report(&self, start: &Instant) -> String {
         let elapsed = start.elapsed();
         let total_samples = self.iter;
         let total_crashes = self.crashes;
         let correctness = 100.0 - (((self.incorrect + self.timeouts) as f64 /(total_samples as f64))*100.0);
         let fcps = total_samples as f64 / ((elapsed.as_micros()) as f64 / 1000000 as f64);
-        // println!("{}[2J{}[1;1H\
-        println!("
+        let mut out = format!("
 -----------------------
 fcps            = {:.0}/s
 Timeouts        = {}
 Crashes         = {}
 Incorrect Cases = {}
+Unstable Cases  = {}
+Out of Memory   = {}
+Miscompiles     = {}
 Correctness     = {:.2}%
+Edges Found     = {}
 Runtime         = {} seconds
-Total Cases     = {}",
-                 // esc,
-                 // esc,
+Total Cases     = {}\n",
                  fcps,
                  self.timeouts,
                  total_crashes,
                  self.incorrect,
+                 self.unstable,
+                 self.ooms,
+                 self.miscompiles,
                  correctness,
+                 self.edges,
                  elapsed.as_secs(),
                  total_samples
         );
+        self.report_exec_times(&mut out);
+        self.report_dead_code(&mut out);
+        self.report_syntax(&mut out);
+        out
     }
 
     pub fn update(&mut self, other: &Stats) {
@@ -51,5 +266,25 @@ Total Cases     = {}",
         self.crashes   += other.crashes;
         self.timeouts  += other.timeouts;
         self.incorrect += other.incorrect;
+        self.unstable  += other.unstable;
+        self.ooms      += other.ooms;
+        self.miscompiles += other.miscompiles;
+        self.edges     += other.edges;
+        self.checked   += other.checked;
+        self.valid     += other.valid;
+        self.exec_micros += other.exec_micros;
+        self.peak_rss = std::cmp::max(self.peak_rss, other.peak_rss);
+        self.insts     += other.insts;
+        self.dead_insts += other.dead_insts;
+        self.duplicates += other.duplicates;
+
+        add_counts(&mut self.exec_times, &other.exec_times);
+        add_counts(&mut self.generator_checked, &other.generator_checked);
+        add_counts(&mut self.generator_valid, &other.generator_valid);
+        add_counts(&mut self.generator_chosen, &other.generator_chosen);
+        add_counts(&mut self.generator_failed, &other.generator_failed);
+        add_counts(&mut self.generator_insts, &other.generator_insts);
+        add_counts(&mut self.generator_crashes, &other.generator_crashes);
+        add_counts(&mut self.generator_timeouts, &other.generator_timeouts);
     }
 }
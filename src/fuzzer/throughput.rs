@@ -0,0 +1,92 @@
+//! Keeps track of how fast the campaign runs programs lately. The rate over the
+//! whole campaign hides slowdowns that start late, like an engine that gets
+//! slower as it leaks memory, so this looks at a window of the last minute.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How far back the rate is measured
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+pub struct Throughput {
+    /// When the total no. of programs that ran was sampled, along with it and
+    /// the no. of them that timed out. The oldest one is the last one from
+    /// before the window, so that the window is covered in full.
+    samples: VecDeque<(Instant, u64, u64)>,
+}
+
+impl Throughput {
+
+    pub fn new() -> Self {
+        Self { samples: VecDeque::new() }
+    }
+
+    /// Record that `total` programs ran so far, at `now`, and `timeouts` of
+    /// them timed out
+    pub fn add(&mut self, now: Instant, total: u64, timeouts: u64) {
+        self.samples.push_back((now, total, timeouts));
+        while self.samples.len() > 2 &&
+            now.duration_since(self.samples[1].0) >= WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The time that the rate is measured over. This is about [WINDOW], or
+    /// the time since the first sample when there is less of it.
+    pub fn span(&self) -> Duration {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first, ..)), Some((last, ..))) => last.duration_since(*first),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The no. of programs that ran per second over the [Throughput::span].
+    /// Returns `None` until there are two samples to measure it with.
+    pub fn rate(&self) -> Option<f64> {
+        let (programs, _) = self.recent();
+        let elapsed = self.span().as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some(programs as f64 / elapsed)
+    }
+
+    /// The no. of programs that ran over the [Throughput::span], and how many
+    /// of them timed out
+    pub fn recent(&self) -> (u64, u64) {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((_, first, first_timeouts)), Some((_, last, last_timeouts))) => {
+                (last.saturating_sub(*first),
+                 last_timeouts.saturating_sub(*first_timeouts))
+            },
+            _ => (0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_throughput() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut throughput = Throughput::new();
+        throughput.add(at(0), 0, 0);
+        assert_eq!(throughput.rate(), None);
+
+        // 100 programs a second for 2 minutes, then 10 a second
+        for secs in 1..=120 {
+            throughput.add(at(secs), secs * 100, 0);
+        }
+        assert_eq!(throughput.rate(), Some(100.0));
+        for secs in 121..=180 {
+            throughput.add(at(secs), 12000 + (secs - 120) * 10, secs - 120);
+        }
+        assert_eq!(throughput.rate(), Some(10.0));
+        assert_eq!(throughput.span(), Duration::from_secs(60));
+        assert_eq!(throughput.recent(), (600, 60));
+    }
+}
@@ -0,0 +1,103 @@
+//! Adaptive timeouts. Most programs finish in a small part of the fixed
+//! timeout, so waiting for the full timeout on every hang wastes most of the
+//! time that is spent on them. Instead, the timeout is derived from how long
+//! the programs that ran before took, and is never longer than `--timeout`.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The no. of the latest execution times that the timeout is derived from
+const WINDOW: usize = 1000;
+
+/// The no. of execution times that are needed before the timeout is made any
+/// shorter than the max
+const MIN_SAMPLES: usize = 100;
+
+/// How many times longer than the 99th percentile of the execution times a
+/// program may run
+const FACTOR: u32 = 4;
+
+/// The no. of execution times after which the timeout is derived again
+const UPDATE_INTERVAL: u64 = 100;
+
+/// The timeout never gets shorter than this
+const MIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub struct AdaptiveTimeout {
+    /// The latest execution times, oldest first
+    times: VecDeque<Duration>,
+
+    /// The timeout that is given on the command line
+    max:   Duration,
+
+    /// The no. of execution times that were added so far
+    added: u64,
+}
+
+impl AdaptiveTimeout {
+
+    pub fn new(max: Duration) -> Self {
+        Self {
+            times: VecDeque::with_capacity(WINDOW),
+            max,
+            added: 0,
+        }
+    }
+
+    /// Add the time that a program took to run to completion. Returns true
+    /// every [UPDATE_INTERVAL] times, when the timeout should be derived again.
+    pub fn add(&mut self, time: Duration) -> bool {
+        if self.times.len() == WINDOW {
+            self.times.pop_front();
+        }
+        self.times.push_back(time);
+        self.added += 1;
+        self.added.is_multiple_of(UPDATE_INTERVAL)
+    }
+
+    /// Get the execution time that `fraction` of the execution times are
+    /// shorter than
+    fn percentile(&self, fraction: f64) -> Duration {
+        let mut times = self.times.iter().copied().collect::<Vec<Duration>>();
+        times.sort_unstable();
+        let idx = ((times.len() - 1) as f64 * fraction).round() as usize;
+        times[idx]
+    }
+
+    /// Get the timeout for the programs that come next
+    pub fn timeout(&self) -> Duration {
+        if self.times.len() < MIN_SAMPLES {
+            return self.max;
+        }
+        std::cmp::min(std::cmp::max(self.percentile(0.99) * FACTOR,
+                                    MIN_TIMEOUT), self.max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_timeout() {
+        let max = Duration::from_secs(5);
+        let mut timeout = AdaptiveTimeout::new(max);
+        assert!(!timeout.add(Duration::from_millis(10)));
+        assert_eq!(timeout.timeout(), max);
+
+        for _ in 0..MIN_SAMPLES {
+            timeout.add(Duration::from_millis(10));
+        }
+        assert_eq!(timeout.timeout(), MIN_TIMEOUT);
+
+        for _ in 0..WINDOW {
+            timeout.add(Duration::from_millis(500));
+        }
+        assert_eq!(timeout.timeout(), Duration::from_secs(2));
+
+        for _ in 0..WINDOW {
+            timeout.add(Duration::from_secs(3));
+        }
+        assert_eq!(timeout.timeout(), max);
+    }
+}
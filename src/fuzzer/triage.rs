@@ -0,0 +1,96 @@
+//! Sorts the crashes that are found into directories, so that crashes which are
+//! most likely caused by the same bug end up next to each other. Crashes are
+//! first split up by the signal that they crashed with and then into buckets
+//! that are derived from the stderr of the target.
+
+use crate::utils::hash::fnv1a;
+
+/// The no. of frames at the top of a stack trace that make up a bucket
+const BUCKET_FRAMES: usize = 3;
+
+/// The bucket of the crashes whose stderr has nothing to tell them apart by
+pub const UNKNOWN_BUCKET: &str = "unknown";
+
+/// Get the name of `signal` to use for its directory
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        4  => "SIGILL",
+        5  => "SIGTRAP",
+        6  => "SIGABRT",
+        // SIGBUS is the one that differs between Linux and the BSDs
+        #[cfg(target_os = "linux")]
+        7  => "SIGBUS",
+        #[cfg(not(target_os = "linux"))]
+        10 => "SIGBUS",
+        8  => "SIGFPE",
+        11 => "SIGSEGV",
+        _  => return format!("SIG{}", signal),
+    };
+    name.to_string()
+}
+
+/// Get the function out of a line of a stack trace like the ones printed by the
+/// sanitizers or gdb, which look like `#0 0x5555 in js::Foo(int) file.cpp:1`
+fn frame_function(line: &str) -> Option<&str> {
+    let line = line.trim_start().strip_prefix('#')?;
+    if !line.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let function = &line[line.find(" in ")? + 4..];
+    function.split(|c: char| c == '(' || c.is_whitespace()).next()
+}
+
+/// Get the bucket of a crash out of the `stderr` of the target. Crashes with
+/// the same top frames in their stack trace, or with the same last line of
+/// output when there is no stack trace, get the same bucket. Numbers and
+/// addresses are left out of the last line, as they change between runs.
+pub fn bucket(stderr: &str) -> String {
+    let frames = stderr.lines()
+        .filter_map(frame_function)
+        .take(BUCKET_FRAMES)
+        .collect::<Vec<&str>>();
+
+    let key = if !frames.is_empty() {
+        frames.join("\n")
+    } else {
+        let last = stderr.lines().rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("");
+        last.split_whitespace()
+            .filter(|word| !word.starts_with("0x"))
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .replace(|c: char| c.is_ascii_digit(), "")
+    };
+
+    if key.is_empty() {
+        UNKNOWN_BUCKET.to_string()
+    } else {
+        format!("{:016x}", fnv1a(&key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(signal_name(11), "SIGSEGV");
+        assert_eq!(signal_name(31), "SIG31");
+
+        let first  = "ERROR: AddressSanitizer: heap-use-after-free\n\
+                      #0 0x5555 in js::Foo(int) foo.cpp:1\n\
+                      #1 0x5556 in js::Bar() foo.cpp:2\n";
+        let second = "ERROR: AddressSanitizer: heap-use-after-free\n\
+                      #0 0x7777 in js::Foo(int) foo.cpp:1\n\
+                      #1 0x7778 in js::Bar() foo.cpp:2\n";
+        assert_eq!(bucket(first), bucket(second));
+        assert_ne!(bucket(first), bucket("#0 0x5555 in js::Baz() foo.cpp:1"));
+
+        assert_eq!(bucket("Assertion failure: x, at foo.cpp:10\n"),
+                   bucket("Assertion failure: x, at foo.cpp:12\n\n"));
+        assert_eq!(bucket(""), UNKNOWN_BUCKET);
+    }
+}
@@ -9,3 +9,6 @@ pub mod operators;
 pub mod program;
 pub mod variable;
 pub mod codeanalysis;
+pub mod serialize;
+pub mod fuzzilli;
+pub mod normalize;
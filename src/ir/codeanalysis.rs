@@ -1,3 +1,4 @@
 pub mod types;
 pub mod analyzers;
 pub mod typeanalyzer;
+pub mod liveness;
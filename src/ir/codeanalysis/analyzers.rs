@@ -1,10 +1,18 @@
 #![allow(dead_code)]
 
+use std::collections::HashMap;
+
 use super::super::instruction::Instruction;
 use super::super::variable::Variable;
+use super::super::operation::OperationInfo;
+use super::types::PType;
+
+/// The no. of primitive types, one for each bit of a [PType]
+const PTYPES: usize = 8;
 
 /// This will be used to tell the current context of the instruction and if its
 /// in a Loop context or not.
+#[derive(Clone)]
 pub struct ContextAnalyzer {
     context: Vec<u8>,
 }
@@ -22,6 +30,12 @@ impl ContextAnalyzer {
         }
     }
 
+    /// Go back to the state of a new analyzer, keeping the memory around
+    pub fn reset(&mut self) {
+        self.context.clear();
+        self.context.push(ContextAnalyzer::GLOBAL_CONTEXT);
+    }
+
     pub fn analyze(&mut self, inst: &Instruction) {
 
         // If the current inst is a loop start instruction, then first we need
@@ -135,8 +149,22 @@ impl ContextAnalyzer {
 /// information will be later used to find the appropriate variable to use in
 /// the code generators as we don't wan't to use a variable that has gone out of
 /// scope or is not yet declared.
+///
+/// The variables of each scope are indexed by the primitive types that they can
+/// be of as well, so that looking for a variable of a type only goes over the
+/// ones that can be of it. The type analyzer tells this about the types that
+/// the variables gain and lose, see [ScopeAnalyzer::add_type].
+#[derive(Clone)]
 pub struct ScopeAnalyzer<> {
     scope: Vec<Vec<Variable>>,
+
+    /// For each of the scopes, the positions in it of the variables that can
+    /// be of each of the primitive types, by the bit of the type
+    typed: Vec<[Vec<usize>; PTYPES]>,
+
+    /// The scope that each of the visible variables is in, and its position
+    /// in there
+    position: HashMap<u32, (usize, usize)>,
 }
 
 impl ScopeAnalyzer{
@@ -145,6 +173,28 @@ impl ScopeAnalyzer{
         scope.push(Vec::<Variable>::new());
         Self {
             scope: scope,
+            typed: vec![Default::default()],
+            position: HashMap::new(),
+        }
+    }
+
+    /// Go back to the state of a new analyzer, keeping the memory of the
+    /// global scope around
+    pub fn reset(&mut self) {
+        self.scope.truncate(1);
+        self.scope[0].clear();
+        self.typed.truncate(1);
+        self.typed[0].iter_mut().for_each(|positions| positions.clear());
+        self.position.clear();
+    }
+
+    /// Add `variables` to the current scope
+    fn declare(&mut self, variables: &[Variable]) {
+        let depth = self.scope.len() - 1;
+        let scope = self.scope.last_mut().unwrap();
+        for variable in variables {
+            self.position.insert(variable.0, (depth, scope.len()));
+            scope.push(*variable);
         }
     }
 
@@ -152,8 +202,7 @@ impl ScopeAnalyzer{
 
         // For all instructions we need to append the variables that they create
         // into the current scope so that they can be tracked.
-        let scope = self.scope.last_mut().unwrap();
-        scope.extend(inst.outputs());
+        self.declare(inst.outputs());
 
         if inst.operation.is_block_end() {
 
@@ -161,7 +210,12 @@ impl ScopeAnalyzer{
             // topmost scope in the scopes stack.
 
             debug_assert!(self.scope.len() > 1, "trying to pop global scope");
-            self.scope.pop();
+            if let Some(scope) = self.scope.pop() {
+                for variable in scope {
+                    self.position.remove(&variable.0);
+                }
+            }
+            self.typed.pop();
 
         }
 
@@ -171,9 +225,9 @@ impl ScopeAnalyzer{
             // scope and push all the temp variables of this scope, any onto the
             // current scope
 
-            let mut scope = Vec::<Variable>::new();
-            scope.extend(inst.temp());
-            self.scope.push(scope);
+            self.scope.push(Vec::<Variable>::new());
+            self.typed.push(Default::default());
+            self.declare(inst.temp());
 
         }
 
@@ -203,12 +257,115 @@ impl ScopeAnalyzer{
         &self.scope
     }
 
+    /// Record that `variable` can be of the primitive types in `ptype` now, on
+    /// top of the ones that it could be of before. Variables that are not
+    /// visible anymore are left out.
+    pub fn add_type(&mut self, variable: &Variable, ptype: PType) {
+        if let Some(&(depth, position)) = self.position.get(&variable.0) {
+            for (bit, positions) in self.typed[depth].iter_mut().enumerate() {
+                if ptype.bits() & (1 << bit) != 0 {
+                    positions.push(position);
+                }
+            }
+        }
+    }
+
+    /// Record that `variable` can't be of the primitive types in `ptype`
+    /// anymore, like after it was assigned a value of another type
+    pub fn remove_type(&mut self, variable: &Variable, ptype: PType) {
+        if let Some(&(depth, position)) = self.position.get(&variable.0) {
+            for (bit, positions) in self.typed[depth].iter_mut().enumerate() {
+                if ptype.bits() & (1 << bit) != 0 {
+                    positions.retain(|&other| other != position);
+                }
+            }
+        }
+    }
+
+    /// The variables of the scope at `depth` that can be of any of the
+    /// primitive types in `ptype`, in the order that they were declared in
+    pub fn variables_of_type(&self, depth: usize, ptype: PType) -> Vec<Variable> {
+        let mut positions = self.typed[depth].iter().enumerate()
+            .filter(|(bit, _)| ptype.bits() & (1 << bit) != 0)
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect::<Vec<usize>>();
+        positions.sort_unstable();
+        positions.dedup();
+        positions.into_iter().map(|position| self.scope[depth][position])
+                 .collect()
+    }
+
+    /// The variables of all the visible scopes that can be of any of the
+    /// primitive types in `ptype`, like [ScopeAnalyzer::get_visible_variables]
+    pub fn visible_variables_of_type(&self, ptype: PType) -> Vec<Variable> {
+        (0..self.scope.len())
+            .flat_map(|depth| self.variables_of_type(depth, ptype))
+            .collect()
+    }
+
     #[cfg(debug_assertions)]
     fn debug_print(&self) {
         println!("scopes = {:?}", self.get_visible_variables());
     }
 }
 
+/// Records where each of the variables is defined and used, by the indices of
+/// the instructions in the program. A variable is defined by the instruction
+/// that outputs it, or by the block start that it is a temp of, like the
+/// parameters of a function. Every instruction that takes it as an input uses
+/// it, an assignment to it included.
+#[derive(Clone)]
+pub struct DefUseAnalyzer {
+    definitions: HashMap<u32, u32>,
+
+    /// The instructions that use each of the variables, in order
+    uses:        HashMap<u32, Vec<u32>>,
+}
+
+impl DefUseAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            definitions: HashMap::new(),
+            uses:        HashMap::new(),
+        }
+    }
+
+    /// Go back to the state of a new analyzer, keeping the memory around
+    pub fn reset(&mut self) {
+        self.definitions.clear();
+        self.uses.clear();
+    }
+
+    pub fn analyze(&mut self, inst: &Instruction) {
+        for variable in inst.outputs().iter().chain(inst.temp().iter()) {
+            self.definitions.insert(variable.0, inst.idx);
+        }
+
+        // An instruction that takes the same variable twice uses it once
+        for (idx, variable) in inst.inputs().iter().enumerate() {
+            if inst.inputs()[..idx].iter().any(|v| v.0 == variable.0) {
+                continue;
+            }
+            self.uses.entry(variable.0).or_default().push(inst.idx);
+        }
+    }
+
+    /// The index of the instruction that defines `variable`, if it was
+    /// defined yet
+    pub fn definition(&self, variable: &Variable) -> Option<u32> {
+        self.definitions.get(&variable.0).copied()
+    }
+
+    /// The indices of the instructions that use `variable`, in order
+    pub fn uses(&self, variable: &Variable) -> &[u32] {
+        self.uses.get(&variable.0).map_or(&[], |uses| uses.as_slice())
+    }
+
+    pub fn is_used(&self, variable: &Variable) -> bool {
+        !self.uses(variable).is_empty()
+    }
+}
+
 
 #[cfg(test)]
 mod test {
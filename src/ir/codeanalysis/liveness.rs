@@ -0,0 +1,178 @@
+//! Finds the instructions of a finished program whose values are never used.
+//! An instruction is live if it can have an effect that the engine or the
+//! program can see, like a call or a store, or if a live instruction uses one
+//! of its outputs. The rest of them are dead, and leaving them out doesn't
+//! change what the program does.
+
+use std::collections::HashSet;
+
+use super::super::instruction::Instruction;
+use super::super::operation::*;
+use super::super::operators::UnaryOperators;
+use super::super::opcodes::Opcodes as op;
+use super::types::ConstructorType;
+
+pub struct Liveness {
+    /// Whether each of the instructions of the program is dead, by its index
+    dead: Vec<bool>,
+}
+
+/// The variables of `buffer` that can only ever hold primitive values, so that
+/// the arithmetic on them can't call into the program, like through `valueOf`.
+/// A variable is one if it is the output of a primitive load or of arithmetic,
+/// and if all of the values that are copied into it are primitives as well.
+fn primitives(buffer: &[Instruction]) -> HashSet<u32> {
+    let mut primitives = buffer.iter()
+        .filter(|inst| matches!(inst.operation.opcode(),
+                                op::LoadInt | op::LoadFloat | op::LoadBool |
+                                op::LoadString | op::LoadUndefined |
+                                op::BinaryOp | op::UnaryOp | op::CompareOp))
+        .flat_map(|inst| inst.outputs().iter().map(|v| v.0))
+        .collect::<HashSet<u32>>();
+
+    // Throwing out a variable can throw out the ones that it is copied into,
+    // so go on until none are left to throw out
+    loop {
+        let copied = buffer.iter()
+            .filter(|inst| inst.operation.opcode() == op::Copy)
+            .filter(|inst| primitives.contains(&inst.input_at(0).0) &&
+                           !primitives.contains(&inst.input_at(1).0))
+            .map(|inst| inst.input_at(0).0)
+            .collect::<Vec<u32>>();
+        if copied.is_empty() {
+            return primitives;
+        }
+        for variable in copied {
+            primitives.remove(&variable);
+        }
+    }
+}
+
+/// Check if `inst` can't have any effect other than through its outputs, and
+/// through the variable that it assigns to for a copy or an increment
+fn is_pure(inst: &Instruction, primitives: &HashSet<u32>) -> bool {
+    let on_primitives = || {
+        inst.inputs().iter().all(|input| primitives.contains(&input.0))
+    };
+
+    match inst.operation.opcode() {
+        op::LoadInt       |
+        op::LoadFloat     |
+        op::LoadBool      |
+        op::LoadString    |
+        op::LoadUndefined |
+        op::LoadThis      |
+        op::CreateArray   |
+        op::CreateObject  |
+        op::Copy          => true,
+
+        op::LoadBuiltin => matches!(inst.cast_into::<LoadBuiltin>().0,
+                                    ConstructorType::NonCallable(..)),
+
+        op::BinaryOp  |
+        op::UnaryOp   |
+        op::CompareOp => on_primitives(),
+
+        _ => false,
+    }
+}
+
+/// The variable that `inst` assigns to, other than its outputs
+fn assigned(inst: &Instruction) -> Option<u32> {
+    match inst.operation.opcode() {
+        op::Copy => Some(inst.input_at(0).0),
+        op::UnaryOp => match inst.cast_into::<UnaryOp>().0 {
+            UnaryOperators::Inc | UnaryOperators::Dec => {
+                Some(inst.input_at(0).0)
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Liveness {
+
+    /// Find the dead instructions of the program made of `buffer`
+    pub fn analyze(buffer: &[Instruction]) -> Self {
+        let primitives = primitives(buffer);
+        let mut dead = buffer.iter()
+            .map(|inst| is_pure(inst, &primitives))
+            .collect::<Vec<bool>>();
+
+        // The variables that a live instruction uses. The instructions are
+        // walked backwards, so that the uses are mostly seen before the
+        // definitions. A loop can use a variable before it is assigned to
+        // further down though, so this goes on until nothing changes.
+        let mut live = HashSet::<u32>::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (idx, inst) in buffer.iter().enumerate().rev() {
+                if dead[idx] {
+                    let used = inst.outputs().iter()
+                        .any(|output| live.contains(&output.0)) ||
+                        assigned(inst).is_some_and(|v| live.contains(&v));
+                    if !used {
+                        continue;
+                    }
+                    dead[idx] = false;
+                    changed = true;
+                }
+
+                for input in inst.inputs() {
+                    changed |= live.insert(input.0);
+                }
+            }
+        }
+
+        Self { dead }
+    }
+
+    pub fn is_dead(&self, idx: usize) -> bool {
+        self.dead[idx]
+    }
+
+    /// The no. of dead instructions
+    pub fn dead_count(&self) -> usize {
+        self.dead.iter().filter(|&&dead| dead).count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::program::Program;
+    use crate::ir::operators::{BinaryOperators, Comparators};
+    use crate::jsruntime::jsruntime::JSRuntime;
+
+    #[test]
+    fn test_liveness() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        let one = p.load_int(1);
+        let counter = p.load_int(2);
+        let sum = p.binary_op(one, counter, BinaryOperators::Add);
+        let array = p.create_array(vec![counter]);
+        let start = p.load_int(0);
+        p.begin_for(start, start, start, "++".to_string(),
+                    Comparators::LessThan);
+        p.store_element(array, start, counter);
+        p.copy(counter, sum);
+        p.end_for();
+
+        let liveness = Liveness::analyze(&p.buffer);
+        let dead = (0..p.buffer.len())
+            .filter(|&idx| liveness.is_dead(idx))
+            .collect::<Vec<usize>>();
+
+        // Nothing is dead, as the store in the loop sees the copy into
+        // `counter` the next time around
+        assert_eq!(dead, Vec::<usize>::new());
+
+        p.binary_op(sum, sum, BinaryOperators::Mul);
+        let liveness = Liveness::analyze(&p.buffer);
+        assert!(liveness.is_dead(p.buffer.len() - 1));
+        assert_eq!(liveness.dead_count(), 1);
+    }
+}
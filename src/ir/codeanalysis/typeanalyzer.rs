@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::super::instruction::Instruction;
 use super::super::variable::Variable;
@@ -12,13 +12,54 @@ use super::super::opcodes::Opcodes as op;
 /// A basic typing system designed to be used by the fuzzer. In the current
 /// state the typing system support type propogation and type inference in case
 /// the initial type is unknown.
+///
+/// The types are tracked along the flow of the program. An assignment sets the
+/// type of a variable to the assigned one, and the ones that are assigned in a
+/// block that may not run, or may run more than once, are merged with the
+/// types from before the block at its end.
 
+#[derive(Clone)]
 pub struct TypeAnalyzer {
    
     type_map:               HashMap<u32, Type>,
     function_stack:         Vec<(Vec<Variable>, Type)>,
     signature_map:          HashMap<u32, FunctionSignature>,
 
+    /// The variables whose primitive types changed since the last
+    /// [TypeAnalyzer::take_changes], along with the types that they gained and
+    /// the ones that they lost
+    changes:                Vec<(Variable, PType, PType)>,
+
+    /// The blocks that are open, the innermost one last
+    blocks:                 Vec<Block>,
+
+    /// The element types of the variables that are known to be typed arrays
+    elements:               HashMap<u32, ElementType>,
+
+    /// The properties of the objects that were created with an object
+    /// literal, in the order that they were added in
+    properties:             HashMap<u32, Vec<String>>,
+
+    /// The values of the variables that hold a string constant, to know which
+    /// property a delete removes
+    strings:                HashMap<u32, String>,
+
+}
+
+/// A block whose types are merged with the ones from before it at its end, like
+/// the body of a loop or the branches of an if
+#[derive(Clone, Default)]
+struct Block {
+    /// The types that the variables from before the block had when it was
+    /// entered, for the ones that were assigned to in it
+    entry:   HashMap<u32, Type>,
+
+    /// The types of those variables at the end of the then branch, once the
+    /// else branch started
+    then:    Option<HashMap<u32, Type>>,
+
+    /// The variables that were defined in the block
+    defined: HashSet<u32>,
 }
 
 impl TypeAnalyzer {
@@ -28,23 +69,101 @@ impl TypeAnalyzer {
             type_map:               HashMap::<u32, Type>::new(),
             function_stack:         Vec::<(Vec<Variable>, Type)>::new(),
             signature_map:          HashMap::<u32, FunctionSignature>::new(),
+            changes:                Vec::new(),
+            blocks:                 Vec::new(),
+            elements:               HashMap::new(),
+            properties:             HashMap::new(),
+            strings:                HashMap::new(),
         }
     }
 
+    /// Go back to the state of a new analyzer, keeping the memory around
+    pub fn reset(&mut self) {
+        self.type_map.clear();
+        self.function_stack.clear();
+        self.signature_map.clear();
+        self.changes.clear();
+        self.blocks.clear();
+        self.elements.clear();
+        self.properties.clear();
+        self.strings.clear();
+    }
+
     pub fn set_type(&mut self, variable: &Variable, var_type: Type) {
 
         // If this variable already exists, then we just add the new type info
         // to the existing type otherwise we create a field for this variable.
-        if let Some(cur_type) = self.type_map.get_mut(&variable.0) {
-            cur_type.ptype |= var_type.ptype;
-            if var_type.shape != Shape::None {
-                cur_type.shape = var_type.shape;
+        match self.type_map.get(&variable.0) {
+            Some(&cur_type) => {
+                let mut new_type = cur_type;
+                new_type.ptype |= var_type.ptype;
+                if var_type.shape != Shape::None {
+                    new_type.shape = var_type.shape;
+                }
+                self.assign(variable.0, new_type);
+            },
+            None => {
+                self.type_map.insert(variable.0, var_type);
+                if let Some(block) = self.blocks.last_mut() {
+                    block.defined.insert(variable.0);
+                }
+                self.changes.push((*variable, var_type.ptype, PType::empty()));
+            },
+        }
+    }
+
+    /// Set the type of the existing variable `id` to `new_type`, remembering
+    /// the type that it had when the current block was entered
+    fn assign(&mut self, id: u32, new_type: Type) {
+        let cur_type = self.type_map[&id];
+        if let Some(block) = self.blocks.last_mut() {
+            if !block.defined.contains(&id) {
+                block.entry.entry(id).or_insert(cur_type);
+            }
+        }
+
+        self.type_map.insert(id, new_type);
+        let gained = new_type.ptype & !cur_type.ptype;
+        let lost = cur_type.ptype & !new_type.ptype;
+        if !gained.is_empty() || !lost.is_empty() {
+            self.changes.push((Variable(id), gained, lost));
+        }
+    }
+
+    /// Close the innermost block. The variables that were assigned to in it
+    /// get the types that they had before it, or at the end of the then
+    /// branch if it had one, on top of the ones that they have now.
+    fn end_block(&mut self) {
+        let block = match self.blocks.pop() {
+            Some(block) => block,
+            None        => return,
+        };
+
+        for (id, entry_type) in block.entry {
+            let before = match &block.then {
+                Some(then) => *then.get(&id).unwrap_or(&entry_type),
+                None       => entry_type,
+            };
+
+            // The variable was assigned to in the outer block as well now,
+            // so that one has to know the type that it had before
+            if let Some(outer) = self.blocks.last_mut() {
+                if !outer.defined.contains(&id) {
+                    outer.entry.entry(id).or_insert(entry_type);
+                }
             }
-        } else {
-            self.type_map.insert(variable.0, var_type);
+
+            let merged = self.type_map[&id] | before;
+            self.assign(id, merged);
         }
     }
 
+    /// The variables whose primitive types changed since the last call, along
+    /// with the types that they gained and the ones that they lost
+    pub fn take_changes(&mut self) -> Vec<(Variable, PType, PType)> {
+        std::mem::take(&mut self.changes)
+    }
+
     pub fn get_type(&self, variable: &Variable) -> Type {
         match self.type_map.get(&variable.0) {
             Some(vtype) => *vtype,
@@ -52,25 +171,115 @@ impl TypeAnalyzer {
         }
     }
 
+    /// The element type of `variable`, if it is known to be a typed array
+    pub fn get_element_type(&self, variable: &Variable) -> Option<ElementType> {
+        self.elements.get(&variable.0).copied()
+    }
+
+    /// The properties of `variable`, if it is an object literal whose
+    /// properties are known
+    pub fn get_properties(&self, variable: &Variable) -> Option<&[String]> {
+        self.properties.get(&variable.0).map(|props| props.as_slice())
+    }
+
     pub fn get_signature_for(&self, func: Variable) -> &FunctionSignature {
         self.signature_map.get(&func.0).unwrap()
     }
 
+    pub fn has_signature(&self, func: Variable) -> bool {
+        self.signature_map.contains_key(&func.0)
+    }
+
+    /// Set the types of the arguments, `args`, of a method call whose type is
+    /// still unknown from the signature of the method.
+    fn infer_arg_types(&mut self, signature: &MethodSignature,
+                       args: &[Variable]) {
+        for (idx, inp) in args.iter().enumerate() {
+            if self.get_type(inp).is_unknown() {
+                let idx = idx % signature.min_args_count();
+                let itype = match signature.input_type_at(idx) {
+                    MethodArg::Type(itype) |
+                    MethodArg::Optional(itype) |
+                    MethodArg::Repeat(_ , itype) => *itype,
+                };
+                self.set_type(inp, itype);
+            }
+        }
+    }
+
+    /// Set the type of the `output` of a call to the method `signature` on
+    /// `this`. A function that it returns can be called later on if we know
+    /// its signature, which is either the one of the method or, for a method
+    /// of a function like `bind`, the one of the function that it was called
+    /// on.
+    fn set_output_type(&mut self, output: &Variable,
+                       signature: &MethodSignature, this: Option<&Variable>) {
+        self.set_type(output, signature.output_type());
+        if signature.output_type() != Function {
+            return;
+        }
+
+        let function = match (signature.output_signature(), this) {
+            (Some(function), _) => Some(function.clone()),
+            (None, Some(this)) if signature.get_this_type() == Function => {
+                self.signature_map.get(&this.0)
+                    .filter(|function| !function.is_constructing())
+                    .cloned()
+            },
+            _ => None,
+        };
+        if let Some(function) = function {
+            self.signature_map.insert(output.0, function);
+        }
+    }
+
     pub fn analyze(&mut self, inst: &mut Instruction) {
         match inst.operation.opcode() {
 
             // ignore if the instruction does not produce an output
             op::Nop         |
-            op::EndIf       |
             op::Continue    |
-            op::Break       |
-            op::BeginElse   |
-            op::EndFor => {},
+            op::Break       => {},
+
+            // Both branches start off with the types from before the if, and
+            // either one of them may have run after it
+            op::BeginElse => {
+                let block = match self.blocks.last_mut() {
+                    Some(block) => block,
+                    None        => return,
+                };
+                let mut then = HashMap::new();
+                for (&id, &entry_type) in &block.entry {
+                    then.insert(id, self.type_map[&id]);
+                    self.type_map.insert(id, entry_type);
+                }
+                for (&id, then_type) in &then {
+                    let entry_type = block.entry[&id];
+                    let gained = entry_type.ptype & !then_type.ptype;
+                    let lost = then_type.ptype & !entry_type.ptype;
+                    if !gained.is_empty() || !lost.is_empty() {
+                        self.changes.push((Variable(id), gained, lost));
+                    }
+                }
+                block.then = Some(then);
+                block.defined.clear();
+            },
+
+            // The body of a loop may run any no. of times, so the types of the
+            // variables that it assigns to are widened to the ones from before
+            // it as well. The same goes for the body of a function, which runs
+            // each time that it is called.
+            op::EndIf       |
+            op::EndFor => self.end_block(),
 
             op::LoadInt       => self.set_type(&inst.output_at(0), Int),
             op::LoadFloat     => self.set_type(&inst.output_at(0), Float),
             op::LoadBool      => self.set_type(&inst.output_at(0), Bool),
-            op::LoadString    => self.set_type(&inst.output_at(0), String),
+            op::LoadString    => {
+                let op = inst.cast_into::<LoadString>();
+                self.strings.insert(inst.output_at(0).0, op.0.clone());
+                self.set_type(&inst.output_at(0), String);
+            },
             op::LoadUndefined => self.set_type(&inst.output_at(0), Undefined),
 
             op::BeginIf => {
@@ -78,14 +287,43 @@ impl TypeAnalyzer {
                 if self.get_type(arg).is_unknown() {
                     self.set_type(arg, Bool | Unknown);
                 }
+                self.blocks.push(Block::default());
             },
 
             op::Copy => {
-                let t = self.get_type(&inst.input_at(1));
-                self.set_type(inst.input_at(0), t);
+                let (lhs, rhs) = (inst.input_at(0).0, inst.input_at(1).0);
+                let t = self.get_type(inst.input_at(1));
+                self.assign(lhs, t);
+
+                // The element type is only known after a copy in a block if
+                // the variable had the same one before it
+                let before = self.elements.get(&lhs).copied();
+                match self.elements.get(&rhs).copied() {
+                    Some(element) if self.blocks.is_empty() ||
+                                     before == Some(element) => {
+                        self.elements.insert(lhs, element);
+                    },
+                    _ => {
+                        self.elements.remove(&lhs);
+                    },
+                }
+
+                // The same goes for the properties
+                self.strings.remove(&lhs);
+                let before = self.properties.get(&lhs);
+                match self.properties.get(&rhs).cloned() {
+                    Some(props) if self.blocks.is_empty() ||
+                                   before == Some(&props) => {
+                        self.properties.insert(lhs, props);
+                    },
+                    _ => {
+                        self.properties.remove(&lhs);
+                    },
+                }
             },
 
             op::BeginFor => {
+                self.blocks.push(Block::default());
                 self.set_type(inst.temp_at(0), Int | Float | Bool);
             }
 
@@ -149,6 +387,9 @@ impl TypeAnalyzer {
                     self.set_type(lhs, Int | Unknown);
                 }
                 let op = inst.cast_into::<UnaryOp>();
+                if matches!(op.0, UnaryOperators::Inc | UnaryOperators::Dec) {
+                    self.strings.remove(&lhs.0);
+                }
                 let output = inst.output_at(0);
                 let input_type = self.get_type(&inst.input_at(0));
                 match op.0 {
@@ -204,6 +445,7 @@ impl TypeAnalyzer {
                 self.signature_map.insert(output_var.0, signature);
 
                 self.set_type(&output_var, Function);
+                self.blocks.push(Block::default());
 
             },
 
@@ -236,9 +478,24 @@ impl TypeAnalyzer {
                 sig.set_output_type(output_type);
                 sig.set_input_types(input_types);
                 sig.done_constructing();
+                self.end_block();
+
+            },
 
+            // An IIFE is an anonymous function that is called right away, so
+            // we only need a slot on the function stack for its returns.
+            op::BeginIIFE => {
+                self.function_stack.push((vec![], Unknown));
             },
 
+            op::EndIIFE => {
+                self.function_stack.pop();
+            },
+
+            // We don't look into the evaluated code, so we know nothing about
+            // the value that it completes with.
+            op::Eval => self.set_type(inst.output_at(0), Unknown),
+
             op::Return => {
 
                 let output_type = self.get_type(&inst.input_at(0));
@@ -249,13 +506,39 @@ impl TypeAnalyzer {
 
             op::FunctionCall => {
 
+                // If we are calling a value that we have no signature for,
+                // like a function that was passed in as an argument, then we
+                // can only infer that it is a function and know nothing about
+                // what it returns.
                 let func_var = inst.input_at(0);
-                let signature = self.signature_map.get(&func_var.0).unwrap();
-                let output_type = signature.get_output_type();
+                let output_type = match self.signature_map.get(&func_var.0) {
+                    Some(signature) => signature.get_output_type(),
+                    None => {
+                        if self.get_type(func_var).is_unknown() {
+                            self.set_type(func_var, Function);
+                        }
+                        Unknown
+                    }
+                };
                 self.set_type(inst.output_at(0), output_type);
 
             },
 
+            // Objects created with `new` on a function that we generated have
+            // the properties that the function stored on `this`, so they get
+            // the custom object shape.
+            op::Construct => {
+                let func_var = inst.input_at(0);
+                if self.get_type(func_var).is_unknown() {
+                    self.set_type(func_var, Function);
+                }
+                self.set_type(inst.output_at(0), Type::obj(Shape::Custom));
+            },
+
+            op::LoadThis => {
+                self.set_type(inst.output_at(0), Type::obj(Shape::Custom));
+            },
+
             op::CreateArray => {
                 let output = inst.output_at(0);
                 self.set_type(output, Array);
@@ -296,21 +579,32 @@ impl TypeAnalyzer {
                 let op = inst.cast_into::<MethodCall>();
                 let output = inst.output_at(0);
 
-                let signature = &op.0;
-                for (idx, inp) in inst.inputs()[1..].iter().enumerate() {
-                    if self.get_type(inp).is_unknown() {
-                        let idx = idx % op.0.min_args_count();
-                        let itype = match signature.input_type_at(idx) {
-                            MethodArg::Type(itype) |
-                            MethodArg::Optional(itype) |
-                            MethodArg::Repeat(_ , itype) => *itype,
-                        };
-                        self.set_type(inp, itype);
-                    }
+                self.infer_arg_types(&op.0, &inst.inputs()[1..]);
+                self.set_output_type(output, &op.0, Some(inst.input_at(0)));
+            },
+
+            op::BuiltinCall => {
+                let op = inst.cast_into::<BuiltinCall>();
+                let output = inst.output_at(0);
+
+                self.infer_arg_types(&op.0, inst.inputs());
+                self.set_output_type(output, &op.0, None);
+            },
+
+            op::MethodChain => {
+                let op = inst.cast_into::<MethodChain>();
+                let output = inst.output_at(0);
+
+                // Walk over the arguments of each call in the chain
+                let mut start = 1;
+                for (signature, len) in &op.0 {
+                    let end = start + *len as usize;
+                    self.infer_arg_types(signature, &inst.inputs()[start..end]);
+                    start = end;
                 }
 
-                let output_type = op.0.output_type();
-                self.set_type(&output, output_type);
+                let (last, _) = op.0.last().unwrap();
+                self.set_output_type(output, last, None);
             },
 
             op::LoadProperty => {
@@ -321,9 +615,17 @@ impl TypeAnalyzer {
                 self.set_type(inst.output_at(0), Float | Int | Object);
             },
 
+            // A store in a block may not run, so the property may be missing
+            // after it, but it is never known to be missing
             op::StoreProperty => {
                 let input = inst.input_at(0);
                 let value = inst.input_at(1);
+                if let Some(props) = self.properties.get_mut(&input.0) {
+                    let prop = &inst.cast_into::<StoreProperty>().0;
+                    if !props.contains(prop) {
+                        props.push(prop.clone());
+                    }
+                }
                 if self.get_type(input).is_unknown() {
                     self.set_type(input, Object);
                 }
@@ -336,6 +638,9 @@ impl TypeAnalyzer {
                 let op = inst.cast_into::<LoadBuiltin>();
                 let otype = match &op.0 {
                     ConstructorType::Callable(ms) => {
+                        if let Some(element) = ElementType::of(ms.get_name()) {
+                            self.elements.insert(inst.output_at(0).0, element);
+                        }
                         ms.output_type()
                     },
                     ConstructorType::NonCallable(_, otype) => *otype
@@ -349,6 +654,13 @@ impl TypeAnalyzer {
                     ptype: PType::Object,
                     shape: Shape::Custom
                 };
+                let mut props = Vec::new();
+                for prop in &inst.cast_into::<CreateObject>().0 {
+                    if !props.contains(prop) {
+                        props.push(prop.clone());
+                    }
+                }
+                self.properties.insert(inst.output_at(0).0, props);
                 self.set_type(inst.output_at(0), custom_type);
             },
 
@@ -361,6 +673,23 @@ impl TypeAnalyzer {
                     self.set_type(prop, Int);
                 }
 
+                // A delete that is sure to run, of a property that we know
+                // the name of, removes it. After any other one we can't tell
+                // which properties are left. An index is never one of them.
+                if !is_indexed_prop {
+                    match self.strings.get(&prop.0) {
+                        Some(name) if self.blocks.is_empty() => {
+                            if let Some(props) =
+                                    self.properties.get_mut(&object.0) {
+                                props.retain(|p| p != name);
+                            }
+                        },
+                        _ => {
+                            self.properties.remove(&object.0);
+                        },
+                    }
+                }
+
                 if self.get_type(object).is_unknown() {
                     let custom_type = Type {
                         ptype: PType::Object,
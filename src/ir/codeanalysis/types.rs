@@ -9,6 +9,8 @@ use std::ops::{BitOr, BitOrAssign};
 // analogous to `Shape` (spidermonkey), `Map` (v8) or `Structure` (JSC).
 // Primitive types have a shape of `None`. The `Any` shape is used to signify
 // that we don't care what type of an object this is, as long as its an object.
+// The functions have a shape of `None` as well, and `Function` is only the
+// shape of the builtin that holds the methods that can be called on them.
 bitflags! {
     pub struct Shape: u64 {
         const None          = 0;
@@ -21,6 +23,7 @@ bitflags! {
         const Math          = 1 << 6 | Shape::Object.bits;
         const String        = 1 << 7 | Shape::Object.bits;
         const Custom        = 1 << 8 | Shape::Object.bits;
+        const Function      = 1 << 9;
         const Any           = u64::MAX;
     }
 }
@@ -253,6 +256,32 @@ pub const TypedArray: Type = Type {
     shape: Shape::TypedArray
 };
 
+/// The type of the elements of a typed array. The values that are stored to
+/// one are converted to it, and the ones of the BigInt arrays have to be
+/// BigInts, as storing a number to them throws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElementType {
+    Int,
+    Float,
+    BigInt,
+}
+
+impl ElementType {
+
+    /// The element type of the typed array constructor called `name`, or
+    /// `None` if it is not one
+    pub fn of(name: &str) -> Option<Self> {
+        match name {
+            "Int8Array"    | "Uint8Array"  | "Uint8ClampedArray" |
+            "Int16Array"   | "Uint16Array" | "Int32Array" |
+            "Uint32Array"  => Some(ElementType::Int),
+            "Float32Array" | "Float64Array" => Some(ElementType::Float),
+            "BigInt64Array" | "BigUint64Array" => Some(ElementType::BigInt),
+            _ => None,
+        }
+    }
+}
+
 /// A FunctionSignature is used to hold all the data related to a function call.
 #[derive(Debug,Clone)]
 pub struct FunctionSignature {
@@ -274,6 +303,18 @@ impl FunctionSignature {
         }
     }
 
+    /// Create the signature of a function returned by a builtin, which takes
+    /// arguments of `input_types` and can be called right away
+    pub fn builtin(input_types: Vec<Type>, output_type: Type) -> Self {
+        Self {
+            num_inputs:      input_types.len() as u8,
+            input_types,
+            is_constructing: false,
+            output_type,
+            output_shape:    None,
+        }
+    }
+
     pub fn is_constructing(&self) -> bool {
         self.is_constructing
     }
@@ -373,6 +414,10 @@ pub struct MethodSignature {
     this_type:       Type,
     input_types:     Vec<MethodArg>,
     output_type:     Type,
+
+    /// The signature of the function that the method returns, if it returns
+    /// one that we know how to call
+    output_signature: Option<FunctionSignature>,
 }
 
 impl MethodSignature {
@@ -386,9 +431,21 @@ impl MethodSignature {
             this_type:       this_type,
             input_types:     input_types,
             output_type:     output_type,
+            output_signature: None,
         }
     }
 
+    /// Set the signature of the function that this method returns
+    pub fn with_output_signature(mut self,
+                                 signature: FunctionSignature) -> Self {
+        self.output_signature = Some(signature);
+        self
+    }
+
+    pub fn output_signature(&self) -> Option<&FunctionSignature> {
+        self.output_signature.as_ref()
+    }
+
     pub fn set_name(&mut self, name: &str) {
         self.name = name.to_string();
     }
@@ -431,4 +488,8 @@ impl MethodSignature {
     pub fn get_name(&self) -> &String {
         &self.name
     }
+
+    pub fn get_this_type(&self) -> Type {
+        self.this_type
+    }
 }
@@ -4,19 +4,18 @@ use super::variable::Variable;
 // use super::codeanalysis::types::{Type, PType, Shape, FunctionSignature};
 use super::codeanalysis::types::*;
 
-use crate::jsruntime::constants::PROPERTIES;
+use crate::jsruntime::constants::{PROPERTIES, MISSING_PROPERTIES,
+                                  DETACH_FUNCTIONS};
+use crate::lifter::lifter::Lifter;
+use crate::trace;
 
 pub struct CodeGenerators();
 
-const DEBUG: bool = false;
-
 impl CodeGenerators {
 
     pub fn integer_literal_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("integer_literal_generato");
-        }
+        trace!("integer_literal_generator");
 
         let int = program.getint();
         program.load_int(int);
@@ -25,9 +24,7 @@ impl CodeGenerators {
 
     pub fn float_literal_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("float_literal_generator");
-        }
+        trace!("float_literal_generator");
 
         let float = program.getfloat();
         program.load_float(float);
@@ -36,9 +33,7 @@ impl CodeGenerators {
 
     pub fn string_literal_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("string_literal_generator");
-        }
+        trace!("string_literal_generator");
 
         let string = program.getstring().to_string();
         program.load_string(string);
@@ -47,9 +42,7 @@ impl CodeGenerators {
 
     pub fn bool_literal_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("bool_literal_generator");
-        }
+        trace!("bool_literal_generator");
 
         let boolean = program.prob.probablity(0.5);
         program.load_bool(boolean);
@@ -58,9 +51,7 @@ impl CodeGenerators {
 
     pub fn undefined_literal_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("undefined_literal_generator");
-        }
+        trace!("undefined_literal_generator");
 
         program.load_undefined();
         Some(())
@@ -68,9 +59,7 @@ impl CodeGenerators {
 
     pub fn nop_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("nop_generator");
-        }
+        trace!("nop_generator");
 
         program.nop();
         Some(())
@@ -78,9 +67,7 @@ impl CodeGenerators {
 
     pub fn copy_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("copy_generator");
-        }
+        trace!("copy_generator");
 
 
         let lhs = program.random_variable(Any);
@@ -91,9 +78,7 @@ impl CodeGenerators {
 
     pub fn if_condition_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!(" if_condition_generator");
-        }
+        trace!("if_condition_generator");
 
         let cond = program.random_variable(Bool);
         let var  = program.random_variable(Unknown);
@@ -114,9 +99,7 @@ impl CodeGenerators {
 
     pub fn for_loop_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("for_loop_generator");
-        }
+        trace!("for_loop_generator");
 
         let (start, end, step) = if program.prob.probablity(0.7) {
             let start = program.load_int(0);
@@ -145,9 +128,7 @@ impl CodeGenerators {
 
     pub fn break_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!(" break_generator");
-        }
+        trace!("break_generator");
 
         if program.is_in_loop() {
             program.insert_break();
@@ -159,9 +140,7 @@ impl CodeGenerators {
 
     pub fn continue_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("continue_generator");
-        }
+        trace!("continue_generator");
 
         if program.is_in_loop() {
             program.insert_continue();
@@ -173,9 +152,7 @@ impl CodeGenerators {
 
     pub fn binary_op_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("binary_op_generator");
-        }
+        trace!("binary_op_generator");
 
         let lhs = program.random_variable(Int | Float);
         let rhs = program.random_variable(Int | Float);
@@ -189,9 +166,7 @@ impl CodeGenerators {
 
     pub fn compare_op_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("compare_op_generator");
-        }
+        trace!("compare_op_generator");
 
         let lhs = program.random_variable(Int | Float);
         let rhs = program.random_variable(Int | Float);
@@ -205,9 +180,7 @@ impl CodeGenerators {
 
     pub fn unary_op_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("unary_op_generator");
-        }
+        trace!("unary_op_generator");
 
         let lhs = program.random_variable(Int);
 
@@ -222,12 +195,10 @@ impl CodeGenerators {
     // unchecked.
     pub fn function_definition_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("function_definition_generator");
-        }
+        trace!("function_definition_generator");
 
 
-        let args_count = program.rng.rand_in_range(0, 5) as u8;
+        let args_count = program.rng.u64_in_range(0, 5) as u8;
         let signature = FunctionSignature::new(args_count);
         let func = program.begin_function_definition(signature);
         program.generate_random_insts(3);
@@ -247,13 +218,11 @@ impl CodeGenerators {
 
     pub fn function_call_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("function_call_generator");
-        }
+        trace!("function_call_generator");
 
         let func = program.random_variable_of_type(Function, Mode::Strict)?;
         let typ = program.get_type(&func);
-        if typ != Function {
+        if typ != Function || !program.has_signature(&func) {
             return None;
         }
 
@@ -275,9 +244,7 @@ impl CodeGenerators {
 
     pub fn function_return_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("function_return_generator");
-        }
+        trace!("function_return_generator");
 
 
         if program.is_in_function() {
@@ -292,9 +259,7 @@ impl CodeGenerators {
 
     pub fn int_array_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("int_array_generator");
-        }
+        trace!("int_array_generator");
 
 
         let size = program.rng.rand_idx(30);
@@ -319,9 +284,7 @@ impl CodeGenerators {
 
     pub fn float_array_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("float_array_generator");
-        }
+        trace!("float_array_generator");
 
 
         let size = program.rng.rand_idx(30);
@@ -346,9 +309,7 @@ impl CodeGenerators {
 
     pub fn load_element_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("load_element_generator");
-        }
+        trace!("load_element_generator");
 
 
         let array = program.random_variable_of_type(Array | Unknown | String,
@@ -368,9 +329,7 @@ impl CodeGenerators {
 
     pub fn store_element_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("store_element_generator");
-        }
+        trace!("store_element_generator");
 
 
         let array = program.random_variable_of_type(Array, Mode::Strict);
@@ -382,7 +341,7 @@ impl CodeGenerators {
             program.load_int(idx)
         };
 
-        let value = program.random_variable(Any);
+        let value = program.element_value(array);
         program.store_element(array, idx, value);
         Some(())
 
@@ -390,12 +349,10 @@ impl CodeGenerators {
 
     pub fn method_call_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("method_call_generator");
-        }
+        trace!("method_call_generator");
 
-        let object = program.random_variable_of_type(Object | Unknown,
-                                                     Mode::Strict)?;
+        let object = program.random_variable_of_type(Object | Unknown |
+                                                     Function, Mode::Strict)?;
 
         let object_type = program.get_type(&object);
 
@@ -404,8 +361,14 @@ impl CodeGenerators {
         // now as we don't define custom methods yet
         // if object_type.shape == Shape::Any {return None;}
 
-        // First select a random method from the possible methods
-        let method = program.random_method_for_shape(object_type.shape)?;
+        // First select a random method from the possible methods. The methods
+        // of the functions are the ones of the Function builtin.
+        let shape = if object_type == Function {
+            Shape::Function
+        } else {
+            object_type.shape
+        };
+        let method = program.random_method_for_shape(shape)?;
 
         // Now generate arguments for the selected method.
         let inputs = program.generate_method_args(&method, Some(object));
@@ -416,28 +379,35 @@ impl CodeGenerators {
 
     pub fn load_property_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("load_property_generator");
-        }
+        trace!("load_property_generator");
 
 
         let object = program.random_variable_of_type(Object, Mode::Strict)?;
 
         let object_type = program.get_type(&object);
 
-        // For now ignore if don't know the concrete shape. TODO: change this
-        // later otherwise we will not load props on custom objs. Its fine
-        // now as we don't define custom objects yet
-        // if object_type.shape == Shape::Any {return None;}
+        // Mostly read a property that the object is known to have, so that
+        // the loads hit the same way that they do in real code. Those are the
+        // ones of an object literal, or else the ones of its builtin. Some of
+        // the loads read a property that it is sure not to have instead.
+        let runtime = program.jsruntime;
+        let known = match program.get_properties(&object) {
+            Some(props) => Some(props.to_vec()),
+            None => runtime.get_properties(object_type.shape)
+                           .map(|props| props.into_owned()),
+        };
 
-        let prop = if program.prob.probablity(0.6) {
-            program.rng.random_element(&PROPERTIES).to_string()
-        } else {
-            let prop = program.jsruntime.get_properties(object_type.shape)?;
-            program.rng.random_element(&prop).clone()
+        let prop = match known {
+            _ if program.prob.probablity(program.missing_props) => {
+                program.rng.random_element(&MISSING_PROPERTIES).to_string()
+            },
+            Some(known) if !known.is_empty() => {
+                program.rng.random_element(&known).clone()
+            },
+            _ => program.rng.random_element(&PROPERTIES).to_string(),
         };
 
-        program.load_property(prop.to_string(), object);
+        program.load_property(prop, object);
         Some(())
     }
 
@@ -454,9 +424,7 @@ impl CodeGenerators {
 
     pub fn load_builtin_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("load_builtin_generator");
-        }
+        trace!("load_builtin_generator");
 
         let constructor = program.jsruntime.get_constructors();
         let constructor = program.rng.random_element(&constructor);
@@ -520,9 +488,7 @@ impl CodeGenerators {
 
     pub fn empty_loop_generator(program: &mut Program) -> Option<()> {
 
-        if DEBUG {
-            println!("empty_loop_generator");
-        }
+        trace!("empty_loop_generator");
 
 
         if !program.is_in_function() {return None;}
@@ -554,7 +520,7 @@ impl CodeGenerators {
 
         let func = program.random_variable_of_type(Function, Mode::Strict)?;
         let typ = program.get_type(&func);
-        if typ != Function {
+        if typ != Function || !program.has_signature(&func) {
             // println!("bail due to incorrect variable {:?}", typ);
             return None;
         }
@@ -588,6 +554,664 @@ impl CodeGenerators {
 
         Some(())
     } 
+
+    /// Create a TypedArray view over a fresh ArrayBuffer, use the view in a
+    /// loop, then detach the buffer and keep on using the view in loops and
+    /// builtin calls.
+    pub fn arraybuffer_detach_generator(program: &mut Program) -> Option<()> {
+
+        trace!("arraybuffer_detach_generator");
+
+        // Keep the length a multiple of 8 so that the buffer can be viewed
+        // with any of the typed array element sizes.
+        let length = program.rng.rand_in_range(1, 0x20) * 8;
+        let length = program.load_int(length);
+
+        let arraybuf = Type::obj(Shape::ArrayBuffer);
+        let constructor = MethodSignature::new("ArrayBuffer", arraybuf,
+                                               vec![MethodArg::Type(Int)],
+                                               arraybuf);
+        let constructor = ConstructorType::Callable(constructor);
+        let buffer = program.load_builtin(&constructor, Some(vec![length]));
+
+        let constructor = MethodSignature::new("TypedArray", TypedArray,
+                                               vec![MethodArg::Type(arraybuf)],
+                                               TypedArray);
+        let constructor = ConstructorType::Callable(constructor);
+        let view = program.load_builtin(&constructor, Some(vec![buffer]));
+
+        // Warm up the accesses to the view while the buffer is still attached
+        CodeGenerators::typed_array_access_loop(program, view);
+
+        // Prefer the engine's own detach helper if the profile provides one,
+        // else fall back to the standard ways of transferring the buffer.
+        let helper = DETACH_FUNCTIONS.iter()
+            .find_map(|name| program.jsruntime.get_function(name))
+            .cloned();
+
+        match helper {
+            Some(detach) if program.prob.probablity(0.5) => {
+                program.builtin_call(vec![buffer], detach);
+            },
+            _ if program.prob.probablity(1.0 / 3.0) => {
+                // structuredClone(buffer, {transfer: [buffer]})
+                let transfer = program.create_array(vec![buffer]);
+                let prop = "transfer".to_string();
+                let options = program.create_object(vec![prop], vec![transfer]);
+                let clone = MethodSignature::new("structuredClone", Undefined,
+                                                 vec![MethodArg::Type(arraybuf),
+                                                      MethodArg::Type(Object)],
+                                                 arraybuf);
+                program.builtin_call(vec![buffer, options], clone);
+            },
+            _ => {
+                let name = *program.rng.random_element(&["transfer",
+                                                         "transferToFixedLength"]);
+                let detach = program.jsruntime.get_method(Shape::ArrayBuffer,
+                                                          name)?;
+                program.method_call(vec![buffer], detach);
+            },
+        }
+
+        // Now the view is backed by a detached buffer, so hit it again both
+        // with element accesses and with builtin methods.
+        CodeGenerators::typed_array_access_loop(program, view);
+
+        let method = program.random_method_for_shape(Shape::TypedArray)?;
+        let inputs = program.generate_method_args(&method, Some(view));
+        program.method_call(inputs, method);
+
+        Some(())
+    }
+
+    /// Helper to emit a loop that stores to and loads from the elements of
+    /// `view`.
+    fn typed_array_access_loop(program: &mut Program, view: Variable) {
+        let end = program.rng.rand_in_range(1, 0x100);
+        let start = program.load_int(0);
+        let end = program.load_int(end);
+        let step = program.load_int(1);
+
+        let idx = program.begin_for(start, end, step, "++".to_string(),
+                                    Comparators::LessThan);
+
+        let value = program.element_value(view);
+        program.store_element(view, idx, value);
+        program.load_element(view, idx);
+
+        program.end_for();
+    }
+
+    /// Iterate over an array while shrinking, growing or splicing it, either
+    /// directly from the loop body or from inside a callback that is passed
+    /// to one of the Array builtins.
+    pub fn array_length_mutation_generator(program: &mut Program)
+                                           -> Option<()> {
+
+        trace!("array_length_mutation_generator");
+
+        let array = match program.random_variable_of_type(Array, Mode::Strict) {
+            Some(array) => array,
+            None => {
+                let size = program.rng.u64_in_range(1, 30) as usize;
+                let mut elements = Vec::<Variable>::with_capacity(size);
+                for _ in 0..size {
+                    let v = program.getint();
+                    elements.push(program.load_int(v));
+                }
+                program.create_array(elements)
+            }
+        };
+
+        if program.prob.probablity(0.5) {
+
+            // Loop up to the length at loop entry, mutating the length as we go
+            let start = program.load_int(0);
+            let end = program.load_property("length".to_string(), array);
+            let step = program.load_int(1);
+            let idx = program.begin_for(start, end, step, "++".to_string(),
+                                        Comparators::LessThan);
+
+            program.load_element(array, idx);
+            CodeGenerators::mutate_array_length(program, array, idx);
+            program.load_element(array, idx);
+
+            program.end_for();
+
+        } else {
+
+            // Mutate the array from a callback while a builtin iterates over it
+            let signature = FunctionSignature::new(2);
+            let callback = program.begin_function_definition(signature);
+            let idx = program.random_variable(Int);
+            CodeGenerators::mutate_array_length(program, array, idx);
+            program.generate_random_insts(1);
+            let ret = program.random_variable(Any);
+            program.insert_return(ret);
+            program.end_function_definition();
+
+            let name = *program.rng.random_element(&["sort", "forEach", "map",
+                                                     "filter", "some", "every",
+                                                     "find", "findIndex"]);
+            let method = program.jsruntime.get_method(Shape::Array, name)?;
+            program.method_call(vec![array, callback], method);
+        }
+
+        Some(())
+    }
+
+    /// Helper to change the length of `array` by either setting the length
+    /// directly or calling one of the Array builtins that grow or shrink it.
+    /// This never bails out as it is called from within open blocks.
+    fn mutate_array_length(program: &mut Program, array: Variable,
+                           idx: Variable) {
+
+        let name = *program.rng.random_element(&["length", "splice", "pop",
+                                                 "shift", "push", "unshift"]);
+
+        // Setting the length is a plain property store, everything else is a
+        // call to one of the Array builtins. The length is set instead of
+        // calling a method that the runtime doesn't know about.
+        let method = match name {
+            "length" => None,
+            _ => program.jsruntime.get_method(Shape::Array, name),
+        };
+        let Some(method) = method else {
+            let length = program.rng.rand_in_range(0, 0x20);
+            let length = program.load_int(length);
+            program.store_property("length".to_string(), array, length);
+            return;
+        };
+
+        let mut inputs = vec![array];
+        match name {
+            "splice" => {
+                let count = program.load_int(1);
+                inputs.push(idx);
+                inputs.push(count);
+            },
+            "push" | "unshift" => inputs.push(program.random_variable(Any)),
+            _ => {},
+        }
+
+        program.method_call(inputs, method);
+    }
+
+    /// Define a function that accesses a property on its argument, train it
+    /// in a loop with objects of several different shapes and finally call it
+    /// with an object of a shape that it has never seen before.
+    pub fn polymorphic_ic_generator(program: &mut Program) -> Option<()> {
+
+        trace!("polymorphic_ic_generator");
+
+        let prop = program.rng.random_element(&PROPERTIES).to_string();
+        let fillers = PROPERTIES.iter()
+                                .filter(|p| **p != prop)
+                                .map(|p| p.to_string())
+                                .collect::<Vec<String>>();
+
+        let func = program.begin_function_definition(FunctionSignature::new(1));
+        let param = program.last_temp()[0];
+        let value = program.load_property(prop.clone(), param);
+        let ret = if program.prob.probablity(0.5) {
+            let one = program.load_int(1);
+            program.binary_op(value, one, BinaryOperators::Add)
+        } else {
+            value
+        };
+        program.insert_return(ret);
+        program.end_function_definition();
+
+        // Each of the shapes has a different number of properties in front of
+        // the one that the function loads, so they are all distinct and the
+        // property lives at a different offset in each of them.
+        let num_shapes = program.rng.u64_in_range(2, 9) as usize;
+        let mut objects = Vec::<Variable>::with_capacity(num_shapes);
+        for i in 0..num_shapes {
+            let mut props = fillers[..i].to_vec();
+            props.push(prop.clone());
+            let values = props.iter()
+                              .map(|_| program.random_variable(Int))
+                              .collect::<Vec<Variable>>();
+            objects.push(program.create_object(props, values));
+        }
+        let objects = program.create_array(objects);
+
+        let start = program.load_int(0);
+        let end = program.rng.rand_in_range(100, 0x500);
+        let end = program.load_int(end);
+        let step = program.load_int(1);
+        let num_shapes = program.load_int(num_shapes as isize);
+        let idx = program.begin_for(start, end, step, "++".to_string(),
+                                    Comparators::LessThan);
+        let idx = program.binary_op(idx, num_shapes, BinaryOperators::Mod);
+        let object = program.load_element(objects, idx);
+        program.function_call(func, vec![object]);
+        program.end_for();
+
+        // Now bust the inline cache with an entirely new shape
+        let mut props = vec![prop];
+        props.extend(fillers);
+        let values = props.iter()
+                          .map(|_| program.random_variable(Any))
+                          .collect::<Vec<Variable>>();
+        let object = program.create_object(props, values);
+        program.function_call(func, vec![object]);
+
+        Some(())
+    }
+
+    /// JIT-warm a function with integer arguments and then call it once with
+    /// a value that breaks the type assumptions made during warm-up, like a
+    /// string or an object with a `valueOf` method, in order to force a
+    /// bailout. Optionally the hot loop is re-entered afterwards.
+    pub fn deopt_trigger_generator(program: &mut Program) -> Option<()> {
+
+        trace!("deopt_trigger_generator");
+
+        let ops = [BinaryOperators::Add, BinaryOperators::Sub,
+                   BinaryOperators::Mul, BinaryOperators::BitAnd,
+                   BinaryOperators::BitOr, BinaryOperators::Xor];
+        let op = *program.rng.random_element(&ops);
+
+        let func = program.begin_function_definition(FunctionSignature::new(2));
+        let params = program.last_temp();
+        let result = program.binary_op(params[0], params[1], op);
+        program.insert_return(result);
+        program.end_function_definition();
+
+        let other = program.getint();
+        let other = program.load_int(other);
+        let iterations = program.rng.rand_in_range(0x500, 0x2000);
+        CodeGenerators::warmup_loop(program, func, other, iterations);
+
+        // Now call the function with a value it has never seen before
+        let value = if program.prob.probablity(0.5) {
+            let string = program.getstring().to_string();
+            program.load_string(string)
+        } else {
+            let value_of = program.begin_function_definition(
+                FunctionSignature::new(0));
+            let int = program.getint();
+            let int = program.load_int(int);
+            program.insert_return(int);
+            program.end_function_definition();
+            program.create_object(vec!["valueOf".to_string()], vec![value_of])
+        };
+        program.function_call(func, vec![value, other]);
+
+        if program.prob.probablity(0.5) {
+            CodeGenerators::warmup_loop(program, func, other, iterations);
+        }
+
+        Some(())
+    }
+
+    /// Helper to call `func` with the loop counter and `other` from within a
+    /// loop that runs for `iterations` iterations.
+    fn warmup_loop(program: &mut Program, func: Variable, other: Variable,
+                   iterations: isize) {
+        let start = program.load_int(0);
+        let end = program.load_int(iterations);
+        let step = program.load_int(1);
+        let idx = program.begin_for(start, end, step, "++".to_string(),
+                                    Comparators::LessThan);
+        program.function_call(func, vec![idx, other]);
+        program.end_for();
+    }
+
+    /// Emit a single very hot loop that is long enough to trigger on stack
+    /// replacement, where a loop-carried variable changes its type at one or
+    /// two iterations in the middle of the loop.
+    pub fn osr_stress_generator(program: &mut Program) -> Option<()> {
+
+        trace!("osr_stress_generator");
+
+        // Nesting a loop this hot in another loop would just time out
+        if program.is_in_loop() {return None;}
+
+        let iterations = program.rng.rand_in_range(10000, 100000);
+        let num_phases = program.rng.rand_in_range(1, 3);
+        let phases = (0..num_phases)
+            .map(|_| program.rng.rand_in_range(1, iterations))
+            .collect::<Vec<isize>>();
+
+        let acc = program.load_int(0);
+        let start = program.load_int(0);
+        let end = program.load_int(iterations);
+        let step = program.load_int(1);
+        let idx = program.begin_for(start, end, step, "++".to_string(),
+                                    Comparators::LessThan);
+
+        let sum = program.binary_op(acc, idx, BinaryOperators::Add);
+        program.copy(acc, sum);
+
+        for phase in phases {
+            let phase = program.load_int(phase);
+            let cond = program.compare_op(idx, phase, Comparators::Equal);
+            program.begin_if(cond);
+            let value = match program.rng.rand_idx(3) {
+                0 => {
+                    let float = program.getfloat();
+                    program.load_float(float)
+                },
+                1 => {
+                    let string = program.getstring().to_string();
+                    program.load_string(string)
+                },
+                _ => program.create_object(vec![], vec![]),
+            };
+            program.copy(acc, value);
+            program.end_if();
+        }
+
+        program.end_for();
+
+        Some(())
+    }
+
+    /// Define a function that takes a function as its first argument and calls
+    /// it, and then call it with several generated functions that differ in
+    /// their arity and bodies.
+    pub fn higher_order_function_generator(program: &mut Program)
+                                           -> Option<()> {
+
+        trace!("higher_order_function_generator");
+
+        let num_args = program.rng.u64_in_range(0, 3) as u8;
+        let signature = FunctionSignature::new(num_args + 1);
+        let hof = program.begin_function_definition(signature);
+        let params = program.last_temp();
+        let ret = if program.prob.probablity(0.5) {
+            program.function_call(params[0], params[1..].to_vec())
+        } else {
+            // Call the callback from a loop so that the call site gets hot
+            let start = program.load_int(0);
+            let end = program.rng.rand_in_range(1, 0x500);
+            let end = program.load_int(end);
+            let step = program.load_int(1);
+            program.begin_for(start, end, step, "++".to_string(),
+                              Comparators::LessThan);
+            program.function_call(params[0], params[1..].to_vec());
+            program.end_for();
+            program.random_variable(Any)
+        };
+        program.insert_return(ret);
+        program.end_function_definition();
+
+        let num_callbacks = program.rng.rand_in_range(2, 5);
+        for _ in 0..num_callbacks {
+            let arity = program.rng.u64_in_range(0, 4) as u8;
+            let callback = program.begin_function_definition(
+                FunctionSignature::new(arity));
+            program.generate_random_insts(1);
+            let ret = program.random_variable(Any);
+            program.insert_return(ret);
+            program.end_function_definition();
+
+            let mut args = vec![callback];
+            for _ in 0..num_args {
+                args.push(program.random_variable(Any));
+            }
+            program.function_call(hof, args);
+        }
+
+        Some(())
+    }
+
+    /// Wrap a few random instructions in an immediately invoked function
+    /// expression, i.e `(function() { ... })()`.
+    pub fn iife_generator(program: &mut Program) -> Option<()> {
+
+        trace!("iife_generator");
+
+        program.begin_iife();
+        let count = program.rng.u64_in_range(2, 5) as u8;
+        program.generate_random_insts(count);
+        program.end_iife();
+
+        Some(())
+    }
+
+    /// Call an existing function through `Function.prototype.call` or
+    /// `Function.prototype.apply`, or bind it and then call the bound function
+    /// from a loop.
+    pub fn call_apply_bind_generator(program: &mut Program) -> Option<()> {
+
+        trace!("call_apply_bind_generator");
+
+        let func = program.random_variable_of_type(Function, Mode::Strict)?;
+        let typ = program.get_type(&func);
+        if typ != Function || !program.has_signature(&func) {
+            return None;
+        }
+
+        let signature = program.get_signature_for(&func);
+        if signature.is_constructing() {
+            return None;
+        }
+        let output_type = signature.get_output_type();
+
+        let this = program.random_variable(Any);
+        let args = program.generate_function_args(func);
+
+        match program.rng.rand_idx(3) {
+            0 => {
+                let method = MethodSignature::new(
+                    "call", Function,
+                    vec![MethodArg::Type(Any), MethodArg::Repeat(10, Any)],
+                    output_type);
+                let mut inputs = vec![func, this];
+                inputs.extend(args);
+                program.method_call(inputs, method);
+            },
+            1 => {
+                let method = MethodSignature::new(
+                    "apply", Function,
+                    vec![MethodArg::Type(Any), MethodArg::Type(Array)],
+                    output_type);
+                let args = program.create_array(args);
+                program.method_call(vec![func, this, args], method);
+            },
+            _ => {
+                // Bind `this` and some of the leading arguments and pass the
+                // rest when calling the bound function.
+                let num_bound = program.rng.rand_idx(args.len() + 1);
+                let method = MethodSignature::new(
+                    "bind", Function,
+                    vec![MethodArg::Type(Any), MethodArg::Repeat(10, Any)],
+                    Function);
+                let mut inputs = vec![func, this];
+                inputs.extend_from_slice(&args[..num_bound]);
+                let bound = program.method_call(inputs, method);
+
+                let start = program.load_int(0);
+                let end = program.rng.rand_in_range(1, 0x500);
+                let end = program.load_int(end);
+                let step = program.load_int(1);
+                program.begin_for(start, end, step, "++".to_string(),
+                                  Comparators::LessThan);
+                program.function_call(bound, args[num_bound..].to_vec());
+                program.end_for();
+            },
+        }
+
+        Some(())
+    }
+
+    /// Emit a direct or an indirect `eval` of the code of a small, separately
+    /// generated program. This is opt-in as direct evals change the scoping
+    /// rules and disable a lot of the optimizations in the engines.
+    pub fn eval_generator(program: &mut Program) -> Option<()> {
+
+        trace!("eval_generator");
+
+        if !program.allow_eval {return None;}
+
+        // The nested program allocates its variables from the ids of this
+        // program, so that the names in the evaluated code never clash with
+        // the ones around the eval.
+        let seed = program.rng.rand();
+        let mut nested = Program::new(program.jsruntime);
+        nested.seed(seed);
+        nested.allow_eval = true;
+        nested.max_insts = program.max_insts.saturating_sub(program.buffer.len());
+        nested.next_free_variable_id = program.next_free_variable_id;
+        let count = program.rng.u64_in_range(1, 4) as u8;
+        nested.generate_random_insts(count);
+        program.next_free_variable_id = nested.next_free_variable_id;
+
+        // The code is never wrapped in a function of its own, so that the
+        // declarations of a direct eval still go into the scope around it
+        let mut lifter = Lifter::new();
+        lifter.seed(seed);
+        lifter.set_iife_probability(0.0);
+        lifter.do_lifting(&nested);
+
+        let direct = program.prob.probablity(0.5);
+        program.eval(lifter.get_code().to_string(), direct,
+                     nested.next_free_variable_id);
+
+        Some(())
+    }
+
+    pub fn builtin_function_call_generator(program: &mut Program) -> Option<()> {
+
+        trace!("builtin_function_call_generator");
+
+        // These are the global functions that the runtime knows about, like
+        // the engine specific helpers registered by the profile.
+        let functions = program.jsruntime.get_functions();
+        if functions.is_empty() {
+            return None;
+        }
+
+        let function = program.rng.random_element(functions).clone();
+
+        let inputs = program.generate_method_args(&function, None);
+        program.builtin_call(inputs, function);
+
+        Some(())
+    }
+
+    pub fn method_chain_generator(program: &mut Program) -> Option<()> {
+
+        trace!("method_chain_generator");
+
+        let object = program.random_variable_of_type(Object, Mode::Strict)?;
+        let mut shape = program.get_type(&object).shape;
+
+        let length = program.rng.rand_in_range(2, 5);
+        let mut calls = Vec::new();
+        for _ in 0..length {
+            let method = match program.random_method_for_shape(shape) {
+                Some(method) => method,
+                None         => break,
+            };
+
+            let args = program.generate_method_args(&method, None);
+            let output = method.output_type();
+            calls.push((method, args));
+
+            // The next method is picked based on what this one returns, so we
+            // can only go on if it surely returns an object of a known shape.
+            if output.ptype != PType::Object || output.shape == Shape::Any {
+                break;
+            }
+            shape = output.shape;
+        }
+
+        // A single call is just a plain method call
+        if calls.len() < 2 {
+            return None;
+        }
+
+        program.method_chain(object, calls);
+
+        Some(())
+    }
+
+    pub fn constructor_generator(program: &mut Program) -> Option<()> {
+
+        trace!("constructor_generator");
+
+        let count = program.rng.u64_in_range(1, 4) as usize;
+        let properties = program.rng.get_n_random_elements(&PROPERTIES, count)
+                                .iter().map(|prop| prop.to_string())
+                                .collect::<Vec<String>>();
+
+        // Define a function that initializes the properties on `this`, mostly
+        // from its parameters, so that every object constructed by it has the
+        // same shape.
+        let args_count = program.rng.u64_in_range(0, 4) as u8;
+        let func = program.begin_function_definition(
+            FunctionSignature::new(args_count));
+        let params = program.last_temp();
+        let this = program.load_this();
+        for prop in &properties {
+            let value = if !params.is_empty() && program.prob.probablity(0.7) {
+                *program.rng.random_element(&params)
+            } else {
+                program.random_variable(Any)
+            };
+            program.store_property(prop.to_string(), this, value);
+        }
+        program.generate_random_insts(1);
+        program.end_function_definition();
+
+        // Now construct a few objects and use the properties on them
+        let count = program.rng.rand_in_range(1, 4);
+        for _ in 0..count {
+            let inputs = program.generate_function_args(func);
+            let object = program.construct(func, inputs);
+            let prop = program.rng.random_element(&properties).to_string();
+            program.load_property(prop, object);
+        }
+
+        Some(())
+    }
+
+    pub fn gc_pressure_generator(program: &mut Program) -> Option<()> {
+
+        trace!("gc_pressure_generator");
+
+        // First allocate a burst of short lived objects, strings and arrays so
+        // that the heap fills up with garbage.
+        let end = program.rng.rand_in_range(100, 2000);
+        let start = program.load_int(0);
+        let end = program.load_int(end);
+        let step = program.load_int(1);
+
+        let idx = program.begin_for(start, end, step, "++".to_string(),
+                                    Comparators::LessThan);
+
+        let value = program.random_variable(Any);
+        let prop = program.rng.random_element(&PROPERTIES).to_string();
+        program.create_object(vec![prop], vec![idx]);
+        program.create_array(vec![idx, value]);
+        let string = program.load_string("garbage".to_string());
+        program.binary_op(string, idx, BinaryOperators::Add);
+
+        program.end_for();
+
+        // Then force a collection if the engine lets us, else the burst above
+        // is hopefully enough to trigger one on its own.
+        if let Some(gc) = program.jsruntime.get_gc_function().cloned() {
+            let count = program.rng.rand_in_range(1, 3);
+            for _ in 0..count {
+                program.builtin_call(vec![], gc.clone());
+            }
+        }
+
+        // And finally touch an object that has survived the collection
+        if let Some(object) = program.random_variable_of_type(Object,
+                                                              Mode::Strict) {
+            let prop = program.rng.random_element(&PROPERTIES).to_string();
+            program.load_property(prop, object);
+        }
+
+        Some(())
+    }
 }
 
 
@@ -0,0 +1,556 @@
+//! Convert programs to and from the protobuf format that Fuzzilli uses for its
+//! corpus files (`.fzil`). This way an existing Fuzzilli corpus can seed zebra
+//! and the programs that zebra found can be looked at with Fuzzilli's tools.
+//!
+//! Only the operations that both IRs have in common are converted, so
+//! programs using anything else fail to convert. Fuzzilli has renumbered the
+//! fields of its `Instruction` message over time. The numbers in [field]
+//! follow the releases that still had the `Copy` operation.
+//!
+//! A few operations don't map exactly:
+//! * zebra's for loops step with `++` or `--` and ignore the step input, while
+//!   Fuzzilli applies a binary operator with the step. They are converted to
+//!   `+` and `-` and back.
+//! * Method signatures and builtin types are not part of the Fuzzilli format.
+//!   On import, builtins are looked up in the runtime and method calls take
+//!   arguments of any type.
+
+use std::collections::HashMap;
+
+use super::instruction::Instruction;
+use super::opcodes::Opcodes;
+use super::operation::*;
+use super::operators::*;
+use super::variable::Variable;
+use super::codeanalysis::types::{FunctionSignature, MethodArg};
+use super::codeanalysis::types::{MethodSignature, ConstructorType};
+use super::codeanalysis::types::{Any, Unknown};
+use crate::jsruntime::jsruntime::JSRuntime;
+use crate::utils::protobuf::{decode_message, repeated_varints};
+use crate::utils::protobuf::{Writer, WireValue};
+
+#[derive(Debug)]
+struct InteropError(String);
+impl std::fmt::Display for InteropError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InteropError {}
+
+type InteropResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn error<T>(msg: String) -> InteropResult<T> {
+    Err(Box::new(InteropError(msg)))
+}
+
+/// The field numbers of the `Program` and `Instruction` messages
+mod field {
+    pub const UUID:    u32 = 1;
+    pub const CODE:    u32 = 2;
+
+    pub const INOUTS:  u32 = 1;
+    pub const OP_IDX:  u32 = 2;
+
+    pub const LOAD_INTEGER:             u32 = 3;
+    pub const LOAD_FLOAT:               u32 = 4;
+    pub const LOAD_STRING:              u32 = 5;
+    pub const LOAD_BOOLEAN:             u32 = 6;
+    pub const LOAD_UNDEFINED:           u32 = 7;
+    pub const CREATE_OBJECT:            u32 = 9;
+    pub const CREATE_ARRAY:             u32 = 10;
+    pub const LOAD_BUILTIN:             u32 = 13;
+    pub const LOAD_PROPERTY:            u32 = 14;
+    pub const STORE_PROPERTY:           u32 = 15;
+    pub const LOAD_COMPUTED_PROPERTY:   u32 = 20;
+    pub const STORE_COMPUTED_PROPERTY:  u32 = 21;
+    pub const DELETE_COMPUTED_PROPERTY: u32 = 22;
+    pub const BEGIN_FUNCTION:           u32 = 26;
+    pub const RETURN:                   u32 = 27;
+    pub const END_FUNCTION:             u32 = 28;
+    pub const CALL_METHOD:              u32 = 29;
+    pub const CALL_FUNCTION:            u32 = 30;
+    pub const CONSTRUCT:                u32 = 31;
+    pub const UNARY_OPERATION:          u32 = 33;
+    pub const BINARY_OPERATION:         u32 = 34;
+    pub const COPY:                     u32 = 36;
+    pub const COMPARE:                  u32 = 37;
+    pub const NOP:                      u32 = 43;
+    pub const BEGIN_IF:                 u32 = 44;
+    pub const BEGIN_ELSE:               u32 = 45;
+    pub const END_IF:                   u32 = 46;
+    pub const BEGIN_FOR:                u32 = 51;
+    pub const END_FOR:                  u32 = 52;
+    pub const BREAK:                    u32 = 57;
+    pub const CONTINUE:                 u32 = 58;
+}
+
+/// The values of Fuzzilli's `Comparator` enum, in the order of [Comparators]
+const COMPARATORS: [u64; 7] = [0, 1, 2, 4, 5, 6, 7];
+
+/// The values of Fuzzilli's `UnaryOperator` enum, in the order of
+/// [UnaryOperators]. `++` and `--` are the postfix ones.
+const UNARY_OPERATORS: [u64; 4] = [2, 3, 4, 5];
+
+fn position<T: PartialEq>(values: &[T], value: &T) -> Option<usize> {
+    values.iter().position(|v| v == value)
+}
+
+/// Convert the `instructions` of a program into an encoded Fuzzilli program
+pub fn export_program(instructions: &[Instruction]) -> InteropResult<Vec<u8>> {
+
+    // Fuzzilli expects the variables to be numbered in the order in which they
+    // are defined, which may not hold after zebra reduced a program.
+    let mut numbering = HashMap::new();
+    let mut code = Vec::with_capacity(instructions.len());
+
+    for inst in instructions {
+        let (op_field, op) = export_operation(&inst.operation)?;
+
+        let mut inouts = Vec::new();
+        for input in inst.inputs() {
+            match numbering.get(&input.0) {
+                Some(number) => inouts.push(*number),
+                None => return error(format!("Undefined variable {}", input.0)),
+            }
+        }
+        for output in inst.outputs().iter().chain(inst.temp().iter()) {
+            let number = numbering.len() as u64;
+            numbering.insert(output.0, number);
+            inouts.push(number);
+        }
+
+        let mut writer = Writer::new();
+        writer.packed(field::INOUTS, &inouts);
+        writer.bytes(op_field, &op);
+        code.push(writer.finish());
+    }
+
+    let mut program = Writer::new();
+    program.bytes(field::UUID, &uuid(&code));
+    for inst in code {
+        program.bytes(field::CODE, &inst);
+    }
+    Ok(program.finish())
+}
+
+/// Derive the uuid of a program from its code, so that exporting the same
+/// program twice gives the same file.
+fn uuid(code: &[Vec<u8>]) -> [u8; 16] {
+    let mut hashes = [0xcbf29ce484222325u64, 0x84222325cbf29ce4u64];
+    for hash in hashes.iter_mut() {
+        for byte in code.iter().flatten() {
+            *hash ^= *byte as u64;
+            *hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    let mut uuid = [0; 16];
+    uuid[..8].copy_from_slice(&hashes[0].to_le_bytes());
+    uuid[8..].copy_from_slice(&hashes[1].to_le_bytes());
+    uuid
+}
+
+/// Get the field number of the Fuzzilli operation for `operation` along with
+/// the encoded operation message
+fn export_operation(operation: &Operation) -> InteropResult<(u32, Vec<u8>)> {
+    let mut writer = Writer::new();
+
+    let op_field = match operation {
+        Operation::Nop(_)           => field::NOP,
+        Operation::LoadUndefined(_) => field::LOAD_UNDEFINED,
+        Operation::Copy(_)          => field::COPY,
+        Operation::BeginIf(_)       => field::BEGIN_IF,
+        Operation::BeginElse(_)     => field::BEGIN_ELSE,
+        Operation::EndIf(_)         => field::END_IF,
+        Operation::EndFor(_)        => field::END_FOR,
+        Operation::Break(_)         => field::BREAK,
+        Operation::Continue(_)      => field::CONTINUE,
+        Operation::Return(_)        => field::RETURN,
+        Operation::FunctionCall(_)  => field::CALL_FUNCTION,
+        Operation::Construct(_)     => field::CONSTRUCT,
+        Operation::CreateArray(_)   => field::CREATE_ARRAY,
+        Operation::LoadElement(_)   => field::LOAD_COMPUTED_PROPERTY,
+        Operation::StoreElement(_)  => field::STORE_COMPUTED_PROPERTY,
+        Operation::Delete(_)        => field::DELETE_COMPUTED_PROPERTY,
+        Operation::EndFunctionDefinition(_) => field::END_FUNCTION,
+
+        Operation::LoadInt(op) => {
+            writer.varint(1, op.0 as i64 as u64);
+            field::LOAD_INTEGER
+        },
+        Operation::LoadFloat(op) => {
+            writer.fixed64(1, op.0.to_bits());
+            field::LOAD_FLOAT
+        },
+        Operation::LoadString(op) => {
+            writer.bytes(1, op.0.as_bytes());
+            field::LOAD_STRING
+        },
+        Operation::LoadBool(op) => {
+            writer.varint(1, op.0 as u64);
+            field::LOAD_BOOLEAN
+        },
+        Operation::LoadProperty(op) => {
+            writer.bytes(1, op.0.as_bytes());
+            field::LOAD_PROPERTY
+        },
+        Operation::StoreProperty(op) => {
+            writer.bytes(1, op.0.as_bytes());
+            field::STORE_PROPERTY
+        },
+        Operation::CreateObject(op) => {
+            for property in op.0.iter() {
+                writer.bytes(1, property.as_bytes());
+            }
+            field::CREATE_OBJECT
+        },
+        Operation::MethodCall(op) => {
+            writer.bytes(1, op.0.get_name().as_bytes());
+            field::CALL_METHOD
+        },
+        Operation::LoadBuiltin(LoadBuiltin(ConstructorType::NonCallable(name, _),
+                                           0)) => {
+            writer.bytes(1, name.as_bytes());
+            field::LOAD_BUILTIN
+        },
+        Operation::BinaryOp(op) => {
+            writer.varint(1, op.0 as u64);
+            field::BINARY_OPERATION
+        },
+        Operation::UnaryOp(op) => {
+            let idx = position(&UnaryOperators::all(), &op.0).unwrap();
+            writer.varint(1, UNARY_OPERATORS[idx]);
+            field::UNARY_OPERATION
+        },
+        Operation::CompareOp(op) => {
+            let idx = position(&Comparators::all(), &op.0).unwrap();
+            writer.varint(1, COMPARATORS[idx]);
+            field::COMPARE
+        },
+        Operation::BeginFor(op) => {
+            let step = match op.0.as_str() {
+                "++" => BinaryOperators::Add,
+                "--" => BinaryOperators::Sub,
+                step => return error(format!("Unsupported loop step {}", step)),
+            };
+            let idx = position(&Comparators::all(), &op.1).unwrap();
+            writer.varint(1, COMPARATORS[idx]);
+            writer.varint(2, step as u64);
+            field::BEGIN_FOR
+        },
+        Operation::BeginFunctionDefinition(op) => {
+            // A signature with an input type for each parameter, the types
+            // are left empty for Fuzzilli to figure out
+            let mut signature = Writer::new();
+            for _ in 0..op.0.args_count() {
+                signature.bytes(1, &[]);
+            }
+            writer.bytes(1, &signature.finish());
+            field::BEGIN_FUNCTION
+        },
+
+        operation => {
+            return error(format!("{:?} can't be converted",
+                                 operation.opcode()));
+        },
+    };
+
+    Ok((op_field, writer.finish()))
+}
+
+/// Read back the instructions of an encoded Fuzzilli program. Builtins are
+/// looked up in `jsruntime`.
+pub fn import_program(data: &[u8],
+                      jsruntime: &JSRuntime) -> InteropResult<Vec<Instruction>> {
+    let mut instructions = Vec::new();
+    let mut blocks = Vec::new();
+    let mut next_variable = 0;
+
+    // Operations that were already seen can be referred to by their index
+    let mut operations = Vec::new();
+
+    for (_, inst) in decode_message(data)?.iter().filter(|(f, _)| {
+        *f == field::CODE
+    }) {
+        let inst = match inst.as_bytes() {
+            Some(inst) => decode_message(inst)?,
+            None => return error("Invalid instruction".into()),
+        };
+
+        let inouts = repeated_varints(&inst, field::INOUTS)?;
+        let (op_field, op) = match inst.iter().find(|(f, _)| {
+            *f != field::INOUTS
+        }) {
+            Some((field::OP_IDX, idx)) => {
+                let idx = idx.as_varint().unwrap_or(u64::MAX) as usize;
+                match operations.get(idx) {
+                    Some(op) => *op,
+                    None => return error(format!("Invalid opIdx {}", idx)),
+                }
+            },
+            Some((op_field, WireValue::Bytes(op))) => {
+                operations.push((*op_field, *op));
+                (*op_field, *op)
+            },
+            _ => return error("Missing operation".into()),
+        };
+
+        let operation = import_operation(op_field, &decode_message(op)?,
+                                         inouts.len(), jsruntime)?;
+        let num_inputs = operation.num_inputs() as usize;
+        let num_outputs = operation.num_outputs() as usize;
+        let num_temp = operation.num_temp() as usize;
+        if inouts.len() != num_inputs + num_outputs + num_temp {
+            return error(format!("Wrong no. of variables for {:?}",
+                                 operation.opcode()));
+        }
+
+        // Variables have to be defined in order before they are used
+        let inputs = &inouts[..num_inputs];
+        if inputs.iter().any(|v| *v >= next_variable) ||
+           inouts[num_inputs..].iter().enumerate().any(|(idx, v)| {
+               *v != next_variable + idx as u64
+           }) {
+            return error("Invalid variable numbering".into());
+        }
+        next_variable += (num_outputs + num_temp) as u64;
+
+        check_block(&mut blocks, &operation)?;
+
+        let mut vars = inouts.iter().map(|v| Variable(*v as u32));
+        let inputs = vars.by_ref().take(num_inputs).collect();
+        let outputs = vars.by_ref().take(num_outputs).collect();
+        let temp = vars.collect();
+        instructions.push(Instruction::new(instructions.len() as u32,
+                                           operation, inputs, outputs, temp));
+    }
+
+    if !blocks.is_empty() {
+        return error("Unterminated block".into());
+    }
+
+    Ok(instructions)
+}
+
+/// Check that `operation` opens or closes a block in the right place, using
+/// the stack of open `blocks`
+fn check_block(blocks: &mut Vec<Opcodes>,
+               operation: &Operation) -> InteropResult<()> {
+    if operation.is_block_end() {
+        let matches = matches!((blocks.pop(), operation.opcode()),
+            (Some(Opcodes::BeginIf), Opcodes::BeginElse) |
+            (Some(Opcodes::BeginIf), Opcodes::EndIf)     |
+            (Some(Opcodes::BeginElse), Opcodes::EndIf)   |
+            (Some(Opcodes::BeginFor), Opcodes::EndFor)   |
+            (Some(Opcodes::BeginFunctionDefinition),
+             Opcodes::EndFunctionDefinition));
+
+        if !matches {
+            return error(format!("Unexpected {:?}", operation.opcode()));
+        }
+    }
+
+    if operation.is_block_start() {
+        blocks.push(operation.opcode());
+    }
+    Ok(())
+}
+
+/// Get the last value of the field `field` of a message
+fn get_field<'a>(message: &[(u32, WireValue<'a>)],
+                 field: u32) -> Option<WireValue<'a>> {
+    message.iter().rev().find(|(f, _)| *f == field).map(|(_, value)| *value)
+}
+
+/// Get the integer field `field` of a message. Fields that are missing have the
+/// default value of 0.
+fn get_varint(message: &[(u32, WireValue)], field: u32) -> InteropResult<u64> {
+    match get_field(message, field) {
+        Some(WireValue::Varint(value)) => Ok(value),
+        None => Ok(0),
+        Some(_) => error(format!("Expected an integer for field {}", field)),
+    }
+}
+
+fn get_string(message: &[(u32, WireValue)],
+              field: u32) -> InteropResult<String> {
+    match get_field(message, field) {
+        Some(WireValue::Bytes(value)) => {
+            Ok(String::from_utf8_lossy(value).to_string())
+        },
+        None => Ok(String::new()),
+        Some(_) => error(format!("Expected a string for field {}", field)),
+    }
+}
+
+fn get_enum<T: std::marker::Copy, const N: usize>(values: &[u64; N],
+                                                  all: [T; N],
+                                                  value: u64) -> InteropResult<T> {
+    match position(values, &value) {
+        Some(idx) => Ok(all[idx]),
+        None => error(format!("Unsupported operator {}", value)),
+    }
+}
+
+/// Build the zebra operation for the Fuzzilli operation that is in the field
+/// `op_field`. `num_inouts` is the total no. of variables of the instruction,
+/// which gives the no. of arguments of calls and the like.
+fn import_operation(op_field: u32, op: &[(u32, WireValue)], num_inouts: usize,
+                    jsruntime: &JSRuntime) -> InteropResult<Operation> {
+
+    // The no. of inputs for operations that take any no. of inputs and have a
+    // single output
+    let variadic = || -> InteropResult<u8> {
+        match u8::try_from(num_inouts.saturating_sub(1)) {
+            Ok(inputs) => Ok(inputs),
+            Err(_) => error("Too many inputs".into()),
+        }
+    };
+
+    // The calls take the function or the object as the first input
+    let args = || -> InteropResult<u8> {
+        Ok(variadic()?.saturating_sub(1))
+    };
+
+    let operation = match op_field {
+        field::NOP            => Nop().into(),
+        field::LOAD_UNDEFINED => LoadUndefined().into(),
+        field::COPY           => Copy().into(),
+        field::BEGIN_IF       => BeginIf().into(),
+        field::BEGIN_ELSE     => BeginElse().into(),
+        field::END_IF         => EndIf().into(),
+        field::END_FOR        => EndFor().into(),
+        field::BREAK          => Break().into(),
+        field::CONTINUE       => Continue().into(),
+        field::RETURN         => Return().into(),
+        field::END_FUNCTION   => EndFunctionDefinition().into(),
+        field::CALL_FUNCTION  => FunctionCall(args()?).into(),
+        field::CONSTRUCT      => Construct(args()?).into(),
+        field::CREATE_ARRAY   => CreateArray(variadic()?).into(),
+        field::LOAD_COMPUTED_PROPERTY   => LoadElement().into(),
+        field::STORE_COMPUTED_PROPERTY  => StoreElement().into(),
+        field::DELETE_COMPUTED_PROPERTY => Delete(false).into(),
+
+        field::LOAD_INTEGER => {
+            LoadInt(get_varint(op, 1)? as i64 as isize).into()
+        },
+        field::LOAD_FLOAT => match get_field(op, 1) {
+            Some(WireValue::Fixed64(bits)) => LoadFloat(f64::from_bits(bits))
+                                              .into(),
+            None => LoadFloat(0.0).into(),
+            Some(_) => return error("Expected a double".into()),
+        },
+        field::LOAD_STRING    => LoadString(get_string(op, 1)?).into(),
+        field::LOAD_BOOLEAN   => LoadBool(get_varint(op, 1)? != 0).into(),
+        field::LOAD_PROPERTY  => LoadProperty(get_string(op, 1)?).into(),
+        field::STORE_PROPERTY => StoreProperty(get_string(op, 1)?).into(),
+        field::CREATE_OBJECT  => {
+            let mut properties = Vec::new();
+            for (_, name) in op.iter().filter(|(f, _)| *f == 1) {
+                match name.as_bytes() {
+                    Some(name) => properties.push(String::from_utf8_lossy(name)
+                                                  .to_string()),
+                    None => return error("Expected a property name".into()),
+                }
+            }
+            CreateObject(properties).into()
+        },
+        field::CALL_METHOD => {
+            let args = args()?;
+            let signature = MethodSignature::new(get_string(op, 1)?, Unknown,
+                                                 vec![MethodArg::Type(Any);
+                                                      args as usize],
+                                                 Unknown);
+            MethodCall(signature, args).into()
+        },
+        field::LOAD_BUILTIN => {
+            let name = get_string(op, 1)?;
+            let builtin = jsruntime.get_constructors().iter().find(|ctype| {
+                matches!(ctype, ConstructorType::NonCallable(n, _) if *n == name)
+            });
+
+            let ctype = match builtin {
+                Some(ctype) => ctype.clone(),
+                None => ConstructorType::NonCallable(name, Unknown),
+            };
+            LoadBuiltin(ctype, 0).into()
+        },
+        field::BINARY_OPERATION => {
+            match BinaryOperators::all().get(get_varint(op, 1)? as usize) {
+                Some(op) => BinaryOp(*op).into(),
+                None => return error("Unsupported binary operator".into()),
+            }
+        },
+        field::UNARY_OPERATION => {
+            // The prefix increment and decrement are treated like the postfix
+            // ones
+            let value = match get_varint(op, 1)? {
+                0 => 2,
+                1 => 3,
+                value => value,
+            };
+            UnaryOp(get_enum(&UNARY_OPERATORS, UnaryOperators::all(),
+                             value)?).into()
+        },
+        field::COMPARE => {
+            CompareOp(get_enum(&COMPARATORS, Comparators::all(),
+                               get_varint(op, 1)?)?).into()
+        },
+        field::BEGIN_FOR => {
+            let cmp = get_enum(&COMPARATORS, Comparators::all(),
+                               get_varint(op, 1)?)?;
+            let step = match get_varint(op, 2)? {
+                0 => "++",
+                1 => "--",
+                _ => return error("Unsupported loop step".into()),
+            };
+            BeginFor(step.to_string(), cmp).into()
+        },
+        field::BEGIN_FUNCTION => {
+            let params = variadic()?;
+            BeginFunctionDefinition(FunctionSignature::new(params)).into()
+        },
+
+        op_field => {
+            return error(format!("Unsupported operation {}", op_field));
+        },
+    };
+
+    Ok(operation)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::program::Program;
+    use crate::ir::serialize::encode_program;
+
+    #[test]
+    fn test_fuzzilli() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+
+        let start = program.load_int(-3);
+        let end = program.load_float(10.5);
+        let step = program.load_int(1);
+        let idx = program.begin_for(start, end, step, "++".to_string(),
+                                    Comparators::LessThan);
+        let sum = program.binary_op(idx, end, BinaryOperators::Mul);
+        program.unary_op(sum, UnaryOperators::Inc);
+        program.end_for();
+        let array = program.create_array(vec![start, end]);
+        program.load_element(array, step);
+
+        let data = export_program(&program.buffer).unwrap();
+        let imported = import_program(&data, &runtime).unwrap();
+        assert_eq!(encode_program(&imported).to_string(),
+                   encode_program(&program.buffer).to_string());
+
+        // Programs with operations that Fuzzilli doesn't have are not converted
+        program.eval("1 + 1".to_string(), false, 0);
+        assert!(export_program(&program.buffer).is_err());
+        assert!(import_program(&data[..data.len() - 1], &runtime).is_err());
+    }
+}
@@ -15,9 +15,10 @@ pub enum Value {
 
 /// A Zebra IR instruction. This will contain all the runtime data required for
 /// the operation of a single Operation.
+#[derive(Clone)]
 pub struct Instruction {
     pub idx:        u32,
-    pub operation:  Box<dyn Operation>,
+    pub operation:  Operation,
     pub inputs:     Vec<Variable>,
     pub outputs:    Vec<Variable>,
     pub temp:       Vec<Variable>,
@@ -25,7 +26,7 @@ pub struct Instruction {
 
 impl Instruction {
 
-    pub fn new(idx: u32, operation: Box<dyn Operation>, inputs: Vec<Variable>,
+    pub fn new(idx: u32, operation: Operation, inputs: Vec<Variable>,
                outputs: Vec<Variable>, temp: Vec<Variable>,) -> Self {
 
         debug_assert!(inputs.len() == operation.num_inputs() as usize,
@@ -50,23 +51,16 @@ impl Instruction {
                    "Invalid Opcode {:?} called getval() ",
                    self.operation.opcode());
 
-        // First cast the operation to `Any` type
-        let val = self.operation.as_any();
-
-        // Now find the concrete type of the operation
-        if let Some(val) = val.downcast_ref::<LoadInt>() {
-            Value::Int(val.0)
-        } else if let Some(val) = val.downcast_ref::<LoadFloat>() {
-            Value::Float(val.0)
-        } else if let Some(val) = val.downcast_ref::<LoadBool>() {
-            Value::Bool(val.0)
-        } else if let Some(val) = val.downcast_ref::<LoadString>() {
-            Value::Str(val.0.clone())
-        } else if let Some(_) = val.downcast_ref::<LoadUndefined>() {
-            Value::Undefined
-        } else {
-            debug_assert!(false, "unreachable branch");
-            Value::None
+        match &self.operation {
+            Operation::LoadInt(val)    => Value::Int(val.0),
+            Operation::LoadFloat(val)  => Value::Float(val.0),
+            Operation::LoadBool(val)   => Value::Bool(val.0),
+            Operation::LoadString(val) => Value::Str(val.0.clone()),
+            Operation::LoadUndefined(_) => Value::Undefined,
+            _ => {
+                debug_assert!(false, "unreachable branch");
+                Value::None
+            }
         }
     }
 
@@ -102,17 +96,18 @@ impl Instruction {
         &self.temp
     }
 
-    pub fn cast_into<T: Operation + 'static>(&self) -> &T {
-        self.operation.as_any().downcast_ref::<T>().unwrap()
+    /// Get the Operation struct of this instruction. The type has to match the
+    /// opcode of the instruction.
+    pub fn cast_into<T: Cast>(&self) -> &T {
+        T::cast(&self.operation).unwrap()
     }
 
-    pub fn cast_into_mut<T: Operation + 'static>(&mut self) -> &mut T {
-        self.operation.as_any_mut().downcast_mut::<T>().unwrap()
+    pub fn cast_into_mut<T: Cast>(&mut self) -> &mut T {
+        T::cast_mut(&mut self.operation).unwrap()
     }
 
-    /// Display the instruction. Only valid for debugging
-    #[cfg(debug_assertions)]
-    pub fn _print(&self) -> String {
+    /// Display the instruction in a form like `v2 = BinaryOp(v0, v1)`
+    pub fn print(&self) -> String {
         use super::opcodes::Opcodes;
 
         let mut s: String = String::new();
@@ -131,7 +126,7 @@ impl Instruction {
         if self.operation.opcode() == Opcodes::BeginFor {
 
             let tmp =  self.temp_at(0);
-            let op =  self.operation.as_any().downcast_ref::<BeginFor>().unwrap();
+            let op =  self.cast_into::<BeginFor>();
             let out = format!("{} = {}, {} {} {}, {}{}",
                         tmp.print(), self.input_at(0).print(),
                         tmp.print(), op.1.rep(), self.input_at(1).print(),
@@ -169,86 +164,86 @@ impl Instruction {
 #[cfg(test)]
 pub mod test {
     use super::*;
-    use crate::operators::*;
-    use crate::operation as ops;
-    use crate::variable::Variable;
+    use crate::ir::operators::*;
+    use crate::ir::operation as ops;
+    use crate::ir::variable::Variable;
 
     pub fn _createinst() -> Vec<Instruction> {
 
         let uop = ops::UnaryOp(UnaryOperators::Inc);
 
         vec![
-            Instruction::new(0, Box::new(ops::LoadInt(1337)),
+            Instruction::new(0, ops::LoadInt(1337).into(),
                              vec![],
                              vec![Variable(1)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::LoadFloat(133.37)),
+            Instruction::new(0, ops::LoadFloat(133.37).into(),
                              vec![],
                              vec![Variable(2)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::LoadUndefined()),
+            Instruction::new(0, ops::LoadUndefined().into(),
                              vec![],
                              vec![Variable(3)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::LoadBool(true)),
+            Instruction::new(0, ops::LoadBool(true).into(),
                              vec![],
                              vec![Variable(4)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::LoadString("Hello all".to_string())),
+            Instruction::new(0, ops::LoadString("Hello all".to_string()).into(),
                              vec![],
                              vec![Variable(5)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::BeginFor("++".to_string(), Comparators::LessThan)),
+            Instruction::new(0, ops::BeginFor("++".to_string(), Comparators::LessThan).into(),
                              vec![Variable(7), Variable(8), Variable(9)],
                              vec![],
                              vec![Variable(10)]),
 
-            Instruction::new(0, Box::new(ops::BeginIf()),
+            Instruction::new(0, ops::BeginIf().into(),
                              vec![Variable(4)],
                              vec![],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::BeginFor("++".to_string(), Comparators::LessThan)),
+            Instruction::new(0, ops::BeginFor("++".to_string(), Comparators::LessThan).into(),
                              vec![Variable(7), Variable(8), Variable(9)],
                              vec![],
                              vec![Variable(10)]),
 
-            Instruction::new(0, Box::new(ops::BinaryOp(BinaryOperators::Add)),
+            Instruction::new(0, ops::BinaryOp(BinaryOperators::Add).into(),
                              vec![Variable(1), Variable(2)],
                              vec![Variable(6)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::EndFor()),
+            Instruction::new(0, ops::EndFor().into(),
                              vec![],
                              vec![],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::BeginElse()),
+            Instruction::new(0, ops::BeginElse().into(),
                              vec![],
                              vec![],
                              vec![]),
 
-            Instruction::new(0, Box::new(uop),
+            Instruction::new(0, uop.into(),
                              vec![Variable(1)],
                              vec![Variable(6)],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::EndIf()),
+            Instruction::new(0, ops::EndIf().into(),
                              vec![],
                              vec![],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::EndFor()),
+            Instruction::new(0, ops::EndFor().into(),
                              vec![],
                              vec![],
                              vec![]),
 
-            Instruction::new(0, Box::new(ops::LoadBool(true)),
+            Instruction::new(0, ops::LoadBool(true).into(),
                              vec![],
                              vec![Variable(7)],
                              vec![]),
@@ -0,0 +1,114 @@
+//! Brings programs into a normal form, so that programs which only differ in
+//! ways that the engine can't tell apart look the same. The variables are
+//! numbered in the order that they are defined in, the Nops are left out, and
+//! the constants that are the same value in JS are written the same way. The
+//! hash of the normal form says which programs are the same, see
+//! [structural_hash].
+
+use std::collections::HashMap;
+
+use super::instruction::Instruction;
+use super::operation::*;
+use super::opcodes::Opcodes as op;
+use super::serialize::encode_program;
+use super::variable::Variable;
+use crate::utils::hash::fnv1a;
+
+/// The largest integer that a float holds exactly, 2^53
+const MAX_SAFE_FLOAT: f64 = 9007199254740992.0;
+
+/// Get the number of the normal form of `variable`, numbering the ones that
+/// weren't seen yet in order
+fn renumber(ids: &mut HashMap<u32, u32>, variable: &Variable) -> Variable {
+    let next = ids.len() as u32;
+    Variable(*ids.entry(variable.0).or_insert(next))
+}
+
+/// Write the constants that are the same value in JS the same way. Floats
+/// with an integer value are the same as the integer, and all the NaNs are
+/// the same NaN. A -0 is a value of its own.
+fn canonical(operation: &Operation) -> Operation {
+    match operation {
+        Operation::LoadFloat(LoadFloat(val)) if val.is_nan() => {
+            LoadFloat(f64::NAN).into()
+        },
+        Operation::LoadFloat(LoadFloat(val))
+            if val.fract() == 0.0 && val.abs() <= MAX_SAFE_FLOAT &&
+               !(*val == 0.0 && val.is_sign_negative()) => {
+            LoadInt(*val as isize).into()
+        },
+        operation => operation.clone(),
+    }
+}
+
+/// Get the normal form of the program made of `instructions`
+pub fn normalize(instructions: &[Instruction]) -> Vec<Instruction> {
+    let mut ids = HashMap::new();
+    let mut normal = Vec::with_capacity(instructions.len());
+
+    for inst in instructions {
+        if inst.operation.opcode() == op::Nop {
+            continue;
+        }
+
+        // The variables that an instruction defines are numbered before the
+        // ones that it uses are looked up, but an instruction only ever uses
+        // the ones that were defined before it
+        let inputs = inst.inputs().iter()
+            .map(|input| renumber(&mut ids, input))
+            .collect();
+        let outputs = inst.outputs().iter()
+            .map(|output| renumber(&mut ids, output))
+            .collect();
+        let temp = inst.temp().iter()
+            .map(|temp| renumber(&mut ids, temp))
+            .collect();
+        normal.push(Instruction::new(normal.len() as u32,
+                                     canonical(&inst.operation),
+                                     inputs, outputs, temp));
+    }
+
+    normal
+}
+
+/// The hash of the normal form of the program made of `instructions`, which
+/// stays the same across builds of the fuzzer
+pub fn structural_hash(instructions: &[Instruction]) -> u64 {
+    fnv1a(&encode_program(&normalize(instructions)).to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::program::Program;
+    use crate::ir::operators::BinaryOperators;
+    use crate::jsruntime::jsruntime::JSRuntime;
+
+    #[test]
+    fn test_structural_hash() {
+        let runtime = JSRuntime::new();
+        let mut first = Program::new(&runtime);
+        let lhs = first.load_int(3);
+        let rhs = first.load_float(f64::NAN);
+        first.binary_op(lhs, rhs, BinaryOperators::Add);
+
+        // The same program with other variables, a Nop and other constants
+        // that are the same values
+        let mut second = Program::new(&runtime);
+        second.next_free_variable_id = 10;
+        let lhs = second.load_float(3.0);
+        second.nop();
+        let rhs = second.load_float(-f64::NAN);
+        second.binary_op(lhs, rhs, BinaryOperators::Add);
+        assert_eq!(structural_hash(&first.buffer),
+                   structural_hash(&second.buffer));
+        assert_eq!(normalize(&second.buffer).len(), 3);
+
+        let mut third = Program::new(&runtime);
+        let lhs = third.load_float(-0.0);
+        let rhs = third.load_float(f64::NAN);
+        third.binary_op(lhs, rhs, BinaryOperators::Add);
+        assert_ne!(structural_hash(&first.buffer),
+                   structural_hash(&third.buffer));
+    }
+}
@@ -30,4 +30,11 @@ pub enum Opcodes {
     LoadBuiltin,
     CreateObject,
     Delete,
+    BeginIIFE,
+    EndIIFE,
+    Eval,
+    BuiltinCall,
+    MethodChain,
+    LoadThis,
+    Construct,
 }
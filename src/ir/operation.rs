@@ -1,5 +1,4 @@
 use bitflags::bitflags;
-use std::any::Any;
 use super::opcodes::Opcodes;
 use super::operators::*;
 use super::codeanalysis::types::{FunctionSignature, MethodSignature};
@@ -24,7 +23,7 @@ bitflags! {
 /// This trait implements functions that all Operations must satisfy. Over-ride
 /// these functions in the respective struct implementations so as to return the
 /// properties of that particular Operation.
-pub trait Operation {
+pub trait OperationInfo {
     fn opcode(&self) -> Opcodes;
     fn attributes(&self) -> Attributes {
         Attributes::NONE
@@ -101,20 +100,12 @@ pub trait Operation {
             false
         }
     }
-
-    // a function to help cast the object back to the concrete type
-    // https://stackoverflow.com/a/33687996
-    fn as_any(&self) -> &dyn Any;
-
-    fn as_any_mut(&mut self) -> &mut dyn Any {
-        panic!("Should not mut access");
-    }
 }
 
 macro_rules! define_impl {
     ($opcode: ident, $attr: ident,
      $inputs: stmt, $outputs: stmt) => {
-        impl Operation for $opcode {
+        impl OperationInfo for $opcode {
             fn opcode(&self) -> Opcodes {
                 Opcodes::$opcode
             }
@@ -130,10 +121,6 @@ macro_rules! define_impl {
             fn num_inputs(&self) -> u8 {
                 $inputs
             }
-
-            fn as_any(&self) -> &dyn Any {
-                self
-            }
         }
     };
 }
@@ -142,7 +129,7 @@ macro_rules! define {
     ($opcode: ident, $attr: ident, $type: ty,
      $inputs: literal, $outputs: literal) => {
 
-        #[derive(Debug)]
+        #[derive(Debug, Clone)]
         pub struct $opcode(pub $type);
         define_impl!($opcode, $attr, $inputs, $outputs);
     };
@@ -150,7 +137,7 @@ macro_rules! define {
     ($opcode: ident, $attr: ident,
      $inputs: literal, $outputs: literal) => {
 
-        #[derive(Debug)]
+        #[derive(Debug, Clone)]
         pub struct $opcode();
         define_impl!($opcode, $attr, $inputs, $outputs);
     };
@@ -185,13 +172,17 @@ define!(EndFunctionDefinition,  IS_FUNCTION_END, 0, 0);
 define!(Return,                 NONE,            1, 0);
 define!(LoadElement,            NONE,            2, 1);
 define!(StoreElement,           NONE,            3, 0);
+define!(BeginIIFE,              IS_FUNCTION_START, 0, 0);
+define!(EndIIFE,                IS_FUNCTION_END, 0, 0);
+define!(LoadThis,               NONE,            0, 1);
 
 //
 // Define opcodes with more complex functionality
 //
 
+#[derive(Clone)]
 pub struct BeginElse();
-impl Operation for BeginElse {
+impl OperationInfo for BeginElse {
     fn opcode(&self) -> Opcodes {
         Opcodes::BeginElse
     }
@@ -199,18 +190,16 @@ impl Operation for BeginElse {
     fn attributes(&self) -> Attributes {
         Attributes::IS_BLOCK_START | Attributes::IS_BLOCK_END
     }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
 }
 
+#[derive(Clone)]
 pub struct BeginFor (
     // The operation that is used to step, eg - ++, --, += etc...
     pub String,
     // The comparator that is used to test the end condition. Eg, < , > etc
     pub Comparators,
 );
-impl Operation for BeginFor {
+impl OperationInfo for BeginFor {
     fn opcode(&self) -> Opcodes {
         Opcodes::BeginFor
     }
@@ -226,15 +215,11 @@ impl Operation for BeginFor {
     fn num_temp(&self) -> u8 {
         1
     }
-
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
 }
 
 #[derive(Debug,Clone)]
 pub struct BeginFunctionDefinition (pub FunctionSignature);
-impl Operation for BeginFunctionDefinition {
+impl OperationInfo for BeginFunctionDefinition {
     fn opcode(&self) -> Opcodes {
         Opcodes::BeginFunctionDefinition
     }
@@ -250,18 +235,11 @@ impl Operation for BeginFunctionDefinition {
     fn num_outputs(&self) -> u8 {
         1
     }
-
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn as_any_mut(&mut self) -> &mut dyn Any {
-        self
-    }
 }
 
+#[derive(Clone)]
 pub struct FunctionCall(pub u8);
-impl Operation for FunctionCall {
+impl OperationInfo for FunctionCall {
 
     fn opcode(&self) -> Opcodes {
         Opcodes::FunctionCall
@@ -274,14 +252,30 @@ impl Operation for FunctionCall {
     fn num_outputs(&self) -> u8 {
         1
     }
+}
+
+/// A call to a function with `new`. Like a `FunctionCall`, the first input is
+/// the function and the rest are the arguments.
+#[derive(Clone)]
+pub struct Construct(pub u8);
+impl OperationInfo for Construct {
+
+    fn opcode(&self) -> Opcodes {
+        Opcodes::Construct
+    }
 
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn num_inputs(&self) -> u8 {
+        self.0 + 1
+    }
+
+    fn num_outputs(&self) -> u8 {
+        1
     }
 }
 
+#[derive(Clone)]
 pub struct CreateArray(pub u8);
-impl Operation for CreateArray {
+impl OperationInfo for CreateArray {
     fn opcode(&self) -> Opcodes {
         Opcodes::CreateArray
     }
@@ -293,18 +287,15 @@ impl Operation for CreateArray {
     fn num_outputs(&self) -> u8 {
         1
     }
-
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
 }
 
 /// A method call. The method signature is assumed to be a reference. Note that
 /// this was built with builtin methods only, might need to clone the method sig
 /// in future. Its assumed that the signature will live forever, which is true
 /// for js builtin methods
+#[derive(Clone)]
 pub struct MethodCall(pub MethodSignature, pub u8);
-impl Operation for MethodCall {
+impl OperationInfo for MethodCall {
 
     fn opcode(&self) -> Opcodes {
         Opcodes::MethodCall
@@ -317,15 +308,12 @@ impl Operation for MethodCall {
     fn num_outputs(&self) -> u8 {
         1
     }
-
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
 }
 
+#[derive(Clone)]
 pub struct LoadBuiltin(pub ConstructorType, pub u8);
 
-impl Operation for LoadBuiltin {
+impl OperationInfo for LoadBuiltin {
 
     fn opcode(&self) -> Opcodes {
         Opcodes::LoadBuiltin
@@ -338,14 +326,71 @@ impl Operation for LoadBuiltin {
     fn num_outputs(&self) -> u8 {
         1
     }
+}
 
-    fn as_any(&self) -> &dyn Any {
-        self
+/// An eval of a piece of JS code. The second member is true if this is a
+/// direct eval, i.e one that runs in the scope of the caller. The code is
+/// lifted from a nested program, whose variables are named after ids of the
+/// program around it, so the third member is the first id after the ones that
+/// the code uses. The program never hands out those ids again.
+#[derive(Clone)]
+pub struct Eval(pub String, pub bool, pub u32);
+impl OperationInfo for Eval {
+
+    fn opcode(&self) -> Opcodes {
+        Opcodes::Eval
+    }
+
+    fn num_outputs(&self) -> u8 {
+        1
     }
 }
 
+/// A call to a global builtin function, like the engine specific shell helpers
+/// that are registered by a profile. Unlike a `MethodCall`, there is no object
+/// that this function is called on, so all the inputs are arguments.
+#[derive(Clone)]
+pub struct BuiltinCall(pub MethodSignature, pub u8);
+impl OperationInfo for BuiltinCall {
+
+    fn opcode(&self) -> Opcodes {
+        Opcodes::BuiltinCall
+    }
+
+    fn num_inputs(&self) -> u8 {
+        self.1
+    }
+
+    fn num_outputs(&self) -> u8 {
+        1
+    }
+}
+
+/// A chain of method calls where each method is called on the result of the
+/// previous one, i.e `v0.a(v1).b().c(v2)`. Every entry holds the signature of
+/// the method and the number of arguments that it takes. The first input is
+/// the object that the chain starts on, followed by the arguments of all the
+/// calls in order. Only the result of the last call is kept in a variable.
+#[derive(Clone)]
+pub struct MethodChain(pub Vec<(MethodSignature, u8)>);
+impl OperationInfo for MethodChain {
+
+    fn opcode(&self) -> Opcodes {
+        Opcodes::MethodChain
+    }
+
+    fn num_inputs(&self) -> u8 {
+        self.0.iter().map(|(_, len)| len).sum::<u8>() + 1
+    }
+
+    fn num_outputs(&self) -> u8 {
+        1
+    }
+}
+
+#[derive(Clone)]
 pub struct CreateObject(pub Vec<String>);
-impl Operation for CreateObject {
+impl OperationInfo for CreateObject {
 
     fn opcode(&self) -> Opcodes {
         Opcodes::CreateObject
@@ -358,8 +403,119 @@ impl Operation for CreateObject {
     fn num_outputs(&self) -> u8 {
         1
     }
+}
 
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
+/// Implemented by every Operation struct to get it back out of an
+/// [Operation] enum. See [cast_into](super::instruction::Instruction::cast_into).
+pub trait Cast: Sized {
+    fn cast(operation: &Operation) -> Option<&Self>;
+    fn cast_mut(operation: &mut Operation) -> Option<&mut Self>;
 }
+
+macro_rules! operations {
+    ($($opcode: ident),* $(,)?) => {
+
+        /// An Operation of the IR. There is a variant for each opcode, which
+        /// holds the struct with the data of that Operation.
+        #[derive(Clone)]
+        pub enum Operation {
+            $($opcode($opcode),)*
+        }
+
+        impl OperationInfo for Operation {
+            fn opcode(&self) -> Opcodes {
+                match self {
+                    $(Operation::$opcode(op) => op.opcode(),)*
+                }
+            }
+
+            fn attributes(&self) -> Attributes {
+                match self {
+                    $(Operation::$opcode(op) => op.attributes(),)*
+                }
+            }
+
+            fn num_inputs(&self) -> u8 {
+                match self {
+                    $(Operation::$opcode(op) => op.num_inputs(),)*
+                }
+            }
+
+            fn num_outputs(&self) -> u8 {
+                match self {
+                    $(Operation::$opcode(op) => op.num_outputs(),)*
+                }
+            }
+
+            fn num_temp(&self) -> u8 {
+                match self {
+                    $(Operation::$opcode(op) => op.num_temp(),)*
+                }
+            }
+        }
+
+        $(
+            impl From<$opcode> for Operation {
+                fn from(op: $opcode) -> Self {
+                    Operation::$opcode(op)
+                }
+            }
+
+            impl Cast for $opcode {
+                fn cast(operation: &Operation) -> Option<&Self> {
+                    match operation {
+                        Operation::$opcode(op) => Some(op),
+                        _ => None,
+                    }
+                }
+
+                fn cast_mut(operation: &mut Operation) -> Option<&mut Self> {
+                    match operation {
+                        Operation::$opcode(op) => Some(op),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+operations!(
+    Nop,
+    LoadInt,
+    LoadFloat,
+    LoadString,
+    LoadUndefined,
+    LoadBool,
+    Copy,
+    BeginIf,
+    EndIf,
+    BeginElse,
+    BeginFor,
+    EndFor,
+    Break,
+    Continue,
+    BinaryOp,
+    UnaryOp,
+    CompareOp,
+    BeginFunctionDefinition,
+    EndFunctionDefinition,
+    Return,
+    FunctionCall,
+    CreateArray,
+    LoadElement,
+    StoreElement,
+    MethodCall,
+    LoadProperty,
+    StoreProperty,
+    LoadBuiltin,
+    CreateObject,
+    Delete,
+    BeginIIFE,
+    EndIIFE,
+    Eval,
+    BuiltinCall,
+    MethodChain,
+    LoadThis,
+    Construct,
+);
@@ -1,9 +1,10 @@
-use crate::utils::random::Random;
+use crate::utils::random::{Random, derive_seed};
 use crate::utils::probablity::Probablity;
-use crate::fuzzer::settings::{GENERATORS, BASIC_GENERATORS};
+use crate::fuzzer::settings::{BASIC_GENERATORS, generators, generator_weights};
 use crate::jsruntime::jsruntime::JSRuntime;
 use crate::jsruntime::constants::{TYPED_ARRAY_NAMES};
-use crate::fuzzer::interesting::INTERESTING_INTS;
+use crate::fuzzer::interesting::{INTERESTING_INTS, INTERESTING_FLOATS};
+use crate::fuzzer::interesting::{INTERESTING_STRINGS, INTERESTING_LENGTHS};
 
 use super::operation::*;
 use super::operators::*;
@@ -12,7 +13,24 @@ use super::instruction::Instruction;
 use super::codeanalysis::typeanalyzer::TypeAnalyzer;
 use super::codeanalysis::types::{PType, Type, FunctionSignature, MethodArg};
 use super::codeanalysis::types::{self, MethodSignature, Shape, ConstructorType};
+use super::codeanalysis::types::ElementType;
 use super::codeanalysis::analyzers::{ContextAnalyzer, ScopeAnalyzer};
+use super::codeanalysis::analyzers::DefUseAnalyzer;
+
+/// The no. of instructions after which the generators stop adding more, by
+/// default. The generators that create blocks generate more code in the
+/// blocks, so without a limit a few of the programs grow to hundreds of
+/// thousands of instructions.
+pub const MAX_INSTS: usize = 1000;
+
+/// The default share of the property loads that read a property which the
+/// object doesn't have
+pub const MISSING_PROPS: f64 = 0.1;
+
+/// The no. of times that a random generator is picked for each of the
+/// instructions that [Program::generate_random_insts] is asked for, before it
+/// gives up
+const MAX_ATTEMPTS: usize = 100;
 
 /// A Mode to help in type of selection that we want to use in the random number
 /// selector of the program. See the comment before
@@ -42,6 +60,9 @@ pub struct Program<'a> {
     /// The type tracking instance for this program.
     pub type_analyzer:          TypeAnalyzer,
 
+    /// Where each of the variables of this program is defined and used
+    pub def_use_analyzer:       DefUseAnalyzer,
+
     /// The id of the next free variable available for use in this program
     pub next_free_variable_id:  u32,
 
@@ -63,6 +84,63 @@ pub struct Program<'a> {
 
     /// A probablity instance to calcutate the probablity
     pub prob:                   Probablity,
+
+    /// Whether the generators are allowed to emit calls to `eval`
+    pub allow_eval:             bool,
+
+    /// The generators stop adding instructions once the program has this
+    /// many. The blocks that were started are still closed, so the program
+    /// can end up a few instructions longer than this.
+    pub max_insts:              usize,
+
+    /// The share of the property loads that read a property which the object
+    /// is sure not to have, from 0 to 1
+    pub missing_props:          f64,
+
+    /// The indices into [GENERATORS](crate::fuzzer::settings::GENERATORS) of
+    /// the generators that added code to this program, in the order in which
+    /// they ran
+    pub generators:             Vec<usize>,
+
+    /// The generators that were called for this program since it was created
+    /// or cloned, along with the no. of instructions that each one added
+    /// itself, or `None` if it failed. The instructions of the generators that
+    /// it called in turn are counted for those.
+    pub generator_calls:        Vec<(usize, Option<usize>)>,
+
+    /// The no. of instructions that are counted for the generators in
+    /// `generator_calls` so far
+    counted_insts:              usize,
+}
+
+/// A clone gets its own random number generators. Otherwise every program
+/// that is generated or mutated from the clones of a program would be the same.
+/// The calls of the generators are only counted for the program that they were
+/// made for, so the clone starts without them.
+impl<'a> Clone for Program<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer:                 self.buffer.clone(),
+            num_instr:              self.num_instr,
+            context_analyzer:       self.context_analyzer.clone(),
+            scope_analyzer:         self.scope_analyzer.clone(),
+            type_analyzer:          self.type_analyzer.clone(),
+            def_use_analyzer:       self.def_use_analyzer.clone(),
+            next_free_variable_id:  self.next_free_variable_id,
+            jsruntime:              self.jsruntime,
+            seen_ints:              self.seen_ints.clone(),
+            seen_floats:            self.seen_floats.clone(),
+            seen_strings:           self.seen_strings.clone(),
+            rng:                    Random::new(0),
+            prob:                   Probablity::new(Random::new(0)),
+            allow_eval:             self.allow_eval,
+            max_insts:              self.max_insts,
+            missing_props:          self.missing_props,
+            generators:             self.generators.clone(),
+            generator_calls:        Vec::new(),
+            counted_insts:          0,
+        }
+    }
 }
 
 impl<'a> Program<'a> {
@@ -77,6 +155,7 @@ impl<'a> Program<'a> {
             context_analyzer:       ContextAnalyzer::new(),
             scope_analyzer:         ScopeAnalyzer::new(),
             type_analyzer:          TypeAnalyzer::new(),
+            def_use_analyzer:       DefUseAnalyzer::new(),
             next_free_variable_id:  0,
             jsruntime:              jsruntime,
             seen_ints:              vec![],
@@ -84,10 +163,49 @@ impl<'a> Program<'a> {
             seen_strings:           vec![],
             rng:                    Random::new(0),
             prob:                   Probablity::new(Random::new(0)),
+            allow_eval:             false,
+            max_insts:              MAX_INSTS,
+            missing_props:          MISSING_PROPS,
+            generators:             Vec::new(),
+            generator_calls:        Vec::new(),
+            counted_insts:          0,
         }
     }
 
-    fn next_free_variable(&mut self) -> Variable {
+    /// Empty this program, so that it is the same as a new one. The memory of
+    /// the buffers, the analyzers and the seen values is kept around, so that
+    /// the program can be used again for the next one without allocating all
+    /// of it again.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.num_instr = 0;
+        self.context_analyzer.reset();
+        self.scope_analyzer.reset();
+        self.type_analyzer.reset();
+        self.def_use_analyzer.reset();
+        self.next_free_variable_id = 0;
+        self.seen_ints.clear();
+        self.seen_floats.clear();
+        self.seen_strings.clear();
+        self.rng = Random::new(0);
+        self.prob = Probablity::new(Random::new(0));
+        self.allow_eval = false;
+        self.max_insts = MAX_INSTS;
+        self.missing_props = MISSING_PROPS;
+        self.generators.clear();
+        self.generator_calls.clear();
+        self.counted_insts = 0;
+    }
+
+    /// Seed the random number generators of this program, so that the code
+    /// that it generates only depends on `seed`
+    pub fn seed(&mut self, seed: u64) {
+        self.rng  = Random::new(derive_seed(seed, 0));
+        self.prob = Probablity::new(Random::new(derive_seed(seed, 1)));
+    }
+
+    /// Allocate a new variable that is not used anywhere in this program yet
+    pub fn next_free_variable(&mut self) -> Variable {
         let id = self.next_free_variable_id;
         self.next_free_variable_id += 1;
         Variable(id)
@@ -97,10 +215,10 @@ impl<'a> Program<'a> {
     /// the program buffer. It creates the output and temp variables as
     /// necessary, analyzes the instruction and returns a reference to the
     /// output variables back to the caller
-    fn insert<T: Operation + 'static>(&mut self, ops: T,
-                                      inputs: Vec<Variable>) -> &Vec<Variable> {
+    fn insert<T: Into<Operation>>(&mut self, ops: T,
+                                  inputs: Vec<Variable>) -> &Vec<Variable> {
 
-        let ops = Box::new(ops);
+        let ops: Operation = ops.into();
 
         // First create the output and temp variables for this instructions
         let num_outputs = ops.num_outputs();
@@ -117,19 +235,100 @@ impl<'a> Program<'a> {
         }
 
         // Create the instruction itself
-        let mut inst = Instruction::new(self.num_instr, ops, inputs, outputs, temp);
+        let inst = Instruction::new(self.num_instr, ops, inputs, outputs, temp);
+
+        self.analyze_and_push(inst)
+
+    }
+
+    /// Append an existing instruction, like one taken from another program, to
+    /// this program. The instruction keeps its variables, so it is up to the
+    /// caller to make sure that its inputs are visible at this point and that
+    /// its outputs don't clash with the variables of this program.
+    pub fn append(&mut self, mut inst: Instruction) -> &Vec<Variable> {
+        let mut max_id = inst.outputs().iter().chain(inst.temp().iter())
+                                       .map(|v| v.0 + 1).max().unwrap_or(0);
+        if let Operation::Eval(eval) = &inst.operation {
+            max_id = max_id.max(eval.2);
+        }
+        self.next_free_variable_id = self.next_free_variable_id.max(max_id);
+
+        inst.idx = self.num_instr;
+        self.analyze_and_push(inst)
+    }
+
+    fn analyze_and_push(&mut self, mut inst: Instruction) -> &Vec<Variable> {
 
         // Analyze the instruction now
         self.scope_analyzer.analyze(&inst);
         self.context_analyzer.analyze(&inst);
+        self.def_use_analyzer.analyze(&inst);
         self.type_analyzer.analyze(&mut inst);
+        for (variable, gained, lost) in self.type_analyzer.take_changes() {
+            self.scope_analyzer.add_type(&variable, gained);
+            self.scope_analyzer.remove_type(&variable, lost);
+        }
 
         // Finally add it to the program buffer
         self.buffer.push(inst);
         self.num_instr += 1;
 
         self.buffer.last().unwrap().outputs()
+    }
+
+    /// Throw away the state of the analyzers and rebuild it from the
+    /// instructions in the program buffer. This is needed after the buffer is
+    /// modified directly.
+    pub fn reanalyze(&mut self) {
+        let buffer = std::mem::take(&mut self.buffer);
+
+        self.num_instr = 0;
+        self.context_analyzer.reset();
+        self.scope_analyzer.reset();
+        self.type_analyzer.reset();
+        self.def_use_analyzer.reset();
 
+        for inst in buffer {
+            self.append(inst);
+        }
+    }
+
+    /// Build a program out of existing `instructions`, like the ones of a
+    /// program that was saved earlier.
+    pub fn from_instructions(jsruntime: &'a JSRuntime,
+                             instructions: Vec<Instruction>) -> Self {
+        let mut program = Program::new(jsruntime);
+        program.buffer = instructions;
+        program.reanalyze();
+        program
+    }
+
+    /// Make this program the one that is made of `instructions`, like
+    /// [Program::from_instructions] does, reusing its memory
+    pub fn load(&mut self, instructions: &[Instruction]) {
+        self.reset();
+        for inst in instructions {
+            self.append(inst.clone());
+        }
+    }
+
+    /// Empty this program and give it the same settings as `program`. The new
+    /// variables that it creates don't clash with the ones of `program`, so
+    /// the instructions of `program` can be appended to it. The memory of this
+    /// program is kept around, like with [Program::reset].
+    pub fn derive_from(&mut self, program: &Program) {
+        self.reset();
+        self.allow_eval = program.allow_eval;
+        self.max_insts = program.max_insts;
+        self.missing_props = program.missing_props;
+        self.next_free_variable_id = program.next_free_variable_id;
+    }
+
+    /// Get the temp variables of the last instruction that was inserted. This
+    /// is useful to get hold of the parameters of a function definition that
+    /// was just started.
+    pub fn last_temp(&self) -> Vec<Variable> {
+        self.buffer.last().map_or(vec![], |inst| inst.temp().clone())
     }
 
     /// Helper functions for accessing anazyzer data
@@ -151,9 +350,9 @@ impl<'a> Program<'a> {
         } else {
             // let tmp = self.rng.rand_in_range(-0x100000000, 0x100000000);
             let tmp = if self.prob.probablity(0.8) {
-                self.rng.rand_in_range(0, 0x10000)
+                self.rng.i64_in_range(0, 0x10000) as isize
             } else {
-                self.rng.rand_in_range(-0x1000, 0x1000)
+                self.rng.i64_in_range(-0x1000, 0x1000) as isize
             };
            
             self.seen_ints.push(tmp);
@@ -164,10 +363,12 @@ impl<'a> Program<'a> {
     }
 
     pub fn getfloat(&mut self) -> f64 {
-        let val = if self.prob.probablity(0.5) && self.seen_floats.len() >= 4 {
+        let val = if self.prob.probablity(0.3) {
+            *self.rng.random_element(&INTERESTING_FLOATS)
+        } else if self.prob.probablity(0.5) && self.seen_floats.len() >= 4 {
             *self.rng.random_element(&self.seen_floats)
         } else {
-            let tmp = self.rng.float_in_range(-0x1000, 0x1000);
+            let tmp = self.rng.float_in_range(-4096.0, 4096.0);
             self.seen_floats.push(tmp);
             tmp
         };
@@ -179,8 +380,19 @@ impl<'a> Program<'a> {
         let val = if self.prob.probablity(0.5) && !self.seen_strings.is_empty() {
             self.rng.random_element(&self.seen_strings)
         } else {
-            let len = self.rng.rand_in_range(0, 100) as u64;
-            let tmp = self.rng.random_string(len);
+            // The random strings are rarely converted to a number or looked up
+            // as a property, so mix in the strings that are
+            let tmp = if self.prob.probablity(0.3) {
+                self.rng.random_element(&INTERESTING_STRINGS).to_string()
+            } else if self.prob.probablity(0.2) {
+                self.rng.random_element(&INTERESTING_INTS).to_string()
+            } else if self.prob.probablity(0.1) {
+                let len = *self.rng.random_element(&INTERESTING_LENGTHS);
+                self.rng.random_string(1).repeat(len as usize)
+            } else {
+                let len = self.rng.u64_in_range(0, 100);
+                self.rng.random_string(len)
+            };
             self.seen_strings.push(tmp);
             self.seen_strings.last().unwrap()
         };
@@ -209,12 +421,11 @@ impl<'a> Program<'a> {
                                    mut rtype: Type,
                                    mode: Mode) -> Option<Variable> {
 
-        // Get a list of all available scopes and the variables they contain
-        let list = self.scope_analyzer.get_all_scopes();
-
         // Choose a random scope from the scope list, giving preference to the
         // ones that were created more recently.
-        let scope = self.prob.choose_biased(&list, 1.2);
+        let depths = (0..self.scope_analyzer.get_all_scopes().len())
+            .collect::<Vec<usize>>();
+        let depth = *self.prob.choose_biased(&depths, 1.2);
 
         // If the mode is free, then we can search for either unknown or a fixed
         // type
@@ -222,33 +433,27 @@ impl<'a> Program<'a> {
             rtype.ptype |= PType::Unknown;
         }
 
-        // We define the searching closure. We will basically iterate over all
-        // the variables in the current list and filter based on whether the
-        // variable contains the required type.
-        let filter = |x: &&Variable| -> bool {
+        // We define the searching closure. The scope analyzer only gives us the
+        // variables that can be of one of the primitive types that we want, and
+        // this checks the rest of the type, like the shape of an object.
+        let filter = |x: &Variable| -> bool {
             self.get_type(x).contains(rtype)
         };
 
         // Let the current set of candidates be the variables from the selected
-        // scope
-        let candidates = scope;
-
-        // Now filter the candidates based on the filter closure that we defined
-        // above
-        let mut candidates = candidates.iter()
-                                       .filter(&filter)
-                                       .collect::<Vec<&Variable>>();
-
-        let list = &self.scope_analyzer.get_visible_variables();
+        // scope that satisfy the filter closure that we defined above
+        let mut candidates = self.scope_analyzer
+                                 .variables_of_type(depth, rtype.ptype);
+        candidates.retain(filter);
 
         // If we did not find any candidate variables in the current scope that
         // satisfy the required type, then we will set the new candidate list as
         // all the varibles that are visible from this part of the program and
         // also satisfy the filter function.
         if candidates.is_empty() {
-            candidates = list.iter()
-                             .filter(&filter)
-                             .collect();
+            candidates = self.scope_analyzer
+                             .visible_variables_of_type(rtype.ptype);
+            candidates.retain(filter);
         }
 
         // If candidates is still empty then we have failed to find a variable
@@ -260,11 +465,11 @@ impl<'a> Program<'a> {
             if mode == Mode::Strict {
                 return None;
             } else {
-                candidates = list.iter().map(|x| x).collect();
+                candidates = self.scope_analyzer.get_visible_variables();
             }
         }
 
-        Some(**self.rng.random_element(&candidates))
+        Some(*self.rng.random_element(&candidates))
     }
 
     /// Get a random method for the shape that is passed in as an arg
@@ -281,6 +486,46 @@ impl<'a> Program<'a> {
        self.type_analyzer.get_type(variable)
     }
 
+    /// The element type of `variable`, if it is known to be a typed array
+    pub fn get_element_type(&self, variable: &Variable) -> Option<ElementType> {
+        self.type_analyzer.get_element_type(variable)
+    }
+
+    /// The properties of `variable`, if it is an object literal whose
+    /// properties are known
+    pub fn get_properties(&self, variable: &Variable) -> Option<&[String]> {
+        self.type_analyzer.get_properties(variable)
+    }
+
+    /// Get a value to store to an element of `array`. A typed array gets one
+    /// of the type of its elements, and a BigInt array a BigInt, as the others
+    /// would throw.
+    pub fn element_value(&mut self, array: Variable) -> Variable {
+        match self.get_element_type(&array) {
+            Some(ElementType::Int)    => self.random_variable(types::Int),
+            Some(ElementType::Float)  => {
+                self.random_variable(types::Int | types::Float)
+            },
+            Some(ElementType::BigInt) => self.load_bigint(),
+            None => self.random_variable(types::Any),
+        }
+    }
+
+    /// List the instructions of the program, each followed by the types that
+    /// the type analyzer inferred for the variables that it defines
+    pub fn print_ir(&self) -> String {
+        let mut listing = String::new();
+        for (idx, inst) in self.buffer.iter().enumerate() {
+            listing.push_str(&format!("[{}] {}\n", idx, inst.print()));
+            for v in inst.outputs().iter().chain(inst.temp().iter()) {
+                let t = self.get_type(v);
+                listing.push_str(&format!("      {}: {:?} ({:?})\n",
+                                          v.print(), t.ptype, t.shape));
+            }
+        }
+        listing
+    }
+
     /// Get the function signature for the variable that is passed in as the
     /// arg. This will crash if the variable passed in does not have a valid
     /// function signature, so its current the job of the caller to verify that
@@ -289,7 +534,15 @@ impl<'a> Program<'a> {
        self.type_analyzer.get_signature_for(*variable)
     }
 
-    /// Generate random instructions by calling random code generators
+    /// Check if we know the function signature for the variable. Functions
+    /// that were not defined in this program, like bound functions, don't have
+    /// one.
+    pub fn has_signature(&self, variable: &Variable) -> bool {
+       self.type_analyzer.has_signature(*variable)
+    }
+
+    /// Generate random instructions by calling `count` random code generators,
+    /// or fewer once the program has [Program::max_insts] instructions
     pub fn generate_random_insts(&mut self, count: u8) {
 
         // TODO: Optimize this. It might be too expensive to create a vec for
@@ -302,15 +555,29 @@ impl<'a> Program<'a> {
             }
         }
 
+        // The generators that run in here call this again for the bodies of
+        // their blocks, so the limit is checked before each of them. The
+        // weights can leave only generators that fail in some places, like
+        // `break` outside of a loop, so the attempts are limited too.
+        let generators = generators();
+        let weights = generator_weights();
         let mut cnt = 0;
-        loop {
-            let generator = self.prob.choose_weighted_baised(&GENERATORS);
-            if generator(self).is_some() {
+        let mut attempts = 0;
+        while cnt < count && self.buffer.len() < self.max_insts &&
+            attempts < MAX_ATTEMPTS * count as usize {
+            attempts += 1;
+            let idx = self.prob.choose_from_table(weights);
+            let (len, counted) = (self.buffer.len(), self.counted_insts);
+            if generators[idx].0(self).is_some() {
+               self.generators.push(idx);
                cnt += 1;
-            }
 
-            if cnt == count {
-                break;
+               let added = self.buffer.len().saturating_sub(len);
+               let own = added.saturating_sub(self.counted_insts - counted);
+               self.counted_insts += own;
+               self.generator_calls.push((idx, Some(own)));
+            } else {
+               self.generator_calls.push((idx, None));
             }
         }
     }
@@ -440,11 +707,13 @@ impl<'a> Program<'a> {
         self.insert(BeginElse(), vec![]);
     }
 
+    /// Begin a for loop. Returns the loop induction variable.
     pub fn begin_for(&mut self, start:Variable, end: Variable, step: Variable,
-                     op: String, comparator: Comparators) {
+                     op: String, comparator: Comparators) -> Variable {
         let ops = BeginFor(op, comparator);
         let inputs = vec![start, end, step];
         self.insert(ops, inputs);
+        *self.buffer.last().unwrap().temp_at(0)
     }
 
     pub fn end_for(&mut self) {
@@ -483,6 +752,14 @@ impl<'a> Program<'a> {
         self.insert(EndFunctionDefinition(), vec![]);
     }
 
+    pub fn begin_iife(&mut self) {
+        self.insert(BeginIIFE(), vec![]);
+    }
+
+    pub fn end_iife(&mut self) {
+        self.insert(EndIIFE(), vec![]);
+    }
+
     pub fn insert_return(&mut self, inp: Variable) {
         self.insert(Return(), vec![inp]);
     }
@@ -494,6 +771,17 @@ impl<'a> Program<'a> {
         self.insert(FunctionCall(len), inputs)[0]
     }
 
+    pub fn construct(&mut self, func: Variable, args: Vec<Variable>) -> Variable {
+        let mut inputs = vec![func];
+        let len = args.len() as u8;
+        inputs.extend(args);
+        self.insert(Construct(len), inputs)[0]
+    }
+
+    pub fn load_this(&mut self) -> Variable {
+        self.insert(LoadThis(), vec![])[0]
+    }
+
     pub fn create_array(&mut self, inputs: Vec<Variable>) -> Variable {
         self.insert(CreateArray(inputs.len() as u8), inputs)[0]
     }
@@ -536,6 +824,41 @@ impl<'a> Program<'a> {
         self.insert(Delete(is_indexed_prop), vec![object, prop]);
     }
 
+    /// Eval `code`, that uses the variable ids up to `reserved`, see [Eval]
+    pub fn eval(&mut self, code: String, direct: bool,
+                reserved: u32) -> Variable {
+        self.insert(Eval(code, direct, reserved), vec![])[0]
+    }
+
+    /// Make a BigInt out of a random int. There is no type for BigInts, so it
+    /// is of an unknown one.
+    pub fn load_bigint(&mut self) -> Variable {
+        let val = self.getint();
+        let int = self.load_int(val);
+        let signature = MethodSignature::new("BigInt", types::Undefined,
+                                             vec![MethodArg::Type(types::Int)],
+                                             types::Unknown);
+        self.builtin_call(vec![int], signature)
+    }
+
+    pub fn builtin_call(&mut self,
+                        args: Vec<Variable>, ms: MethodSignature) -> Variable {
+        let len = args.len() as u8;
+        self.insert(BuiltinCall(ms, len), args)[0]
+    }
+
+    pub fn method_chain(&mut self, object: Variable,
+                        calls: Vec<(MethodSignature, Vec<Variable>)>)
+                        -> Variable {
+        let mut inputs = vec![object];
+        let mut chain = Vec::with_capacity(calls.len());
+        for (ms, args) in calls {
+            chain.push((ms, args.len() as u8));
+            inputs.extend(args);
+        }
+        self.insert(MethodChain(chain), inputs)[0]
+    }
+
     pub fn load_builtin(&mut self, ctype: &ConstructorType,
                         args: Option<Vec<Variable>>) -> Variable {
         let mut ctype = ctype.clone();
@@ -578,12 +901,18 @@ impl<'a> Program<'a> {
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
+
     use super::*;
-    use crate::lifter::Lifter;
+    use crate::ir::codegenerators::CodeGenerators;
+    use crate::lifter::lifter::Lifter;
+    use crate::mutators::insertionmutator::InsertionMutator;
+    use crate::mutators::mutator::Mutator;
 
     #[test]
     fn test_prog() {
-        let mut p = Program::new();
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
         p.generate_random_insts(10);
         // for i in p.buffer {
         //     println!("{}", i.print());
@@ -591,7 +920,320 @@ mod test {
         // p.type_analyzer.debug_print();
 
         let mut lifter = Lifter::new();
-        lifter.do_lifting(p);
+        lifter.do_lifting(&p);
         println!("{}", lifter.get_code());
     }
+
+    #[test]
+    fn test_max_insts() {
+        let runtime = JSRuntime::new();
+        for seed in 1..200 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.max_insts = 20;
+            p.generate_random_insts(100);
+
+            // A generator can add a few instructions at once, and the blocks
+            // that are open at the limit are still closed after it
+            assert!(p.buffer.len() <= 5 * p.max_insts);
+        }
+    }
+
+    #[test]
+    fn test_generator_calls() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        p.seed(7);
+        p.generate_random_insts(20);
+
+        // The instructions of nested generators are only counted once, and the
+        // basic generators that start off the program are not counted at all
+        let counted = p.generator_calls.iter()
+            .filter_map(|(_, insts)| *insts)
+            .sum::<usize>();
+        assert!(counted > 0 && counted <= p.buffer.len());
+        assert!(p.generator_calls.len() >= p.generators.len());
+        assert!(p.clone().generator_calls.is_empty());
+    }
+
+    #[test]
+    fn test_reset() {
+        let runtime = JSRuntime::new();
+        let mut reused = Program::new(&runtime);
+        for seed in 1..30 {
+            let mut fresh = Program::new(&runtime);
+            fresh.seed(seed);
+            fresh.generate_random_insts(20);
+
+            // A program that was used before generates the same code as a new
+            // one after a reset
+            reused.reset();
+            reused.seed(seed);
+            reused.generate_random_insts(20);
+
+            let mut expected = Lifter::new();
+            expected.seed(seed);
+            expected.do_lifting(&fresh);
+            let mut lifter = Lifter::new();
+            lifter.seed(seed);
+            lifter.do_lifting(&reused);
+            assert_eq!(lifter.get_code(), expected.get_code());
+        }
+    }
+
+    #[test]
+    fn test_branch_types() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        let cond = p.load_bool(true);
+        let string = p.load_string("a".to_string());
+        let float = p.load_float(1.5);
+        let ptype = |p: &Program, v| p.get_type(&v).ptype;
+
+        // An assignment outside of any block replaces the type
+        let replaced = p.load_int(1);
+        p.copy(replaced, string);
+        assert_eq!(ptype(&p, replaced), PType::String);
+
+        // An if without an else may not run at all
+        let maybe = p.load_int(1);
+        p.begin_if(cond);
+        p.copy(maybe, string);
+        assert_eq!(ptype(&p, maybe), PType::String);
+        p.end_if();
+        assert_eq!(ptype(&p, maybe), PType::Int | PType::String);
+
+        // The else branch starts off with the type from before the if, and
+        // one of the branches always runs
+        let either = p.load_int(1);
+        p.begin_if(cond);
+        p.copy(either, string);
+        p.begin_else();
+        assert_eq!(ptype(&p, either), PType::Int);
+        p.copy(either, float);
+        p.end_if();
+        assert_eq!(ptype(&p, either), PType::String | PType::Float);
+
+        // The types that a loop assigns are widened to the ones from before it
+        let carried = p.load_int(1);
+        let start = p.load_int(0);
+        let end = p.load_int(10);
+        p.begin_for(start, end, start, "++".to_string(), Comparators::LessThan);
+        p.begin_if(cond);
+        p.copy(carried, string);
+        p.end_if();
+        p.copy(carried, float);
+        p.end_for();
+        assert_eq!(ptype(&p, carried), PType::Int | PType::Float);
+
+        // The index of the variables by type keeps up with all of this
+        let found = p.scope_analyzer.visible_variables_of_type(PType::Int)
+            .iter()
+            .map(|v| v.0)
+            .collect::<Vec<u32>>();
+        assert!(found.contains(&carried.0) && !found.contains(&replaced.0));
+        assert!(!found.contains(&either.0));
+    }
+
+    #[test]
+    fn test_def_use() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        let lhs = p.load_int(1);
+        let rhs = p.load_int(2);
+        let sum = p.binary_op(lhs, lhs, BinaryOperators::Add);
+        p.copy(lhs, rhs);
+
+        let def_use = &p.def_use_analyzer;
+        assert_eq!(def_use.definition(&sum), Some(2));
+        assert_eq!(def_use.uses(&lhs), &[2, 3]);
+        assert_eq!(def_use.uses(&rhs), &[3]);
+        assert!(!def_use.is_used(&sum));
+
+        // The chains of the generated programs hold up after they are built
+        // again, like a mutation does
+        let mut p = Program::new(&runtime);
+        for seed in 1..30 {
+            let mut q = Program::new(&runtime);
+            q.seed(seed);
+            q.generate_random_insts(30);
+            InsertionMutator.mutate_into(&q, &mut p, &mut Random::new(seed));
+
+            for inst in &p.buffer {
+                for variable in inst.inputs() {
+                    let def_use = &p.def_use_analyzer;
+                    assert!(def_use.definition(variable).unwrap() < inst.idx);
+                    assert!(def_use.uses(variable).contains(&inst.idx));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_element_types() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        p.seed(5);
+        let length = MethodArg::Type(types::Int);
+        let constructor = MethodSignature::new("TypedArray", types::TypedArray,
+                                               vec![length], types::TypedArray);
+        let constructor = ConstructorType::Callable(constructor);
+
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            let length = p.load_int(8);
+            let array = p.load_builtin(&constructor, Some(vec![length]));
+            let name = match &p.buffer.last().unwrap().operation {
+                Operation::LoadBuiltin(LoadBuiltin(
+                    ConstructorType::Callable(ms), _)) => ms.get_name().clone(),
+                _ => unreachable!(),
+            };
+            let element = p.get_element_type(&array);
+            assert_eq!(element, ElementType::of(&name));
+            seen.push(element);
+
+            // The BigInt arrays are the only ones that get BigInts
+            let value = p.element_value(array);
+            let made = p.buffer.last().unwrap();
+            let bigint = made.outputs()[0].0 == value.0 &&
+                         matches!(made.operation, Operation::BuiltinCall(..));
+            assert_eq!(bigint, element == Some(ElementType::BigInt));
+        }
+        assert!(seen.contains(&Some(ElementType::BigInt)));
+        assert!(seen.contains(&Some(ElementType::Float)));
+        assert!(seen.contains(&None));
+    }
+
+    #[test]
+    fn test_properties() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        let one = p.load_int(1);
+        let names = |props: &[&str]| {
+            props.iter().map(|prop| prop.to_string()).collect::<Vec<_>>()
+        };
+        let object = p.create_object(names(&["a", "b", "a"]),
+                                     vec![one, one, one]);
+        p.store_property("c".to_string(), object, one);
+        let name = p.load_string("a".to_string());
+        p.delete_property(object, name, false);
+        assert_eq!(p.get_properties(&object), Some(&names(&["b", "c"])[..]));
+
+        let copied = p.load_int(2);
+        p.copy(copied, object);
+        assert_eq!(p.get_properties(&copied), p.get_properties(&object));
+
+        // A delete that may not run leaves the properties unknown
+        let cond = p.load_bool(true);
+        let name = p.load_string("b".to_string());
+        p.begin_if(cond);
+        p.delete_property(object, name, false);
+        p.end_if();
+        assert_eq!(p.get_properties(&object), None);
+        assert_eq!(p.get_properties(&one), None);
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        let func = p.begin_function_definition(FunctionSignature::new(2));
+        let param = p.last_temp()[0];
+        p.insert_return(param);
+        p.end_function_definition();
+
+        // The function that bind returns takes the same arguments
+        let bind = runtime.get_method(Shape::Function, "bind").unwrap();
+        let bound = p.method_call(vec![func], bind);
+        assert_eq!(p.get_type(&bound), types::Function);
+        assert!(p.has_signature(&bound));
+        assert_eq!(p.get_signature_for(&bound).args_count(), 2);
+
+        // A builtin that returns a function says how to call it
+        let returns = FunctionSignature::builtin(vec![types::Int],
+                                                 types::Float);
+        let wrapper = MethodSignature::new("wrapper", types::Undefined, vec![],
+                                           types::Function)
+            .with_output_signature(returns);
+        let wrapped = p.builtin_call(vec![], wrapper);
+        let output = p.function_call(wrapped, vec![param]);
+        assert!(!p.get_signature_for(&wrapped).is_constructing());
+        assert_eq!(p.get_type(&output), types::Float);
+
+        let call = runtime.get_method(Shape::Function, "call").unwrap();
+        let called = p.method_call(vec![func], call);
+        assert!(!p.has_signature(&called));
+    }
+
+    #[test]
+    fn test_typed_variables() {
+        let runtime = JSRuntime::new();
+        for seed in 1..50 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.generate_random_insts(30);
+
+            // The index has the same variables, in the same order, as going
+            // over all of them would find
+            let visible = p.scope_analyzer.get_visible_variables();
+            for bit in 0..8 {
+                let ptype = PType::from_bits_truncate(1 << bit);
+                let expected = visible.iter()
+                    .filter(|v| !(p.get_type(v).ptype & ptype).is_empty())
+                    .map(|v| v.0)
+                    .collect::<Vec<u32>>();
+                let found = p.scope_analyzer.visible_variables_of_type(ptype)
+                    .iter()
+                    .map(|v| v.0)
+                    .collect::<Vec<u32>>();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_ids() {
+        let runtime = JSRuntime::new();
+
+        // The code of the eval names a variable that comes after its output
+        let mut p = Program::new(&runtime);
+        p.eval("var v7 = 1;".to_string(), true, 8);
+        let mut q = Program::from_instructions(&runtime, p.buffer.clone());
+        assert_eq!(q.load_int(1).0, 8);
+
+        for seed in 1..20 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.allow_eval = true;
+            p.generate_random_insts(3);
+            CodeGenerators::eval_generator(&mut p).unwrap();
+
+            // The code that is inserted after the program is loaded again
+            // doesn't define any of the variables of the eval
+            let mut loaded = Program::new(&runtime);
+            loaded.load(&p.buffer);
+            let mut q = Program::new(&runtime);
+            InsertionMutator.mutate_into(&loaded, &mut q,
+                                         &mut Random::new(seed));
+
+            let defined = p.buffer.iter()
+                .flat_map(|inst| inst.outputs().iter().chain(inst.temp()))
+                .map(|v| v.0)
+                .collect::<HashSet<u32>>();
+            let code = match &p.buffer.last().unwrap().operation {
+                Operation::Eval(eval) => eval.0.clone(),
+                _ => unreachable!(),
+            };
+            for inst in q.buffer.iter() {
+                for v in inst.outputs().iter().chain(inst.temp()) {
+                    if !defined.contains(&v.0) {
+                        let name = format!("v{}", v.0);
+                        assert!(!code.split(|c: char| !c.is_alphanumeric())
+                                     .any(|word| word == name),
+                                "{} in {}", name, code);
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,465 @@
+//! Convert programs to and from JSON, so that they can be saved to the disk and
+//! loaded again later, like when the fuzzer restarts. A program is an array of
+//! instructions and each instruction looks like
+//!
+//! ```json
+//! {"op": "BinaryOp", "args": ["+"], "inputs": [0, 1], "outputs": [2], "temp": []}
+//! ```
+//!
+//! where `args` holds the data of the Operation. Integers and floats are kept
+//! in strings so that they are not rounded and types are written as the bits of
+//! their primitive type and shape.
+
+use super::instruction::Instruction;
+use super::program::Program;
+use super::operation::*;
+use super::operators::*;
+use super::variable::Variable;
+use super::codeanalysis::types::{Type, PType, Shape, FunctionSignature};
+use super::codeanalysis::types::{MethodSignature, MethodArg, ConstructorType};
+use crate::utils::json::JsonValue;
+
+#[derive(Debug)]
+struct SerializeError(String);
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+type SerializeResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn error<T>(msg: String) -> SerializeResult<T> {
+    Err(Box::new(SerializeError(msg)))
+}
+
+/// Convert the `instructions` of a program into JSON
+pub fn encode_program(instructions: &[Instruction]) -> JsonValue {
+    JsonValue::Array(instructions.iter().map(encode_instruction).collect())
+}
+
+/// Get the types that the type analyzer found for the variables of `program`.
+/// There is one entry for each instruction, holding the types of its outputs
+/// followed by the ones of its temporaries.
+pub fn encode_types(program: &Program) -> JsonValue {
+    JsonValue::Array(program.buffer.iter().map(|inst| {
+        JsonValue::Array(inst.outputs().iter().chain(inst.temp().iter())
+                             .map(|v| encode_type(program.get_type(v)))
+                             .collect())
+    }).collect())
+}
+
+/// Read back a list of instructions that was written by [encode_program]
+pub fn decode_program(value: &JsonValue) -> SerializeResult<Vec<Instruction>> {
+    let instructions = match value.as_array() {
+        Some(instructions) => instructions,
+        None => return error("A program must be an array".into()),
+    };
+
+    let mut program = Vec::with_capacity(instructions.len());
+    for (idx, inst) in instructions.iter().enumerate() {
+        program.push(decode_instruction(idx as u32, inst)?);
+    }
+    Ok(program)
+}
+
+fn string(s: &str) -> JsonValue {
+    JsonValue::String(s.to_string())
+}
+
+fn num<T: Into<f64>>(n: T) -> JsonValue {
+    JsonValue::Number(n.into())
+}
+
+fn encode_variables(variables: &[Variable]) -> JsonValue {
+    JsonValue::Array(variables.iter().map(|v| num(v.0)).collect())
+}
+
+fn encode_type(t: Type) -> JsonValue {
+    JsonValue::String(format!("{:#x}:{:#x}", t.ptype.bits(), t.shape.bits()))
+}
+
+fn encode_signature(ms: &MethodSignature) -> JsonValue {
+    let args = ms.get_input_types().iter().map(|arg| match arg {
+        MethodArg::Type(t)          => JsonValue::Array(vec![string("type"),
+                                                            encode_type(*t)]),
+        MethodArg::Optional(t)      => JsonValue::Array(vec![string("optional"),
+                                                            encode_type(*t)]),
+        MethodArg::Repeat(times, t) => JsonValue::Array(vec![string("repeat"),
+                                                            encode_type(*t),
+                                                            num(*times)]),
+    }).collect();
+
+    let mut members = vec![
+        ("name".into(),   string(ms.get_name())),
+        ("this".into(),   encode_type(ms.get_this_type())),
+        ("args".into(),   JsonValue::Array(args)),
+        ("output".into(), encode_type(ms.output_type())),
+    ];
+
+    // The signature of the function that the method returns
+    if let Some(function) = ms.output_signature() {
+        let args = function.get_input_types().iter()
+                           .map(|t| encode_type(*t))
+                           .collect();
+        members.push(("returns".into(), JsonValue::Object(vec![
+            ("args".into(),   JsonValue::Array(args)),
+            ("output".into(), encode_type(function.get_output_type())),
+        ])));
+    }
+
+    JsonValue::Object(members)
+}
+
+fn encode_args(operation: &Operation) -> Vec<JsonValue> {
+    match operation {
+        Operation::LoadInt(op)       => vec![string(&op.0.to_string())],
+        Operation::LoadFloat(op)     => vec![string(&op.0.to_string())],
+        Operation::LoadBool(op)      => vec![JsonValue::Bool(op.0)],
+        Operation::LoadString(op)    => vec![string(&op.0)],
+        Operation::BinaryOp(op)      => vec![string(op.0.rep())],
+        Operation::UnaryOp(op)       => vec![string(op.0.rep())],
+        Operation::CompareOp(op)     => vec![string(op.0.rep())],
+        Operation::LoadProperty(op)  => vec![string(&op.0)],
+        Operation::StoreProperty(op) => vec![string(&op.0)],
+        Operation::Delete(op)        => vec![JsonValue::Bool(op.0)],
+        Operation::BeginFor(op)      => vec![string(&op.0), string(op.1.rep())],
+        Operation::FunctionCall(op)  => vec![num(op.0)],
+        Operation::Construct(op)     => vec![num(op.0)],
+        Operation::CreateArray(op)   => vec![num(op.0)],
+        Operation::Eval(op)          => vec![string(&op.0),
+                                             JsonValue::Bool(op.1), num(op.2)],
+        Operation::MethodCall(op)    => vec![encode_signature(&op.0),
+                                             num(op.1)],
+        Operation::BuiltinCall(op)   => vec![encode_signature(&op.0),
+                                             num(op.1)],
+
+        // The rest of the signature is filled in by the type analyzer
+        Operation::BeginFunctionDefinition(op) => vec![num(op.0.args_count())],
+
+        Operation::LoadBuiltin(op) => {
+            let ctype = match &op.0 {
+                ConstructorType::Callable(ms) => {
+                    JsonValue::Object(vec![("callable".into(),
+                                            encode_signature(ms))])
+                },
+                ConstructorType::NonCallable(name, t) => {
+                    JsonValue::Object(vec![("name".into(), string(name)),
+                                           ("type".into(), encode_type(*t))])
+                },
+            };
+            vec![ctype, num(op.1)]
+        },
+
+        Operation::MethodChain(op) => {
+            op.0.iter().map(|(ms, len)| {
+                JsonValue::Array(vec![encode_signature(ms), num(*len)])
+            }).collect()
+        },
+
+        Operation::CreateObject(op) => op.0.iter().map(|p| string(p)).collect(),
+
+        _ => vec![],
+    }
+}
+
+fn encode_instruction(inst: &Instruction) -> JsonValue {
+    let name = format!("{:?}", inst.operation.opcode());
+    JsonValue::Object(vec![
+        ("op".into(),      JsonValue::String(name)),
+        ("args".into(),    JsonValue::Array(encode_args(&inst.operation))),
+        ("inputs".into(),  encode_variables(inst.inputs())),
+        ("outputs".into(), encode_variables(inst.outputs())),
+        ("temp".into(),    encode_variables(inst.temp())),
+    ])
+}
+
+fn get_array<'a>(value: &'a JsonValue,
+                 key: &str) -> SerializeResult<&'a [JsonValue]> {
+    match value.get(key).and_then(|v| v.as_array()) {
+        Some(array) => Ok(array),
+        None => error(format!("Expected an array for {}", key)),
+    }
+}
+
+fn get_arg(args: &[JsonValue], idx: usize) -> SerializeResult<&JsonValue> {
+    match args.get(idx) {
+        Some(arg) => Ok(arg),
+        None => error(format!("Missing argument {}", idx)),
+    }
+}
+
+fn decode_str(value: &JsonValue) -> SerializeResult<&str> {
+    match value.as_str() {
+        Some(s) => Ok(s),
+        None => error("Expected a string".into()),
+    }
+}
+
+fn decode_bool(value: &JsonValue) -> SerializeResult<bool> {
+    match value.as_bool() {
+        Some(b) => Ok(b),
+        None => error("Expected a bool".into()),
+    }
+}
+
+fn decode_u32(value: &JsonValue) -> SerializeResult<u32> {
+    match value.as_number() {
+        Some(n) if n >= 0.0 && n <= u32::MAX as f64 && n.fract() == 0.0 => {
+            Ok(n as u32)
+        },
+        _ => error("Expected a positive integer".into()),
+    }
+}
+
+fn decode_u8(value: &JsonValue) -> SerializeResult<u8> {
+    match u8::try_from(decode_u32(value)?) {
+        Ok(n)  => Ok(n),
+        Err(_) => error("Integer out of range".into()),
+    }
+}
+
+fn decode_variables(value: &JsonValue,
+                    key: &str) -> SerializeResult<Vec<Variable>> {
+    get_array(value, key)?.iter().map(|v| Ok(Variable(decode_u32(v)?)))
+                                 .collect()
+}
+
+fn decode_type(value: &JsonValue) -> SerializeResult<Type> {
+    let s = decode_str(value)?;
+    let bits = s.split_once(':').and_then(|(ptype, shape)| {
+        let ptype = u8::from_str_radix(ptype.trim_start_matches("0x"), 16);
+        let shape = u64::from_str_radix(shape.trim_start_matches("0x"), 16);
+        Some((PType::from_bits(ptype.ok()?)?, Shape::from_bits(shape.ok()?)?))
+    });
+
+    match bits {
+        Some((ptype, shape)) => Ok(Type::new(ptype, shape)),
+        None => error(format!("Invalid type {}", s)),
+    }
+}
+
+fn decode_signature(value: &JsonValue) -> SerializeResult<MethodSignature> {
+    let name = match value.get("name") {
+        Some(name) => decode_str(name)?,
+        None => return error("Missing method name".into()),
+    };
+
+    let mut args = Vec::new();
+    for arg in get_array(value, "args")? {
+        let arg = match arg.as_array() {
+            Some(arg) => arg,
+            None => return error("Method arguments must be arrays".into()),
+        };
+
+        let itype = decode_type(get_arg(arg, 1)?)?;
+        args.push(match decode_str(get_arg(arg, 0)?)? {
+            "type"     => MethodArg::Type(itype),
+            "optional" => MethodArg::Optional(itype),
+            "repeat"   => MethodArg::Repeat(decode_u8(get_arg(arg, 2)?)?, itype),
+            kind       => return error(format!("Invalid argument {}", kind)),
+        });
+    }
+
+    let this_type = match value.get("this") {
+        Some(this_type) => decode_type(this_type)?,
+        None => return error("Missing this type".into()),
+    };
+
+    let output_type = match value.get("output") {
+        Some(output_type) => decode_type(output_type)?,
+        None => return error("Missing output type".into()),
+    };
+
+    let signature = MethodSignature::new(name, this_type, args, output_type);
+    let returns = match value.get("returns") {
+        Some(returns) => returns,
+        None => return Ok(signature),
+    };
+
+    let args = get_array(returns, "args")?.iter()
+        .map(decode_type)
+        .collect::<SerializeResult<Vec<Type>>>()?;
+    let output_type = match returns.get("output") {
+        Some(output_type) => decode_type(output_type)?,
+        None => return error("Missing output type".into()),
+    };
+    let function = FunctionSignature::builtin(args, output_type);
+    Ok(signature.with_output_signature(function))
+}
+
+/// Find the operator of type `T` that is written as `rep`
+fn decode_operator<T, const N: usize>(value: &JsonValue, all: [T; N],
+                                      rep: fn(&T) -> &str) -> SerializeResult<T>
+    where T: std::marker::Copy {
+
+    let s = decode_str(value)?;
+    match all.iter().find(|op| rep(op) == s) {
+        Some(op) => Ok(*op),
+        None => error(format!("Invalid operator {}", s)),
+    }
+}
+
+fn decode_operation(name: &str,
+                    args: &[JsonValue]) -> SerializeResult<Operation> {
+    let arg = |idx| get_arg(args, idx);
+
+    let operation = match name {
+        "Nop"           => Nop().into(),
+        "LoadInt"       => match decode_str(arg(0)?)?.parse::<isize>() {
+            Ok(val) => LoadInt(val).into(),
+            Err(_)  => return error("Invalid integer".into()),
+        },
+        "LoadFloat"     => match decode_str(arg(0)?)?.parse::<f64>() {
+            Ok(val) => LoadFloat(val).into(),
+            Err(_)  => return error("Invalid float".into()),
+        },
+        "LoadString"    => LoadString(decode_str(arg(0)?)?.to_string()).into(),
+        "LoadUndefined" => LoadUndefined().into(),
+        "LoadBool"      => LoadBool(decode_bool(arg(0)?)?).into(),
+        "Copy"          => Copy().into(),
+        "BeginIf"       => BeginIf().into(),
+        "EndIf"         => EndIf().into(),
+        "BeginElse"     => BeginElse().into(),
+        "BeginFor"      => {
+            let step = decode_str(arg(0)?)?.to_string();
+            let cmp = decode_operator(arg(1)?, Comparators::all(),
+                                      Comparators::rep)?;
+            BeginFor(step, cmp).into()
+        },
+        "EndFor"        => EndFor().into(),
+        "Break"         => Break().into(),
+        "Continue"      => Continue().into(),
+        "BinaryOp"      => BinaryOp(decode_operator(arg(0)?,
+                                                    BinaryOperators::all(),
+                                                    BinaryOperators::rep)?)
+                           .into(),
+        "UnaryOp"       => UnaryOp(decode_operator(arg(0)?,
+                                                   UnaryOperators::all(),
+                                                   UnaryOperators::rep)?)
+                           .into(),
+        "CompareOp"     => CompareOp(decode_operator(arg(0)?,
+                                                     Comparators::all(),
+                                                     Comparators::rep)?)
+                           .into(),
+        "BeginFunctionDefinition" => {
+            let signature = FunctionSignature::new(decode_u8(arg(0)?)?);
+            BeginFunctionDefinition(signature).into()
+        },
+        "EndFunctionDefinition" => EndFunctionDefinition().into(),
+        "Return"        => Return().into(),
+        "FunctionCall"  => FunctionCall(decode_u8(arg(0)?)?).into(),
+        "Construct"     => Construct(decode_u8(arg(0)?)?).into(),
+        "CreateArray"   => CreateArray(decode_u8(arg(0)?)?).into(),
+        "LoadElement"   => LoadElement().into(),
+        "StoreElement"  => StoreElement().into(),
+        "MethodCall"    => MethodCall(decode_signature(arg(0)?)?,
+                                      decode_u8(arg(1)?)?).into(),
+        "BuiltinCall"   => BuiltinCall(decode_signature(arg(0)?)?,
+                                       decode_u8(arg(1)?)?).into(),
+        "LoadProperty"  => LoadProperty(decode_str(arg(0)?)?.to_string())
+                           .into(),
+        "StoreProperty" => StoreProperty(decode_str(arg(0)?)?.to_string())
+                           .into(),
+        "LoadBuiltin"   => {
+            let ctype = arg(0)?;
+            let ctype = match (ctype.get("callable"), ctype.get("name")) {
+                (Some(ms), _) => ConstructorType::Callable(decode_signature(ms)?),
+                (None, Some(name)) => {
+                    let otype = match ctype.get("type") {
+                        Some(otype) => decode_type(otype)?,
+                        None => return error("Missing builtin type".into()),
+                    };
+                    ConstructorType::NonCallable(decode_str(name)?.to_string(),
+                                                 otype)
+                },
+                _ => return error("Invalid constructor".into()),
+            };
+            LoadBuiltin(ctype, decode_u8(arg(1)?)?).into()
+        },
+        "CreateObject"  => {
+            let props = args.iter().map(|p| Ok(decode_str(p)?.to_string()))
+                                   .collect::<SerializeResult<Vec<String>>>()?;
+            CreateObject(props).into()
+        },
+        "Delete"        => Delete(decode_bool(arg(0)?)?).into(),
+        "BeginIIFE"     => BeginIIFE().into(),
+        "EndIIFE"       => EndIIFE().into(),
+        // The programs that were saved before the ids were reserved have no
+        // third argument, and use the ids of their outputs only
+        "Eval"          => Eval(decode_str(arg(0)?)?.to_string(),
+                                decode_bool(arg(1)?)?,
+                                match args.get(2) {
+                                    Some(reserved) => decode_u32(reserved)?,
+                                    None => 0,
+                                }).into(),
+        "MethodChain"   => {
+            let mut chain = Vec::new();
+            for call in args {
+                let call = match call.as_array() {
+                    Some(call) => call,
+                    None => return error("Invalid method chain".into()),
+                };
+                chain.push((decode_signature(get_arg(call, 0)?)?,
+                            decode_u8(get_arg(call, 1)?)?));
+            }
+            MethodChain(chain).into()
+        },
+        "LoadThis"      => LoadThis().into(),
+        _ => return error(format!("Unknown operation {}", name)),
+    };
+
+    Ok(operation)
+}
+
+fn decode_instruction(idx: u32,
+                      value: &JsonValue) -> SerializeResult<Instruction> {
+    let name = match value.get("op") {
+        Some(name) => decode_str(name)?,
+        None => return error("Missing operation".into()),
+    };
+
+    let operation = decode_operation(name, get_array(value, "args")?)?;
+    let inputs  = decode_variables(value, "inputs")?;
+    let outputs = decode_variables(value, "outputs")?;
+    let temp    = decode_variables(value, "temp")?;
+
+    if inputs.len()  != operation.num_inputs() as usize  ||
+       outputs.len() != operation.num_outputs() as usize ||
+       temp.len()    != operation.num_temp() as usize {
+        return error(format!("Wrong no. of variables for {}", name));
+    }
+
+    Ok(Instruction::new(idx, operation, inputs, outputs, temp))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::program::Program;
+    use crate::jsruntime::jsruntime::JSRuntime;
+
+    #[test]
+    fn test_serialize() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        p.generate_random_insts(10);
+        let returns = FunctionSignature::builtin(vec![Type::basic(PType::Int)],
+                                                 Type::basic(PType::Bool));
+        let wrapper = MethodSignature::new("wrapper", Type::default(), vec![],
+                                           Type::basic(PType::Function))
+            .with_output_signature(returns);
+        p.builtin_call(vec![], wrapper);
+
+        let text = encode_program(&p.buffer).to_string();
+        assert!(text.contains("returns"));
+        let value = JsonValue::parse(&text).unwrap();
+        let decoded = decode_program(&value).unwrap();
+        let decoded = Program::from_instructions(&runtime, decoded);
+
+        assert_eq!(decoded.buffer.len(), p.buffer.len());
+        assert_eq!(encode_program(&decoded.buffer).to_string(), text);
+    }
+}
@@ -1,6 +1,12 @@
 #[derive(Debug,Clone,Copy)]
 pub struct Variable(pub u32);
 
+impl std::fmt::Display for Variable {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "v{}", self.0)
+    }
+}
+
 impl Variable {
     pub fn print(&self) -> String {
         format!("v{}",self.0)
@@ -4,3 +4,4 @@
 pub mod jsbuiltin;
 pub mod jsruntime;
 pub mod constants;
+pub mod spec;
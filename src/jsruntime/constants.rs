@@ -1,7 +1,9 @@
 //! A crate to hold all the constants. Currently only contains the list of Typed
-//! Arrays and a list of properties that can be modified by the fuzzer
+//! Arrays, a list of properties that can be modified by the fuzzer, a list of
+//! ones that it never defines and the names of engine helpers that some
+//! generators look for
 
-pub const TYPED_ARRAY_NAMES: [&str; 10] = [
+pub const TYPED_ARRAY_NAMES: [&str; 12] = [
     "Array",
     "Int8Array",
     "Uint8Array",
@@ -12,11 +14,21 @@ pub const TYPED_ARRAY_NAMES: [&str; 10] = [
     "Uint32Array",
     "Float32Array",
     "Float64Array",
-    // "BigInt64Array",
-    // "BigUint64Array",
+    "BigInt64Array",
+    "BigUint64Array",
 ];
 
 
 pub const PROPERTIES: [&str; 8] = [
     "a", "b", "c", "d", "w", "x", "y", "z"
 ];
+
+/// Properties that none of the generators define and that none of the
+/// builtins have, so that a load of one of them always misses
+pub const MISSING_PROPERTIES: [&str; 4] = ["e", "f", "g", "h"];
+
+/// The engine specific shell helpers that detach an ArrayBuffer. These are
+/// only present in the runtime if the profile registers them.
+pub const DETACH_FUNCTIONS: [&str; 3] = [
+    "detachArrayBuffer", "transferArrayBuffer", "%ArrayBufferDetach"
+];
@@ -25,5 +25,10 @@ pub struct JSBuiltin {
     /// The list of methods that can be statically called, i.e called directly
     /// on the object instead of an instance, on this builtin
     pub static_methods: Option<Vec<MethodSignature>>,
+
+    /// The list of global functions that come along with this builtin. These
+    /// are called directly, without an object, like the engine specific shell
+    /// helpers `gc()` or `noInline()`
+    pub functions: Option<Vec<MethodSignature>>,
 }
 
@@ -9,7 +9,11 @@
 //! it is necessary that we implement a dedicated code generator, specifically
 //! designed to create the proxy handlers and then call the Proxy constructor.
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+
 use super::jsbuiltin::JSBuiltin;
+use super::spec::BuiltinSpec;
 use crate::ir::codeanalysis::types::MethodSignature as MS;
 use crate::ir::codeanalysis::types::MethodArg as MA;
 use crate::ir::codeanalysis::types::*;
@@ -29,15 +33,29 @@ pub struct JSRuntime {
     /// will provide some speedup while fetching the constructors as we would
     /// not have to iterate over all possible builtins each time.
     constructors: Vec<ConstructorType>,
+
+    /// A list of all the global functions that are present in the runtime.
+    /// Like the constructors, this is collected from the builtins for faster
+    /// access.
+    functions: Vec<MS>,
+
+    /// The methods and properties of each of the shapes of the builtins, and of
+    /// the static variant of each, as [JSRuntime::get_methods] and
+    /// [JSRuntime::get_properties] find them. These are looked up each time a
+    /// generator calls a method or loads a property, so they are collected
+    /// once instead of going through all the builtins each time.
+    methods: HashMap<Shape, Option<Vec<MS>>>,
+    properties: HashMap<Shape, Option<Vec<String>>>,
+
+    /// The name of the global function that forces a garbage collection on
+    /// the engine being fuzzed, if there is one.
+    gc_function: Option<String>,
 }
 
 impl JSRuntime {
     pub fn new() -> Self {
 
-        let mut runtime = Self {
-            builtins: Vec::<JSBuiltin>::new(),
-            constructors: Vec::<ConstructorType>::new(),
-        };
+        let mut runtime = Self::empty();
 
         runtime.register_array();
         runtime.register_math();
@@ -45,12 +63,54 @@ impl JSRuntime {
         runtime.register_object();
         runtime.register_arraybuffer();
         runtime.register_typedarray();
+        runtime.register_function();
 
         runtime.init_constructors();
+        runtime.init_functions();
+        runtime.init_lookups();
 
         runtime
     }
 
+    /// Create a runtime that holds the builtins defined in the spec file at
+    /// `path`. The hardcoded builtins are registered as well, unless the spec
+    /// says otherwise.
+    pub fn from_spec(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let spec = BuiltinSpec::load(path)?;
+
+        let mut runtime = if spec.include_defaults {
+            Self::new()
+        } else {
+            Self::empty()
+        };
+
+        runtime.register_builtins(spec.builtins);
+        Ok(runtime)
+    }
+
+    fn empty() -> Self {
+        Self {
+            builtins: Vec::<JSBuiltin>::new(),
+            constructors: Vec::<ConstructorType>::new(),
+            functions: Vec::<MS>::new(),
+            methods: HashMap::new(),
+            properties: HashMap::new(),
+            gc_function: None,
+        }
+    }
+
+    /// Register additional builtins, like the engine specific helpers provided
+    /// by a profile, with this runtime.
+    pub fn register_builtins(&mut self, builtins: Vec<JSBuiltin>) {
+        self.builtins.extend(builtins);
+
+        self.constructors.clear();
+        self.functions.clear();
+        self.init_constructors();
+        self.init_functions();
+        self.init_lookups();
+    }
+
     /// Find and fill in all the constructors accessible from this runtime.
     pub fn init_constructors(&mut self) {
         for builtin in &self.builtins {
@@ -60,8 +120,43 @@ impl JSRuntime {
         }
     }
 
+    /// Find and fill in all the global functions accessible from this runtime.
+    pub fn init_functions(&mut self) {
+        for builtin in &self.builtins {
+            if let Some(functions) = &builtin.functions {
+                self.functions.extend_from_slice(&functions[..]);
+            }
+        }
+    }
+
+    /// Fill in the methods and properties of each of the shapes of the
+    /// builtins. See [JSRuntime::methods].
+    fn init_lookups(&mut self) {
+        let shapes = self.builtins.iter()
+            .flat_map(|builtin| [builtin.shape, builtin.shape | Shape::Static])
+            .collect::<Vec<Shape>>();
+
+        self.methods.clear();
+        self.properties.clear();
+        for shape in shapes {
+            let methods = self.find_methods(shape);
+            self.methods.insert(shape, methods);
+            let properties = self.find_properties(shape);
+            self.properties.insert(shape, properties);
+        }
+    }
+
     /// Get a list of methods for an object with the shape `shape`
-    pub fn get_methods(&self, mut shape: Shape) -> Option<Vec<MS>> {
+    pub fn get_methods(&self, shape: Shape) -> Option<Cow<'_, [MS]>> {
+        match self.methods.get(&shape) {
+            Some(methods) => methods.as_deref().map(Cow::Borrowed),
+            None          => self.find_methods(shape).map(Cow::Owned),
+        }
+    }
+
+    /// Go through all the builtins to find the methods for an object with the
+    /// shape `shape`
+    fn find_methods(&self, mut shape: Shape) -> Option<Vec<MS>> {
 
         // Rip the static type out of the shape.
         let is_static = shape.fetch_clear_static();
@@ -112,9 +207,27 @@ impl JSRuntime {
 
     }
 
+    /// Get the method called `name` for an object with the shape `shape`, if
+    /// the runtime knows about such a method.
+    pub fn get_method(&self, shape: Shape, name: &str) -> Option<MS> {
+        self.get_methods(shape)?
+            .iter()
+            .find(|method| method.get_name() == name)
+            .cloned()
+    }
+
     /// Get access to all the properties that might be present on a instance of
     /// an object with the shape `shape`
-    pub fn get_properties(&self, shape: Shape) -> Option<Vec<String>> {
+    pub fn get_properties(&self, shape: Shape) -> Option<Cow<'_, [String]>> {
+        match self.properties.get(&shape) {
+            Some(properties) => properties.as_deref().map(Cow::Borrowed),
+            None             => self.find_properties(shape).map(Cow::Owned),
+        }
+    }
+
+    /// Go through all the builtins to find the properties of an object with
+    /// the shape `shape`
+    fn find_properties(&self, shape: Shape) -> Option<Vec<String>> {
 
         let mut ret = Vec::new();
 
@@ -144,6 +257,28 @@ impl JSRuntime {
         &self.constructors
     }
 
+    /// Get access to a list of global functions that are present on this
+    /// runtime.
+    pub fn get_functions(&self) -> &Vec<MS> {
+        &self.functions
+    }
+
+    /// Set the name of the global function that forces a garbage collection.
+    pub fn set_gc_function(&mut self, name: Option<&str>) {
+        self.gc_function = name.map(|name| name.to_string());
+    }
+
+    /// Get the global function that forces a garbage collection, if the
+    /// engine has one and the runtime knows about it.
+    pub fn get_gc_function(&self) -> Option<&MS> {
+        self.get_function(self.gc_function.as_ref()?)
+    }
+
+    /// Get the global function called `name`, if the runtime knows about it.
+    pub fn get_function(&self, name: &str) -> Option<&MS> {
+        self.functions.iter().find(|function| function.get_name() == name)
+    }
+
 
     ////
     //// Define JSBuiltins from here
@@ -192,6 +327,7 @@ impl JSRuntime {
             properties:     properties,
             methods:        None,
             static_methods: Some(static_methods),
+            functions:      None,
         });
     }
     fn register_array(&mut self) {
@@ -212,7 +348,7 @@ impl JSRuntime {
             MS::new("push",    Array, vec![MA::Type(Any)], Int),
             MS::new("pop",     Array, vec![], Any),
             MS::new("shift",   Array, vec![], Any),
-            MS::new("sort",    Array, vec![], Array),
+            MS::new("sort",    Array, vec![MA::Optional(Function)], Array),
             MS::new("join",    Array, vec![], String),
             MS::new("concat",  Array, vec![MA::Repeat(10, Any)], Array),
             MS::new("unshift", Array, vec![MA::Repeat(10, Any)], Int),
@@ -222,6 +358,13 @@ impl JSRuntime {
             MS::new("slice",       Array, vec![MA::Type(Int), MA::Optional(Int)], Array),
             MS::new("copyWithin",  Array, vec![MA::Type(Int), MA::Repeat(2, Int)], Array),
             MS::new("splice", Array, vec![MA::Type(Int), MA::Optional(Int), MA::Repeat(10, Any)], Undefined),
+            MS::new("forEach",   Array, vec![MA::Type(Function)], Undefined),
+            MS::new("map",       Array, vec![MA::Type(Function)], Array),
+            MS::new("filter",    Array, vec![MA::Type(Function)], Array),
+            MS::new("some",      Array, vec![MA::Type(Function)], Bool),
+            MS::new("every",     Array, vec![MA::Type(Function)], Bool),
+            MS::new("find",      Array, vec![MA::Type(Function)], Any),
+            MS::new("findIndex", Array, vec![MA::Type(Function)], Int),
         ];
 
         let static_methods = vec![
@@ -236,6 +379,7 @@ impl JSRuntime {
             properties:     properties,
             methods:        Some(methods),
             static_methods: Some(static_methods),
+            functions:      None,
         });
     }
 
@@ -296,6 +440,7 @@ impl JSRuntime {
             properties:     properties,
             methods:        Some(methods),
             static_methods: Some(static_methods),
+            functions:      None,
         });
 
     }
@@ -361,7 +506,8 @@ impl JSRuntime {
             constructor:    constructor,
             properties:     properties,
             methods:        None,
-            static_methods: Some(methods)
+            static_methods: Some(methods),
+            functions:      None,
         });
 
     }
@@ -376,14 +522,21 @@ impl JSRuntime {
             ConstructorType::NonCallable(String::from("ArrayBuffer"), arraybuf_static)
         ];
 
-        let properties = vec![String::from("byteLength")];
+        let properties = vec![
+            String::from("byteLength"),
+            String::from("detached"),
+        ];
 
         let static_methods = vec![
             MS::new("isView", arraybuf_static, vec![MA::Type(Any)], Bool),
         ];
 
+        // Note that `transfer` and `transferToFixedLength` detach the buffer
+        // they are called on.
         let methods = vec![
             MS::new("slice", arraybuf, vec![MA::Type(Int), MA::Optional(Int)], arraybuf),
+            MS::new("transfer", arraybuf, vec![MA::Optional(Int)], arraybuf),
+            MS::new("transferToFixedLength", arraybuf, vec![MA::Optional(Int)], arraybuf),
         ];
 
         self.builtins.push(JSBuiltin {
@@ -391,8 +544,8 @@ impl JSRuntime {
             constructor:        constructor,
             properties:         properties,
             methods:            Some(methods),
-            static_methods:     Some(static_methods)
-
+            static_methods:     Some(static_methods),
+            functions:          None,
         });
 
     }
@@ -453,8 +606,68 @@ impl JSRuntime {
             properties:     properties,
             methods:        Some(methods),
             static_methods: Some(static_methods),
+            functions:      None,
         })
 
     }
 
+    /// The methods that can be called on any function. There is nothing to
+    /// construct, the functions come from the function definitions and from
+    /// the builtins that return one. The function that `bind` returns is
+    /// called the same way as the one that it was called on.
+    fn register_function(&mut self) {
+
+        let properties = vec![
+            String::from("length"),
+            String::from("name"),
+        ];
+
+        let methods = vec![
+            MS::new("bind", Function, vec![MA::Optional(Any), MA::Repeat(3, Any)], Function),
+            MS::new("call", Function, vec![MA::Optional(Any), MA::Repeat(5, Any)], Unknown),
+            MS::new("apply", Function, vec![MA::Optional(Any), MA::Optional(Array)], Unknown),
+            MS::new("toString", Function, vec![], String),
+        ];
+
+        self.builtins.push(JSBuiltin {
+            shape:          Shape::Function,
+            constructor:    vec![],
+            properties,
+            methods:        Some(methods),
+            static_methods: None,
+            functions:      None,
+        });
+
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(methods: Option<Cow<'_, [MS]>>) -> Option<Vec<String>> {
+        methods.map(|methods| methods.iter()
+                                     .map(|method| method.get_name().clone())
+                                     .collect())
+    }
+
+    #[test]
+    fn test_lookups() {
+        let runtime = JSRuntime::new();
+        let shapes = [Shape::Array, Shape::Array | Shape::Static, Shape::Math,
+                      Shape::Object, Shape::String | Shape::Static,
+                      Shape::TypedArray, Shape::Custom, Shape::Function,
+                      Shape::Any];
+        for shape in shapes {
+            assert_eq!(names(runtime.get_methods(shape)),
+                       names(runtime.find_methods(shape).map(Cow::Owned)));
+            assert_eq!(runtime.get_properties(shape).map(|p| p.to_vec()),
+                       runtime.find_properties(shape));
+        }
+        assert!(runtime.methods.contains_key(&(Shape::Array | Shape::Static)));
+        assert!(!runtime.methods.contains_key(&Shape::Custom));
+        assert_eq!(names(runtime.get_methods(Shape::Function)).unwrap()[0],
+                   "bind");
+    }
 }
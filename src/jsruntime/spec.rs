@@ -0,0 +1,266 @@
+//! Read builtin definitions from a JSON data file, so that the surface of the
+//! runtime can be extended without recompiling the fuzzer. The file looks like
+//!
+//! ```json
+//! {
+//!     "include_defaults": true,
+//!     "builtins": [
+//!         {
+//!             "shape": "ArrayBuffer",
+//!             "constructors": [
+//!                 {"name": "ArrayBuffer", "args": ["Int"]},
+//!                 {"name": "ArrayBuffer", "callable": false}
+//!             ],
+//!             "properties": ["byteLength"],
+//!             "methods": [
+//!                 {"name": "slice", "args": ["Int", "Int?"], "output": "ArrayBuffer"}
+//!             ],
+//!             "static_methods": [
+//!                 {"name": "isView", "args": ["Any"], "output": "Bool"}
+//!             ],
+//!             "functions": [
+//!                 {"name": "gc"},
+//!                 {"name": "wrapper", "output": "Function",
+//!                  "returns": {"args": ["Int"], "output": "Int"}}
+//!             ]
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! Types are named after the primitive types (`Int`, `Float`, `String`, `Bool`,
+//! `Function`, `Undefined`, `Unknown`, `Object` and `Any`) or after a shape, in
+//! which case they stand for an object of that shape. They can be combined
+//! with `|`. `String` and `Object` always mean the primitive types. An argument
+//! type can be suffixed with `?` to make it optional or with `*N` to repeat it
+//! up to N times. A missing `output` is `Undefined`, except for constructors
+//! where it is an instance of the builtin. A method that returns a `Function`
+//! can give the `args` and the `output` of that function in `returns`, so that
+//! the generated programs call it. The types of those args are plain types.
+//! Only the shapes that the fuzzer already knows about can be used.
+
+use std::fs;
+
+use super::jsbuiltin::JSBuiltin;
+use crate::utils::json::JsonValue;
+use crate::ir::codeanalysis::types::MethodSignature as MS;
+use crate::ir::codeanalysis::types::MethodArg as MA;
+use crate::ir::codeanalysis::types::*;
+
+#[derive(Debug)]
+struct SpecError(String);
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+type SpecResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn error<T>(msg: String) -> SpecResult<T> {
+    Err(Box::new(SpecError(msg)))
+}
+
+/// The builtins read from a spec file.
+pub struct BuiltinSpec {
+    /// Whether the builtins that are hardcoded in the runtime should be
+    /// registered along with the ones from the file
+    pub include_defaults: bool,
+
+    pub builtins: Vec<JSBuiltin>,
+}
+
+impl BuiltinSpec {
+
+    /// Read and parse the spec file at `path`
+    pub fn load(path: &str) -> SpecResult<Self> {
+        let data = fs::read_to_string(path)?;
+        let spec = JsonValue::parse(&data)?;
+
+        let include_defaults = match spec.get("include_defaults") {
+            Some(value) => match value.as_bool() {
+                Some(value) => value,
+                None => return error("include_defaults must be a bool".into()),
+            },
+            None => true,
+        };
+
+        let mut builtins = Vec::new();
+        for builtin in list(&spec, "builtins")? {
+            builtins.push(parse_builtin(builtin)?);
+        }
+
+        Ok(Self {
+            include_defaults,
+            builtins,
+        })
+    }
+}
+
+/// Get the array member `key` of `value`, treating a missing one as empty.
+fn list<'a>(value: &'a JsonValue, key: &str) -> SpecResult<&'a [JsonValue]> {
+    match value.get(key) {
+        Some(member) => match member.as_array() {
+            Some(array) => Ok(&array[..]),
+            None => error(format!("{} must be an array", key)),
+        },
+        None => Ok(&[]),
+    }
+}
+
+/// Get the string member `key` of `value`
+fn string<'a>(value: &'a JsonValue, key: &str) -> SpecResult<&'a str> {
+    match value.get(key).and_then(|v| v.as_str()) {
+        Some(s) => Ok(s),
+        None => error(format!("Expected a string for {}", key)),
+    }
+}
+
+fn parse_shape(name: &str) -> SpecResult<Shape> {
+    let shape = match name {
+        "None"        => Shape::None,
+        "Object"      => Shape::Object,
+        "Array"       => Shape::Array,
+        "ArrayBuffer" => Shape::ArrayBuffer,
+        "TypedArray"  => Shape::TypedArray,
+        "Reflect"     => Shape::Reflect,
+        "Math"        => Shape::Math,
+        "String"      => Shape::String,
+        "Custom"      => Shape::Custom,
+        "Function"    => Shape::Function,
+        _ => return error(format!("Unknown shape {}", name)),
+    };
+    Ok(shape)
+}
+
+fn parse_type(name: &str) -> SpecResult<Type> {
+    let mut ret = Type::basic(PType::None);
+    for name in name.split('|') {
+        ret |= match name.trim() {
+            "Int"       => Int,
+            "Float"     => Float,
+            "String"    => String,
+            "Bool"      => Bool,
+            "Function"  => Function,
+            "Undefined" => Undefined,
+            "Unknown"   => Unknown,
+            "Object"    => Object,
+            "Any"       => Any,
+            shape       => Type::obj(parse_shape(shape)?),
+        };
+    }
+    Ok(ret)
+}
+
+fn parse_arg(arg: &JsonValue) -> SpecResult<MA> {
+    let arg = match arg.as_str() {
+        Some(arg) => arg,
+        None => return error("Method arguments must be strings".into()),
+    };
+
+    if let Some(itype) = arg.strip_suffix('?') {
+        return Ok(MA::Optional(parse_type(itype)?));
+    }
+
+    if let Some((itype, times)) = arg.split_once('*') {
+        let times = match times.parse::<u8>() {
+            Ok(times) => times,
+            Err(_) => return error(format!("Invalid repeat count in {}", arg)),
+        };
+        return Ok(MA::Repeat(times, parse_type(itype)?));
+    }
+
+    Ok(MA::Type(parse_type(arg)?))
+}
+
+fn parse_method(method: &JsonValue, this_type: Type,
+                default_output: Type) -> SpecResult<MS> {
+    let name = string(method, "name")?;
+
+    let mut args = Vec::new();
+    for arg in list(method, "args")? {
+        args.push(parse_arg(arg)?);
+    }
+
+    let output = match method.get("output") {
+        Some(_) => parse_type(string(method, "output")?)?,
+        None    => default_output,
+    };
+
+    let signature = MS::new(name, this_type, args, output);
+    let returns = match method.get("returns") {
+        Some(returns) => returns,
+        None => return Ok(signature),
+    };
+
+    let mut args = Vec::new();
+    for arg in list(returns, "args")? {
+        match arg.as_str() {
+            Some(arg) => args.push(parse_type(arg)?),
+            None => return error("Function arguments must be strings".into()),
+        }
+    }
+    let output = match returns.get("output") {
+        Some(_) => parse_type(string(returns, "output")?)?,
+        None    => Undefined,
+    };
+    let function = FunctionSignature::builtin(args, output);
+    Ok(signature.with_output_signature(function))
+}
+
+fn parse_methods(builtin: &JsonValue, key: &str,
+                 this_type: Type) -> SpecResult<Option<Vec<MS>>> {
+    if builtin.get(key).is_none() {
+        return Ok(None);
+    }
+
+    let mut methods = Vec::new();
+    for method in list(builtin, key)? {
+        methods.push(parse_method(method, this_type, Undefined)?);
+    }
+    Ok(Some(methods))
+}
+
+fn parse_builtin(builtin: &JsonValue) -> SpecResult<JSBuiltin> {
+    let shape = match builtin.get("shape") {
+        Some(_) => parse_shape(string(builtin, "shape")?)?,
+        None    => Shape::None,
+    };
+
+    let instance = Type::obj(shape);
+    let static_type = Type::obj(shape | Shape::Static);
+
+    // Callable constructors create an instance of the builtin while the non
+    // callable ones just give access to the static builtin object.
+    let mut constructor = Vec::new();
+    for cons in list(builtin, "constructors")? {
+        let callable = cons.get("callable").and_then(|c| c.as_bool())
+                                           .unwrap_or(true);
+        if callable {
+            let ms = parse_method(cons, instance, instance)?;
+            constructor.push(ConstructorType::Callable(ms));
+        } else {
+            let name = string(cons, "name")?.to_string();
+            constructor.push(ConstructorType::NonCallable(name, static_type));
+        }
+    }
+
+    let mut properties = Vec::new();
+    for property in list(builtin, "properties")? {
+        match property.as_str() {
+            Some(property) => properties.push(property.to_string()),
+            None => return error("Properties must be strings".into()),
+        }
+    }
+
+    Ok(JSBuiltin {
+        shape,
+        constructor,
+        properties,
+        methods:        parse_methods(builtin, "methods", instance)?,
+        static_methods: parse_methods(builtin, "static_methods", static_type)?,
+        functions:      parse_methods(builtin, "functions", Undefined)?,
+    })
+}
@@ -1,15 +1,31 @@
-const INDENT_SPACES: u8 = 3;
+use std::fmt::Write;
+
+const INDENT_SPACES: usize = 3;
+
+/// The code of a program takes at least this many bytes of memory up front
+const MIN_CAPACITY: usize = 4096;
+
+/// A Rust string can't hold a lone surrogate, so the strings of the IR hold
+/// the last 2048 code points of the private use plane in their place, from
+/// this one on for U+D800. [write_string] writes them as the `\u` escapes of
+/// the surrogates.
+pub const SURROGATE_BASE: u32 = 0x10f800;
 
 pub struct Emitter {
     code: String,
-    indent_level: u8,
+    indent_level: usize,
+
+    /// A moving average of the size of the programs that were emitted, which
+    /// the memory for the next one is reserved from
+    average_size: usize,
 }
 
 impl Emitter {
     pub fn new() -> Self {
         Self {
-            code: String::new(),
+            code: String::with_capacity(MIN_CAPACITY),
             indent_level: 0,
+            average_size: 0,
         }
     }
 
@@ -22,9 +38,11 @@ impl Emitter {
         self.indent_level -= INDENT_SPACES;
     }
 
-    pub fn add(&mut self, code: String) {
-        self.code += &" ".repeat(self.indent_level as usize);
-        self.code += &code;
+    pub fn add(&mut self, code: &str) {
+        for _ in 0..self.indent_level {
+            self.code.push(' ');
+        }
+        self.code.push_str(code);
         self.code.push('\n');
     }
 
@@ -37,7 +55,190 @@ impl Emitter {
         // self.code += "\0";
     }
 
+    /// Start on a new program. The memory of the code is kept around for it,
+    /// with room for a program a bit larger than the average one, so that it
+    /// doesn't have to grow as the program is emitted. One very large program
+    /// doesn't keep all of its memory around for the rest of them though.
     pub fn reset(&mut self) {
+        self.average_size = (self.average_size * 7 + self.code.len()) / 8;
+        let wanted = std::cmp::max(MIN_CAPACITY, self.average_size * 2);
+
         self.code.clear();
+        self.indent_level = 0;
+        if self.code.capacity() > wanted * 4 {
+            self.code.shrink_to(wanted);
+        }
+        self.code.reserve(wanted);
+    }
+}
+
+/// Write `s` to `code` as a JS string literal, in double quotes. Everything
+/// that can't be in between them as it is, or that reads differently there, is
+/// escaped, and the stand-ins of the lone surrogates are written as those.
+/// See [SURROGATE_BASE].
+pub fn write_string(code: &mut String, s: &str) {
+    code.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => code.push_str("\\\""),
+            '\\' => code.push_str("\\\\"),
+            '\n' => code.push_str("\\n"),
+            '\r' => code.push_str("\\r"),
+            '\t' => code.push_str("\\t"),
+            // Older engines end a line at these even in a string
+            '\u{2028}' | '\u{2029}' => {
+                let _ = write!(code, "\\u{:04x}", c as u32);
+            },
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                let _ = write!(code, "\\x{:02x}", c as u32);
+            },
+            c if c as u32 >= SURROGATE_BASE => {
+                let _ = write!(code, "\\u{:04x}",
+                               c as u32 - SURROGATE_BASE + 0xd800);
+            },
+            c    => code.push(c),
+        }
+    }
+    code.push('"');
+}
+
+/// Check if `name` can be written out as it is, as an identifier or after a
+/// `.`. This only allows the ASCII ones, and quotes the rest.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {},
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Write the access to the property `name` of an object, which is written to
+/// `code` before this. The names that aren't identifiers are put in brackets.
+pub fn write_property(code: &mut String, name: &str) {
+    if is_identifier(name) {
+        code.push('.');
+        code.push_str(name);
+    } else {
+        code.push('[');
+        write_string(code, name);
+        code.push(']');
+    }
+}
+
+/// The forms that an integer literal can be written in, see [write_int]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntForm {
+    Decimal,
+    Hex,
+    Binary,
+    Octal,
+
+    /// With an exponent for the zeros at the end, like `12e3`
+    Exponent,
+
+    /// With a `_` in between each group of 3 digits, like `65_536`
+    Separated,
+}
+
+/// The forms other than the decimal one
+pub const INT_FORMS: [IntForm; 5] = [IntForm::Hex, IntForm::Binary,
+                                     IntForm::Octal, IntForm::Exponent,
+                                     IntForm::Separated];
+
+/// Write `val` to `code` as a JS integer literal of the given `form`. The sign
+/// is an operator in JS, so it goes in front of the literal in all of them.
+pub fn write_int(code: &mut String, val: isize, form: IntForm) {
+    if val < 0 {
+        code.push('-');
+    }
+    let abs = val.unsigned_abs();
+    let _ = match form {
+        IntForm::Decimal => write!(code, "{}", abs),
+        IntForm::Hex     => write!(code, "0x{:x}", abs),
+        IntForm::Binary  => write!(code, "0b{:b}", abs),
+        IntForm::Octal   => write!(code, "0o{:o}", abs),
+        IntForm::Exponent => {
+            let mut mantissa = abs;
+            let mut exponent = 0;
+            while mantissa != 0 && mantissa.is_multiple_of(10) {
+                mantissa /= 10;
+                exponent += 1;
+            }
+            write!(code, "{}e{}", mantissa, exponent)
+        },
+        IntForm::Separated => {
+            let digits = abs.to_string();
+            for (idx, digit) in digits.chars().enumerate() {
+                if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+                    code.push('_');
+                }
+                code.push(digit);
+            }
+            Ok(())
+        },
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strings() {
+        let mut code = String::new();
+        write_string(&mut code, "a\"b\\c\nd\0e\u{2028}\u{e9}");
+        assert_eq!(code, "\"a\\\"b\\\\c\\nd\\x00e\\u2028\u{e9}\"");
+
+        code.clear();
+        write_string(&mut code, "\u{10f800}\u{10fc00}\u{10ffff}\u{10f7ff}");
+        assert_eq!(code, "\"\\ud800\\udc00\\udfff\u{10f7ff}\"");
+
+        code.clear();
+        write_property(&mut code, "length");
+        write_property(&mut code, "__proto__");
+        write_property(&mut code, "0x10");
+        write_property(&mut code, "a b");
+        assert_eq!(code, ".length.__proto__[\"0x10\"][\"a b\"]");
+        assert!(!is_identifier(""));
+    }
+
+    #[test]
+    fn test_ints() {
+        let forms = [(IntForm::Decimal, "-42"), (IntForm::Hex, "-0x2a"),
+                     (IntForm::Binary, "-0b101010"), (IntForm::Octal, "-0o52"),
+                     (IntForm::Exponent, "-42e0"), (IntForm::Separated, "-42")];
+        for (form, literal) in forms {
+            let mut code = String::new();
+            write_int(&mut code, -42, form);
+            assert_eq!(code, literal);
+        }
+
+        let mut code = String::new();
+        write_int(&mut code, 65536000, IntForm::Exponent);
+        code.push(' ');
+        write_int(&mut code, 65536000, IntForm::Separated);
+        code.push(' ');
+        write_int(&mut code, 0, IntForm::Exponent);
+        code.push(' ');
+        write_int(&mut code, isize::MIN, IntForm::Hex);
+        assert_eq!(code, "65536e3 65_536_000 0e0 -0x8000000000000000");
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut emitter = Emitter::new();
+        emitter.add(&"x".repeat(1 << 20));
+        emitter.reset();
+        assert!(emitter.get_code().is_empty());
+
+        // The memory of the large program goes away once the programs after it
+        // are small again
+        for _ in 0..50 {
+            emitter.add("var v0 = 1;");
+            emitter.reset();
+        }
+        assert!(emitter.get_code().capacity() < 1 << 20);
+        assert!(emitter.get_code().capacity() >= MIN_CAPACITY);
     }
 }
@@ -1,16 +1,117 @@
-use super::emitter::Emitter;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::emitter::{Emitter, write_property, write_string, is_identifier};
+use super::emitter::{IntForm, INT_FORMS, write_int};
+use super::names::Names;
 use crate::ir::program::Program;
 use crate::ir::instruction::{Instruction, Value};
+use crate::ir::variable::Variable;
 use crate::ir::opcodes::Opcodes as op;
 use crate::ir::operators::*;
 use crate::ir::operation::*;
 use crate::ir::codeanalysis::types::ConstructorType;
-use crate::utils::probablity::Probablity;
-use crate::utils::random::Random;
+use crate::ir::codeanalysis::liveness::Liveness;
+use crate::utils::probablity::{Probablity, WeightTable};
+use crate::utils::random::{Random, derive_seed};
+
+/// The probablity with which the entire lifted program is wrapped in an
+/// immediately invoked function expression by default, see
+/// [Lifter::set_iife_probability]
+pub const WRAP_IN_IIFE_PROBABLITY: f64 = 0.1;
+
+/// The keywords that the variables can be declared with, in the order of their
+/// weights in [Lifter::set_declarations]
+pub const DECLARATIONS: [&str; 3] = ["var", "let", "const"];
+
+/// How often each of the [DECLARATIONS] is used by default
+pub const DECLARATION_WEIGHTS: [u16; 3] = [50, 30, 20];
+
+/// The chance that a number is written in another form than the usual one with
+/// `--literal-forms`
+const LITERAL_FORM_PROBABLITY: f64 = 0.3;
+
+/// The line that the digest of a program is printed in starts with this
+pub const DIGEST_PREFIX: &str = "digest: ";
+
+/// Find the digest that a program printed in its `output`
+pub fn find_digest(output: &str) -> Option<&str> {
+    output.lines().rev()
+        .find_map(|line| line.strip_prefix(DIGEST_PREFIX))
+        .map(|digest| digest.trim())
+}
+
+/// Defines the digest of the program and the function that adds values to it.
+/// Only primitives are added with their value, as converting objects to strings
+/// could run code of the program. Math.imul is saved up front, in case the
+/// program overwrites it.
+const DIGEST_PRELUDE: [&str; 11] = [
+    "var __digest = 0;",
+    "var __imul = Math.imul;",
+    "function __record(v) {",
+    "   var s = v === null ? \"null\" : typeof v;",
+    "   if (s !== \"object\" && s !== \"function\" && s !== \"symbol\") {",
+    "      s += \":\" + (v === 0 && 1 / v < 0 ? \"-0\" : v);",
+    "   }",
+    "   for (var i = 0; i < s.length; i++) {",
+    "      __digest = (__imul(__digest, 31) + s.charCodeAt(i)) | 0;",
+    "   }",
+    "}",
+];
+
+/// Write the `names` of `variables` to `code`, separated by commas, like the
+/// args of a call
+fn write_list(code: &mut String, names: &Names, variables: &[Variable]) {
+    for (idx, variable) in variables.iter().enumerate() {
+        if idx > 0 {
+            code.push_str(", ");
+        }
+        let _ = write!(code, "{}", names.of(variable));
+    }
+}
 
 pub struct Lifter {
     emitter: Emitter,
     probablity: Probablity,
+
+    /// Whether the values of the variables are added to a digest that is
+    /// printed at the end, so that two runs of a program can be compared
+    digest: bool,
+
+    /// Whether each instruction is preceded by a comment with the instruction
+    /// itself, so that the lines of the code can be mapped back to the IR
+    comments: bool,
+
+    /// Whether the numbers are sometimes written in other forms than the
+    /// decimal one, see [IntForm]
+    literal_forms: bool,
+
+    /// How often the whole program is run from inside of a function
+    iife_probability: f64,
+
+    /// The weights of the [DECLARATIONS], both as they are and as a table to
+    /// choose from
+    declaration_weights: [u16; 3],
+    declarations: WeightTable,
+
+    /// The variables of the program that is being lifted that are assigned to
+    /// after they are declared, which can't be declared with const
+    reassigned: HashSet<u32>,
+
+    /// Whether the variables are given names like the ones of real code, see
+    /// [Names]
+    realistic_names: bool,
+    names: Names,
+
+    /// Whether the instructions that are dead are left out, see [Liveness]
+    strip_dead_code: bool,
+
+    /// The lines of code that are added to the end of the program, like a call
+    /// to the gc function of the engine
+    epilogue: Vec<String>,
+
+    /// The statement that is being lifted, see [Lifter::lift]
+    line: String,
 }
 
 impl Lifter {
@@ -18,13 +119,178 @@ impl Lifter {
         Self {
             emitter: Emitter::new(),
             probablity: Probablity::new(Random::new(0)),
+            digest: false,
+            comments: false,
+            literal_forms: false,
+            iife_probability: WRAP_IN_IIFE_PROBABLITY,
+            declaration_weights: DECLARATION_WEIGHTS,
+            declarations: declaration_table(DECLARATION_WEIGHTS),
+            reassigned: HashSet::new(),
+            realistic_names: false,
+            names: Names::default(),
+            strip_dead_code: false,
+            epilogue: vec![],
+            line: String::new(),
+        }
+    }
+
+    pub fn do_lifting(&mut self, program: &Program) {
+
+        self.reassigned.clear();
+        for inst in &program.buffer {
+            let reassigned = match inst.operation.opcode() {
+                op::Copy => Some(inst.input_at(0)),
+                op::UnaryOp => match inst.cast_into::<UnaryOp>().0 {
+                    UnaryOperators::Inc | UnaryOperators::Dec => {
+                        Some(inst.input_at(0))
+                    },
+                    _ => None,
+                },
+                op::BeginFor => Some(inst.temp_at(0)),
+                _ => None,
+            };
+            if let Some(variable) = reassigned {
+                self.reassigned.insert(variable.0);
+            }
+        }
+
+        self.names.clear();
+        if self.realistic_names {
+            for inst in &program.buffer {
+                for variable in inst.outputs().iter().chain(inst.temp()) {
+                    self.names.assign(variable, &mut self.probablity);
+                }
+            }
+        }
+
+        if self.digest {
+            for line in DIGEST_PRELUDE {
+                self.emitter.add(line);
+            }
+        }
+
+        // Top level code and function code are treated differently by the
+        // engines, so sometimes run the whole program from inside a function.
+        let wrap = self.iife_probability > 0.0 &&
+                   self.probablity.probablity(self.iife_probability);
+        if wrap {
+            self.emitter.add("(function() {");
+            self.emitter.indent();
+        }
+
+        let liveness = self.strip_dead_code
+            .then(|| Liveness::analyze(&program.buffer));
+        for (idx, i) in program.buffer.iter().enumerate() {
+            if liveness.as_ref().is_some_and(|liveness| liveness.is_dead(idx)) {
+                continue;
+            }
+            if self.comments {
+                self.emitter.add(&format!("// [{}] {}", idx, i.print()));
+            }
+            self.lift(i);
+
+            // The outputs of blocks are only defined inside of them
+            let block = i.operation.is_block_start() ||
+                        i.operation.is_block_end();
+            if self.digest && !block {
+                for output in i.outputs() {
+                    self.emitter.add(&format!("__record({});",
+                                              self.names.of(output)));
+                }
+            }
         }
+
+        if wrap {
+            self.emitter.unindent();
+            self.emitter.add("})();");
+        }
+
+        for line in &self.epilogue {
+            self.emitter.add(line);
+        }
+
+        // REPRL targets get the digest through the fuzzilli() builtin, as
+        // their stdout is not read
+        if self.digest {
+            self.emitter.add(&format!(
+                "if (typeof fuzzilli === \"function\") \
+                 fuzzilli(\"FUZZILLI_PRINT\", \"{0}\" + __digest); \
+                 else print(\"{0}\" + __digest);", DIGEST_PREFIX));
+        }
+    }
+
+    /// Add a digest of the values in the program to the code that is lifted.
+    /// See [DIGEST_PREFIX].
+    pub fn set_digest(&mut self, digest: bool) {
+        self.digest = digest;
+    }
+
+    /// Add a comment like `// [3] v2 = BinaryOp(v0, v1)` with the index and
+    /// the IR of each instruction before the code that it is lifted to
+    pub fn set_comments(&mut self, comments: bool) {
+        self.comments = comments;
+    }
+
+    /// Write some of the integers in hex, binary, octal, with an exponent or
+    /// with separators, and some of the floats with an exponent. The engines
+    /// parse each of these in their own way.
+    pub fn set_literal_forms(&mut self, literal_forms: bool) {
+        self.literal_forms = literal_forms;
+    }
+
+    /// Wrap this fraction (0 to 1) of the programs in `(function() { ... })()`,
+    /// so that their code is function code instead of top level code. 0 never
+    /// wraps them.
+    pub fn set_iife_probability(&mut self, probability: f64) {
+        self.iife_probability = probability;
+    }
+
+    /// Declare the variables with var, let and const by the `weights` of each,
+    /// in the order of [DECLARATIONS]. The ones that are assigned to are never
+    /// declared with const.
+    pub fn set_declarations(&mut self, weights: [u16; 3]) {
+        self.declaration_weights = weights;
+        self.declarations = declaration_table(weights);
     }
 
-    pub fn do_lifting(&mut self, program: Program ) {
-        for i in program.buffer {
-            self.lift(&i);
+    /// Choose the keyword that `variable` is declared with
+    fn declaration(&mut self, variable: &Variable) -> &'static str {
+        let [_, let_weight, const_weight] = self.declaration_weights;
+        if let_weight == 0 && const_weight == 0 {
+            return "var";
         }
+
+        let idx = self.probablity.choose_from_table(&self.declarations);
+        match DECLARATIONS[idx] {
+            "const" if self.reassigned.contains(&variable.0) => {
+                if let_weight > 0 { "let" } else { "var" }
+            },
+            declaration => declaration,
+        }
+    }
+
+    /// Give the variables names like `length`, `i` or `π` instead of `v` and
+    /// their ids
+    pub fn set_realistic_names(&mut self, realistic_names: bool) {
+        self.realistic_names = realistic_names;
+    }
+
+    /// Leave out the instructions whose values are never used and that have no
+    /// other effect, like loads and arithmetic on primitives, so that more of
+    /// the code that the engine runs does something
+    pub fn set_strip_dead_code(&mut self, strip_dead_code: bool) {
+        self.strip_dead_code = strip_dead_code;
+    }
+
+    /// Add the lines of `epilogue` to the end of every program that is lifted
+    pub fn set_epilogue(&mut self, epilogue: Vec<String>) {
+        self.epilogue = epilogue;
+    }
+
+    /// Seed the choices that are made while lifting, so that the same program
+    /// is always lifted the same way for the same `seed`
+    pub fn seed(&mut self, seed: u64) {
+        self.probablity = Probablity::new(Random::new(derive_seed(seed, 2)));
     }
 
     pub fn get_code(&self) -> &String {
@@ -41,6 +307,22 @@ impl Lifter {
 
     fn lift(&mut self, inst: &Instruction) {
 
+        // The statement is built up in `line`, which keeps its memory from one
+        // statement to the next, and then copied to the emitter. A block start
+        // indents the lines after it.
+        let decl = match inst.operation.opcode() {
+            op::BeginFor => self.declaration(inst.temp_at(0)),
+            op::BeginFunctionDefinition => "function",
+            _ if !inst.outputs().is_empty() => {
+                self.declaration(inst.output_at(0))
+            },
+            _ => "var",
+        };
+        self.line.clear();
+        let code = &mut self.line;
+        let names = &self.names;
+        let mut indent = false;
+
         match inst.operation.opcode() {
 
             op::Nop => {},
@@ -50,288 +332,530 @@ impl Lifter {
             op::LoadString |
             op::LoadBool   |
             op::LoadUndefined => {
-                let mut code = "var ".to_owned();
-                code += &inst.output_at(0).print();
-                code += &" = ";
+                let _ = write!(code, "{} {} = ", decl,
+                               names.of(inst.output_at(0)));
                 let val = inst.getval();
 
-                match val {
-                    Value::Int(val) => code += &val.to_string(),
-                    Value::Float(val) => code += &val.to_string(),
+                let other_form = self.literal_forms &&
+                    self.probablity.probablity(LITERAL_FORM_PROBABLITY);
+                let _ = match val {
+                    Value::Int(val) => {
+                        let form = if other_form {
+                            *self.probablity.0.random_element(&INT_FORMS)
+                        } else {
+                            IntForm::Decimal
+                        };
+                        write_int(code, val, form);
+                        Ok(())
+                    },
+                    Value::Float(val) if other_form && val.is_finite() => {
+                        write!(code, "{:e}", val)
+                    },
+                    Value::Float(val) => write_float(code, val),
                     Value::Str(val) => {
-                        code.push('"');
-                        code +=  &val.to_string();
-                        code.push('"');
+                        write_string(code, &val);
+                        Ok(())
                     },
-                    Value::Bool(val) => code += &val.to_string(),
-                    Value::Undefined => code += "undefined",
-                    Value::None => assert!(false, "Incorrect value for: {:?}",
-                                           inst.operation.opcode()),
-                }
+                    Value::Bool(val) => write!(code, "{}", val),
+                    Value::Undefined => write!(code, "undefined"),
+                    Value::None => panic!("Incorrect value for: {:?}",
+                                          inst.operation.opcode()),
+                };
 
                 code.push(';');
-                self.emitter.add(code);
             },
 
             op::Copy => {
-                let lhs = inst.input_at(0).print();
-                let rhs = inst.input_at(1).print();
-                let code = format!("var {} = {};", lhs, rhs);
-                self.emitter.add(code);
+                // This assigns to a variable that was declared before, which
+                // can't be declared again with let or const
+                let _ = write!(code, "{} = {};", names.of(inst.input_at(0)),
+                               names.of(inst.input_at(1)));
             },
 
             op::BeginIf => {
-                let mut code = "if (".to_owned();
-                code += &inst.input_at(0).print();
-                code += ") {";
-                self.emitter.add(code);
-                self.emitter.indent();
+                let _ = write!(code, "if ({}) {{", names.of(inst.input_at(0)));
+                indent = true;
             },
 
             op::BeginElse => {
                 self.emitter.unindent();
-                self.emitter.add("} else {".to_owned());
-                self.emitter.indent();
+                code.push_str("} else {");
+                indent = true;
             },
 
             op::EndIf => {
                 self.emitter.unindent();
-                self.emitter.add("}".to_owned());
+                code.push('}');
             },
 
             op::BeginFor => {
 
-                let tmp = inst.temp_at(0);
+                let tmp = names.of(inst.temp_at(0));
                 let op = inst.cast_into::<BeginFor>();
 
-                let mut code = format!("for (var {} = {}; {} {} {}; {}{})",
-                                   tmp.print(), inst.input_at(0).print(),
-                                   tmp.print(), op.1.rep(),
-                                   inst.input_at(1).print(),
-                                   tmp.print(), op.0);
-
-                code.push('{');
-                self.emitter.add(code);
-                self.emitter.indent();
+                let _ = write!(code, "for ({} {} = {}; {} {} {}; {}{}){{", decl,
+                               tmp, names.of(inst.input_at(0)), tmp, op.1.rep(),
+                               names.of(inst.input_at(1)), tmp, op.0);
+                indent = true;
             },
 
             op::EndFor => {
                 self.emitter.unindent();
-                self.emitter.add("}".to_owned());
+                code.push('}');
             },
 
             op::Break => {
-                self.emitter.add("break;".to_owned());
+                code.push_str("break;");
             },
 
             op::Continue => {
-                self.emitter.add("continue;".to_owned());
+                code.push_str("continue;");
             }
 
             op::BinaryOp => {
                 let op = inst.cast_into::<BinaryOp>();
-                let out = inst.output_at(0);
-                let lhs = inst.input_at(0);
-                let rhs = inst.input_at(1);
-                self.emitter.add(format!("var {} = {} {} {};",
-                                         out.print(), lhs.print(),
-                                         op.0.rep(), rhs.print()
-                ));
+                let _ = write!(code, "{} {} = {} {} {};", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)), op.0.rep(),
+                               names.of(inst.input_at(1)));
             }
 
             op::UnaryOp => {
                 let op = inst.cast_into::<UnaryOp>();
-                let out = inst.output_at(0);
-                let lhs = inst.input_at(0);
-                let code = match op.0 {
+                let out = names.of(inst.output_at(0));
+                let lhs = names.of(inst.input_at(0));
+                let _ = match op.0 {
                     UnaryOperators::Inc | UnaryOperators::Dec => {
-                        format!("var {} = {}{};", out.print(), lhs.print(),
-                                op.0.rep())
+                        write!(code, "{} {} = {}{};", decl, out, lhs,
+                               op.0.rep())
                     },
                     _ => {
-                        format!("var {} = {}{};", out.print(), op.0.rep(),
-                                lhs.print())
+                        write!(code, "{} {} = {}{};", decl, out,
+                               op.0.rep(), lhs)
                     }
                 };
-
-                self.emitter.add(code);
             },
 
             op::CompareOp => {
                 let op = inst.cast_into::<CompareOp>();
-                let out = inst.output_at(0);
-                let lhs = inst.input_at(0);
-                let rhs = inst.input_at(1);
-                self.emitter.add(format!("var {} = {} {} {};",
-                                         out.print(), lhs.print(),
-                                         op.0.rep(), rhs.print()
-                ));
+                let _ = write!(code, "{} {} = {} {} {};", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)), op.0.rep(),
+                               names.of(inst.input_at(1)));
             },
 
             op::BeginFunctionDefinition => {
-                let mut code = format!("function {}(", inst.output_at(0).print());
+                let _ = write!(code, "function {}(",
+                               names.of(inst.output_at(0)));
                 for v in inst.temp() {
-                    code.push_str(&v.print());
-                    code += ", ";
+                    let _ = write!(code, "{}, ", names.of(v));
                 }
                 if inst.temp.len() != 0 {
                     code.remove(code.len()-2);
                 }
-                code += ") {";
-                self.emitter.add(code);
-                self.emitter.indent();
-
+                code.push_str(") {");
+                indent = true;
             },
 
             op::EndFunctionDefinition => {
                 self.emitter.unindent();
-                self.emitter.add("}".to_string());
+                code.push('}');
+            },
+
+            op::BeginIIFE => {
+                code.push_str("(function() {");
+                indent = true;
+            },
+
+            op::EndIIFE => {
+                self.emitter.unindent();
+                code.push_str("})();");
+            },
+
+            op::Eval => {
+                let op = inst.cast_into::<Eval>();
+                let callee = if op.1 { "eval" } else { "(0, eval)" };
+                let _ = write!(code, "{} {} = {}(", decl,
+                               names.of(inst.output_at(0)), callee);
+                write_string(code, &op.0);
+                code.push_str(");");
             },
 
             op::Return => {
-                let code = format!("return {};", inst.input_at(0).print());
-                self.emitter.add(code);
+                let _ = write!(code, "return {};", names.of(inst.input_at(0)));
             },
 
             op::FunctionCall => {
 
                 let inputs = inst.inputs();
-                let function_name = inst.input_at(0);
-                let output = inst.output_at(0);
-
-                let mut code = format!("var {} = {}(",
-                                       output.print(),
-                                       function_name.print());
+                let _ = write!(code, "{} {} = {}(", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)));
 
                 for v in &inputs[1..] {
-                    code.push_str(&v.print());
-                    code += ", ";
+                    let _ = write!(code, "{}, ", names.of(v));
                 }
 
                 if inputs.len() > 1 {
                     code.remove(code.len()-2);
                 }
 
-                code += ");";
+                code.push_str(");");
+
+            },
 
-                self.emitter.add(code);
+            op::Construct => {
+                let _ = write!(code, "{} {} = new {}(", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)));
+                write_list(code, names, &inst.inputs()[1..]);
+                code.push_str(");");
+            },
 
+            op::LoadThis => {
+                let _ = write!(code, "{} {} = this;", decl,
+                               names.of(inst.output_at(0)));
             },
 
             op::CreateArray => {
-                let mut code = "var ".to_string() + &inst.output_at(0).print();
-                let inputs = &inst.inputs().iter()
-                                           .map(|x| x.print())
-                                           .collect::<Vec<String>>().join(", ");
+                let _ = write!(code, "{} {}", decl,
+                               names.of(inst.output_at(0)));
                 if self.probablity.probablity(0.5) {
-                    code += " = [";
-                    code += inputs;
-                    code += "];";
+                    code.push_str(" = [");
+                    write_list(code, names, inst.inputs());
+                    code.push_str("];");
                 } else {
-                    code += " = Array(";
-                    code += inputs;
-                    code += ");";
+                    code.push_str(" = Array(");
+                    write_list(code, names, inst.inputs());
+                    code.push_str(");");
                 }
-                self.emitter.add(code);
             },
 
             op::LoadElement => {
-                let array  = inst.input_at(0).print();
-                let index  = inst.input_at(1).print();
-                let output = inst.output_at(0).print();
-                let code   = format!("var {} = {}[{}];", output, array, index);
-                self.emitter.add(code);
+                let _ = write!(code, "{} {} = {}[{}];", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)),
+                               names.of(inst.input_at(1)));
             },
 
             op::StoreElement => {
-                let array = inst.input_at(0).print();
-                let index = inst.input_at(1).print();
-                let value = inst.input_at(2).print();
-                let code  = format!("{}[{}] = {};", array, index, value);
-                self.emitter.add(code);
+                let _ = write!(code, "{}[{}] = {};", names.of(inst.input_at(0)),
+                               names.of(inst.input_at(1)),
+                               names.of(inst.input_at(2)));
             },
 
             op::MethodCall => {
                 let op = inst.cast_into::<MethodCall>();
-                let inps = &inst.inputs()[1..];
-                let args = inps.iter().map(|x| x.print())
-                                      .collect::<Vec<String>>().join(", ");
+                let _ = write!(code, "{} {} = {}", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)));
+                write_property(code, op.0.get_name());
+                code.push('(');
+                write_list(code, names, &inst.inputs()[1..]);
+                code.push_str(");");
+            },
 
-                let code = format!("var {} = {}.{}({});",
-                                   inst.output_at(0).print(),
-                                   inst.input_at(0).print(),
-                                   op.0.get_name(), args);
+            op::BuiltinCall => {
+                let op = inst.cast_into::<BuiltinCall>();
+                let _ = write!(code, "{} {} = {}(", decl,
+                               names.of(inst.output_at(0)), op.0.get_name());
+                write_list(code, names, inst.inputs());
+                code.push_str(");");
+            },
 
-                self.emitter.add(code);
+            op::MethodChain => {
+                let op = inst.cast_into::<MethodChain>();
+                let _ = write!(code, "{} {} = {}", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)));
+
+                let mut args = &inst.inputs()[1..];
+                for (signature, len) in &op.0 {
+                    let (call_args, rest) =
+                        args.split_at(std::cmp::min(*len as usize, args.len()));
+                    let _ = write!(code, ".{}(", signature.get_name());
+                    write_list(code, names, call_args);
+                    code.push(')');
+                    args = rest;
+                }
+                code.push(';');
             },
 
             op::LoadProperty => {
                 let op = inst.cast_into::<LoadProperty>();
-                let code = format!("var {} = {}.{}",
-                                   inst.output_at(0).print(),
-                                   inst.input_at(0).print(), op.0);
-                self.emitter.add(code);
+                let _ = write!(code, "{} {} = {}", decl,
+                               names.of(inst.output_at(0)),
+                               names.of(inst.input_at(0)));
+                write_property(code, &op.0);
+                code.push(';');
             },
 
             op::StoreProperty => {
                 let op = inst.cast_into::<StoreProperty>();
-                let obj = inst.input_at(0);
-                let val = inst.input_at(1);
-                let code = if self.probablity.probablity(0.7) {
-                    format!("{}.{} = {}", obj.print(), op.0, val.print())
+                let obj = names.of(inst.input_at(0));
+                let val = names.of(inst.input_at(1));
+                let _ = write!(code, "{}", obj);
+                if self.probablity.probablity(0.7) {
+                    write_property(code, &op.0);
                 } else {
-                    format!("{}[\"{}\"] = {}", obj.print(), op.0, val.print())
-                };
-
-                self.emitter.add(code);
+                    code.push('[');
+                    write_string(code, &op.0);
+                    code.push(']');
+                }
+                let _ = write!(code, " = {};", val);
             }
 
             op::LoadBuiltin => {
                 let op = inst.cast_into::<LoadBuiltin>();
-                let output = inst.output_at(0).print();
-                let code;
-                match &op.0 {
+                let output = names.of(inst.output_at(0));
+                let _ = match &op.0 {
                     ConstructorType::Callable(ms) => {
-                        let inps = &inst.inputs();
-                        let args = inps.iter().map(|x| x.print())
-                                              .collect::<Vec<String>>()
-                                              .join(", ");
-
-                        code = format!("var {} = new {}({});",
-                                        output, ms.get_name(), args);
+                        let _ = write!(code, "{} {} = new {}(", decl, output,
+                                       ms.get_name());
+                        write_list(code, names, inst.inputs());
+                        write!(code, ");")
                     },
                     ConstructorType::NonCallable(name, _) => {
-                        code = format!("var {} = {}", output, name)
+                        write!(code, "{} {} = {};", decl, output, name)
                     }
                 };
-
-                self.emitter.add(code);
             },
 
             op::CreateObject => {
                 let op = inst.cast_into::<CreateObject>();
-                let output = inst.output_at(0);
-                let object = op.0.iter()
-                                 .zip(inst.inputs())
-                                 .map(|(prop, val)| format!("{}: {}", prop,
-                                                            val.print()))
-                                 .collect::<Vec<String>>().join(", ");
-
-                let code = format!("var {} = {{{}}};", output.print(), object);
-                self.emitter.add(code);
-
+                let _ = write!(code, "{} {} = {{", decl,
+                               names.of(inst.output_at(0)));
+                for (idx, (prop, val)) in op.0.iter()
+                                              .zip(inst.inputs())
+                                              .enumerate() {
+                    if idx > 0 {
+                        code.push_str(", ");
+                    }
+                    if is_identifier(prop) {
+                        code.push_str(prop);
+                    } else {
+                        write_string(code, prop);
+                    }
+                    let _ = write!(code, ": {}", names.of(val));
+                }
+                code.push_str("};");
 
             },
 
             op::Delete => {
-                let object = inst.input_at(0);
-                let prop   = inst.input_at(1);
-                let code = format!("delete {}[{}]",
-                                   object.print(), prop.print());
-                self.emitter.add(code);
+                let _ = write!(code, "delete {}[{}];",
+                               names.of(inst.input_at(0)),
+                               names.of(inst.input_at(1)));
             }
 
             // op => assert!(false, "Unimplemented opcode for lifting : {:?}", op),
         }
+
+        if !code.is_empty() {
+            self.emitter.add(code);
+        }
+        if indent {
+            self.emitter.indent();
+        }
+    }
+}
+
+fn declaration_table(weights: [u16; 3]) -> WeightTable {
+    let weighted = DECLARATIONS.iter().zip(weights).collect::<Vec<_>>();
+    WeightTable::new(&weighted)
+}
+
+/// Write `val` out as a JS literal that reads back as the same double. The Rust
+/// forms are the shortest ones that do, but they don't have an exponent, which
+/// runs the very large and very small values to hundreds of digits. Those get
+/// one where JS would give them one too.
+fn write_float(code: &mut String, val: f64) -> std::fmt::Result {
+    let abs = val.abs();
+    if val.is_nan() {
+        write!(code, "NaN")
+    } else if val.is_infinite() {
+        write!(code, "{}Infinity", if val > 0.0 { "" } else { "-" })
+    } else if abs >= 1e21 || (abs < 1e-6 && abs != 0.0) {
+        write!(code, "{:e}", val)
+    } else {
+        // This has the sign of -0 too
+        write!(code, "{}", val)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jsruntime::jsruntime::JSRuntime;
+    use crate::fuzzer::interesting::{INTERESTING_FLOATS, INTERESTING_STRINGS};
+
+    #[test]
+    fn test_floats() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        for val in INTERESTING_FLOATS {
+            program.load_float(val);
+        }
+
+        let mut lifter = Lifter::new();
+        lifter.seed(1);
+        lifter.do_lifting(&program);
+        let code = lifter.get_code();
+        for literal in [" = NaN;", " = Infinity;", " = -Infinity;", " = -0;",
+                        " = 4503599627370495.5;"] {
+            assert!(code.contains(literal), "{} in {}", literal, code);
+        }
+        assert!(!code.contains("inf;"));
+
+        // The finite ones read back as the same double
+        let mut rng = Random::new(5);
+        let randoms = (0..1000).map(|_| f64::from_bits(rng.rand()));
+        for val in INTERESTING_FLOATS.into_iter().chain(randoms)
+                                     .filter(|val| val.is_finite()) {
+            let mut literal = String::new();
+            write_float(&mut literal, val).unwrap();
+            assert!(literal.len() <= 24, "{}", literal);
+            assert_eq!(literal.parse::<f64>().unwrap().to_bits(), val.to_bits(),
+                       "{}", literal);
+        }
+    }
+
+    #[test]
+    fn test_surrogates() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        for val in INTERESTING_STRINGS {
+            program.load_string(val.to_string());
+        }
+
+        let mut lifter = Lifter::new();
+        lifter.seed(1);
+        lifter.do_lifting(&program);
+        let code = lifter.get_code();
+        for literal in ["\"\\ud800\";", "\"\\udc00\";", "\"a\\ud800b\";",
+                        "\"\\udc00\\ud800\";"] {
+            assert!(code.contains(literal), "{} in {}", literal, code);
+        }
+        assert!(!code.contains('\u{10f800}'));
+    }
+
+    #[test]
+    fn test_digest() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        program.generate_random_insts(5);
+
+        let mut lifter = Lifter::new();
+        lifter.do_lifting(&program);
+        assert!(!lifter.get_code().contains("__record"));
+
+        lifter.reset();
+        lifter.set_digest(true);
+        lifter.do_lifting(&program);
+        let code = lifter.get_code();
+        assert!(code.starts_with(DIGEST_PRELUDE[0]));
+        assert!(code.contains("__record(v"));
+        assert!(code.trim_end().ends_with("print(\"digest: \" + __digest);"));
+
+        assert_eq!(find_digest("1\ndigest: -42\n"), Some("-42"));
+        assert_eq!(find_digest("digest"), None);
+    }
+
+    #[test]
+    fn test_declarations() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        let vars = (0..50).map(|val| program.load_int(val)).collect::<Vec<_>>();
+        for pair in vars.chunks(2) {
+            program.copy(pair[0], pair[1]);
+        }
+
+        let mut lifter = Lifter::new();
+        lifter.seed(3);
+        lifter.set_declarations([0, 0, 1]);
+        lifter.do_lifting(&program);
+        let code = lifter.get_code();
+        // The variables that are assigned to fall back to var, as let is off
+        assert!(code.contains("var v0 = 0;"));
+        assert!(code.contains("const v1 = 1;"));
+        assert!(code.lines().any(|line| line.trim() == "v0 = v1;"));
+
+        lifter.reset();
+        lifter.set_declarations([1, 0, 0]);
+        lifter.do_lifting(&program);
+        assert!(!lifter.get_code().contains("let ") &&
+                !lifter.get_code().contains("const "));
+    }
+
+    #[test]
+    fn test_strip_dead_code() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        let used = program.load_int(7);
+        let unused = program.load_int(8);
+        program.binary_op(used, unused, BinaryOperators::Sub);
+        let array = program.create_array(vec![used]);
+        program.store_element(array, used, used);
+
+        let mut lifter = Lifter::new();
+        lifter.set_declarations([1, 0, 0]);
+        lifter.set_strip_dead_code(true);
+        lifter.do_lifting(&program);
+        let code = lifter.get_code();
+        assert!(code.contains("var v0 = 7;") && code.contains("v3[v0] = v0;"));
+        assert!(!code.contains("v1") && !code.contains("v2"));
+
+        for seed in 1..50 {
+            let mut program = Program::new(&runtime);
+            program.seed(seed);
+            program.generate_random_insts(30);
+            let dead = Liveness::analyze(&program.buffer).dead_count();
+
+            let mut stripped = Lifter::new();
+            stripped.set_comments(true);
+            stripped.set_strip_dead_code(true);
+            stripped.do_lifting(&program);
+            let comments = stripped.get_code().lines()
+                .filter(|line| line.trim_start().starts_with("// ["))
+                .count();
+            assert_eq!(comments, program.buffer.len() - dead);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        program.generate_random_insts(5);
+
+        let mut lifter = Lifter::new();
+        lifter.set_comments(true);
+        lifter.do_lifting(&program);
+        let code = lifter.get_code();
+        let comments = code.lines()
+            .filter(|line| line.trim_start().starts_with("// ["))
+            .count();
+        assert_eq!(comments, program.buffer.len());
+        assert!(code.contains(&format!("// [0] {}\n",
+                                       program.buffer[0].print())));
+    }
+
+    #[test]
+    fn test_iife_probability() {
+        let runtime = JSRuntime::new();
+        let mut program = Program::new(&runtime);
+        program.load_int(7);
+
+        let mut lifter = Lifter::new();
+        for (probability, wrapped) in [(0.0, false), (1.0, true)] {
+            lifter.set_iife_probability(probability);
+            for seed in 0..20 {
+                lifter.seed(seed);
+                lifter.reset();
+                lifter.do_lifting(&program);
+                assert_eq!(lifter.get_code().starts_with("(function() {"),
+                           wrapped);
+            }
+        }
     }
 }
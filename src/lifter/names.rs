@@ -0,0 +1,124 @@
+//! Gives the variables of a program names like the ones of real code, instead
+//! of `v` and their ids. The engines intern and look up short names, the names
+//! of the properties of the builtins and unicode names each in their own way,
+//! and the saved programs read a bit more naturally with them too.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ir::variable::Variable;
+use crate::utils::probablity::Probablity;
+
+/// Short names, like the ones of loop counters and of minified code
+const SHORT: [&str; 30] = [
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o",
+    "p", "q", "r", "s", "t", "u", "w", "x", "y", "z", "_", "$", "_a", "$$",
+    "fn",
+];
+
+/// The names of the properties of the builtins, which the engines intern up
+/// front
+const PROPERTIES: [&str; 20] = [
+    "length", "toString", "valueOf", "constructor", "prototype", "name",
+    "size", "value", "done", "next", "then", "index", "input", "source",
+    "flags", "caller", "callee", "message", "stack", "lastIndex",
+];
+
+/// Longer names, like the ones that people give their variables
+const WORDS: [&str; 16] = [
+    "result", "data", "obj", "arr", "str", "tmp", "count", "key", "target",
+    "handler", "callback", "options", "item", "list", "node", "offset",
+];
+
+/// Names with letters from outside of ASCII
+const UNICODE: [&str; 8] = [
+    "π", "ß", "ñandú", "変数", "Δx", "ℊ", "데이터", "переменная",
+];
+
+const POOLS: [&[&str]; 4] = [&SHORT, &PROPERTIES, &WORDS, &UNICODE];
+
+/// The names of the variables of the program that is being lifted. The ones
+/// that weren't given a name go by their ids.
+#[derive(Default)]
+pub struct Names {
+    names: HashMap<u32, String>,
+    taken: HashSet<String>,
+}
+
+/// The name of a variable, as it is written to the code
+pub enum Name<'a> {
+    Id(&'a Variable),
+    Named(&'a str),
+}
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Name::Id(variable) => write!(fmt, "{}", variable),
+            Name::Named(name)  => write!(fmt, "{}", name),
+        }
+    }
+}
+
+impl Names {
+
+    /// Forget the names of the last program
+    pub fn clear(&mut self) {
+        self.names.clear();
+        self.taken.clear();
+    }
+
+    /// Give `variable` a name out of one of the pools, unless it has one. All
+    /// of the names are different, so that it doesn't matter which scope a
+    /// variable is declared in. A name that is taken gets the id of the
+    /// variable added to it.
+    pub fn assign(&mut self, variable: &Variable, prob: &mut Probablity) {
+        if self.names.contains_key(&variable.0) {
+            return;
+        }
+
+        let pool = *prob.0.random_element(&POOLS);
+        let mut name = prob.0.random_element(&pool).to_string();
+        if self.taken.contains(&name) {
+            name = format!("{}{}", name, variable.0);
+        }
+        while self.taken.contains(&name) {
+            name.push('_');
+        }
+
+        self.taken.insert(name.clone());
+        self.names.insert(variable.0, name);
+    }
+
+    pub fn of<'a>(&'a self, variable: &'a Variable) -> Name<'a> {
+        match self.names.get(&variable.0) {
+            Some(name) => Name::Named(name),
+            None       => Name::Id(variable),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::random::Random;
+
+    #[test]
+    fn test_names() {
+        let mut names = Names::default();
+        let mut prob = Probablity::new(Random::new(9));
+        let variables = (0..500).map(Variable).collect::<Vec<_>>();
+        for variable in &variables {
+            names.assign(variable, &mut prob);
+        }
+
+        let written = variables.iter()
+            .map(|variable| names.of(variable).to_string())
+            .collect::<HashSet<_>>();
+        assert_eq!(written.len(), variables.len());
+        assert!(written.contains("length") && written.contains("π"));
+
+        names.clear();
+        assert_eq!(names.of(&variables[3]).to_string(), "v3");
+    }
+}
@@ -1,7 +1,6 @@
 use std::fs;
-use std::thread;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 mod ir;
 mod lifter;
@@ -10,45 +9,205 @@ mod fuzzer;
 mod jsruntime;
 mod cmdlineoptions;
 mod execution;
+mod profiles;
+mod mutators;
 
-use fuzzer::fuzzer::Fuzzer;
-use cmdlineoptions::CmdLineOptions;
+use cmdlineoptions::{CmdLineOptions, Command};
 use jsruntime::jsruntime::JSRuntime;
-use fuzzer::fuzz_globals::FuzzGlobals;
+use fuzzer::fuzz_globals::{FuzzGlobals, handle_interrupts};
+use fuzzer::corpus::Corpus;
+use fuzzer::scheduler::get_scheduler;
+use fuzzer::minimize::minimize;
+use fuzzer::replay::{lift, replay};
+use fuzzer::bench::bench_gen;
+use fuzzer::preflight::check_engine;
+use fuzzer::session::SessionState;
+use fuzzer::settings::set_generator_weights;
+use profiles::profile::{Profile, get_profile};
+use utils::alloc::CountingAllocator;
+use utils::log::{Level, set_level};
 
-extern "C" {
-    fn signal(signum: i32, handler: *const ());
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run the command that was given on the command line
+fn run(cmdline: CmdLineOptions) {
+
+    // The weights have to be set before the first program is generated
+    set_generator_weights(&cmdline.weights);
+
+    let mut runtime: JSRuntime = match &cmdline.builtins {
+        Some(path) => match JSRuntime::from_spec(path) {
+            Ok(runtime) => runtime,
+            Err(err)    => {
+                error!("Error occured while loading the builtins: {}", err);
+                return;
+            }
+        },
+        None => JSRuntime::new(),
+    };
+
+    // Register the shell helpers of the engine with the runtime
+    let profile = get_profile(cmdline.profile,
+                              !cmdline.disk && !cmdline.forkserver);
+    runtime.register_builtins(profile.get_extra_builtins());
+    runtime.set_gc_function(profile.get_gc_function());
+
+    // These commands don't need the corpus
+    match &cmdline.command {
+        Command::Lift(crash) => {
+            if let Err(err) = lift(&cmdline, &runtime, profile.as_ref(), crash) {
+                error!("Error occured while lifting the program: {}", err);
+            }
+            return;
+        },
+        Command::Replay(crash) => {
+            if check(&cmdline, profile.as_ref()) {
+                if let Err(err) = replay(&cmdline, &runtime, profile.as_ref(),
+                                         crash) {
+                    error!("Error occured while replaying the program: {}",
+                           err);
+                }
+            }
+            return;
+        },
+        Command::Bench(count) => {
+            info!("Seed: {}", cmdline.seed);
+            bench_gen(&cmdline, &runtime, *count);
+            return;
+        },
+        _ => {},
+    }
+
+    let mut corpus = match Corpus::load(&cmdline.corpus_dir()) {
+        Ok(corpus) => corpus,
+        Err(err)   => {
+            error!("Error occured while loading the corpus: {}", err);
+            return;
+        }
+    };
+    info!("Loaded {} programs from the corpus", corpus.len());
+
+    let import = match &cmdline.command {
+        Command::CorpusImport(dir) => Some(dir),
+        _                          => cmdline.fuzzilli_corpus.as_ref(),
+    };
+    if let Some(dir) = import {
+        match corpus.import_fuzzilli(dir, &runtime) {
+            Ok(count) => info!("Imported {} programs from {}", count, dir),
+            Err(err)  => {
+                error!("Error occured while importing the Fuzzilli corpus: {}",
+                       err);
+                return;
+            }
+        }
+    }
+
+    let runs_engine = matches!(cmdline.command,
+                               Command::Fuzz | Command::Minimize(_));
+    if runs_engine && !check(&cmdline, profile.as_ref()) {
+        return;
+    }
+
+    match &cmdline.command {
+        Command::CorpusInfo => corpus_info(&corpus),
+        Command::Minimize(output) => {
+            if let Err(err) = minimize(&cmdline, &runtime, profile.as_ref(),
+                                       &corpus, output) {
+                error!("Error occured while minimizing the corpus: {}", err);
+            }
+        },
+        Command::Fuzz => fuzz(cmdline, runtime, profile, corpus),
+        _ => {},
+    }
 }
 
-fn handle() {
-    println!("CTRL-C!");
-    std::process::exit(-1);
+/// Check that the engine can be run before running anything on it. Returns
+/// false if it can't.
+fn check(cmdline: &CmdLineOptions, profile: &dyn Profile) -> bool {
+    match check_engine(cmdline, profile) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Error occured while checking the engine: {}", err);
+            false
+        }
+    }
+}
+
+/// Print out the no. of entries of `corpus` and how big they are
+fn corpus_info(corpus: &Corpus) {
+    let instructions = corpus.entries().iter()
+        .map(|entry| entry.instructions.len())
+        .collect::<Vec<usize>>();
+    let total = instructions.iter().sum::<usize>();
+    let edges = corpus.entries().iter()
+        .map(|entry| entry.new_edges)
+        .sum::<usize>();
+
+    println!("Entries         = {}", corpus.len());
+    println!("Instructions    = {} in total, {} on average, {} at most", total,
+             total / std::cmp::max(corpus.len(), 1),
+             instructions.iter().max().unwrap_or(&0));
+    println!("New Edges       = {}", edges);
 }
 
 /// The function that will create all the fuzzers and invoke them to start
 /// fuzzing. This will only ever return out if this is a dry run fuzzing test.
-fn fuzz(cmdline: CmdLineOptions) {
+fn fuzz(mut cmdline: CmdLineOptions, runtime: JSRuntime,
+        profile: Box<dyn Profile>, mut corpus: Corpus) {
 
     let nthreads   = cmdline.threads;
     let is_dry_run = cmdline.dry_run;
-    let start = Instant::now();
+    let mut start = Instant::now();
+
+    // The name was already checked when parsing the options
+    corpus.set_scheduler(get_scheduler(&cmdline.scheduler).unwrap());
 
-    let runtime: JSRuntime = JSRuntime::new();
-    let globals = FuzzGlobals::new("test".to_string(), cmdline, runtime);
+    let (session_dir, resumed) = match &cmdline.resume {
+        Some(dir) => match SessionState::load(dir) {
+            Ok(state) => (dir.to_string(), Some(state)),
+            Err(err)  => {
+                error!("Error occured while loading the session: {}", err);
+                return;
+            }
+        },
+        None => match prepare_dir(&cmdline.output_dir) {
+            Ok(dir)  => (dir, None),
+            Err(err) => {
+                error!("Error occured while creating the directories: {}",
+                       err);
+                return;
+            }
+        },
+    };
 
-    let mut threads = vec![];
+    // The session goes on with the seed that it was started with, and its run
+    // time counts from when it was started
+    if let Some(state) = &resumed {
+        info!("Resuming the session after {} seconds and {} programs",
+              state.elapsed.as_secs(), state.stats.iter);
+        cmdline.seed = state.seed;
+        start = start.checked_sub(state.elapsed).unwrap_or(start);
+    }
 
-    let globals = Arc::new(globals);
-    for i in 0..nthreads {
-        let globals = globals.clone();
-        let t = thread::spawn(move || {
-            let mut fuzzer = Fuzzer::new(i, globals);
-            fuzzer.fuzzloop();
-        });
-
-        threads.push(t);
+    info!("Seed: {}", cmdline.seed);
+    info!("Saving the results to {}", session_dir);
+    info!("Starting {} fuzzer threads", nthreads);
+    if cmdline.log_files {
+        info!("The fuzzer threads log to {}/logs", session_dir);
     }
 
+    let mut globals = FuzzGlobals::new("test".to_string(), cmdline, runtime,
+                                       corpus, profile, session_dir);
+    if let Some(state) = resumed {
+        globals.resume(state);
+    }
+
+    let globals = Arc::new(globals);
+    let mut threads = (0..nthreads)
+        .map(|id| FuzzGlobals::spawn_worker(&globals, id))
+        .collect::<Vec<_>>();
+
 
     if is_dry_run {
         for t in threads {
@@ -57,19 +216,52 @@ fn fuzz(cmdline: CmdLineOptions) {
         return;
     }
 
-    unsafe {
-        signal(2, handle as *const ());
+    handle_interrupts();
+    FuzzGlobals::mainloop(&globals, start, &mut threads);
+
+    // Let the threads finish the programs that they are running, so that the
+    // final stats include them
+    for t in threads {
+        let _ = t.join();
     }
+    info!("The campaign is over");
+    globals.print_stats(&start);
+    globals.save_stats(&start);
+    globals.save_session(&start);
 
-    globals.mainloop(start);
+    // The last entries of the corpus may still be on their way to the disk
+    globals.corpus.write().expect("Lock Poisoned").flush();
 }
 
-/// Creates the directories that will be used by the fuzzers for storing
-/// testcases and crashes.
-fn prepare_dir() -> std::io::Result<()> {
-    fs::create_dir_all("./tests")?;
-    fs::create_dir_all("./crashes")?;
-    Ok(())
+/// Creates the directory that this run of the fuzzer saves its results to,
+/// inside of `output_dir`, and returns its path. Runs that start in the same
+/// second get a no. after the timestamp, so that no two runs share a session.
+fn prepare_dir(output_dir: &str) -> std::io::Result<String> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    fs::create_dir_all(output_dir)?;
+
+    let mut count = 0;
+    loop {
+        let session_dir = if count == 0 {
+            format!("{}/session_{}", output_dir, timestamp)
+        } else {
+            format!("{}/session_{}_{}", output_dir, timestamp, count)
+        };
+
+        // Creating the directory fails if another run has it already
+        match fs::create_dir(&session_dir) {
+            Ok(()) => {
+                fs::create_dir(format!("{}/crashes", session_dir))?;
+                return Ok(session_dir);
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                count += 1;
+            },
+            Err(err) => return Err(err),
+        }
+    }
 }
 
 
@@ -80,20 +272,13 @@ fn main() {
     let cmdline_options = match CmdLineOptions::parse(cmdline) {
         Ok(cmd)  => cmd,
         Err(err) => {
-            println!("Invalid cmd line syntax found: {}", err);
+            error!("Invalid cmd line syntax found: {}", err);
             CmdLineOptions::help();
             return;
         }
     };
 
-    match prepare_dir() {
-        Ok(_)    => {},
-        Err(err) => {
-            println!("Error occured while creating the directories: {}", err);
-            return;
-        }
-    };
-
-    fuzz(cmdline_options);
+    set_level(Level::Info.adjust(cmdline_options.verbosity));
+    run(cmdline_options);
 
 }
@@ -0,0 +1,8 @@
+//! The mutators that derive new programs from the ones that the fuzzer has
+//! retained, instead of generating each program from scratch.
+
+pub mod mutator;
+pub mod inputmutator;
+pub mod splicemutator;
+pub mod insertionmutator;
+pub mod reductionmutator;
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use super::mutator::Mutator;
+use crate::ir::program::Program;
+use crate::ir::operation::OperationInfo;
+use crate::ir::opcodes::Opcodes as op;
+use crate::ir::codeanalysis::types::Any;
+use crate::utils::random::Random;
+
+/// On average, mutate the inputs of one in every `MUTATION_RATE` instructions
+const MUTATION_RATE: usize = 10;
+
+/// The input mutator rewires the data flow of a program. It replaces an input
+/// of a few random instructions with another variable that is visible at that
+/// point of the program.
+pub struct InputMutator;
+
+impl Mutator for InputMutator {
+    fn mutate_into(&self, program: &Program, mutated: &mut Program,
+                   rng: &mut Random) -> bool {
+
+        // Collect the instructions that have inputs which can be replaced. The
+        // first input of a `Copy` is the variable being assigned to, so that
+        // one is left alone.
+        let candidates = program.buffer.iter().enumerate()
+            .filter(|(_, inst)| match inst.operation.opcode() {
+                op::Copy => false,
+                _        => !inst.inputs().is_empty(),
+            })
+            .map(|(idx, _)| idx)
+            .collect::<Vec<usize>>();
+
+        if candidates.is_empty() {
+            return false;
+        }
+
+        let count = std::cmp::max(1, candidates.len() / MUTATION_RATE);
+        let targets = rng.get_n_random_elements(&candidates, count)
+                         .into_iter().copied().collect::<HashSet<usize>>();
+
+        // Rebuild the program instruction by instruction, so that the
+        // analyzers of the new program know what is visible at each point.
+        mutated.derive_from(program);
+        mutated.seed(rng.rand());
+        for (idx, inst) in program.buffer.iter().enumerate() {
+            let mut inst = inst.clone();
+
+            if targets.contains(&idx) &&
+                !mutated.scope_analyzer.get_visible_variables().is_empty() {
+                let input = rng.rand_idx(inst.inputs.len());
+                inst.inputs[input] = mutated.random_variable(Any);
+            }
+
+            mutated.append(inst);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jsruntime::jsruntime::JSRuntime;
+    use crate::mutators::mutator::test::{is_well_formed, same_instruction};
+
+    #[test]
+    fn test_input_mutator() {
+        let runtime = JSRuntime::new();
+        let mut changed = 0;
+        let mut mutated = Program::new(&runtime);
+
+        for seed in 1..20 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.generate_random_insts(10);
+
+            let mut rng = Random::new(seed);
+            if !InputMutator.mutate_into(&p, &mut mutated, &mut rng) {
+                continue;
+            }
+            assert_eq!(mutated.buffer.len(), p.buffer.len());
+            assert!(is_well_formed(&mutated));
+
+            // Only the inputs of the instructions may change
+            for (old, new) in p.buffer.iter().zip(mutated.buffer.iter()) {
+                assert!(old.operation.opcode() == new.operation.opcode());
+                assert_eq!(old.inputs().len(), new.inputs().len());
+                let mut new = new.clone();
+                new.inputs = old.inputs.clone();
+                assert!(same_instruction(old, &new));
+            }
+
+            if p.buffer.iter().zip(mutated.buffer.iter())
+                .any(|(old, new)| !same_instruction(old, new)) {
+                changed += 1;
+            }
+        }
+
+        assert!(changed > 0);
+    }
+}
@@ -0,0 +1,62 @@
+use super::mutator::Mutator;
+use crate::ir::program::Program;
+use crate::utils::random::Random;
+
+/// The max no. of instructions that are generated in a single mutation
+const MAX_INSERTED: u64 = 3;
+
+/// The insertion mutator generates a few new instructions at a random point in
+/// the program. The new code can use any variable that is visible at that
+/// point, and the code after it can use the new variables in later mutations.
+pub struct InsertionMutator;
+
+impl Mutator for InsertionMutator {
+    fn mutate_into(&self, program: &Program, mutated: &mut Program,
+                   rng: &mut Random) -> bool {
+
+        mutated.derive_from(program);
+        mutated.seed(rng.rand());
+        let index = rng.rand_idx(program.buffer.len() + 1);
+        let count = rng.u64_in_range(1, MAX_INSERTED + 1) as u8;
+
+        // The generators see the state of the program at `index`, and the code
+        // after it is analyzed again as the new code may change its types
+        let (head, tail) = program.buffer.split_at(index);
+        for inst in head {
+            mutated.append(inst.clone());
+        }
+        mutated.generate_random_insts(count);
+        for inst in tail {
+            mutated.append(inst.clone());
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jsruntime::jsruntime::JSRuntime;
+    use crate::mutators::mutator::test::{is_well_formed, kept_and_new};
+
+    #[test]
+    fn test_insertion_mutator() {
+        let runtime = JSRuntime::new();
+        let mut mutated = Program::new(&runtime);
+
+        for seed in 1..20 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.generate_random_insts(10);
+
+            let mut rng = Random::new(seed);
+            assert!(InsertionMutator.mutate_into(&p, &mut mutated, &mut rng));
+            assert!(is_well_formed(&mutated));
+
+            // All of the program is still there, with new code around it
+            let added = kept_and_new(&p, &mutated).unwrap();
+            assert!(!added.is_empty());
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use crate::ir::program::Program;
+use crate::ir::instruction::Instruction;
+use crate::ir::operation::OperationInfo;
+use crate::utils::random::Random;
+
+/// Trait to hold the public functions of all the mutators. A mutator never
+/// modifies the program that it is given, it builds the mutated program in
+/// place of another one, so that the fuzzer can reuse the memory of that.
+pub trait Mutator {
+    /// Build a mutated copy of `program` in place of `mutated`. Returns false
+    /// if this mutator could not do anything useful with `program`, in which
+    /// case `mutated` may hold any program.
+    fn mutate_into(&self, program: &Program, mutated: &mut Program,
+                   rng: &mut Random) -> bool;
+
+    /// Whether this mutator combines the program with another program of the
+    /// corpus, the donor, in which case [Mutator::mutate_with_into] is used
+    /// instead of [Mutator::mutate_into].
+    fn takes_donor(&self) -> bool {
+        false
+    }
+
+    /// Build a mutated copy of `program` that takes code from the
+    /// instructions of `donor` in place of `mutated`.
+    fn mutate_with_into(&self, program: &Program, _donor: &[Instruction],
+                        mutated: &mut Program, rng: &mut Random) -> bool {
+        self.mutate_into(program, mutated, rng)
+    }
+
+}
+
+/// Map the index of each block start in `buffer` to the index of the
+/// instruction that ends the block. Blocks like `BeginIf` that are continued
+/// by another block, like `BeginElse`, end at the end of the last one.
+pub fn find_block_ends(buffer: &[Instruction]) -> HashMap<usize, usize> {
+    let mut block_ends = HashMap::new();
+    let mut blocks = Vec::new();
+
+    for (idx, inst) in buffer.iter().enumerate() {
+        let op = &inst.operation;
+        if op.is_block_end() && !op.is_block_start() {
+            if let Some(start) = blocks.pop() {
+                block_ends.insert(start, idx);
+            }
+        } else if op.is_block_start() && !op.is_block_end() {
+            blocks.push(idx);
+        }
+    }
+
+    block_ends
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::ir::program::Program;
+    use crate::ir::instruction::Instruction;
+    use crate::ir::operation::OperationInfo;
+    use crate::ir::variable::Variable;
+
+    fn ids(vars: &[Variable]) -> Vec<u32> {
+        vars.iter().map(|v| v.0).collect()
+    }
+
+    /// Whether `a` and `b` are the same operation on the same variables
+    pub fn same_instruction(a: &Instruction, b: &Instruction) -> bool {
+        a.operation.opcode() == b.operation.opcode() &&
+            ids(a.inputs()) == ids(b.inputs()) &&
+            ids(a.outputs()) == ids(b.outputs()) &&
+            ids(a.temp()) == ids(b.temp())
+    }
+
+    /// Split the instructions of `mutated` into the ones that are kept from
+    /// `program`, in order, and the rest. Returns `None` if some instruction
+    /// of `program` is missing from `mutated`.
+    pub fn kept_and_new<'p>(program: &Program, mutated: &'p Program)
+                            -> Option<Vec<&'p Instruction>> {
+        let mut original = program.buffer.iter().peekable();
+        let mut added = Vec::new();
+        for inst in mutated.buffer.iter() {
+            match original.peek() {
+                Some(next) if same_instruction(next, inst) => {
+                    original.next();
+                }
+                _ => added.push(inst),
+            }
+        }
+
+        original.next().is_none().then_some(added)
+    }
+
+    /// Whether the analyzers accept `program`, that is every input is visible
+    /// where it is used and all the blocks are closed
+    pub fn is_well_formed(program: &Program) -> bool {
+        let mut rebuilt = Program::new(program.jsruntime);
+        rebuilt.derive_from(program);
+        for inst in program.buffer.iter() {
+            let visible = ids(&rebuilt.scope_analyzer.get_visible_variables());
+            if !inst.inputs().iter().all(|v| visible.contains(&v.0)) {
+                return false;
+            }
+            rebuilt.append(inst.clone());
+        }
+
+        rebuilt.scope_analyzer.get_all_scopes().len() == 1
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+
+use super::mutator::{Mutator, find_block_ends};
+use crate::ir::program::Program;
+use crate::ir::operation::OperationInfo;
+use crate::utils::random::Random;
+
+/// On average, remove one in every `REDUCTION_RATE` of the removable parts
+const REDUCTION_RATE: usize = 5;
+
+/// The reduction mutator makes a program smaller by removing the code whose
+/// results are not used by the rest of the program. A part of the program that
+/// can be removed is either a single instruction or a whole block, so that the
+/// block structure stays intact. It is removable if none of the variables that
+/// it defines are used outside of it.
+pub struct ReductionMutator;
+
+impl Mutator for ReductionMutator {
+    fn mutate_into(&self, program: &Program, mutated: &mut Program,
+                   rng: &mut Random) -> bool {
+
+        let candidates = ReductionMutator::find_removable(program);
+        if candidates.is_empty() {
+            return false;
+        }
+
+        let count = std::cmp::max(1, candidates.len() / REDUCTION_RATE);
+        let mut removed = HashSet::<usize>::new();
+        for &(start, end) in rng.get_n_random_elements(&candidates, count) {
+            removed.extend(start..=end);
+        }
+
+        mutated.derive_from(program);
+        for (idx, inst) in program.buffer.iter().enumerate() {
+            if !removed.contains(&idx) {
+                mutated.append(inst.clone());
+            }
+        }
+
+        true
+    }
+}
+
+impl ReductionMutator {
+
+    /// Find the parts of `program` that can be removed as the start and end
+    /// index of each part. Removing any combination of these leaves a valid
+    /// program behind, as a part that defines a variable used by another part
+    /// is not removable itself.
+    pub fn find_removable(program: &Program) -> Vec<(usize, usize)> {
+
+        let buffer = &program.buffer;
+        let block_ends = find_block_ends(&program.buffer);
+
+        // Def-use information: where each variable is used
+        let mut uses = HashMap::<u32, Vec<usize>>::new();
+        for (idx, inst) in buffer.iter().enumerate() {
+            for input in inst.inputs() {
+                uses.entry(input.0).or_default().push(idx);
+            }
+        }
+
+        let is_used_outside = |start: usize, end: usize| {
+            buffer[start..=end].iter().any(|inst| {
+                inst.outputs().iter().chain(inst.temp().iter()).any(|v| {
+                    uses.get(&v.0).is_some_and(|uses| {
+                        uses.iter().any(|&idx| idx < start || idx > end)
+                    })
+                })
+            })
+        };
+
+        let mut removable = Vec::new();
+        for (idx, inst) in buffer.iter().enumerate() {
+            let op = &inst.operation;
+            let end = if op.is_block_start() && !op.is_block_end() {
+                match block_ends.get(&idx) {
+                    Some(&end) => end,
+                    None       => continue,
+                }
+            } else if op.is_block_end() {
+                continue;
+            } else {
+                idx
+            };
+
+            if !is_used_outside(idx, end) {
+                removable.push((idx, end));
+            }
+        }
+
+        removable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jsruntime::jsruntime::JSRuntime;
+    use crate::mutators::mutator::test::{is_well_formed, kept_and_new};
+
+    #[test]
+    fn test_reduction_mutator() {
+        let runtime = JSRuntime::new();
+        let mut reduced = 0;
+        let mut mutated = Program::new(&runtime);
+
+        for seed in 1..20 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.generate_random_insts(10);
+
+            let mut rng = Random::new(seed);
+            if !ReductionMutator.mutate_into(&p, &mut mutated, &mut rng) {
+                continue;
+            }
+            assert!(mutated.buffer.len() < p.buffer.len());
+            assert!(is_well_formed(&mutated));
+
+            // Nothing is added, the program only loses instructions
+            assert!(kept_and_new(&mutated, &p).is_some());
+            reduced += 1;
+        }
+
+        assert!(reduced > 0);
+    }
+}
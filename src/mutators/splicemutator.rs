@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use super::mutator::{Mutator, find_block_ends};
+use crate::ir::program::Program;
+use crate::ir::operation::OperationInfo;
+use crate::ir::instruction::Instruction;
+use crate::ir::opcodes::Opcodes as op;
+use crate::utils::random::Random;
+
+/// The splice mutator copies a self contained slice of another program of the
+/// corpus, the donor, into the program being mutated. The slice is built from a
+/// random instruction of the donor along with all the instructions that produce
+/// its inputs, transitively. If one of these inputs is defined by a block, like
+/// the parameter of a function or the induction variable of a loop, then the
+/// whole block is part of the slice.
+pub struct SpliceMutator;
+
+impl Mutator for SpliceMutator {
+    /// There is nothing to splice without a donor
+    fn mutate_into(&self, _program: &Program, _mutated: &mut Program,
+                   _rng: &mut Random) -> bool {
+        false
+    }
+
+    fn takes_donor(&self) -> bool {
+        true
+    }
+
+    fn mutate_with_into(&self, program: &Program, donor: &[Instruction],
+                        mutated: &mut Program, rng: &mut Random) -> bool {
+        SpliceMutator::splice(program, donor, mutated, rng).is_some()
+    }
+}
+
+impl SpliceMutator {
+
+    /// Build a copy of `program` with a slice of `donor` inserted at a random
+    /// point in it in place of `mutated`.
+    pub fn splice(program: &Program, donor: &[Instruction],
+                  mutated: &mut Program, rng: &mut Random) -> Option<()> {
+
+        let slice = SpliceMutator::find_slice(donor, rng)?;
+
+        // Now rebuild the program, inserting the slice at a random point. The
+        // variables of the slice are renamed to new variables of the mutated
+        // program as we go.
+        let point = rng.rand_idx(program.buffer.len() + 1);
+        mutated.derive_from(program);
+        mutated.seed(rng.rand());
+
+        for (idx, inst) in program.buffer.iter().enumerate() {
+            if idx == point {
+                SpliceMutator::insert_slice(mutated, donor, &slice)?;
+            }
+            mutated.append(inst.clone());
+        }
+
+        if point == program.buffer.len() {
+            SpliceMutator::insert_slice(mutated, donor, &slice)?;
+        }
+
+        Some(())
+    }
+
+    /// Pick a random instruction from `donor` and find the indices of all the
+    /// instructions that it needs, in program order.
+    fn find_slice(buffer: &[Instruction],
+                  rng: &mut Random) -> Option<Vec<usize>> {
+
+        // Map each variable to the instruction that defines it
+        let mut producers = HashMap::<u32, usize>::new();
+        for (idx, inst) in buffer.iter().enumerate() {
+            for v in inst.outputs().iter().chain(inst.temp().iter()) {
+                producers.insert(v.0, idx);
+            }
+        }
+        let block_end = find_block_ends(buffer);
+
+        // The slice starts off from an instruction that is not a part of the
+        // block structure.
+        let candidates = buffer.iter().enumerate()
+            .filter(|(_, inst)| {
+                !inst.operation.is_block_start() &&
+                    !inst.operation.is_block_end()
+            })
+            .map(|(idx, _)| idx)
+            .collect::<Vec<usize>>();
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let start = *rng.random_element(&candidates);
+        let mut slice = HashSet::<usize>::new();
+        let mut worklist = vec![start];
+
+        while let Some(idx) = worklist.pop() {
+            if !slice.insert(idx) {
+                continue;
+            }
+
+            for input in buffer[idx].inputs() {
+                let producer = *producers.get(&input.0)?;
+
+                if buffer[producer].operation.is_block_start() {
+                    worklist.extend(producer..=*block_end.get(&producer)?);
+                } else {
+                    worklist.push(producer);
+                }
+            }
+        }
+
+        let mut slice = slice.into_iter().collect::<Vec<usize>>();
+        slice.sort_unstable();
+
+        if !SpliceMutator::is_valid_slice(buffer, &slice) {
+            return None;
+        }
+
+        Some(slice)
+    }
+
+    /// A slice is moved to the top level of the program that it is spliced
+    /// into, so it may only break, continue or return from the loops and
+    /// functions that are a part of it. The code in an eval refers to the
+    /// variables of the donor by name, so that can't be moved either.
+    fn is_valid_slice(buffer: &[Instruction], slice: &[usize]) -> bool {
+
+        // Holds true for each open block that is a loop and false for the
+        // functions. Other blocks don't matter here.
+        let mut blocks = Vec::<bool>::new();
+        let mut functions = 0;
+
+        for &idx in slice {
+            let op = &buffer[idx].operation;
+
+            if op.is_block_end() && !op.is_block_start() &&
+                (op.is_loop_end() || op.is_function_end()) {
+                blocks.pop();
+                if op.is_function_end() {
+                    functions -= 1;
+                }
+            } else if op.is_loop_start() {
+                blocks.push(true);
+            } else if op.is_function_start() {
+                blocks.push(false);
+                functions += 1;
+            }
+
+            match op.opcode() {
+                op::Break | op::Continue if blocks.last() != Some(&true) => {
+                    return false
+                },
+                op::Return if functions == 0 => return false,
+                op::Eval => return false,
+                _ => {},
+            }
+        }
+
+        true
+    }
+
+    /// Append the `slice` of `donor` to `program`, renaming the variables that
+    /// it defines.
+    fn insert_slice(program: &mut Program, donor: &[Instruction],
+                    slice: &[usize]) -> Option<()> {
+
+        let mut renamed = HashMap::new();
+
+        for &idx in slice {
+            let mut inst = donor[idx].clone();
+
+            for v in inst.outputs.iter_mut().chain(inst.temp.iter_mut()) {
+                let new = program.next_free_variable();
+                renamed.insert(v.0, new);
+                *v = new;
+            }
+
+            for v in inst.inputs.iter_mut() {
+                *v = *renamed.get(&v.0)?;
+            }
+
+            program.append(inst);
+        }
+
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jsruntime::jsruntime::JSRuntime;
+    use crate::mutators::mutator::test::{is_well_formed, same_instruction};
+
+    #[test]
+    fn test_splice_mutator() {
+        let runtime = JSRuntime::new();
+        let mut spliced = 0;
+        let mut mutated = Program::new(&runtime);
+
+        for seed in 1..20 {
+            let mut p = Program::new(&runtime);
+            p.seed(seed);
+            p.generate_random_insts(10);
+
+            let mut donor = Program::new(&runtime);
+            donor.seed(seed + 100);
+            donor.generate_random_insts(10);
+
+            let mut rng = Random::new(seed);
+            if !SpliceMutator.mutate_with_into(&p, &donor.buffer, &mut mutated,
+                                               &mut rng) {
+                continue;
+            }
+            assert!(is_well_formed(&mutated));
+
+            // The mutated program is the program with a slice of the donor
+            // inserted at some point, keeping the order of the donor
+            let count = mutated.buffer.len() - p.buffer.len();
+            assert!(count > 0);
+
+            let is_split_at = |point: usize| {
+                let (before, rest) = mutated.buffer.split_at(point);
+                let (slice, after) = rest.split_at(count);
+                let mut donor_ops = donor.buffer.iter()
+                    .map(|inst| inst.operation.opcode());
+
+                before.iter().chain(after.iter()).zip(p.buffer.iter())
+                    .all(|(a, b)| same_instruction(a, b)) &&
+                slice.iter().all(|inst| {
+                    let opcode = inst.operation.opcode();
+                    donor_ops.any(|donor_op| donor_op == opcode)
+                })
+            };
+            assert!((0..=p.buffer.len()).any(is_split_at));
+            spliced += 1;
+        }
+
+        assert!(spliced > 0);
+    }
+
+    #[test]
+    fn test_no_donor() {
+        let runtime = JSRuntime::new();
+        let mut p = Program::new(&runtime);
+        p.seed(1);
+        p.generate_random_insts(10);
+
+        assert!(SpliceMutator.takes_donor());
+        let mut mutated = Program::new(&runtime);
+        assert!(!SpliceMutator.mutate_into(&p, &mut mutated,
+                                           &mut Random::new(1)));
+    }
+}
@@ -1,3 +1,8 @@
+//! The engine specific parts of fuzzing, like the args to start the engine
+//! with and the shell helpers that it provides
+
 pub mod profile;
 pub mod spidermonkey;
 pub mod javascriptcore;
+pub mod v8;
+pub mod custom;
@@ -0,0 +1,26 @@
+use super::profile::Profile;
+
+/// The profile for engines that zebra has no profile for. It adds no args and
+/// no builtins, so the engine is only run with the args that are given on the
+/// command line.
+pub struct CustomProfile {
+    args: Vec<&'static str>,
+}
+
+impl Profile for CustomProfile {
+    fn get_args(&self) -> &Vec<&'static str> {
+        &self.args
+    }
+
+    fn get_no_jit_args(&self) -> Vec<&'static str> {
+        vec![]
+    }
+}
+
+impl CustomProfile {
+    pub fn new() -> Self {
+        CustomProfile {
+            args: vec![]
+        }
+    }
+}
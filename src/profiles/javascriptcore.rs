@@ -1,4 +1,8 @@
 use super::profile::Profile;
+use crate::jsruntime::jsbuiltin::JSBuiltin;
+use crate::ir::codeanalysis::types::MethodSignature as MS;
+use crate::ir::codeanalysis::types::MethodArg as MA;
+use crate::ir::codeanalysis::types::*;
 
 pub struct JavaScriptCoreProfile {
     args: Vec<&'static str>,
@@ -8,6 +12,45 @@ impl Profile for JavaScriptCoreProfile {
     fn get_args(&self) -> &Vec<&'static str> {
         &self.args
     }
+
+    fn get_no_jit_args(&self) -> Vec<&'static str> {
+        vec!["--useJIT=false"]
+    }
+
+    fn get_extra_builtins(&self) -> Vec<JSBuiltin> {
+
+        let arraybuf = Type::obj(Shape::ArrayBuffer);
+
+        // The helpers that the jsc shell exposes as globals. The ones on `$vm`
+        // need `--useDollarVM=true` which is not part of the profile, hence
+        // only the global ones here.
+        let functions = vec![
+            MS::new("gc", Undefined, vec![], Undefined),
+            MS::new("edenGC", Undefined, vec![], Undefined),
+            MS::new("fullGC", Undefined, vec![], Undefined),
+            MS::new("noInline", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("noDFG", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("noFTL", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("optimizeNextInvocation", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("numberOfDFGCompiles", Undefined, vec![MA::Type(Function)], Int),
+            MS::new("fiatInt52", Undefined, vec![MA::Type(Int | Float)], Int | Float),
+            MS::new("OSRExit", Undefined, vec![], Undefined),
+            MS::new("transferArrayBuffer", Undefined, vec![MA::Type(arraybuf)], Undefined),
+        ];
+
+        vec![JSBuiltin {
+            shape:          Shape::None,
+            constructor:    vec![],
+            properties:     vec![],
+            methods:        None,
+            static_methods: None,
+            functions:      Some(functions),
+        }]
+    }
+
+    fn get_gc_function(&self) -> Option<&'static str> {
+        Some("gc")
+    }
 }
 
 impl JavaScriptCoreProfile {
@@ -1,13 +1,86 @@
-/// Trait to hold the public functions of all the profiles
-pub trait Profile {
+use crate::jsruntime::jsbuiltin::JSBuiltin;
+
+use super::spidermonkey::SpidermonkeyProfile;
+use super::javascriptcore::JavaScriptCoreProfile;
+use super::v8::V8Profile;
+use super::custom::CustomProfile;
+
+/// Trait to hold the public functions of all the profiles. All the fuzzer
+/// threads share the profile.
+pub trait Profile: Send + Sync {
     /// This will return the command line arguments for the profile selected
     fn get_args(&self) -> &Vec<&'static str>;
+
+    /// This will return the args that turn off all the JIT tiers of the
+    /// engine, so that it only runs programs in the interpreter
+    fn get_no_jit_args(&self) -> Vec<&'static str>;
+
+    /// This will return the args that make the engine compile a program
+    /// without running it, if the engine has a mode for that
+    fn get_compile_only_args(&self) -> Option<Vec<&'static str>> {
+        None
+    }
+
+    /// Whether the engine can be run in REPRL mode. The programs are run from
+    /// files on disk if it can't.
+    fn supports_reprl(&self) -> bool {
+        true
+    }
+
+    /// This will return the engine specific builtins, like the shell helpers
+    /// to force JIT compilation or garbage collection, that should be
+    /// registered with the JS runtime in addition to the standard ones.
+    fn get_extra_builtins(&self) -> Vec<JSBuiltin> {
+        vec![]
+    }
+
+    /// This will return the name of the engine helper that forces a garbage
+    /// collection, if the engine has one. It should be one of the functions
+    /// returned by `get_extra_builtins`.
+    fn get_gc_function(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// This will return the code that is added to the end of every program.
+    /// By default that is a call to the gc function, so that the garbage
+    /// collector runs over whatever the program left behind.
+    fn get_epilogue(&self) -> Vec<String> {
+        match self.get_gc_function() {
+            Some(gc) => vec![format!("{}();", gc)],
+            None     => vec![],
+        }
+    }
 }
 
 /// Types of Profiles allowed
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProfileType {
     Spidermonkey,
     Jsc,
     V8,
+    Custom,
+}
+
+impl ProfileType {
+    /// Get the type of the profile called `name` on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "spidermonkey" => Some(ProfileType::Spidermonkey),
+            "jsc"          => Some(ProfileType::Jsc),
+            "v8"           => Some(ProfileType::V8),
+            "custom"       => Some(ProfileType::Custom),
+            _ => None,
+        }
+    }
+}
+
+/// Create the profile of type `profile`. The args of the profile are for
+/// running the engine in REPRL mode if `repl` is set.
+pub fn get_profile(profile: ProfileType, repl: bool) -> Box<dyn Profile> {
+    match profile {
+        ProfileType::Spidermonkey => Box::new(SpidermonkeyProfile::new(repl)),
+        ProfileType::Jsc          => Box::new(JavaScriptCoreProfile::new(repl)),
+        ProfileType::V8           => Box::new(V8Profile::new()),
+        ProfileType::Custom       => Box::new(CustomProfile::new()),
+    }
 }
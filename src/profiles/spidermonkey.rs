@@ -1,4 +1,8 @@
 use super::profile::Profile;
+use crate::jsruntime::jsbuiltin::JSBuiltin;
+use crate::ir::codeanalysis::types::MethodSignature as MS;
+use crate::ir::codeanalysis::types::MethodArg as MA;
+use crate::ir::codeanalysis::types::*;
 
 pub struct SpidermonkeyProfile {
     args: Vec<&'static str>,
@@ -8,6 +12,45 @@ impl Profile for SpidermonkeyProfile {
     fn get_args(&self) -> &Vec<&'static str> {
         &self.args
     }
+
+    fn get_no_jit_args(&self) -> Vec<&'static str> {
+        vec!["--no-blinterp", "--no-baseline", "--no-ion"]
+    }
+
+    fn get_compile_only_args(&self) -> Option<Vec<&'static str>> {
+        Some(vec!["--compileonly"])
+    }
+
+    fn get_extra_builtins(&self) -> Vec<JSBuiltin> {
+
+        let arraybuf = Type::obj(Shape::ArrayBuffer);
+
+        // The testing functions of the spidermonkey shell that are still
+        // available with `--fuzzing-safe`
+        let functions = vec![
+            MS::new("gc", Undefined, vec![], Undefined),
+            MS::new("minorgc", Undefined, vec![], Undefined),
+            MS::new("gczeal", Undefined, vec![MA::Type(Int), MA::Optional(Int)], Undefined),
+            MS::new("oomTest", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("bailout", Undefined, vec![], Undefined),
+            MS::new("inIon", Undefined, vec![], Bool),
+            MS::new("inJit", Undefined, vec![], Bool),
+            MS::new("detachArrayBuffer", Undefined, vec![MA::Type(arraybuf)], Undefined),
+        ];
+
+        vec![JSBuiltin {
+            shape:          Shape::None,
+            constructor:    vec![],
+            properties:     vec![],
+            methods:        None,
+            static_methods: None,
+            functions:      Some(functions),
+        }]
+    }
+
+    fn get_gc_function(&self) -> Option<&'static str> {
+        Some("gc")
+    }
 }
 
 impl SpidermonkeyProfile {
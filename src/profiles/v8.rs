@@ -0,0 +1,69 @@
+use super::profile::Profile;
+use crate::jsruntime::jsbuiltin::JSBuiltin;
+use crate::ir::codeanalysis::types::MethodSignature as MS;
+use crate::ir::codeanalysis::types::MethodArg as MA;
+use crate::ir::codeanalysis::types::*;
+
+pub struct V8Profile {
+    args: Vec<&'static str>,
+}
+
+impl Profile for V8Profile {
+    fn get_args(&self) -> &Vec<&'static str> {
+        &self.args
+    }
+
+    fn get_no_jit_args(&self) -> Vec<&'static str> {
+        vec!["--jitless"]
+    }
+
+    fn get_extra_builtins(&self) -> Vec<JSBuiltin> {
+
+        let arraybuf = Type::obj(Shape::ArrayBuffer);
+
+        // `gc` comes from `--expose-gc`, the rest are runtime functions that
+        // `--allow-natives-syntax` makes callable. With `--fuzzing` they
+        // ignore arguments of the wrong type instead of aborting.
+        let functions = vec![
+            MS::new("gc", Undefined, vec![], Undefined),
+            MS::new("%PrepareFunctionForOptimization", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("%OptimizeFunctionOnNextCall", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("%NeverOptimizeFunction", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("%DeoptimizeFunction", Undefined, vec![MA::Type(Function)], Undefined),
+            MS::new("%OptimizeOsr", Undefined, vec![], Undefined),
+            MS::new("%ArrayBufferDetach", Undefined, vec![MA::Type(arraybuf)], Undefined),
+        ];
+
+        vec![JSBuiltin {
+            shape:          Shape::None,
+            constructor:    vec![],
+            properties:     vec![],
+            methods:        None,
+            static_methods: None,
+            functions:      Some(functions),
+        }]
+    }
+
+    fn get_gc_function(&self) -> Option<&'static str> {
+        Some("gc")
+    }
+}
+
+impl V8Profile {
+    /// d8 finds out on its own if it was started in REPRL mode, so there is no
+    /// arg for it
+    pub fn new() -> Self {
+        let args = vec![
+                "--expose-gc",
+                "--allow-natives-syntax",
+                "--fuzzing",
+                "--omit-quit",
+                // Tier up to the optimizing JITs quickly
+                "--interrupt-budget=1024",
+        ];
+
+        V8Profile {
+            args
+        }
+    }
+}
@@ -1,4 +1,11 @@
-//! Misc utilities that will be useful in fuzzing like Probablity and an RNG
+//! Misc utilities that will be useful in fuzzing like Probablity, an RNG, a
+//! JSON reader, a protobuf reader, an allocator that counts allocations, a
+//! logger and a stable hash
 
 pub mod random;
 pub mod probablity;
+pub mod json;
+pub mod protobuf;
+pub mod alloc;
+pub mod log;
+pub mod hash;
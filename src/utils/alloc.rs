@@ -0,0 +1,61 @@
+//! A global allocator that can count the allocations that are made, to measure
+//! how much the generators and the lifter allocate. Counting is off unless it
+//! is turned on, so the threads don't fight over the counters while fuzzing.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static COUNTING:    AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64  = AtomicU64::new(0);
+static BYTES:       AtomicU64  = AtomicU64::new(0);
+
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout,
+                      new_size: usize) -> *mut u8 {
+        if COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Start counting the allocations of all the threads
+pub fn start_counting() {
+    COUNTING.store(true, Ordering::Relaxed);
+}
+
+/// Stop counting the allocations and get the no. of allocations and the no. of
+/// bytes that were allocated since counting started first
+pub fn stop_counting() -> (u64, u64) {
+    COUNTING.store(false, Ordering::Relaxed);
+    (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_counting() {
+        start_counting();
+        let data = String::with_capacity(100);
+        let (allocations, bytes) = stop_counting();
+        assert!(allocations >= 1);
+        assert!(bytes >= data.capacity() as u64);
+    }
+}
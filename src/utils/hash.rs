@@ -0,0 +1,9 @@
+//! A hash whose values can be saved, as they stay the same across builds of
+//! the fuzzer, unlike the ones of the hashers of the standard library
+
+/// The FNV-1a hash of `data`
+pub fn fnv1a(data: &str) -> u64 {
+    data.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,378 @@
+//! A small JSON reader. This is only used to read the data files that are
+//! passed to the fuzzer, like the builtin definitions, so it only cares about
+//! parsing correct input reasonably and does not try to be fast.
+
+#[derive(Debug)]
+pub struct JsonError(String);
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+type JsonResult<T> = Result<T, JsonError>;
+
+/// A parsed JSON value. Objects keep their members in the order in which they
+/// appear in the input.
+#[derive(Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+
+    /// Parse `input` into a JSON value. The whole input has to be consumed.
+    pub fn parse(input: &str) -> JsonResult<Self> {
+        let mut parser = Parser { input: input.as_bytes(), pos: 0 };
+        let value = parser.parse_value()?;
+
+        parser.skip_whitespace();
+        if parser.pos != parser.input.len() {
+            return Err(parser.error("Trailing characters after the JSON value"));
+        }
+
+        Ok(value)
+    }
+
+    /// Get the member called `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => {
+                members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+/// Write the value out as JSON text. Objects and arrays are written on a single
+/// line, and the numbers that are not finite as null.
+impl std::fmt::Display for JsonValue {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JsonValue::Null      => write!(fmt, "null"),
+            JsonValue::Bool(b)   => write!(fmt, "{}", b),
+            // JSON has no NaN or Infinity
+            JsonValue::Number(n) if !n.is_finite() => write!(fmt, "null"),
+            JsonValue::Number(n) => write!(fmt, "{}", n),
+            JsonValue::String(s) => write_string(fmt, s),
+            JsonValue::Array(elements) => {
+                write!(fmt, "[")?;
+                for (idx, element) in elements.iter().enumerate() {
+                    if idx != 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{}", element)?;
+                }
+                write!(fmt, "]")
+            },
+            JsonValue::Object(members) => {
+                write!(fmt, "{{")?;
+                for (idx, (key, value)) in members.iter().enumerate() {
+                    if idx != 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write_string(fmt, key)?;
+                    write!(fmt, ": {}", value)?;
+                }
+                write!(fmt, "}}")
+            },
+        }
+    }
+}
+
+fn write_string(fmt: &mut std::fmt::Formatter, s: &str) -> std::fmt::Result {
+    write!(fmt, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"'  => write!(fmt, "\\\"")?,
+            '\\' => write!(fmt, "\\\\")?,
+            '\n' => write!(fmt, "\\n")?,
+            '\r' => write!(fmt, "\\r")?,
+            '\t' => write!(fmt, "\\t")?,
+            c if (c as u32) < 0x20 => write!(fmt, "\\u{:04x}", c as u32)?,
+            c    => write!(fmt, "{}", c)?,
+        }
+    }
+    write!(fmt, "\"")
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos:   usize,
+}
+
+impl<'a> Parser<'a> {
+
+    fn error(&self, msg: &str) -> JsonError {
+        JsonError(format!("{} at offset {}", msg, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.input.get(self.pos) {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> JsonResult<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("Expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_literal(&mut self, literal: &str,
+                     value: JsonValue) -> JsonResult<JsonValue> {
+        if !self.input[self.pos..].starts_with(literal.as_bytes()) {
+            return Err(self.error("Invalid literal"));
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> JsonResult<JsonValue> {
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None    => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn parse_object(&mut self) -> JsonResult<JsonValue> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(members));
+        }
+
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("Expected a string as the key"));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            members.push((key, self.parse_value()?));
+
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members));
+                },
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> JsonResult<JsonValue> {
+        self.expect(b'[')?;
+        let mut elements = Vec::new();
+
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(elements));
+        }
+
+        loop {
+            elements.push(self.parse_value()?);
+
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(elements));
+                },
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> JsonResult<String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+
+        loop {
+            let c = match self.input.get(self.pos) {
+                Some(c) => *c,
+                None    => return Err(self.error("Unterminated string")),
+            };
+            self.pos += 1;
+
+            match c {
+                b'"'  => break,
+                b'\\' => {
+                    let escaped = match self.input.get(self.pos) {
+                        Some(c) => *c,
+                        None    => return Err(self.error("Unterminated string")),
+                    };
+                    self.pos += 1;
+
+                    let decoded = match escaped {
+                        b'"'  => '"',
+                        b'\\' => '\\',
+                        b'/'  => '/',
+                        b'b'  => '\u{8}',
+                        b'f'  => '\u{c}',
+                        b'n'  => '\n',
+                        b'r'  => '\r',
+                        b't'  => '\t',
+                        b'u'  => self.parse_unicode_escape()?,
+                        _     => return Err(self.error("Invalid escape")),
+                    };
+
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(decoded.encode_utf8(&mut buf)
+                                                   .as_bytes());
+                },
+                _ => bytes.push(c),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error("Invalid utf-8"))
+    }
+
+    /// Decode the 4 hex digits after a `\u`. Characters outside of the BMP
+    /// are written as a pair of surrogates, like `\ud83d\ude00`, which
+    /// are combined back into the one character.
+    fn parse_unicode_escape(&mut self) -> JsonResult<char> {
+        let mut code = self.parse_hex_digits()?;
+        if (0xd800..0xdc00).contains(&code) {
+            if self.input.get(self.pos..self.pos + 2) != Some(b"\\u") {
+                return Err(self.error("Unpaired surrogate in unicode escape"));
+            }
+            self.pos += 2;
+
+            let low = self.parse_hex_digits()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("Unpaired surrogate in unicode escape"));
+            }
+            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+        }
+
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn parse_hex_digits(&mut self) -> JsonResult<u32> {
+        let digits = self.input.get(self.pos..self.pos + 4)
+            .filter(|d| d.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok());
+
+        let code = match digits {
+            Some(code) => code,
+            None       => return Err(self.error("Invalid unicode escape")),
+        };
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn parse_number(&mut self) -> JsonResult<JsonValue> {
+        let start = self.pos;
+        while let Some(c) = self.input.get(self.pos) {
+            if !(c.is_ascii_digit() || b"+-.eE".contains(c)) {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        std::str::from_utf8(&self.input[start..self.pos]).ok()
+            .and_then(|n| n.parse::<f64>().ok())
+            .map(JsonValue::Number)
+            .ok_or_else(|| self.error("Invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::JsonValue;
+
+    #[test]
+    fn test_parse() {
+        let value = JsonValue::parse(r#"
+            {"name": "a\"b\u0041", "list": [1, -2.5e1, true, null], "obj": {}}
+        "#).unwrap();
+
+        assert_eq!(value.get("name").unwrap().as_str(), Some("a\"bA"));
+        assert_eq!(value.get("list").unwrap().as_array().unwrap(), &vec![
+            JsonValue::Number(1.0), JsonValue::Number(-25.0),
+            JsonValue::Bool(true), JsonValue::Null
+        ]);
+        assert_eq!(value.get("obj"), Some(&JsonValue::Object(vec![])));
+
+        let text = value.to_string();
+        assert_eq!(JsonValue::parse(&text).unwrap(), value);
+
+        assert!(JsonValue::parse("[1, 2").is_err());
+        assert!(JsonValue::parse("{\"a\": 1} x").is_err());
+    }
+
+    #[test]
+    fn test_surrogates() {
+        let value = JsonValue::parse(r#""\ud83d\ude00 \uD800\uDC00""#).unwrap();
+        assert_eq!(value.as_str(), Some("\u{1f600} \u{10000}"));
+        assert_eq!(JsonValue::parse(&value.to_string()).unwrap(), value);
+
+        for lone in [r#""\ud800""#, r#""\ud800x""#, r#""\ud800\u0041""#,
+                     r#""\udc00""#] {
+            assert!(JsonValue::parse(lone).is_err(), "{}", lone);
+        }
+
+        let numbers = JsonValue::Array(vec![
+            JsonValue::Number(f64::NAN), JsonValue::Number(f64::INFINITY),
+            JsonValue::Number(1.5),
+        ]);
+        assert_eq!(numbers.to_string(), "[null, null, 1.5]");
+    }
+}
@@ -0,0 +1,146 @@
+//! A small leveled logger. The messages go to stderr, each in a single write so
+//! the lines of the threads don't run into each other, and the stats that are
+//! printed to stdout stay readable. A thread can log to a file of its own
+//! instead, see [log_to_file], apart from the errors which show up on stderr
+//! either way.
+//!
+//! Messages are logged with the [error], [warn], [info], [debug] and [trace]
+//! macros, which take the same args as `println!`.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How important a message is. Only the messages up to the level that is set
+/// with [set_level] are logged.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error = 0,
+    Warn  = 1,
+    Info  = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl Level {
+
+    /// The level that is `steps` more verbose than this one, or less verbose
+    /// for a negative no. It stays within the levels that there are.
+    pub fn adjust(self, steps: i8) -> Self {
+        match (self as i8 + steps).clamp(0, 4) {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            3 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    /// What the messages of this level start with
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "[-]",
+            Level::Warn  => "[!]",
+            Level::Info  => "[+]",
+            Level::Debug => "[*]",
+            Level::Trace => "[.]",
+        }
+    }
+}
+
+/// The most verbose level that is logged
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+thread_local! {
+    /// The file that the messages of this thread go to instead of stderr
+    static LOG_FILE: RefCell<Option<File>> = const { RefCell::new(None) };
+}
+
+/// Log the messages up to `level` from here on
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Check if the messages of `level` are logged
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Send the messages of the current thread to `file` instead of stderr
+pub fn log_to_file(file: File) {
+    LOG_FILE.with(|log| *log.borrow_mut() = Some(file));
+}
+
+/// Log a message of `level`. This is what the macros call.
+pub fn log(level: Level, args: std::fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+
+    let line = format!("{} {}\n", level.prefix(), args);
+    let written = LOG_FILE.with(|log| {
+        log.borrow_mut().as_mut()
+            .map(|file| file.write_all(line.as_bytes()).is_ok())
+    });
+
+    // The errors are shown on stderr even when the thread logs to a file, as
+    // the fuzzer might not be able to go on after them
+    if written != Some(true) || level == Level::Error {
+        let _ = std::io::stderr().lock().write_all(line.as_bytes());
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg: tt)*) => {
+        $crate::utils::log::log($crate::utils::log::Level::Error,
+                                format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg: tt)*) => {
+        $crate::utils::log::log($crate::utils::log::Level::Warn,
+                                format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg: tt)*) => {
+        $crate::utils::log::log($crate::utils::log::Level::Info,
+                                format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg: tt)*) => {
+        $crate::utils::log::log($crate::utils::log::Level::Debug,
+                                format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg: tt)*) => {
+        $crate::utils::log::log($crate::utils::log::Level::Trace,
+                                format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(Level::Info.adjust(1), Level::Debug);
+        assert_eq!(Level::Info.adjust(-2), Level::Error);
+        assert_eq!(Level::Warn.adjust(-5), Level::Error);
+        assert_eq!(Level::Debug.adjust(9), Level::Trace);
+        assert!(Level::Error < Level::Trace);
+    }
+}
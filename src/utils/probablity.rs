@@ -2,7 +2,36 @@
 
 use super::random::Random;
 
+/// The running totals of the weights of a list of elements, which is built
+/// once for lists that are chosen from all the time, like the generators. An
+/// element is then chosen with a single random no. and a binary search, see
+/// [Probablity::choose_from_table].
+#[derive(Clone, Debug)]
+pub struct WeightTable {
+    totals: Vec<u32>,
+}
+
+impl WeightTable {
+
+    /// Build the table for the elements of `d`, which are paired with their
+    /// weights like [Probablity::choose_weighted_baised] takes them
+    pub fn new<T>(d: &[(T, u16)]) -> Self {
+        let mut total = 0;
+        let totals = d.iter().map(|(_, weight)| {
+            total += *weight as u32;
+            total
+        }).collect();
+        Self { totals }
+    }
+
+    /// The sum of all the weights
+    pub fn total(&self) -> u32 {
+        self.totals.last().copied().unwrap_or(0)
+    }
+}
+
 /// This struct will be used to perform all the probablity related operations
+#[derive(Clone)]
 pub struct Probablity(pub Random);
 
 impl Probablity {
@@ -11,22 +40,19 @@ impl Probablity {
         Self(rng)
     }
 
-    /// Return a random probablity (no. between 0 and 1)
+    /// Return a random probablity (no. between 0 and 1), uniformly
+    /// distributed in [0, 1)
     pub fn prob(&mut self) -> f64 {
-        self.0.rand_in_range(0, 11) as f64 / 10.0
+        self.0.unit_f64()
     }
 
     /// Returns true if the given probablity is satisfied and false otherwise
     pub fn probablity(&mut self, prob: f64) -> bool {
-        if prob == 1.0 {
+        if prob >= 1.0 {
             return true;
         }
 
-        if self.prob() < prob {
-            true
-        } else {
-            false
-        }
+        self.prob() < prob
     }
 
     /// If the provided probablity is satisfied, then call the `true_func` else
@@ -68,16 +94,16 @@ impl Probablity {
             x += factor.powi(i as i32);
         }
 
-        for i in (0..len).rev() {
-
-            let weight = factor.powi(i as i32) as f64;
-            let prob   = weight * (1.0/x);
+        // A single draw picks the element that it falls on, going from the last
+        // one. What the rounding leaves over goes to the first one.
+        let mut pick = self.prob() * x;
+        for i in (1..len).rev() {
 
-            if self.probablity(prob) {
+            let weight = factor.powi(i as i32);
+            if pick < weight {
                 return &array[i];
-            } else {
-                x -= weight;
             }
+            pick -= weight;
         }
 
         &array[0]
@@ -88,27 +114,87 @@ impl Probablity {
     /// second one is the corresponding weight of the element
     pub fn choose_weighted_baised<'a, T>(&mut self,
                                          d: &'a [(T, u16)]) -> &'a T {
+        &d[self.choose_weighted_index(d)].0
+    }
+
+    /// Like [choose_weighted_baised](Probablity::choose_weighted_baised), but
+    /// return the index of the selected element. A single random no. is drawn
+    /// out of the total of the weights, and the element is the one that it
+    /// falls on. If all of the weights are 0, every element is as likely.
+    pub fn choose_weighted_index<T>(&mut self, d: &[(T, u16)]) -> usize {
 
-        let mut total: u32 = 0;
+        let total = d.iter().map(|(_, w)| *w as u32).sum::<u32>();
+        if total == 0 {
+            return self.0.rand_idx(d.len());
+        }
 
-        for (_, w) in d {
-            total += *w as u32;
+        let mut pick = self.0.u64_below(total as u64) as u32;
+        for (idx, (_, w)) in d.iter().enumerate() {
+            if pick < *w as u32 {
+                return idx;
+            }
+            pick -= *w as u32;
         }
 
-        for pair in d {
-            let prob = pair.1 as f64 * (1.0/total as f64);
-            if self.probablity(prob) {
-                return &pair.0;
-            } else {
-                total -= pair.1 as u32;
-            };
+        unreachable!("The pick is below the total of the weights")
+    }
+
+    /// Choose the index of an element by the weights in `table`, like
+    /// [choose_weighted_index](Probablity::choose_weighted_index) does
+    pub fn choose_from_table(&mut self, table: &WeightTable) -> usize {
+        let total = table.total();
+        if total == 0 {
+            return self.0.rand_idx(table.totals.len());
         }
 
-        assert!(total == 0, "Unbalanced total");
+        let pick = self.0.u64_below(total as u64) as u32;
+        table.totals.partition_point(|&running| running <= pick)
+    }
+}
 
-        let idx = self.0.rand_idx(d.len());
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_weighted() {
+        let weights = [('a', 1), ('b', 0), ('c', 3)];
+        let table = WeightTable::new(&weights);
+        assert_eq!(table.total(), 4);
+
+        let mut prob = Probablity::new(Random::new(7));
+        let mut counts = [0; 3];
+        let mut table_counts = [0; 3];
+        for _ in 0..40000 {
+            counts[prob.choose_weighted_index(&weights)] += 1;
+            table_counts[prob.choose_from_table(&table)] += 1;
+        }
 
-        &d[idx].0
+        // The distribution follows the weights, and an element with no weight
+        // is never chosen
+        for counts in [counts, table_counts] {
+            assert_eq!(counts[1], 0);
+            assert!((9000..11000).contains(&counts[0]), "{:?}", counts);
+            assert!((29000..31000).contains(&counts[2]), "{:?}", counts);
+        }
 
+        let empty = WeightTable::new(&[('a', 0), ('b', 0)]);
+        assert!(prob.choose_from_table(&empty) < 2);
+    }
+
+    #[test]
+    fn test_probablity() {
+        let mut prob = Probablity::new(Random::new(7));
+        let hits = (0..100000).filter(|_| prob.probablity(0.35)).count();
+        assert!((34000..36000).contains(&hits), "{}", hits);
+        assert!((0..1000).all(|_| !prob.probablity(0.0)));
+        assert!((0..1000).all(|_| (0.0..1.0).contains(&prob.prob())));
+
+        // The last element is twice as likely as the first
+        let mut counts = [0; 2];
+        for _ in 0..30000 {
+            counts[*prob.choose_biased(&[0, 1], 2.0)] += 1;
+        }
+        assert!((9000..11000).contains(&counts[0]), "{:?}", counts);
     }
 }
@@ -0,0 +1,197 @@
+//! A small reader and writer for the protobuf wire format. Only the parts that
+//! are needed to exchange programs with Fuzzilli are here, so messages are read
+//! field by field and there is no schema.
+
+#[derive(Debug)]
+pub struct ProtoError(String);
+impl std::fmt::Display for ProtoError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProtoError {}
+
+type ProtoResult<T> = Result<T, ProtoError>;
+
+/// The value of a single field, as it was found on the wire
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    pub fn as_varint(&self) -> Option<u64> {
+        match self {
+            WireValue::Varint(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            WireValue::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+/// Read a varint from the start of `input` and return it along with the no. of
+/// bytes that it took up.
+fn read_varint(input: &[u8]) -> ProtoResult<(u64, usize)> {
+    let mut value = 0u64;
+    for (idx, byte) in input.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * idx);
+        if byte & 0x80 == 0 {
+            return Ok((value, idx + 1));
+        }
+    }
+    Err(ProtoError("Invalid varint".into()))
+}
+
+/// Split the encoded message `input` into its fields. Fields are returned in
+/// the order in which they appear, so repeated fields show up multiple times.
+pub fn decode_message(input: &[u8]) -> ProtoResult<Vec<(u32, WireValue<'_>)>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let (key, len) = read_varint(&input[pos..])?;
+        pos += len;
+
+        let field = (key >> 3) as u32;
+        let rest = &input[pos..];
+        let (value, len) = match key & 7 {
+            0 => {
+                let (value, len) = read_varint(rest)?;
+                (WireValue::Varint(value), len)
+            },
+            1 if rest.len() >= 8 => {
+                let bytes = rest[..8].try_into().unwrap();
+                (WireValue::Fixed64(u64::from_le_bytes(bytes)), 8)
+            },
+            2 => {
+                let (size, len) = read_varint(rest)?;
+                let size = size as usize;
+                match rest.get(len..len.saturating_add(size)) {
+                    Some(bytes) => (WireValue::Bytes(bytes), len + size),
+                    None => return Err(ProtoError("Truncated field".into())),
+                }
+            },
+            5 if rest.len() >= 4 => {
+                let bytes = rest[..4].try_into().unwrap();
+                (WireValue::Fixed32(u32::from_le_bytes(bytes)), 4)
+            },
+            _ => return Err(ProtoError(format!("Invalid field {}", field))),
+        };
+
+        fields.push((field, value));
+        pos += len;
+    }
+
+    Ok(fields)
+}
+
+/// Read all the values of the repeated integer field `field` out of `fields`.
+/// Both the packed and the unpacked encodings are accepted.
+pub fn repeated_varints(fields: &[(u32, WireValue)],
+                        field: u32) -> ProtoResult<Vec<u64>> {
+    let mut values = Vec::new();
+    for (_, value) in fields.iter().filter(|(f, _)| *f == field) {
+        match value {
+            WireValue::Varint(v) => values.push(*v),
+            WireValue::Bytes(mut packed) => {
+                while !packed.is_empty() {
+                    let (v, len) = read_varint(packed)?;
+                    values.push(v);
+                    packed = &packed[len..];
+                }
+            },
+            _ => return Err(ProtoError(format!("Invalid field {}", field))),
+        }
+    }
+    Ok(values)
+}
+
+/// Builds an encoded message one field at a time
+#[derive(Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn write_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn write_key(&mut self, field: u32, wire_type: u64) {
+        self.write_varint(((field as u64) << 3) | wire_type);
+    }
+
+    pub fn varint(&mut self, field: u32, value: u64) {
+        self.write_key(field, 0);
+        self.write_varint(value);
+    }
+
+    pub fn fixed64(&mut self, field: u32, value: u64) {
+        self.write_key(field, 1);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.write_key(field, 2);
+        self.write_varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    /// Write a repeated integer field in the packed encoding
+    pub fn packed(&mut self, field: u32, values: &[u64]) {
+        let mut packed = Writer::new();
+        for value in values {
+            packed.write_varint(*value);
+        }
+        self.bytes(field, &packed.buf);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_protobuf() {
+        let mut writer = Writer::new();
+        writer.varint(1, 300);
+        writer.varint(2, -1i64 as u64);
+        writer.fixed64(3, 1.5f64.to_bits());
+        writer.bytes(4, b"zebra");
+        writer.packed(5, &[1, 2, 1000]);
+        writer.varint(5, 7);
+        let encoded = writer.finish();
+
+        let fields = decode_message(&encoded).unwrap();
+        assert_eq!(fields[0], (1, WireValue::Varint(300)));
+        assert_eq!(fields[1].1.as_varint().map(|v| v as i64), Some(-1));
+        assert_eq!(fields[2], (3, WireValue::Fixed64(1.5f64.to_bits())));
+        assert_eq!(fields[3].1.as_bytes(), Some(&b"zebra"[..]));
+        assert_eq!(repeated_varints(&fields, 5).unwrap(), vec![1, 2, 1000, 7]);
+
+        assert!(decode_message(&encoded[..encoded.len() - 3]).is_err());
+    }
+}
@@ -1,5 +1,36 @@
 //! A xor-shift Random number generator
 
+/// Mix `stream` into `seed` to get the seed for a separate stream of random
+/// numbers. This is the splitmix64 finalizer, so the seeds of neighbouring
+/// streams don't look alike. The result is never 0, as that would make
+/// [Random::new] pick a random seed.
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_add(1)
+                                        .wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    if z == 0 { 1 } else { z }
+}
+
+/// A value that is different each time that this is called, for picking random
+/// seeds and unique names. This is the time stamp counter on x86_64, and the
+/// time in nanoseconds everywhere else.
+pub fn timestamp() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        unsafe { std::arch::x86_64::_rdtsc() }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(1)
+    }
+}
+
+#[derive(Clone)]
 pub struct Random(u64);
 
 impl Random {
@@ -16,7 +47,7 @@ impl Random {
 
     pub fn new(seed: u64) -> Self {
         let seed = if seed == 0 {
-            let r = unsafe { std::arch::x86_64::_rdtsc() };
+            let r = timestamp();
             // // println!("random value = {:x}",r);
             r
             // 0x88880009999
@@ -29,6 +60,12 @@ impl Random {
         Self(seed)
     }
 
+    /// A double that is uniformly distributed in [0, 1). It is made of the top
+    /// 53 bits of a random no., which is all of the precision that it has.
+    pub fn unit_f64(&mut self) -> f64 {
+        (self.rand() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
     pub fn _rand8(&mut self) -> u8 {
         self.rand() as u8
     }
@@ -47,7 +84,7 @@ impl Random {
         where T: AsRef<[U]> {
 
         let len = array.as_ref().len();
-        let idx = self.rand_in_range(0, len as isize) as usize;
+        let idx = self.rand_idx(len);
         &array.as_ref()[idx]
     }
 
@@ -77,37 +114,116 @@ impl Random {
     }
 
     pub fn rand_idx(&mut self, len: usize) -> usize {
-        if len == 0 {
+        self.u64_below(len as u64) as usize
+    }
+
+    /// Returns a random number below `n`, or 0 if `n` is 0. Each of them is as
+    /// likely as the others: taking the random no. modulo `n` would favor the
+    /// small ones, so the random nos. that would do that are drawn again.
+    pub fn u64_below(&mut self, n: u64) -> u64 {
+        if n == 0 {
             return 0;
         }
 
-        self.rand() as usize % len
+        // The no. of values at the bottom of the range of a u64 that are left
+        // over once it is split into whole runs of `n`
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let r = self.rand();
+            if r >= threshold {
+                return r % n;
+            }
+        }
     }
 
-    /// Returns a random number in the range of [min, max)
-    pub fn rand_in_range(&mut self, min: isize, max: isize) -> isize {
-        if min == max {
+    /// Returns a random number in the range of [min, max), or `min` if the
+    /// range is empty
+    pub fn u64_in_range(&mut self, min: u64, max: u64) -> u64 {
+        if max <= min {
+            return min;
+        }
+
+        min + self.u64_below(max - min)
+    }
+
+    /// Returns a random number in the range of [min, max), or `min` if the
+    /// range is empty. Any range within an i64 works, even the whole of it.
+    pub fn i64_in_range(&mut self, min: i64, max: i64) -> i64 {
+        if max <= min {
             return min;
         }
 
-        min.wrapping_add((self.rand() % (max.wrapping_sub(min) as u64)) as isize)
+        let span = max.wrapping_sub(min) as u64;
+        min.wrapping_add(self.u64_below(span) as i64)
+    }
+
+    /// Returns a random number in the range of [min, max)
+    pub fn rand_in_range(&mut self, min: isize, max: isize) -> isize {
+        self.i64_in_range(min as i64, max as i64) as isize
     }
 
-    pub fn float_in_range(&mut self, _min: isize, max: isize) -> f64 {
-        let r = self.rand_in_range(i32::MIN as isize, i32::MAX as isize);
-        let d = i32::MAX as f64 / max as f64;
-        r as f64 / d
+    /// Returns a random float in the range of [min, max)
+    pub fn float_in_range(&mut self, min: f64, max: f64) -> f64 {
+        // Scale both ends instead of the width of the range, which is not
+        // finite when the range is wider than f64::MAX
+        let u = self.unit_f64();
+        let value = min * (1.0 - u) + max * u;
+
+        // The rounding can land on `max` for very wide ranges
+        if value < max { value } else { min }
     }
 
     pub fn random_string(&mut self, len: u64) -> String {
-        let length = Random::PRINTABLE.len();
         let mut s: String = String::new();
         for _ in 0..len {
-            let i = self.rand_in_range(0, length as isize);
-            s.push(Random::PRINTABLE[i as usize] as char);
+            s.push(*self.random_element(&Random::PRINTABLE) as char);
         }
 
         s
     }
 
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ranges() {
+        let mut rng = Random::new(3);
+
+        assert!((0..1000).all(|_| (-5..5).contains(&rng.rand_in_range(-5, 5))));
+        assert!((0..1000).all(|_| rng.u64_in_range(7, 9) >= 7));
+        assert_eq!(rng.i64_in_range(4, 4), 4);
+        assert_eq!(rng.u64_below(0), 0);
+
+        // The whole range doesn't overflow, and neither does a float range
+        // that doesn't start at 0
+        let _ = rng.i64_in_range(i64::MIN, i64::MAX);
+        let _ = rng.u64_in_range(0, u64::MAX);
+        assert!((0..1000).all(|_| {
+            (10.0..20.0).contains(&rng.float_in_range(10.0, 20.0))
+        }));
+
+        // With a range that covers most of a u64, the modulo would make the
+        // lower half twice as likely as the upper half
+        let n = u64::MAX / 3 * 2;
+        let low = (0..30000).filter(|_| rng.u64_below(n) < n / 2).count();
+        assert!((14000..16000).contains(&low), "{}", low);
+    }
+
+    #[test]
+    fn test_extreme_ranges() {
+        let mut rng = Random::new(5);
+
+        assert!((0..1000).all(|_| {
+            let value = rng.float_in_range(-f64::MAX, f64::MAX);
+            value.is_finite() && value < f64::MAX
+        }));
+        assert!((0..1000).all(|_| {
+            (f64::MAX / 2.0..f64::MAX)
+                .contains(&rng.float_in_range(f64::MAX / 2.0, f64::MAX))
+        }));
+        assert_eq!(rng.float_in_range(-1.5, -1.5), -1.5);
+    }
+}